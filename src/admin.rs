@@ -1,25 +1,42 @@
+use crate::api_keys::{
+    BanLogEntry, BanMetricsSnapshot, BanSubject, KeyLifecycleState, KeyLifecycleStatus, current_epoch_seconds, key_hash,
+};
+use crate::auth_protection::AuthScope;
 use crate::config::{AppConfig, BanRule};
-use crate::server::{AppState, build_runtime_state};
+use crate::server::{AppState, build_runtime_state, extract_client_ip};
+use crate::token_stats::TokenStatsSummary;
 use axum::Router;
 use axum::body::Body;
-use axum::extract::{Path as AxumPath, Query, State};
+use axum::extract::{ConnectInfo, Path as AxumPath, Query, Request, State};
 use axum::http::{HeaderMap, Response, StatusCode};
-use axum::routing::{get, post};
+use axum::middleware::{self, Next};
+use axum::routing::{delete, get, post};
 use http::header::CONTENT_TYPE;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{error, info, warn};
 
-pub fn register_admin_routes(router: Router<AppState>, prefix: &str) -> Router<AppState> {
+mod api_keys;
+mod routes;
+
+pub fn register_admin_routes(router: Router<AppState>, prefix: &str, state: &AppState) -> Router<AppState> {
     let prefix = prefix.trim_end_matches('/');
     router
         .route(&format!("{prefix}/ui"), get(admin_ui_handler))
         .route(&format!("{prefix}/login"), get(admin_login_handler))
         .route(&format!("{prefix}/favicon.ico"), get(admin_favicon_handler))
+        .merge(admin_api_routes(prefix).route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            admin_auth_protection,
+        )))
+}
+
+fn admin_api_routes(prefix: &str) -> Router<AppState> {
+    Router::new()
         .route(
             &format!("{prefix}/api/config"),
             get(admin_config_get_handler).put(admin_config_apply_handler),
@@ -33,8 +50,32 @@ pub fn register_admin_routes(router: Router<AppState>, prefix: &str) -> Router<A
             &format!("{prefix}/api/metrics/ip"),
             get(admin_ip_metrics_handler),
         )
+        // 路由管理
+        .route(
+            &format!("{prefix}/api/routes/{{id}}"),
+            get(routes::admin_get_route)
+                .post(routes::admin_create_route)
+                .put(routes::admin_replace_route)
+                .delete(routes::admin_delete_route),
+        )
         // API Key 管理路由
         .route(&format!("{prefix}/api/keys"), get(admin_list_api_keys))
+        .route(&format!("{prefix}/api/keys/expiring"), get(admin_list_expiring_api_keys))
+        .route(
+            &format!("{prefix}/api/keys/{{id}}"),
+            get(api_keys::admin_get_api_key)
+                .post(api_keys::admin_create_api_key)
+                .patch(api_keys::admin_patch_api_key)
+                .delete(api_keys::admin_delete_api_key),
+        )
+        .route(
+            &format!("{prefix}/api/keys/{{id}}/rotate"),
+            post(api_keys::admin_rotate_api_key),
+        )
+        .route(
+            &format!("{prefix}/api/keys/{{id}}/previous-keys"),
+            get(api_keys::admin_get_previous_keys),
+        )
         .route(
             &format!("{prefix}/api/keys/{{id}}/ban"),
             post(admin_ban_api_key),
@@ -47,14 +88,55 @@ pub fn register_admin_routes(router: Router<AppState>, prefix: &str) -> Router<A
             &format!("{prefix}/api/keys/{{id}}/ban-logs"),
             get(admin_get_ban_logs),
         )
+        // IP / 网段封禁（网段在路径中需编码为 `10.0.0.0%2F24`）
+        .route(
+            &format!("{prefix}/api/ip-bans"),
+            get(admin_list_ip_bans).post(admin_ban_ip),
+        )
+        .route(
+            &format!("{prefix}/api/ip-bans/{{target}}"),
+            delete(admin_unban_ip),
+        )
         // 获取所有封禁日志（不指定 API Key）
         .route(&format!("{prefix}/api/ban-logs"), get(admin_get_all_ban_logs))
+        .route(&format!("{prefix}/api/ban-alerts"), get(admin_list_ban_alerts))
+        .route(&format!("{prefix}/api/ban-rules/shadow-stats"), get(admin_shadow_ban_stats))
         // Token统计路由
         .route(&format!("{prefix}/api/token-stats/summary"), get(admin_token_stats_summary))
         .route(&format!("{prefix}/api/token-stats/keys"), get(admin_list_api_key_token_stats))
         .route(&format!("{prefix}/api/token-stats/keys/{{id}}"), get(admin_get_api_key_token_stats))
         .route(&format!("{prefix}/api/token-stats/routes"), get(admin_list_route_token_stats))
         .route(&format!("{prefix}/api/token-stats/routes/{{id}}"), get(admin_get_route_token_stats))
+        .route(&format!("{prefix}/api/token-stats/models"), get(admin_list_model_token_stats))
+        // 鉴权失败锁定
+        .route(&format!("{prefix}/api/auth-lockouts"), get(admin_list_auth_lockouts))
+        .route(
+            &format!("{prefix}/api/auth-lockouts/{{ip}}"),
+            delete(admin_unlock_auth_lockout),
+        )
+}
+
+/// 管理接口鉴权失败防护：锁定中的 IP 直接拒绝，管理 Token 校验失败（401）计入失败次数
+async fn admin_auth_protection(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .and_then(|ConnectInfo(addr)| {
+            extract_client_ip(request.headers(), *addr, &state.runtime.load().trusted_proxies)
+        });
+    if let Some(response) = state.auth_lockout_response(client_ip.as_deref(), AuthScope::Admin) {
+        return response;
+    }
+
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        state.record_auth_failure(client_ip.as_deref(), AuthScope::Admin).await;
+    }
+    response
 }
 
 fn is_admin_authorized(state: &AppState, headers: &HeaderMap) -> bool {
@@ -73,7 +155,7 @@ fn is_admin_authorized(state: &AppState, headers: &HeaderMap) -> bool {
     if !scheme.eq_ignore_ascii_case("bearer") {
         return false;
     }
-    token.trim() == expected_token.as_str()
+    key_hash::constant_time_eq(token.trim().as_bytes(), expected_token.as_bytes())
 }
 
 fn json_response(status: StatusCode, body: String) -> Response<Body> {
@@ -189,8 +271,9 @@ async fn admin_config_apply_handler(
     if !is_admin_authorized(&state, &headers) {
         return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
     }
+    let _config_guard = state.config_lock.lock().await;
 
-    let new_config: AppConfig = match serde_json::from_slice(&body) {
+    let mut new_config: AppConfig = match serde_json::from_slice(&body) {
         Ok(config) => config,
         Err(err) => {
             warn!(error = %err, "admin: invalid config JSON");
@@ -198,7 +281,11 @@ async fn admin_config_apply_handler(
         }
     };
 
-    if let Err(err) = new_config.validate() {
+    // 新增或更换的 Key 以明文提交，落入运行时配置前转换为哈希
+    if let Err(err) = new_config
+        .hash_plaintext_api_keys()
+        .and_then(|_| new_config.validate())
+    {
         warn!(error = %err, "admin: config validation failed");
         return json_error(StatusCode::BAD_REQUEST, &format!("validation_error: {err}"));
     }
@@ -269,6 +356,18 @@ struct MainConfigOnly {
     pub admin: Option<crate::config::AdminConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token_stats: Option<crate::config::TokenStatsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<crate::config::TokenizerConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<crate::config::PricingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_budget: Option<crate::config::BudgetConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_backend: Option<crate::config::StateBackendConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_protection: Option<crate::config::AuthProtectionConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
 }
 
 /// Ban Rules 独立配置文件结构
@@ -309,12 +408,58 @@ where
     Ok(())
 }
 
+/// 分散配置的 data 目录（相对于主配置文件所在目录）
+fn data_dir_path(config: &AppConfig, config_path: &Path) -> PathBuf {
+    let base_dir = config_path.parent().unwrap_or(Path::new("."));
+    let data_dir = config.data_dir.as_deref().unwrap_or("./data");
+    base_dir.join(data_dir)
+}
+
+/// 单个 API Key 的配置文件路径（`data/apikeys/<id>.yaml`）
+fn api_key_file_path(config: &AppConfig, config_path: &Path, key_id: &str) -> PathBuf {
+    data_dir_path(config, config_path)
+        .join("apikeys")
+        .join(format!("{}.yaml", sanitize_filename(key_id)))
+}
+
+/// 单个路由的配置文件路径（`data/routes/<id>.yaml`）
+fn route_file_path(config: &AppConfig, config_path: &Path, route_id: &str) -> PathBuf {
+    data_dir_path(config, config_path)
+        .join("routes")
+        .join(format!("{}.yaml", sanitize_filename(route_id)))
+}
+
+/// 写入单个 route/apikey 配置文件（先写临时文件再替换）
+fn write_item_file(
+    path: &Path,
+    kind: &str,
+    id: &str,
+    item: &impl serde::Serialize,
+) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("创建 {}s 目录失败: {}", kind, e))?;
+    }
+    let item_yaml =
+        serde_yaml::to_string(item).map_err(|e| format!("序列化 {} {} 失败: {}", kind, id, e))?;
+    let temp_path = path.with_extension("yaml.tmp");
+    fs::write(&temp_path, &item_yaml).map_err(|e| format!("写入 {} {} 失败: {}", kind, id, e))?;
+    fs::rename(&temp_path, path).map_err(|e| format!("重命名 {} {} 失败: {}", kind, id, e))
+}
+
+/// 删除单个 route/apikey 配置文件（文件不存在时忽略）
+fn remove_item_file(path: &Path, kind: &str, id: &str) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("删除 {} {} 失败: {}", kind, id, e))
+        }
+        _ => Ok(()),
+    }
+}
+
 /// 将配置保存到多个文件
 fn save_config_to_files(config: &AppConfig, config_path: &Path) -> Result<(), String> {
     // 1. 确定 data 目录路径
-    let base_dir = config_path.parent().unwrap_or(Path::new("."));
-    let data_dir = config.data_dir.as_deref().unwrap_or("./data");
-    let data_path = base_dir.join(data_dir);
+    let data_path = data_dir_path(config, config_path);
 
     // 2. 创建目录结构
     fs::create_dir_all(&data_path).map_err(|e| format!("创建 data 目录失败: {}", e))?;
@@ -335,6 +480,12 @@ fn save_config_to_files(config: &AppConfig, config_path: &Path) -> Result<(), St
         observability: config.observability.clone(),
         admin: config.admin.clone(),
         token_stats: config.token_stats.clone(),
+        tokenizer: config.tokenizer.clone(),
+        pricing: config.pricing.clone(),
+        global_budget: config.global_budget.clone(),
+        state_backend: config.state_backend.clone(),
+        auth_protection: config.auth_protection.clone(),
+        trusted_proxies: config.trusted_proxies.clone(),
     };
     let main_yaml =
         serde_yaml::to_string(&main_config).map_err(|e| format!("序列化主配置失败: {}", e))?;
@@ -350,12 +501,12 @@ fn save_config_to_files(config: &AppConfig, config_path: &Path) -> Result<(), St
         cleanup_old_files(&routes_dir, routes, |r| &r.id)?;
 
         for route in routes {
-            let file_name = format!("{}.yaml", sanitize_filename(&route.id));
-            let route_path = routes_dir.join(&file_name);
-            let route_yaml = serde_yaml::to_string(route)
-                .map_err(|e| format!("序列化 route {} 失败: {}", route.id, e))?;
-            fs::write(&route_path, &route_yaml)
-                .map_err(|e| format!("写入 route {} 失败: {}", route.id, e))?;
+            write_item_file(
+                &route_file_path(config, config_path, &route.id),
+                "route",
+                &route.id,
+                route,
+            )?;
         }
     }
 
@@ -366,12 +517,7 @@ fn save_config_to_files(config: &AppConfig, config_path: &Path) -> Result<(), St
         cleanup_old_files(&keys_dir, &api_keys.keys, |k| &k.id)?;
 
         for key in &api_keys.keys {
-            let file_name = format!("{}.yaml", sanitize_filename(&key.id));
-            let key_path = keys_dir.join(&file_name);
-            let key_yaml = serde_yaml::to_string(key)
-                .map_err(|e| format!("序列化 apikey {} 失败: {}", key.id, e))?;
-            fs::write(&key_path, &key_yaml)
-                .map_err(|e| format!("写入 apikey {} 失败: {}", key.id, e))?;
+            write_item_file(&api_key_file_path(config, config_path, &key.id), "apikey", &key.id, key)?;
         }
 
         // 6. 保存 ban_rules
@@ -433,20 +579,9 @@ async fn admin_metrics_handler(
         // Filter routes to only include valid ones
         summary.routes.retain(|r| valid_routes.contains(&r.route_id));
 
-        // Filter tokens to only include valid API keys
-        // Note: summary.tokens contains token values (original key), not key IDs
-        // We need to check each token value against the config_storage
-        let valid_token_hashes: std::collections::HashSet<String> = state
-            .config_storage
-            .get_valid_api_key_hashes()
-            .await;
-        summary.tokens.retain(|t| {
-            // Compute hash of the token value and check if it's in valid hashes
-            let mut hasher = Sha256::new();
-            hasher.update(t.token.as_bytes());
-            let token_hash = format!("{:x}", hasher.finalize());
-            valid_token_hashes.contains(&token_hash)
-        });
+        // Filter tokens to only include valid API keys (labels are key IDs)
+        let valid_api_keys = state.config_storage.get_valid_api_key_ids().await;
+        summary.tokens.retain(|token| valid_api_keys.contains(&token.token));
 
         // 重新计算总请求数，使其与过滤后的路由列表一致
         summary.total_requests_1h = summary
@@ -683,28 +818,60 @@ struct ApiKeyListResponse {
     keys: Vec<ApiKeyInfo>,
 }
 
+/// 生命周期预警列表响应
+#[derive(Debug, Serialize)]
+struct ApiKeyLifecycleAlertResponse {
+    keys: Vec<KeyLifecycleStatus>,
+}
+
 /// API Key 信息
 #[derive(Debug, Serialize)]
 struct ApiKeyInfo {
     id: String,
+    /// 展示用的 Key（前缀 + `…`），不返回完整 Key
     key: String,
+    key_prefix: String,
     route_id: Option<String>,
     enabled: bool,
     remark: String,
+    /// 生命周期阶段
+    lifecycle_state: Option<KeyLifecycleState>,
+    not_before: Option<u64>,
+    expires_at: Option<u64>,
+    idle_expiry_days: Option<u64>,
+    /// 实际过期时间（`expires_at` 与闲置过期时间中较早者）
+    effective_expires_at: Option<u64>,
+    last_used_at: Option<u64>,
     is_banned: bool,
     banned_at: Option<u64>,
     ban_expires_at: Option<u64>,
     triggered_rule_id: Option<String>,
     ban_reason: Option<String>,
     ban_count: u32,
+    /// 是否为永久封禁（需手动解封）
+    ban_permanent: bool,
     token_quota: Option<crate::config::TokenQuotaConfig>,
 }
 
 /// 封禁请求
 #[derive(Debug, Deserialize)]
 struct BanRequest {
+    /// 封禁时长（秒），永久封禁时忽略
+    #[serde(default)]
     duration_secs: u64,
     reason: String,
+    /// 永久封禁，需手动解封
+    #[serde(default)]
+    permanent: bool,
+}
+
+/// IP / 网段封禁请求
+#[derive(Debug, Deserialize)]
+struct IpBanRequest {
+    /// IP 或网段（IPv4 /24、IPv6 /64）
+    target: String,
+    #[serde(flatten)]
+    ban: BanRequest,
 }
 
 /// 封禁日志列表响应
@@ -717,7 +884,9 @@ struct BanLogListResponse {
 #[derive(Debug, Serialize)]
 struct BanLogInfo {
     id: String,
+    /// API Key ID，IP / 网段封禁时为对应的 IP 或网段
     api_key_id: String,
+    subject: BanSubject,
     rule_id: String,
     reason: String,
     banned_at: u64,
@@ -726,6 +895,38 @@ struct BanLogInfo {
     metrics_requests: u64,
     metrics_errors: u64,
     metrics_error_rate: f64,
+    /// 完整指标快照（含按条件类型附带的字段，如客户端 IP 列表）
+    metrics_snapshot: BanMetricsSnapshot,
+    /// 该 Key 的第几次封禁
+    offense: u32,
+    permanent: bool,
+    /// 影子规则的模拟封禁（未实际执行）
+    shadow: bool,
+    /// 仅告警规则的触发记录（未封禁）
+    alert: bool,
+}
+
+impl From<BanLogEntry> for BanLogInfo {
+    fn from(entry: BanLogEntry) -> Self {
+        Self {
+            id: entry.id,
+            api_key_id: entry.api_key_id,
+            subject: entry.subject,
+            rule_id: entry.rule_id,
+            reason: entry.reason,
+            banned_at: entry.banned_at,
+            banned_until: entry.banned_until,
+            unbanned_at: entry.unbanned_at,
+            metrics_requests: entry.metrics_snapshot.requests,
+            metrics_errors: entry.metrics_snapshot.errors,
+            metrics_error_rate: entry.metrics_snapshot.error_rate,
+            metrics_snapshot: entry.metrics_snapshot,
+            offense: entry.offense,
+            permanent: entry.permanent,
+            shadow: entry.shadow,
+            alert: entry.alert,
+        }
+    }
 }
 
 /// 列出所有 API Keys
@@ -744,10 +945,17 @@ async fn admin_list_api_keys(
     };
 
     let keys = api_key_manager.get_all_keys().await;
+    let mut lifecycles: HashMap<String, KeyLifecycleStatus> = api_key_manager
+        .lifecycle_statuses()
+        .await
+        .into_iter()
+        .map(|status| (status.key_id.clone(), status))
+        .collect();
     let key_infos: Vec<ApiKeyInfo> = keys
         .into_iter()
         .map(|key| {
-            let (is_banned, banned_at, ban_expires_at, triggered_rule_id, ban_reason, ban_count) = key
+            let lifecycle = lifecycles.remove(&key.id);
+            let (is_banned, banned_at, ban_expires_at, triggered_rule_id, ban_reason, ban_count, ban_permanent) = key
                 .ban_status
                 .as_ref()
                 .map(|s| {
@@ -758,22 +966,31 @@ async fn admin_list_api_keys(
                         s.triggered_rule_id.clone(),
                         s.reason.clone(),
                         s.ban_count,
+                        s.permanent,
                     )
                 })
-                .unwrap_or((false, None, None, None, None, 0));
+                .unwrap_or((false, None, None, None, None, 0, false));
 
             ApiKeyInfo {
                 id: key.id,
-                key: key.key,
+                key: key_hash::display_key(&key.key_prefix),
+                key_prefix: key.key_prefix,
                 route_id: key.route_id,
                 enabled: key.enabled,
                 remark: key.remark,
+                lifecycle_state: lifecycle.as_ref().map(|status| status.state),
+                not_before: key.not_before,
+                expires_at: key.expires_at,
+                idle_expiry_days: key.idle_expiry_days,
+                effective_expires_at: lifecycle.as_ref().and_then(|status| status.expires_at),
+                last_used_at: lifecycle.as_ref().and_then(|status| status.last_used_at),
                 is_banned,
                 banned_at,
                 ban_expires_at,
                 triggered_rule_id,
                 ban_reason,
                 ban_count,
+                ban_permanent,
                 token_quota: key.token_quota,
             }
         })
@@ -782,6 +999,24 @@ async fn admin_list_api_keys(
     json_ok(&ApiKeyListResponse { keys: key_infos })
 }
 
+/// 列出后台任务标记的即将过期 / 已过期 / 未生效的 API Key
+async fn admin_list_expiring_api_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let keys = runtime
+        .api_key_manager
+        .as_ref()
+        .map(|manager| manager.lifecycle_alerts())
+        .unwrap_or_default();
+    json_ok(&ApiKeyLifecycleAlertResponse { keys })
+}
+
 /// 手动封禁 API Key
 async fn admin_ban_api_key(
     State(state): State<AppState>,
@@ -806,21 +1041,23 @@ async fn admin_ban_api_key(
         }
     };
 
-    // 通过 ID 获取 key 值
-    let key_value = match api_key_manager.get_key_by_id(&id).await {
-        Some(key) => key,
-        None => return json_error(StatusCode::NOT_FOUND, "api_key_not_found"),
-    };
+    if !req.permanent && req.duration_secs == 0 {
+        return json_error(StatusCode::BAD_REQUEST, "invalid_duration");
+    }
 
     match api_key_manager
-        .ban_key(&key_value, req.duration_secs, req.reason)
+        .ban_key(&id, (!req.permanent).then_some(req.duration_secs), req.reason)
         .await
     {
         Ok(status) => json_ok(&serde_json::json!({
             "status": "banned",
             "banned_until": status.banned_until,
             "ban_count": status.ban_count,
+            "permanent": status.permanent,
         })),
+        Err(crate::api_keys::ApiKeyError::KeyNotFound) => {
+            json_error(StatusCode::NOT_FOUND, "api_key_not_found")
+        }
         Err(err) => json_error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
     }
 }
@@ -841,20 +1078,146 @@ async fn admin_unban_api_key(
         None => return json_error(StatusCode::NOT_FOUND, "api_key_manager_not_available"),
     };
 
-    // 通过 ID 获取 key 值
-    let key_value = match api_key_manager.get_key_by_id(&id).await {
-        Some(key) => key,
-        None => return json_error(StatusCode::NOT_FOUND, "api_key_not_found"),
-    };
-
-    match api_key_manager.unban_key(&key_value).await {
+    match api_key_manager.unban_key(&id).await {
         Ok(()) => json_ok(&serde_json::json!({
             "status": "unbanned",
         })),
+        Err(crate::api_keys::ApiKeyError::KeyNotFound) => {
+            json_error(StatusCode::NOT_FOUND, "api_key_not_found")
+        }
         Err(err) => json_error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
     }
 }
 
+/// 当前封禁中的 IP 与网段
+async fn admin_list_ip_bans(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let bans = runtime
+        .api_key_manager
+        .as_ref()
+        .map(|manager| manager.ip_bans())
+        .unwrap_or_default();
+    json_ok(&serde_json::json!({ "bans": bans }))
+}
+
+/// 手动封禁 IP 或网段
+async fn admin_ban_ip(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let api_key_manager = match runtime.api_key_manager.as_ref() {
+        Some(manager) => manager,
+        None => return json_error(StatusCode::NOT_FOUND, "api_key_manager_not_available"),
+    };
+
+    let req: IpBanRequest = match serde_json::from_slice(&body) {
+        Ok(req) => req,
+        Err(err) => {
+            return json_error(StatusCode::BAD_REQUEST, &format!("invalid_json: {err}"));
+        }
+    };
+    let (subject, target) = match crate::api_keys::ban::parse_ban_target(&req.target) {
+        Ok(parsed) => parsed,
+        Err(err) => return json_error(StatusCode::BAD_REQUEST, &format!("invalid_target: {err}")),
+    };
+    if !req.ban.permanent && req.ban.duration_secs == 0 {
+        return json_error(StatusCode::BAD_REQUEST, "invalid_duration");
+    }
+
+    let status = api_key_manager.ban_ip(
+        subject,
+        &target,
+        (!req.ban.permanent).then_some(req.ban.duration_secs),
+        req.ban.reason,
+    );
+    info!(subject = subject.as_str(), target = %target, "client banned by admin");
+    json_ok(&serde_json::json!({
+        "status": "banned",
+        "subject": subject,
+        "target": target,
+        "banned_until": status.banned_until,
+        "ban_count": status.ban_count,
+        "permanent": status.permanent,
+    }))
+}
+
+/// 手动解封 IP 或网段
+async fn admin_unban_ip(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(target): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let api_key_manager = match runtime.api_key_manager.as_ref() {
+        Some(manager) => manager,
+        None => return json_error(StatusCode::NOT_FOUND, "api_key_manager_not_available"),
+    };
+    let (subject, target) = match crate::api_keys::ban::parse_ban_target(&target) {
+        Ok(parsed) => parsed,
+        Err(err) => return json_error(StatusCode::BAD_REQUEST, &format!("invalid_target: {err}")),
+    };
+
+    if !api_key_manager.unban_ip(subject, &target) {
+        return json_error(StatusCode::NOT_FOUND, "ip_not_banned");
+    }
+    info!(subject = subject.as_str(), target = %target, "client unbanned by admin");
+    json_ok(&serde_json::json!({
+        "status": "unbanned",
+    }))
+}
+
+/// 当前因鉴权失败被锁定的客户端 IP
+async fn admin_list_auth_lockouts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    json_ok(&serde_json::json!({
+        "enabled": runtime.config.auth_protection.is_some(),
+        "locked": state.auth_failures.locked_clients(current_epoch_seconds()),
+    }))
+}
+
+/// 手动解除某个 IP 的鉴权失败锁定
+async fn admin_unlock_auth_lockout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(ip): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    if !state.auth_failures.unlock(&ip, current_epoch_seconds()) {
+        return json_error(StatusCode::NOT_FOUND, "ip_not_locked");
+    }
+    info!(client_ip = %ip, "auth lockout removed by admin");
+    json_ok(&serde_json::json!({
+        "status": "unlocked",
+    }))
+}
+
 /// 获取 API Key 的封禁日志
 async fn admin_get_ban_logs(
     State(state): State<AppState>,
@@ -883,18 +1246,7 @@ async fn admin_get_ban_logs(
         Ok(entries) => {
             let logs: Vec<BanLogInfo> = entries
                 .into_iter()
-                .map(|entry| BanLogInfo {
-                    id: entry.id,
-                    api_key_id: entry.api_key_id,
-                    rule_id: entry.rule_id,
-                    reason: entry.reason,
-                    banned_at: entry.banned_at,
-                    banned_until: entry.banned_until,
-                    unbanned_at: entry.unbanned_at,
-                    metrics_requests: entry.metrics_snapshot.requests,
-                    metrics_errors: entry.metrics_snapshot.errors,
-                    metrics_error_rate: entry.metrics_snapshot.error_rate,
-                })
+                .map(BanLogInfo::from)
                 .collect();
             json_ok(&BanLogListResponse { logs })
         }
@@ -937,18 +1289,7 @@ async fn admin_get_all_ban_logs(
             info!("admin: queried {} ban logs", entries.len());
             let logs: Vec<BanLogInfo> = entries
                 .into_iter()
-                .map(|entry| BanLogInfo {
-                    id: entry.id,
-                    api_key_id: entry.api_key_id,
-                    rule_id: entry.rule_id,
-                    reason: entry.reason,
-                    banned_at: entry.banned_at,
-                    banned_until: entry.banned_until,
-                    unbanned_at: entry.unbanned_at,
-                    metrics_requests: entry.metrics_snapshot.requests,
-                    metrics_errors: entry.metrics_snapshot.errors,
-                    metrics_error_rate: entry.metrics_snapshot.error_rate,
-                })
+                .map(BanLogInfo::from)
                 .collect();
             json_ok(&BanLogListResponse { logs })
         }
@@ -959,6 +1300,60 @@ async fn admin_get_all_ban_logs(
     }
 }
 
+/// 仅告警的封禁规则最近触发记录
+async fn admin_list_ban_alerts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let alerts = runtime
+        .api_key_manager
+        .as_ref()
+        .map(|manager| manager.ban_alerts())
+        .unwrap_or_default();
+    json_ok(&serde_json::json!({ "alerts": alerts }))
+}
+
+/// 影子统计查询参数
+#[derive(Debug, Deserialize)]
+struct ShadowStatsQuery {
+    /// 统计起始时间（Unix秒），默认全部
+    #[serde(default)]
+    since: u64,
+}
+
+/// 影子模式规则的模拟封禁统计
+async fn admin_shadow_ban_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ShadowStatsQuery>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let Some(ban_log_store) = runtime
+        .api_key_manager
+        .as_ref()
+        .and_then(|manager| manager.ban_log_store())
+    else {
+        return json_ok(&serde_json::json!({ "since": query.since, "rules": [] }));
+    };
+
+    match ban_log_store.count_shadow_bans(query.since).await {
+        Ok(rules) => json_ok(&serde_json::json!({ "since": query.since, "rules": rules })),
+        Err(err) => {
+            error!("Failed to count shadow bans: {}", err);
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "query_failed")
+        }
+    }
+}
+
 /// 封禁日志查询参数
 #[derive(Debug, Deserialize)]
 struct BanLogQuery {
@@ -987,8 +1382,11 @@ impl Default for TokenStatsQuery {
 struct TokenStatsSummaryResponse {
     generated_at_unix_ms: u64,
     window: String,
+    /// 费用货币单位（未配置价格表时为 null）
+    currency: Option<String>,
     api_keys: Vec<ApiKeyTokenSummary>,
     routes: Vec<RouteTokenSummary>,
+    models: Vec<ModelTokenSummary>,
     /// 整体时间序列数据（今日：小时级，本周/本月：天级）
     time_series: Vec<TimeSeriesDataPoint>,
 }
@@ -1001,13 +1399,14 @@ struct TimeSeriesDataPoint {
     input_tokens: u64,
     output_tokens: u64,
     request_count: u64,
+    cost: f64,
 }
 
 /// API Key Token统计摘要
 #[derive(Debug, Serialize)]
 struct ApiKeyTokenSummary {
     api_key_id: String,
-    api_key: String, // 实际的API Key值
+    api_key: String, // 展示用的 Key 前缀
     today_input_tokens: u64,
     today_output_tokens: u64,
     today_total_tokens: u64,
@@ -1020,6 +1419,33 @@ struct ApiKeyTokenSummary {
     request_count_today: u64,
     request_count_week: u64,
     request_count_month: u64,
+    today_cost: f64,
+    week_cost: f64,
+    month_cost: f64,
+}
+
+impl ApiKeyTokenSummary {
+    fn new(api_key_id: String, api_key: String, summary: &TokenStatsSummary) -> Self {
+        Self {
+            api_key_id,
+            api_key,
+            today_input_tokens: summary.today_input,
+            today_output_tokens: summary.today_output,
+            today_total_tokens: summary.today_total,
+            week_input_tokens: summary.week_input,
+            week_output_tokens: summary.week_output,
+            week_total_tokens: summary.week_total,
+            month_input_tokens: summary.month_input,
+            month_output_tokens: summary.month_output,
+            month_total_tokens: summary.month_total,
+            request_count_today: summary.request_count_today,
+            request_count_week: summary.request_count_week,
+            request_count_month: summary.request_count_month,
+            today_cost: summary.today_cost,
+            week_cost: summary.week_cost,
+            month_cost: summary.month_cost,
+        }
+    }
 }
 
 /// Route Token统计摘要
@@ -1038,6 +1464,70 @@ struct RouteTokenSummary {
     request_count_today: u64,
     request_count_week: u64,
     request_count_month: u64,
+    today_cost: f64,
+    week_cost: f64,
+    month_cost: f64,
+}
+
+impl RouteTokenSummary {
+    fn new(route_id: String, summary: &TokenStatsSummary) -> Self {
+        Self {
+            route_id,
+            today_input_tokens: summary.today_input,
+            today_output_tokens: summary.today_output,
+            today_total_tokens: summary.today_total,
+            week_input_tokens: summary.week_input,
+            week_output_tokens: summary.week_output,
+            week_total_tokens: summary.week_total,
+            month_input_tokens: summary.month_input,
+            month_output_tokens: summary.month_output,
+            month_total_tokens: summary.month_total,
+            request_count_today: summary.request_count_today,
+            request_count_week: summary.request_count_week,
+            request_count_month: summary.request_count_month,
+            today_cost: summary.today_cost,
+            week_cost: summary.week_cost,
+            month_cost: summary.month_cost,
+        }
+    }
+}
+
+/// 模型 Token统计摘要
+#[derive(Debug, Serialize)]
+struct ModelTokenSummary {
+    model: String,
+    today_input_tokens: u64,
+    today_output_tokens: u64,
+    week_input_tokens: u64,
+    week_output_tokens: u64,
+    month_input_tokens: u64,
+    month_output_tokens: u64,
+    request_count_today: u64,
+    request_count_week: u64,
+    request_count_month: u64,
+    today_cost: f64,
+    week_cost: f64,
+    month_cost: f64,
+}
+
+impl ModelTokenSummary {
+    fn new(model: String, summary: &TokenStatsSummary) -> Self {
+        Self {
+            model,
+            today_input_tokens: summary.today_input,
+            today_output_tokens: summary.today_output,
+            week_input_tokens: summary.week_input,
+            week_output_tokens: summary.week_output,
+            month_input_tokens: summary.month_input,
+            month_output_tokens: summary.month_output,
+            request_count_today: summary.request_count_today,
+            request_count_week: summary.request_count_week,
+            request_count_month: summary.request_count_month,
+            today_cost: summary.today_cost,
+            week_cost: summary.week_cost,
+            month_cost: summary.month_cost,
+        }
+    }
 }
 
 /// API Key Token统计详情响应
@@ -1048,6 +1538,9 @@ struct ApiKeyTokenStatsResponse {
     summary: ApiKeyTokenSummary,
     hourly_breakdown: Vec<HourlyTokenStats>,
     quota: Option<TokenQuotaInfo>,
+    /// 费用预算使用情况（仅配置了预算的窗口）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    budget: Vec<BudgetUsageInfo>,
 }
 
 /// Route Token统计详情响应
@@ -1083,6 +1576,39 @@ struct TokenQuotaInfo {
     weekly_used_total: u64,
     weekly_used_input: u64,
     weekly_used_output: u64,
+    monthly_total_limit: Option<u64>,
+    monthly_input_limit: Option<u64>,
+    monthly_output_limit: Option<u64>,
+    monthly_used_total: u64,
+    monthly_used_input: u64,
+    monthly_used_output: u64,
+    /// 进行中请求预留的输入 / 输出 token
+    reserved_input: u64,
+    reserved_output: u64,
+    /// 持有预留的进行中请求数
+    inflight_reservations: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rolling: Vec<RollingQuotaInfo>,
+}
+
+/// 滚动 N 小时配额信息
+#[derive(Debug, Serialize)]
+struct RollingQuotaInfo {
+    hours: u32,
+    total_limit: Option<u64>,
+    input_limit: Option<u64>,
+    output_limit: Option<u64>,
+    used_total: u64,
+    used_input: u64,
+    used_output: u64,
+}
+
+/// 费用预算使用信息
+#[derive(Debug, Serialize)]
+struct BudgetUsageInfo {
+    window: &'static str,
+    limit: f64,
+    spent: f64,
 }
 
 /// Token统计汇总
@@ -1101,8 +1627,10 @@ async fn admin_token_stats_summary(
             return json_ok(&TokenStatsSummaryResponse {
                 generated_at_unix_ms: current_unix_ms(),
                 window: query.window.clone(),
+                currency: pricing_currency(&state),
                 api_keys: vec![],
                 routes: vec![],
+                models: vec![],
                 time_series: vec![],
             });
         }
@@ -1111,56 +1639,26 @@ async fn admin_token_stats_summary(
     let mut api_keys = Vec::new();
     let mut routes = Vec::new();
 
-    // 加载配置以获取API Key的实际key值
-    let config = state.config_path.as_ref()
-        .and_then(|path| crate::config::AppConfig::load_from_file(path).ok());
+    let config = Arc::clone(&state.runtime.load().config);
 
     // 获取所有API Key统计
     for (api_key_id, summary) in token_stats.get_all_api_key_stats() {
-        // 从配置中查找实际的API Key值
-        let api_key = config.as_ref()
-            .and_then(|c| c.api_keys.as_ref())
-            .and_then(|ak| ak.keys.iter().find(|k| k.id == api_key_id))
-            .map(|k| k.key.clone())
-            .unwrap_or_else(|| api_key_id.clone());
-
-        api_keys.push(ApiKeyTokenSummary {
-            api_key_id,
-            api_key,
-            today_input_tokens: summary.today_input,
-            today_output_tokens: summary.today_output,
-            today_total_tokens: summary.today_total,
-            week_input_tokens: summary.week_input,
-            week_output_tokens: summary.week_output,
-            week_total_tokens: summary.week_total,
-            month_input_tokens: summary.month_input,
-            month_output_tokens: summary.month_output,
-            month_total_tokens: summary.month_total,
-            request_count_today: summary.request_count_today,
-            request_count_week: summary.request_count_week,
-            request_count_month: summary.request_count_month,
-        });
+        let api_key = api_key_display(&config, &api_key_id);
+        api_keys.push(ApiKeyTokenSummary::new(api_key_id, api_key, &summary));
     }
 
     // 获取所有Route统计
     for (route_id, summary) in token_stats.get_all_route_stats() {
-        routes.push(RouteTokenSummary {
-            route_id,
-            today_input_tokens: summary.today_input,
-            today_output_tokens: summary.today_output,
-            today_total_tokens: summary.today_total,
-            week_input_tokens: summary.week_input,
-            week_output_tokens: summary.week_output,
-            week_total_tokens: summary.week_total,
-            month_input_tokens: summary.month_input,
-            month_output_tokens: summary.month_output,
-            month_total_tokens: summary.month_total,
-            request_count_today: summary.request_count_today,
-            request_count_week: summary.request_count_week,
-            request_count_month: summary.request_count_month,
-        });
+        routes.push(RouteTokenSummary::new(route_id, &summary));
     }
 
+    // 获取所有模型统计
+    let models = token_stats
+        .get_all_model_stats()
+        .into_iter()
+        .map(|(model, summary)| ModelTokenSummary::new(model, &summary))
+        .collect();
+
     // 生成时间序列数据
     let time_series = if let Some(storage) = token_stats.storage() {
         let window_enum = match query.window.as_str() {
@@ -1181,6 +1679,7 @@ async fn admin_token_stats_summary(
                         input_tokens: row.input_tokens,
                         output_tokens: row.output_tokens,
                         request_count: row.request_count,
+                        cost: row.cost,
                     }
                 })
                 .collect(),
@@ -1196,12 +1695,63 @@ async fn admin_token_stats_summary(
     json_ok(&TokenStatsSummaryResponse {
         generated_at_unix_ms: current_unix_ms(),
         window: query.window.clone(),
+        currency: pricing_currency(&state),
         api_keys,
         routes,
+        models,
         time_series,
     })
 }
 
+/// 列出所有模型的Token统计与费用
+async fn admin_list_model_token_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let models: Vec<ModelTokenSummary> = state
+        .observability
+        .token_stats
+        .as_ref()
+        .map(|stats| {
+            stats
+                .get_all_model_stats()
+                .into_iter()
+                .map(|(model, summary)| ModelTokenSummary::new(model, &summary))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    json_ok(&serde_json::json!({
+        "currency": pricing_currency(&state),
+        "models": models,
+    }))
+}
+
+/// 价格表配置的货币单位
+/// 展示用的 API Key 前缀（配置中不存在时使用 key_id）
+fn api_key_display(config: &AppConfig, api_key_id: &str) -> String {
+    config
+        .api_keys
+        .as_ref()
+        .and_then(|ak| ak.keys.iter().find(|k| k.id == api_key_id))
+        .map(|k| key_hash::display_key(&k.key_prefix))
+        .unwrap_or_else(|| api_key_id.to_string())
+}
+
+fn pricing_currency(state: &AppState) -> Option<String> {
+    state
+        .runtime
+        .load()
+        .config
+        .pricing
+        .as_ref()
+        .map(|pricing| pricing.currency.clone())
+}
+
 /// 列出所有API Key的Token统计
 async fn admin_list_api_key_token_stats(
     State(state): State<AppState>,
@@ -1217,37 +1767,14 @@ async fn admin_list_api_key_token_stats(
         None => return json_ok(&serde_json::json!({"api_keys": []})),
     };
 
-    // 加载配置以获取API Key的实际key值
-    let config = state.config_path.as_ref()
-        .and_then(|path| crate::config::AppConfig::load_from_file(path).ok());
+    let config = Arc::clone(&state.runtime.load().config);
 
     let api_keys: Vec<ApiKeyTokenSummary> = token_stats
         .get_all_api_key_stats()
         .into_iter()
         .map(|(api_key_id, summary)| {
-            // 从配置中查找实际的API Key值
-            let api_key = config.as_ref()
-                .and_then(|c| c.api_keys.as_ref())
-                .and_then(|ak| ak.keys.iter().find(|k| k.id == api_key_id))
-                .map(|k| k.key.clone())
-                .unwrap_or_else(|| api_key_id.clone());
-
-            ApiKeyTokenSummary {
-                api_key_id,
-                api_key,
-                today_input_tokens: summary.today_input,
-                today_output_tokens: summary.today_output,
-                today_total_tokens: summary.today_total,
-                week_input_tokens: summary.week_input,
-                week_output_tokens: summary.week_output,
-                week_total_tokens: summary.week_total,
-                month_input_tokens: summary.month_input,
-                month_output_tokens: summary.month_output,
-                month_total_tokens: summary.month_total,
-                request_count_today: summary.request_count_today,
-                request_count_week: summary.request_count_week,
-                request_count_month: summary.request_count_month,
-            }
+            let api_key = api_key_display(&config, &api_key_id);
+            ApiKeyTokenSummary::new(api_key_id, api_key, &summary)
         })
         .collect();
 
@@ -1293,41 +1820,60 @@ async fn admin_get_api_key_token_stats(
             weekly_used_total: check_result.weekly_used_total,
             weekly_used_input: check_result.weekly_used_input,
             weekly_used_output: check_result.weekly_used_output,
+            monthly_total_limit: check_result.monthly_limit_total,
+            monthly_input_limit: check_result.monthly_limit_input,
+            monthly_output_limit: check_result.monthly_limit_output,
+            monthly_used_total: check_result.monthly_used_total,
+            monthly_used_input: check_result.monthly_used_input,
+            monthly_used_output: check_result.monthly_used_output,
+            reserved_input: check_result.reserved_input_tokens,
+            reserved_output: check_result.reserved_output_tokens,
+            inflight_reservations: check_result.inflight_reservations,
+            rolling: check_result
+                .rolling
+                .iter()
+                .map(|window| RollingQuotaInfo {
+                    hours: window.hours,
+                    total_limit: window.limit_total,
+                    input_limit: window.limit_input,
+                    output_limit: window.limit_output,
+                    used_total: window.used_total,
+                    used_input: window.used_input,
+                    used_output: window.used_output,
+                })
+                .collect(),
         })
     });
 
-    // 加载配置以获取API Key的实际key值
-    let config = state.config_path.as_ref()
-        .and_then(|path| crate::config::AppConfig::load_from_file(path).ok());
-
-    // 从配置中查找实际的API Key值
-    let api_key = config.as_ref()
-        .and_then(|c| c.api_keys.as_ref())
-        .and_then(|ak| ak.keys.iter().find(|k| k.id == id))
-        .map(|k| k.key.clone())
-        .unwrap_or_else(|| id.clone());
+    let api_key = api_key_display(&state.runtime.load().config, &id);
+
+    // 获取费用预算使用情况
+    let budget = state
+        .observability
+        .token_quota_manager
+        .as_ref()
+        .and_then(|qm| {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            qm.budget().key_usage(&id, now)
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .map(|usage| BudgetUsageInfo {
+            window: usage.window.as_str(),
+            limit: usage.limit,
+            spent: usage.spent,
+        })
+        .collect();
 
     let response = ApiKeyTokenStatsResponse {
         api_key_id: id,
         window: query.window.clone(),
-        summary: ApiKeyTokenSummary {
-            api_key_id: String::new(),
-            api_key,
-            today_input_tokens: summary.today_input,
-            today_output_tokens: summary.today_output,
-            today_total_tokens: summary.today_total,
-            week_input_tokens: summary.week_input,
-            week_output_tokens: summary.week_output,
-            week_total_tokens: summary.week_total,
-            month_input_tokens: summary.month_input,
-            month_output_tokens: summary.month_output,
-            month_total_tokens: summary.month_total,
-            request_count_today: summary.request_count_today,
-            request_count_week: summary.request_count_week,
-            request_count_month: summary.request_count_month,
-        },
+        summary: ApiKeyTokenSummary::new(String::new(), api_key, &summary),
         hourly_breakdown: vec![], // TODO: 从SQLite查询详细数据
         quota,
+        budget,
     };
 
     json_ok(&response)
@@ -1351,21 +1897,7 @@ async fn admin_list_route_token_stats(
     let routes: Vec<RouteTokenSummary> = token_stats
         .get_all_route_stats()
         .into_iter()
-        .map(|(route_id, summary)| RouteTokenSummary {
-            route_id,
-            today_input_tokens: summary.today_input,
-            today_output_tokens: summary.today_output,
-            today_total_tokens: summary.today_total,
-            week_input_tokens: summary.week_input,
-            week_output_tokens: summary.week_output,
-            week_total_tokens: summary.week_total,
-            month_input_tokens: summary.month_input,
-            month_output_tokens: summary.month_output,
-            month_total_tokens: summary.month_total,
-            request_count_today: summary.request_count_today,
-            request_count_week: summary.request_count_week,
-            request_count_month: summary.request_count_month,
-        })
+        .map(|(route_id, summary)| RouteTokenSummary::new(route_id, &summary))
         .collect();
 
     json_ok(&serde_json::json!({ "routes": routes }))
@@ -1397,21 +1929,7 @@ async fn admin_get_route_token_stats(
     let response = RouteTokenStatsResponse {
         route_id: id,
         window: query.window.clone(),
-        summary: RouteTokenSummary {
-            route_id: String::new(),
-            today_input_tokens: summary.today_input,
-            today_output_tokens: summary.today_output,
-            today_total_tokens: summary.today_total,
-            week_input_tokens: summary.week_input,
-            week_output_tokens: summary.week_output,
-            week_total_tokens: summary.week_total,
-            month_input_tokens: summary.month_input,
-            month_output_tokens: summary.month_output,
-            month_total_tokens: summary.month_total,
-            request_count_today: summary.request_count_today,
-            request_count_week: summary.request_count_week,
-            request_count_month: summary.request_count_month,
-        },
+        summary: RouteTokenSummary::new(String::new(), &summary),
         hourly_breakdown: vec![], // TODO: 从SQLite查询详细数据
     };
 
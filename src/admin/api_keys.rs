@@ -0,0 +1,473 @@
+//! API Key 增删改接口
+//!
+//! 单个 Key 的变更直接作用于当前 `ApiKeyManager`，其他 Key 的限流器与封禁引擎不受影响，
+//! 并写入 `data/apikeys/<id>.yaml`。修改与删除须携带 `If-Match`（取自响应的 `ETag`），
+//! 避免多个管理员并发编辑时互相覆盖。
+//!
+//! 轮换 Key 时旧密钥移入 `previous_keys`，宽限期内与新密钥共用同一 Key ID。
+
+use super::{
+    api_key_file_path, is_admin_authorized, json_error, json_ok, json_response, remove_item_file,
+    write_item_file,
+};
+use crate::api_keys::{current_epoch_seconds, key_hash};
+use crate::config::{ApiKeyConfig, AppConfig, PreviousKeyConfig, ResolvedApiKey};
+use crate::server::{AppState, RuntimeState, build_runtime_state};
+use axum::body::Body;
+use axum::extract::{Path as AxumPath, State};
+use axum::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use http::header::{ETAG, IF_MATCH};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// 由服务端维护、不能通过接口写入的字段
+const IMMUTABLE_FIELDS: &[&str] = &["id", "key", "key_hash", "key_prefix", "previous_keys", "ban_status"];
+
+/// 轮换请求体
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RotateRequest {
+    /// 旧密钥宽限期（秒），缺省使用 `api_keys.rotation_grace_secs`，0 表示立即失效
+    grace_period_secs: Option<u64>,
+}
+
+/// 单个 Key 的变更
+enum KeyChange<'a> {
+    Upsert(&'a ApiKeyConfig),
+    Remove(&'a str),
+}
+
+/// 接口错误：状态码与错误信息
+type ApiError = (StatusCode, String);
+
+/// 获取单个 API Key（响应头附带 `ETag`）
+pub(super) async fn admin_get_api_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    match find_key(&runtime.config, &id) {
+        Some(key) => key_response(StatusCode::OK, key, None),
+        None => json_error(StatusCode::NOT_FOUND, "api_key_not_found"),
+    }
+}
+
+/// 新建 API Key：密钥由服务端生成，仅在本次响应中返回
+pub(super) async fn admin_create_api_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let mut fields = match parse_fields(&body) {
+        Ok(fields) => fields,
+        Err((status, message)) => return json_error(status, &message),
+    };
+    fields.insert("id".to_string(), Value::String(id.clone()));
+    let mut key: ApiKeyConfig = match serde_json::from_value(Value::Object(fields)) {
+        Ok(key) => key,
+        Err(err) => return json_error(StatusCode::BAD_REQUEST, &format!("invalid_json: {err}")),
+    };
+    let secret = key_hash::generate_key(&key_scope(&key));
+    key.key = secret.clone();
+    key.hash_plaintext_key();
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    if find_key(&runtime.config, &id).is_some() {
+        return json_error(StatusCode::CONFLICT, "api_key_exists");
+    }
+
+    let mut new_config = runtime.config.as_ref().clone();
+    new_config
+        .api_keys
+        .get_or_insert_with(Default::default)
+        .keys
+        .push(key.clone());
+    if let Err((status, message)) = apply_key_change(&state, &runtime, new_config, KeyChange::Upsert(&key)).await {
+        return json_error(status, &message);
+    }
+
+    info!(api_key_id = %id, "admin: api key created");
+    key_response(StatusCode::CREATED, &key, Some(secret))
+}
+
+/// 修改 API Key（JSON Merge Patch），须携带 `If-Match`
+pub(super) async fn admin_patch_api_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let patch = match parse_fields(&body) {
+        Ok(fields) => fields,
+        Err((status, message)) => return json_error(status, &message),
+    };
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    let Some(existing) = find_key(&runtime.config, &id) else {
+        return json_error(StatusCode::NOT_FOUND, "api_key_not_found");
+    };
+    if let Err((status, message)) = check_if_match(&headers, existing) {
+        return json_error(status, &message);
+    }
+
+    let mut merged = match serde_json::to_value(existing) {
+        Ok(value) => value,
+        Err(err) => {
+            return json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("serialization_error: {err}"),
+            );
+        }
+    };
+    merge_patch(&mut merged, &Value::Object(patch));
+    let key: ApiKeyConfig = match serde_json::from_value(merged) {
+        Ok(key) => key,
+        Err(err) => return json_error(StatusCode::BAD_REQUEST, &format!("invalid_json: {err}")),
+    };
+
+    let mut new_config = runtime.config.as_ref().clone();
+    if let Some(slot) = new_config
+        .api_keys
+        .as_mut()
+        .and_then(|api_keys| api_keys.keys.iter_mut().find(|k| k.id == id))
+    {
+        *slot = key.clone();
+    }
+    if let Err((status, message)) = apply_key_change(&state, &runtime, new_config, KeyChange::Upsert(&key)).await {
+        return json_error(status, &message);
+    }
+
+    info!(api_key_id = %id, "admin: api key updated");
+    key_response(StatusCode::OK, &key, None)
+}
+
+/// 删除 API Key，须携带 `If-Match`
+pub(super) async fn admin_delete_api_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    let Some(existing) = find_key(&runtime.config, &id) else {
+        return json_error(StatusCode::NOT_FOUND, "api_key_not_found");
+    };
+    if let Err((status, message)) = check_if_match(&headers, existing) {
+        return json_error(status, &message);
+    }
+
+    let mut new_config = runtime.config.as_ref().clone();
+    if let Some(api_keys) = new_config.api_keys.as_mut() {
+        api_keys.keys.retain(|k| k.id != id);
+    }
+    if let Err((status, message)) = apply_key_change(&state, &runtime, new_config, KeyChange::Remove(&id)).await {
+        return json_error(status, &message);
+    }
+
+    info!(api_key_id = %id, "admin: api key deleted");
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({ "status": "deleted", "id": id }).to_string(),
+    )
+}
+
+/// 轮换 API Key：生成新密钥，旧密钥在宽限期内继续有效
+pub(super) async fn admin_rotate_api_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let request: RotateRequest = if body.iter().all(u8::is_ascii_whitespace) {
+        RotateRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(err) => return json_error(StatusCode::BAD_REQUEST, &format!("invalid_json: {err}")),
+        }
+    };
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    let Some(existing) = find_key(&runtime.config, &id) else {
+        return json_error(StatusCode::NOT_FOUND, "api_key_not_found");
+    };
+
+    // Key 存在时 `api_keys` 必然存在
+    let grace_period_secs = request
+        .grace_period_secs
+        .or_else(|| runtime.config.api_keys.as_ref().map(|ak| ak.rotation_grace_secs))
+        .unwrap_or_default();
+    let now = current_epoch_seconds();
+    let mut key = existing.clone();
+    key.previous_keys.retain(|previous| previous.expires_at > now);
+    if grace_period_secs > 0 {
+        key.previous_keys.push(PreviousKeyConfig {
+            key_hash: std::mem::take(&mut key.key_hash),
+            key_prefix: std::mem::take(&mut key.key_prefix),
+            rotated_at: now,
+            expires_at: now.saturating_add(grace_period_secs),
+        });
+    }
+    let secret = key_hash::generate_key(&key_scope(&key));
+    key.key = secret.clone();
+    key.hash_plaintext_key();
+
+    let mut new_config = runtime.config.as_ref().clone();
+    if let Some(slot) = new_config
+        .api_keys
+        .as_mut()
+        .and_then(|api_keys| api_keys.keys.iter_mut().find(|k| k.id == id))
+    {
+        *slot = key.clone();
+    }
+    if let Err((status, message)) = apply_key_change(&state, &runtime, new_config, KeyChange::Upsert(&key)).await {
+        return json_error(status, &message);
+    }
+
+    info!(api_key_id = %id, grace_period_secs, "admin: api key rotated");
+    key_response(StatusCode::OK, &key, Some(secret))
+}
+
+/// 轮换后旧密钥的使用情况（用于找出尚未迁移的调用方）
+pub(super) async fn admin_get_previous_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    let usage = match &runtime.api_key_manager {
+        Some(manager) => manager.previous_key_usage(&id).await,
+        None => None,
+    };
+    match usage {
+        Some(previous_keys) => json_ok(&serde_json::json!({ "id": id, "previous_keys": previous_keys })),
+        None => json_error(StatusCode::NOT_FOUND, "api_key_not_found"),
+    }
+}
+
+/// 校验新配置、落盘并增量应用到运行时
+async fn apply_key_change(
+    state: &AppState,
+    runtime: &RuntimeState,
+    new_config: AppConfig,
+    change: KeyChange<'_>,
+) -> Result<(), ApiError> {
+    if let Err(err) = new_config.validate() {
+        return Err((StatusCode::BAD_REQUEST, format!("validation_error: {err}")));
+    }
+
+    // 先落盘，失败时运行时保持不变
+    if let Some(config_path) = &state.config_path {
+        let persisted = match change {
+            KeyChange::Upsert(key) => write_item_file(
+                &api_key_file_path(&new_config, config_path, &key.id),
+                "apikey",
+                &key.id,
+                key,
+            ),
+            KeyChange::Remove(key_id) => {
+                remove_item_file(&api_key_file_path(&new_config, config_path, key_id), "apikey", key_id)
+            }
+        };
+        if let Err(err) = persisted {
+            error!(error = %err, "admin: failed to persist api key");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, err));
+        }
+    }
+
+    let new_config = Arc::new(new_config);
+    let new_runtime = match &runtime.api_key_manager {
+        Some(manager) => {
+            match change {
+                KeyChange::Upsert(key) => manager.upsert_key(ResolvedApiKey::from_config(key)).await,
+                KeyChange::Remove(key_id) => {
+                    manager.remove_key(key_id).await;
+                }
+            }
+            runtime.with_config(new_config.clone())
+        }
+        // 尚无 Key 管理器（首次添加 Key）时整体构建
+        None => {
+            let token_quota_checker = runtime._token_quota_checker.clone();
+            build_runtime_state(new_config.clone(), Some(runtime), token_quota_checker)
+                .await
+                .map_err(|err| {
+                    error!(error = %err, "admin: failed to build runtime state");
+                    (StatusCode::INTERNAL_SERVER_ERROR, format!("runtime_build_error: {err}"))
+                })?
+        }
+    };
+
+    if let Some(quota_manager) = &state.observability.token_quota_manager {
+        let resolved_keys = new_config.resolved_api_keys();
+        quota_manager.set_token_rate_limits(&resolved_keys, new_config.routes.as_deref().unwrap_or_default());
+        quota_manager
+            .budget()
+            .set_budgets(&resolved_keys, new_config.global_budget.as_ref());
+        match change {
+            KeyChange::Upsert(key) => quota_manager.set_quota(&key.id, key.token_quota.clone()),
+            KeyChange::Remove(key_id) => quota_manager.set_quota(key_id, None),
+        }
+    }
+
+    state.runtime.store(Arc::new(new_runtime));
+
+    if let Err(err) = state.config_storage.sync_config(&new_config).await {
+        error!(error = %err, "admin: failed to sync config to database");
+    }
+    Ok(())
+}
+
+fn find_key<'a>(config: &'a AppConfig, id: &str) -> Option<&'a ApiKeyConfig> {
+    config.api_keys.as_ref()?.keys.iter().find(|k| k.id == id)
+}
+
+/// 解析请求体为 JSON 对象（空请求体视为 `{}`），拒绝写入服务端维护的字段
+fn parse_fields(body: &[u8]) -> Result<Map<String, Value>, ApiError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Map::new());
+    }
+    let fields = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => return Err((StatusCode::BAD_REQUEST, "invalid_json: expected object".to_string())),
+        Err(err) => return Err((StatusCode::BAD_REQUEST, format!("invalid_json: {err}"))),
+    };
+    if let Some(field) = IMMUTABLE_FIELDS.iter().find(|field| fields.contains_key(**field)) {
+        return Err((StatusCode::BAD_REQUEST, format!("immutable_field: {field}")));
+    }
+    Ok(fields)
+}
+
+/// 生成密钥的作用域段：只允许访问单个路由时使用路由 ID，否则为 `gw`
+fn key_scope(key: &ApiKeyConfig) -> String {
+    let route_id = match (&key.route_ids, &key.route_id) {
+        (Some(route_ids), _) if route_ids.len() == 1 => Some(&route_ids[0]),
+        (None, Some(route_id)) => Some(route_id),
+        _ => None,
+    };
+    route_id
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()))
+        .cloned()
+        .unwrap_or_else(|| "gw".to_string())
+}
+
+/// Key 配置的 ETag（配置内容的 SHA-256 前 16 位）
+fn api_key_etag(key: &ApiKeyConfig) -> String {
+    let digest = Sha256::digest(serde_json::to_vec(key).unwrap_or_default());
+    format!("\"{}\"", &format!("{digest:x}")[..16])
+}
+
+/// 校验 `If-Match`：缺失返回 428，不匹配返回 412
+fn check_if_match(headers: &HeaderMap, key: &ApiKeyConfig) -> Result<(), ApiError> {
+    let Some(if_match) = headers.get(IF_MATCH).and_then(|value| value.to_str().ok()) else {
+        return Err((StatusCode::PRECONDITION_REQUIRED, "precondition_required".to_string()));
+    };
+    let etag = api_key_etag(key);
+    let matched = if_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == etag);
+    if matched {
+        Ok(())
+    } else {
+        Err((StatusCode::PRECONDITION_FAILED, "precondition_failed".to_string()))
+    }
+}
+
+/// Key 响应：新建时附带仅此一次返回的明文密钥
+fn key_response(status: StatusCode, key: &ApiKeyConfig, secret: Option<String>) -> Response<Body> {
+    let body = match secret {
+        Some(secret) => serde_json::json!({ "key": secret, "api_key": key }),
+        None => serde_json::json!({ "api_key": key }),
+    };
+    let mut response = json_response(status, body.to_string());
+    if let Ok(etag) = HeaderValue::from_str(&api_key_etag(key)) {
+        response.headers_mut().insert(ETAG, etag);
+    }
+    response
+}
+
+/// JSON Merge Patch（RFC 7396）：`null` 删除字段，对象递归合并，其余直接替换
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+    for (field, value) in patch {
+        if value.is_null() {
+            target.remove(field);
+        } else {
+            merge_patch(target.entry(field.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_patch_replaces_and_removes_fields() {
+        let mut target = serde_json::json!({
+            "id": "default",
+            "remark": "old",
+            "rate_limit": { "per_minute": 60 },
+            "expires_at": 1800000000
+        });
+        merge_patch(
+            &mut target,
+            &serde_json::json!({ "remark": "new", "rate_limit": { "per_minute": 120 }, "expires_at": null }),
+        );
+        assert_eq!(
+            target,
+            serde_json::json!({ "id": "default", "remark": "new", "rate_limit": { "per_minute": 120 } })
+        );
+    }
+
+    #[test]
+    fn parse_fields_rejects_immutable_fields() {
+        assert!(parse_fields(b"").unwrap().is_empty());
+        assert!(parse_fields(br#"{"remark":"ok"}"#).is_ok());
+        let (status, message) = parse_fields(br#"{"key_hash":"sha256$a$b"}"#).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "immutable_field: key_hash");
+    }
+}
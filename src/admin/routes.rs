@@ -0,0 +1,284 @@
+//! 路由增删改接口
+//!
+//! 单个路由的变更只重建该路由的上游客户端与路由级限流器，其他路由的连接池保持不变，
+//! 并写入 `data/routes/<id>.yaml`。写操作支持 `?dry_run=true`，只校验不生效。
+
+use super::{is_admin_authorized, json_error, json_ok, json_response, remove_item_file, route_file_path, write_item_file};
+use crate::config::{AppConfig, FieldError, RouteConfig};
+use crate::server::{AppState, RuntimeState};
+use axum::body::Body;
+use axum::extract::{Path as AxumPath, Query, State};
+use axum::http::{HeaderMap, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// 写操作查询参数
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct RouteWriteQuery {
+    /// 只校验，不落盘也不生效
+    dry_run: bool,
+}
+
+/// 获取单个路由
+pub(super) async fn admin_get_route(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let runtime = state.runtime.load();
+    match find_route(&runtime.config, &id) {
+        Some(route) => json_ok(&serde_json::json!({ "route": route })),
+        None => json_error(StatusCode::NOT_FOUND, "route_not_found"),
+    }
+}
+
+/// 新建路由
+pub(super) async fn admin_create_route(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<RouteWriteQuery>,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let route = match parse_route(&id, &body) {
+        Ok(route) => route,
+        Err(response) => return *response,
+    };
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    if find_route(&runtime.config, &id).is_some() {
+        return json_error(StatusCode::CONFLICT, "route_exists");
+    }
+
+    let mut new_config = runtime.config.as_ref().clone();
+    new_config.routes.get_or_insert_with(Vec::new).push(route.clone());
+    apply_route_change(&state, &runtime, new_config, &id, query.dry_run, StatusCode::CREATED).await
+}
+
+/// 替换已有路由
+pub(super) async fn admin_replace_route(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<RouteWriteQuery>,
+    body: axum::body::Bytes,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let route = match parse_route(&id, &body) {
+        Ok(route) => route,
+        Err(response) => return *response,
+    };
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    if find_route(&runtime.config, &id).is_none() {
+        return json_error(StatusCode::NOT_FOUND, "route_not_found");
+    }
+
+    let mut new_config = runtime.config.as_ref().clone();
+    if let Some(slot) = new_config
+        .routes
+        .as_mut()
+        .and_then(|routes| routes.iter_mut().find(|r| r.id == id))
+    {
+        *slot = route;
+    }
+    apply_route_change(&state, &runtime, new_config, &id, query.dry_run, StatusCode::OK).await
+}
+
+/// 删除路由
+pub(super) async fn admin_delete_route(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<RouteWriteQuery>,
+) -> Response<Body> {
+    if !is_admin_authorized(&state, &headers) {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let _config_guard = state.config_lock.lock().await;
+    let runtime = state.runtime.load_full();
+    if find_route(&runtime.config, &id).is_none() {
+        return json_error(StatusCode::NOT_FOUND, "route_not_found");
+    }
+
+    let mut new_config = runtime.config.as_ref().clone();
+    if let Some(routes) = new_config.routes.as_mut() {
+        routes.retain(|r| r.id != id);
+    }
+    apply_route_change(&state, &runtime, new_config, &id, query.dry_run, StatusCode::OK).await
+}
+
+/// 校验新配置并只重建目标路由；非 dry-run 时落盘并生效
+async fn apply_route_change(
+    state: &AppState,
+    runtime: &RuntimeState,
+    new_config: AppConfig,
+    route_id: &str,
+    dry_run: bool,
+    success_status: StatusCode,
+) -> Response<Body> {
+    let route = find_route(&new_config, route_id).cloned();
+    if let Some(route) = &route {
+        let errors = route_field_errors(&new_config, route);
+        if !errors.is_empty() {
+            return validation_errors(&errors);
+        }
+    }
+    if let Err(err) = new_config.validate() {
+        return json_error(StatusCode::BAD_REQUEST, &format!("validation_error: {err}"));
+    }
+
+    let new_config = Arc::new(new_config);
+    let new_runtime = match runtime.with_route(new_config.clone(), route_id) {
+        Ok(new_runtime) => new_runtime,
+        Err(err) => return validation_errors(&[FieldError::new("upstream", err)]),
+    };
+
+    let body = match &route {
+        Some(route) => serde_json::json!({ "dry_run": dry_run, "route": route }),
+        None => serde_json::json!({ "dry_run": dry_run, "status": "deleted", "id": route_id }),
+    };
+    if dry_run {
+        return json_response(StatusCode::OK, body.to_string());
+    }
+
+    // 先落盘，失败时运行时保持不变
+    if let Some(config_path) = &state.config_path {
+        let path = route_file_path(&new_config, config_path, route_id);
+        let persisted = match &route {
+            Some(route) => write_item_file(&path, "route", route_id, route),
+            None => remove_item_file(&path, "route", route_id),
+        };
+        if let Err(err) = persisted {
+            error!(error = %err, "admin: failed to persist route");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, &err);
+        }
+    }
+
+    if let Some(quota_manager) = &state.observability.token_quota_manager {
+        quota_manager.set_token_rate_limits(
+            &new_config.resolved_api_keys(),
+            new_config.routes.as_deref().unwrap_or_default(),
+        );
+    }
+
+    state.runtime.store(Arc::new(new_runtime));
+
+    if let Err(err) = state.config_storage.sync_config(&new_config).await {
+        error!(error = %err, "admin: failed to sync config to database");
+    }
+
+    info!(route_id = %route_id, deleted = route.is_none(), "admin: route updated");
+    json_response(success_status, body.to_string())
+}
+
+fn find_route<'a>(config: &'a AppConfig, id: &str) -> Option<&'a RouteConfig> {
+    config.routes.as_deref()?.iter().find(|r| r.id == id)
+}
+
+/// 路由自身的字段错误，以及与其他路由的前缀冲突
+fn route_field_errors(config: &AppConfig, route: &RouteConfig) -> Vec<FieldError> {
+    let mut errors = route.field_errors();
+    if let Some(other) = config
+        .routes
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|other| other.id != route.id && other.prefix == route.prefix)
+    {
+        errors.push(FieldError::new(
+            "prefix",
+            format!("duplicates route `{}`", other.id),
+        ));
+    }
+    errors
+}
+
+/// 解析请求体为路由配置，路径中的 ID 即路由 ID
+fn parse_route(id: &str, body: &[u8]) -> Result<RouteConfig, Box<Response<Body>>> {
+    let mut fields = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => {
+            return Err(Box::new(json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_json: expected object",
+            )));
+        }
+        Err(err) => {
+            return Err(Box::new(json_error(
+                StatusCode::BAD_REQUEST,
+                &format!("invalid_json: {err}"),
+            )));
+        }
+    };
+    if fields.get("id").is_some_and(|body_id| body_id.as_str() != Some(id)) {
+        return Err(Box::new(validation_errors(&[FieldError::new(
+            "id",
+            "must match the route id in the path",
+        )])));
+    }
+    fields.insert("id".to_string(), Value::String(id.to_string()));
+    serde_json::from_value(Value::Object(fields))
+        .map_err(|err| Box::new(json_error(StatusCode::BAD_REQUEST, &format!("invalid_json: {err}"))))
+}
+
+/// 字段级校验失败响应
+fn validation_errors(errors: &[FieldError]) -> Response<Body> {
+    json_response(
+        StatusCode::BAD_REQUEST,
+        serde_json::json!({ "error": "validation_error", "errors": errors }).to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_route_uses_path_id() {
+        let route = parse_route("openai", br#"{"prefix":"/openai","upstream":{"base_url":"https://api.openai.com"}}"#)
+            .expect("route should parse");
+        assert_eq!(route.id, "openai");
+        assert!(route.field_errors().is_empty());
+
+        let mismatch = parse_route("openai", br#"{"id":"other","prefix":"/openai","upstream":{"base_url":"x"}}"#)
+            .expect_err("id mismatch should be rejected");
+        assert_eq!(mismatch.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn route_field_errors_reports_every_field() {
+        let route = parse_route(
+            "openai",
+            br#"{"prefix":"openai/","upstream":{"base_url":" ","connect_timeout_ms":0},"rate_limit":{"per_ip_per_minute":0}}"#,
+        )
+        .expect("route should parse");
+        let fields: Vec<_> = route.field_errors().into_iter().map(|err| err.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "prefix",
+                "upstream.base_url",
+                "upstream.connect_timeout_ms",
+                "rate_limit.per_ip_per_minute"
+            ]
+        );
+    }
+}
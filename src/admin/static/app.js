@@ -276,6 +276,10 @@ const TABS = [
 // ===== API Key 管理数据 =====
 let apiKeysData = [];
 let banLogsData = [];
+let authLockoutsData = { enabled: false, locked: [] };
+let banAlertsData = [];
+let shadowStatsData = [];
+let ipBansData = [];
 let apiKeyFilter = {
   route: '',
   status: 'all', // all, enabled, disabled, banned
@@ -312,11 +316,20 @@ function loadApiKeysFromConfig() {
 
     return {
       id: keyConfig.id,
-      key: keyConfig.key,
+      // 已哈希存储的 Key 只展示前缀
+      key: keyConfig.key || `${keyConfig.key_prefix || ''}…`,
+      key_hash: keyConfig.key_hash || null,
+      key_prefix: keyConfig.key_prefix || null,
+      // 轮换后宽限期内的旧密钥
+      previous_keys: keyConfig.previous_keys || [],
       route_ids: routeIds,
       route_names: routeNames,
       enabled: keyConfig.enabled,
       remark: keyConfig.remark || '',
+      // 生命周期配置
+      not_before: keyConfig.not_before || null,
+      expires_at: keyConfig.expires_at || null,
+      idle_expiry_days: keyConfig.idle_expiry_days || null,
       // 限流配置
       per_minute: keyConfig.rate_limit?.per_minute || 120,
       // 并发配置（新结构：max_inflight）
@@ -358,11 +371,20 @@ function convertApiKeyToConfig(apiKey) {
                         tokenQuota.daily_output_limit || tokenQuota.weekly_total_limit ||
                         tokenQuota.weekly_input_limit || tokenQuota.weekly_output_limit;
 
+  // 已哈希存储的 Key 回传哈希与前缀，新 Key 以明文提交由服务端转换
+  const keyFields = apiKey.key_hash
+    ? { key_hash: apiKey.key_hash, key_prefix: apiKey.key_prefix }
+    : { key: apiKey.key };
+
   return {
     id: apiKey.id,
     route_ids: routeIds,
-    key: apiKey.key,
+    ...keyFields,
+    previous_keys: apiKey.previous_keys || [],
     enabled: apiKey.enabled,
+    not_before: apiKey.not_before || null,
+    expires_at: apiKey.expires_at || null,
+    idle_expiry_days: apiKey.idle_expiry_days || null,
     remark: apiKey.remark || '',
     rate_limit: apiKey.per_minute ? { per_minute: apiKey.per_minute } : null,
     concurrency: apiKey.max_inflight ? { max_inflight: apiKey.max_inflight } : null,
@@ -459,6 +481,55 @@ async function fetchBanLogsFromServer(apiKeyId) {
   }
 }
 
+// 从服务器加载仅告警规则的触发记录
+async function fetchBanAlertsFromServer() {
+  const token = getToken();
+  if (!token) return null;
+
+  const prefix = window.CONFIG?.adminPrefix || '/admin';
+  try {
+    const response = await fetch(`${prefix}/api/ban-alerts`, {
+      headers: { 'Authorization': `Bearer ${token}` }
+    });
+    if (!response.ok) {
+      if (response.status === 401) {
+        logout();
+        return null;
+      }
+      throw new Error(`HTTP ${response.status}`);
+    }
+    const data = await response.json();
+    return data.alerts || [];
+  } catch (err) {
+    console.error('Failed to fetch ban alerts:', err);
+    return null;
+  }
+}
+
+// 从服务器加载鉴权失败锁定的 IP
+async function fetchAuthLockoutsFromServer() {
+  const token = getToken();
+  if (!token) return null;
+
+  const prefix = window.CONFIG?.adminPrefix || '/admin';
+  try {
+    const response = await fetch(`${prefix}/api/auth-lockouts`, {
+      headers: { 'Authorization': `Bearer ${token}` }
+    });
+    if (!response.ok) {
+      if (response.status === 401) {
+        logout();
+        return null;
+      }
+      throw new Error(`HTTP ${response.status}`);
+    }
+    return await response.json();
+  } catch (err) {
+    console.error('Failed to fetch auth lockouts:', err);
+    return null;
+  }
+}
+
 // 合并 API Key 数据（配置数据 + 服务器运行时数据）
 async function loadApiKeys() {
   // 先从配置加载基础数据
@@ -490,7 +561,8 @@ async function loadApiKeys() {
         banned_until: serverKey.ban_expires_at ? parseInt(serverKey.ban_expires_at) : null,
         triggered_rule_id: serverKey.triggered_rule_id || null,
         reason: serverKey.ban_reason || null,
-        ban_count: parseInt(serverKey.ban_count) || 0
+        ban_count: parseInt(serverKey.ban_count) || 0,
+        permanent: serverKey.ban_permanent || false
       };
 
       // 调试：输出转换后的数据
@@ -508,6 +580,9 @@ async function loadApiKeys() {
         max_inflight: serverKey.max_inflight || configKey?.max_inflight || null,
         // 保留 Token 配额配置
         token_quota: serverKey.token_quota || configKey?.token_quota || null,
+        // 保留 Key 哈希（保存配置时回传）
+        key_hash: configKey?.key_hash || null,
+        previous_keys: configKey?.previous_keys || [],
         created_at: serverKey.created_at || configKey?.created_at || new Date().toISOString(),
         updated_at: serverKey.updated_at || configKey?.updated_at || new Date().toISOString()
       };
@@ -520,6 +595,56 @@ async function loadApiKeys() {
   return apiKeysData;
 }
 
+// 从服务器加载封禁中的 IP 与网段
+async function fetchIpBansFromServer() {
+  const token = getToken();
+  if (!token) return null;
+
+  const prefix = window.CONFIG?.adminPrefix || '/admin';
+  try {
+    const response = await fetch(`${prefix}/api/ip-bans`, {
+      headers: { 'Authorization': `Bearer ${token}` }
+    });
+    if (!response.ok) {
+      if (response.status === 401) {
+        logout();
+        return null;
+      }
+      throw new Error(`HTTP ${response.status}`);
+    }
+    const data = await response.json();
+    return data.bans || [];
+  } catch (err) {
+    console.error('Failed to fetch ip bans:', err);
+    return null;
+  }
+}
+
+// 从服务器加载影子模式规则的模拟封禁统计
+async function fetchShadowStatsFromServer() {
+  const token = getToken();
+  if (!token) return null;
+
+  const prefix = window.CONFIG?.adminPrefix || '/admin';
+  try {
+    const response = await fetch(`${prefix}/api/ban-rules/shadow-stats`, {
+      headers: { 'Authorization': `Bearer ${token}` }
+    });
+    if (!response.ok) {
+      if (response.status === 401) {
+        logout();
+        return null;
+      }
+      throw new Error(`HTTP ${response.status}`);
+    }
+    const data = await response.json();
+    return data.rules || [];
+  } catch (err) {
+    console.error('Failed to fetch shadow ban stats:', err);
+    return null;
+  }
+}
+
 // 加载封禁日志
 async function loadBanLogs() {
   console.log('Loading ban logs...');
@@ -531,6 +656,22 @@ async function loadBanLogs() {
   } else {
     console.warn('Failed to load ban logs, keeping existing data');
   }
+  const lockouts = await fetchAuthLockoutsFromServer();
+  if (lockouts !== null) {
+    authLockoutsData = { enabled: !!lockouts.enabled, locked: lockouts.locked || [] };
+  }
+  const alerts = await fetchBanAlertsFromServer();
+  if (alerts !== null) {
+    banAlertsData = alerts;
+  }
+  const shadowStats = await fetchShadowStatsFromServer();
+  if (shadowStats !== null) {
+    shadowStatsData = shadowStats;
+  }
+  const ipBans = await fetchIpBansFromServer();
+  if (ipBans !== null) {
+    ipBansData = ipBans;
+  }
   return banLogsData;
 }
 
@@ -686,8 +827,19 @@ function renderApiKeys() {
       }
     }
     const status = isBanned ? 'banned' : (key.enabled ? 'enabled' : 'disabled');
-    const statusClass = `status-${status}`;
-    const statusText = isBanned ? '封禁中' : (key.enabled ? '启用' : '禁用');
+    let statusClass = `status-${status}`;
+    let statusText = isBanned ? '封禁中' : (key.enabled ? '启用' : '禁用');
+    // 生命周期阶段（未生效 / 即将过期 / 已过期）
+    const lifecycleText = {
+      pending: '未生效',
+      expiring_soon: '即将过期',
+      expired: '已过期',
+      idle_expired: '闲置过期'
+    }[key.lifecycle_state];
+    if (!isBanned && key.enabled && lifecycleText) {
+      statusText = lifecycleText;
+      statusClass = key.lifecycle_state === 'expiring_soon' ? 'status-enabled' : 'status-disabled';
+    }
     const shortKey = key.key;
 
     // 路由标签（支持多路由显示）
@@ -720,7 +872,9 @@ function renderApiKeys() {
       banned_until: key.ban_status?.banned_until,
       type: typeof key.ban_status?.banned_until
     });
-    if (isCurrentlyBanned && bannedUntilTs) {
+    if (isCurrentlyBanned && key.ban_status?.permanent) {
+      banStatusHtml = '<span class="ban-status-badge banned">永久封禁</span>';
+    } else if (isCurrentlyBanned && bannedUntilTs) {
       const bannedUntil = parseInt(bannedUntilTs);
       console.log('Parsed bannedUntil:', bannedUntil, 'isNaN:', isNaN(bannedUntil));
       if (!isNaN(bannedUntil) && bannedUntil > 0) {
@@ -1000,6 +1154,123 @@ async function unbanApiKey(id) {
   );
 }
 
+// 解除 IP 的鉴权失败锁定
+async function unlockAuthLockout(ip) {
+  confirmDelete(
+    '解除 IP 锁定',
+    `确定要解除 ${ip} 的鉴权失败锁定吗？`,
+    async () => {
+      const token = getToken();
+      if (!token) {
+        logout();
+        return;
+      }
+
+      const prefix = window.CONFIG?.adminPrefix || '/admin';
+      try {
+        const response = await fetch(`${prefix}/api/auth-lockouts/${encodeURIComponent(ip)}`, {
+          method: 'DELETE',
+          headers: { 'Authorization': `Bearer ${token}` }
+        });
+        if (!response.ok) {
+          if (response.status === 401) {
+            logout();
+            return;
+          }
+          const err = await response.json().catch(() => ({}));
+          throw new Error(err.error || `HTTP ${response.status}`);
+        }
+
+        authLockoutsData.locked = authLockoutsData.locked.filter(item => item.ip !== ip);
+        Toast.show('IP 锁定已解除', 'success');
+        renderBanLogs();
+      } catch (err) {
+        console.error('Failed to unlock auth lockout:', err);
+        Toast.show(`解除失败: ${err.message}`, 'error');
+      }
+    }
+  );
+}
+
+// 手动封禁 IP 或网段
+async function banIpFromForm() {
+  const target = document.getElementById('ipban-target').value.trim();
+  const duration = parseInt(document.getElementById('ipban-duration').value) || 0;
+  const reason = document.getElementById('ipban-reason').value.trim() || '手动封禁';
+  if (!target) {
+    Toast.show('请输入 IP 或网段', 'error');
+    return;
+  }
+
+  const token = getToken();
+  if (!token) {
+    logout();
+    return;
+  }
+
+  const prefix = window.CONFIG?.adminPrefix || '/admin';
+  try {
+    const response = await fetch(`${prefix}/api/ip-bans`, {
+      method: 'POST',
+      headers: { 'Authorization': `Bearer ${token}`, 'Content-Type': 'application/json' },
+      body: JSON.stringify({ target, reason, duration_secs: duration, permanent: duration === 0 })
+    });
+    if (!response.ok) {
+      if (response.status === 401) {
+        logout();
+        return;
+      }
+      const err = await response.json().catch(() => ({}));
+      throw new Error(err.error || `HTTP ${response.status}`);
+    }
+
+    Toast.show('已封禁', 'success');
+    await loadBanLogs();
+    renderBanLogs();
+  } catch (err) {
+    console.error('Failed to ban ip:', err);
+    Toast.show(`封禁失败: ${err.message}`, 'error');
+  }
+}
+
+// 解除 IP 或网段的封禁
+async function unbanIp(target) {
+  confirmDelete(
+    '解除 IP 封禁',
+    `确定要解除 ${target} 的封禁吗？`,
+    async () => {
+      const token = getToken();
+      if (!token) {
+        logout();
+        return;
+      }
+
+      const prefix = window.CONFIG?.adminPrefix || '/admin';
+      try {
+        const response = await fetch(`${prefix}/api/ip-bans/${encodeURIComponent(target)}`, {
+          method: 'DELETE',
+          headers: { 'Authorization': `Bearer ${token}` }
+        });
+        if (!response.ok) {
+          if (response.status === 401) {
+            logout();
+            return;
+          }
+          const err = await response.json().catch(() => ({}));
+          throw new Error(err.error || `HTTP ${response.status}`);
+        }
+
+        ipBansData = ipBansData.filter(item => item.target !== target);
+        Toast.show('IP 封禁已解除', 'success');
+        renderBanLogs();
+      } catch (err) {
+        console.error('Failed to unban ip:', err);
+        Toast.show(`解除失败: ${err.message}`, 'error');
+      }
+    }
+  );
+}
+
 // 删除 API Key
 function deleteApiKeyById(id) {
   const key = apiKeysData.find(k => k.id === id);
@@ -1330,6 +1601,9 @@ function saveApiKeyV2(id) {
     if (existingKey) {
       keyData.ban_status = existingKey.ban_status || keyData.ban_status;
       keyData.created_at = existingKey.created_at;
+      keyData.key_hash = existingKey.key_hash;
+      keyData.key_prefix = existingKey.key_prefix;
+      keyData.previous_keys = existingKey.previous_keys;
     }
   }
 
@@ -1429,6 +1703,9 @@ function saveApiKey(id) {
       keyData.enabled = apiKeysData[index].enabled;
       keyData.banned = apiKeysData[index].banned;
       keyData.created_at = apiKeysData[index].created_at;
+      keyData.key_hash = apiKeysData[index].key_hash;
+      keyData.key_prefix = apiKeysData[index].key_prefix;
+      keyData.previous_keys = apiKeysData[index].previous_keys;
       apiKeysData[index] = keyData;
     }
     Toast.show('API Key 已更新', 'success');
@@ -1480,6 +1757,35 @@ function deleteApiKeyById(id) {
 // -- 全局封禁规则管理页面 --
 let banRulesData = []; // 全局封禁规则数据
 
+// 封禁条件描述（组合条件递归展开）
+function describeBanCondition(cond) {
+  if (cond.type === 'error_rate') {
+    return `错误率 > ${(cond.threshold * 100).toFixed(0)}% (${cond.window_secs}秒窗口, 最少${cond.min_requests}请求)`;
+  } else if (cond.type === 'request_count') {
+    return `请求数 > ${cond.max_requests} (${cond.window_secs}秒窗口)`;
+  } else if (cond.type === 'consecutive_errors') {
+    return `连续错误 > ${cond.count} 次`;
+  } else if (cond.type === 'status_code') {
+    return `状态码 ${(cond.statuses || []).join('/')} ≥ ${cond.max_count} 次 (${cond.window_secs}秒窗口)`;
+  } else if (cond.type === 'latency') {
+    return `P95 延迟 ≥ ${cond.p95_threshold_ms}ms (${cond.window_secs}秒窗口, 最少${cond.min_requests}请求)`;
+  } else if (cond.type === 'token_usage') {
+    return `Token 用量 ≥ ${cond.max_tokens} (${cond.window_secs}秒窗口)`;
+  } else if (cond.type === 'spike') {
+    const metricText = cond.metric === 'tokens' ? 'Token 用量' : '请求数';
+    return `${metricText}突增 ≥ 基线 ${cond.multiplier} 倍 (${cond.window_secs}秒窗口, 基线${cond.baseline_secs}秒)`;
+  } else if (cond.type === 'key_sharing') {
+    const limits = [];
+    if (cond.max_ips) limits.push(`IP > ${cond.max_ips}`);
+    if (cond.max_subnets) limits.push(`网段 > ${cond.max_subnets}`);
+    return `疑似共享: ${limits.join(' 或 ')} (${cond.window_secs}秒窗口)`;
+  } else if (cond.type === 'all' || cond.type === 'any') {
+    const joiner = cond.type === 'all' ? ' 且 ' : ' 或 ';
+    return (cond.conditions || []).map(c => `(${describeBanCondition(c)})`).join(joiner);
+  }
+  return '';
+}
+
 function renderBanRules() {
   const panel = document.getElementById('tab-banrules');
   if (!panel) return;
@@ -1493,16 +1799,24 @@ function renderBanRules() {
 
   const rulesHtml = banRulesData.map((rule, idx) => {
     const cond = rule.condition || {};
-    let conditionText = '';
-    if (cond.type === 'error_rate') {
-      conditionText = `错误率 > ${(cond.threshold * 100).toFixed(0)}% (${cond.window_secs}秒窗口, 最少${cond.min_requests}请求)`;
-    } else if (cond.type === 'request_count') {
-      conditionText = `请求数 > ${cond.max_requests} (${cond.window_secs}秒窗口)`;
-    } else if (cond.type === 'consecutive_errors') {
-      conditionText = `连续错误 > ${cond.count} 次`;
+    let conditionText = describeBanCondition(cond);
+    if (rule.subject === 'ip' || rule.subject === 'subnet') {
+      conditionText = `按${rule.subject === 'ip' ? ' IP' : '网段'}统计 · ${conditionText}`;
+    }
+    if (rule.action === 'alert') {
+      conditionText += ' · 仅告警';
+    }
+    if (rule.mode === 'shadow') {
+      const shadowCount = shadowStatsData.find(s => s.rule_id === rule.id)?.count || 0;
+      conditionText += ` · 影子模式（模拟封禁 ${shadowCount} 次）`;
     }
 
-    const durationText = formatDuration(rule.ban_duration_secs);
+    let durationText = formatDuration(rule.ban_duration_secs);
+    const escalation = rule.escalation;
+    if (escalation) {
+      durationText += ` · 再犯 ×${escalation.multiplier ?? 2}`;
+      if (escalation.permanent_after) durationText += ` · 第 ${escalation.permanent_after} 次永久`;
+    }
     const triggerThreshold = rule.trigger_count_threshold || 1;
     const triggerWindow = formatDuration(rule.trigger_window_secs || 3600);
     const triggerText = triggerThreshold > 1
@@ -1656,6 +1970,38 @@ function openBanRuleModal(idx) {
               <option value="error_rate" ${condType === 'error_rate' ? 'selected' : ''}>错误率阈值</option>
               <option value="request_count" ${condType === 'request_count' ? 'selected' : ''}>请求数阈值</option>
               <option value="consecutive_errors" ${condType === 'consecutive_errors' ? 'selected' : ''}>连续错误数</option>
+              <option value="status_code" ${condType === 'status_code' ? 'selected' : ''}>状态码次数</option>
+              <option value="latency" ${condType === 'latency' ? 'selected' : ''}>P95 延迟</option>
+              <option value="token_usage" ${condType === 'token_usage' ? 'selected' : ''}>Token 用量</option>
+              <option value="spike" ${condType === 'spike' ? 'selected' : ''}>用量突增</option>
+              <option value="key_sharing" ${condType === 'key_sharing' ? 'selected' : ''}>Key 共享（客户端 IP 数）</option>
+              <option value="all" ${condType === 'all' ? 'selected' : ''}>组合条件（全部满足）</option>
+              <option value="any" ${condType === 'any' ? 'selected' : ''}>组合条件（任一满足）</option>
+            </select>
+          </div>
+
+          <div class="field">
+            <label class="field-label">封禁对象</label>
+            <select class="input select" id="banrule-subject">
+              <option value="key" ${!rule?.subject || rule.subject === 'key' ? 'selected' : ''}>API Key</option>
+              <option value="ip" ${rule?.subject === 'ip' ? 'selected' : ''}>客户端 IP（鉴权前拦截）</option>
+              <option value="subnet" ${rule?.subject === 'subnet' ? 'selected' : ''}>客户端网段 /24、/64（鉴权前拦截）</option>
+            </select>
+          </div>
+
+          <div class="field">
+            <label class="field-label">触发动作</label>
+            <select class="input select" id="banrule-action">
+              <option value="ban" ${rule?.action !== 'alert' ? 'selected' : ''}>封禁</option>
+              <option value="alert" ${rule?.action === 'alert' ? 'selected' : ''}>仅告警</option>
+            </select>
+          </div>
+
+          <div class="field">
+            <label class="field-label">运行模式</label>
+            <select class="input select" id="banrule-mode">
+              <option value="enforce" ${rule?.mode !== 'shadow' ? 'selected' : ''}>生效</option>
+              <option value="shadow" ${rule?.mode === 'shadow' ? 'selected' : ''}>影子模式（只记录，不封禁）</option>
             </select>
           </div>
 
@@ -1673,6 +2019,41 @@ function openBanRuleModal(idx) {
             <div class="field-help">建议：1小时=3600秒，1天=86400秒</div>
           </div>
 
+          <div class="form-section">
+            <h5 class="section-subtitle">重复封禁升级</h5>
+            <div class="field">
+              <label class="toggle-label">
+                <input type="checkbox" id="banrule-escalation-enabled" ${rule?.escalation ? 'checked' : ''}>
+                <span>再犯时延长封禁时长</span>
+              </label>
+            </div>
+            <div class="form-row">
+              <div class="field">
+                <label class="field-label">时长倍数</label>
+                <input type="number" class="input" id="banrule-escalation-multiplier"
+                       value="${rule?.escalation?.multiplier ?? 2}" min="1" step="0.5">
+                <div class="field-help">每次再犯的封禁时长为上一次的倍数</div>
+              </div>
+              <div class="field">
+                <label class="field-label">时长上限 (秒)</label>
+                <input type="number" class="input" id="banrule-escalation-max"
+                       value="${rule?.escalation?.max_duration_secs ?? ''}" min="0" step="60" placeholder="不限制">
+              </div>
+              <div class="field">
+                <label class="field-label">永久封禁阈值</label>
+                <input type="number" class="input" id="banrule-escalation-permanent"
+                       value="${rule?.escalation?.permanent_after ?? ''}" min="0" step="1" placeholder="不启用">
+                <div class="field-help">第 N 次封禁起永久封禁，需手动解封</div>
+              </div>
+              <div class="field">
+                <label class="field-label">静默期 (秒)</label>
+                <input type="number" class="input" id="banrule-escalation-decay"
+                       value="${rule?.escalation?.decay_secs ?? 604800}" min="0" step="3600">
+                <div class="field-help">距上次封禁结束超过该时长后重新计次</div>
+              </div>
+            </div>
+          </div>
+
           <div class="form-section">
             <h5 class="section-subtitle">多次触发设置</h5>
             <div class="form-row">
@@ -1765,6 +2146,134 @@ function getBanRuleConditionFieldsHtml(type, cond) {
         <div class="field-help">连续出现指定次数的错误时触发封禁</div>
       </div>
     `;
+  } else if (type === 'status_code') {
+    return `
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">状态码</label>
+          <input type="text" class="input" id="cond-statuses" value="${esc((cond.statuses || ['4xx']).join(', '))}"
+                 placeholder="401, 4xx">
+          <div class="field-help">具体状态码或类别，逗号分隔</div>
+        </div>
+        <div class="field">
+          <label class="field-label">最大次数</label>
+          <input type="number" class="input" id="cond-max-count" value="${cond.max_count || 50}"
+                 min="1" placeholder="50">
+        </div>
+        <div class="field">
+          <label class="field-label">时间窗口 (秒)</label>
+          <input type="number" class="input" id="cond-window" value="${cond.window_secs || 300}"
+                 min="10" step="10" placeholder="300">
+        </div>
+      </div>
+    `;
+  } else if (type === 'latency') {
+    return `
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">P95 延迟阈值 (毫秒)</label>
+          <input type="number" class="input" id="cond-p95-threshold" value="${cond.p95_threshold_ms || 30000}"
+                 min="1" placeholder="30000">
+        </div>
+        <div class="field">
+          <label class="field-label">时间窗口 (秒)</label>
+          <input type="number" class="input" id="cond-window" value="${cond.window_secs || 300}"
+                 min="10" step="10" placeholder="300">
+        </div>
+        <div class="field">
+          <label class="field-label">最小请求数</label>
+          <input type="number" class="input" id="cond-min-requests" value="${cond.min_requests || 20}"
+                 min="1" placeholder="20">
+          <div class="field-help">避免样本过少误触发</div>
+        </div>
+      </div>
+    `;
+  } else if (type === 'token_usage') {
+    return `
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">最大 Token 数</label>
+          <input type="number" class="input" id="cond-max-tokens" value="${cond.max_tokens || 1000000}"
+                 min="1" placeholder="1000000">
+          <div class="field-help">输入 + 输出 token</div>
+        </div>
+        <div class="field">
+          <label class="field-label">时间窗口 (秒)</label>
+          <input type="number" class="input" id="cond-window" value="${cond.window_secs || 3600}"
+                 min="10" step="10" placeholder="3600">
+        </div>
+      </div>
+    `;
+  } else if (type === 'spike') {
+    return `
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">指标</label>
+          <select class="input select" id="cond-metric">
+            <option value="requests" ${cond.metric !== 'tokens' ? 'selected' : ''}>请求数</option>
+            <option value="tokens" ${cond.metric === 'tokens' ? 'selected' : ''}>Token 用量</option>
+          </select>
+        </div>
+        <div class="field">
+          <label class="field-label">倍数</label>
+          <input type="number" class="input" id="cond-multiplier" value="${cond.multiplier || 10}"
+                 min="1.1" step="0.5" placeholder="10">
+          <div class="field-help">当前窗口用量达到基线的倍数</div>
+        </div>
+        <div class="field">
+          <label class="field-label">最小用量</label>
+          <input type="number" class="input" id="cond-min-value" value="${cond.min_value || 100}"
+                 min="0" placeholder="100">
+        </div>
+      </div>
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">时间窗口 (秒)</label>
+          <input type="number" class="input" id="cond-window" value="${cond.window_secs || 300}"
+                 min="10" step="10" placeholder="300">
+        </div>
+        <div class="field">
+          <label class="field-label">基线窗口 (秒)</label>
+          <input type="number" class="input" id="cond-baseline" value="${cond.baseline_secs || 86400}"
+                 min="10" step="10" placeholder="86400">
+          <div class="field-help">当前窗口之前的这段时间作为基线</div>
+        </div>
+      </div>
+    `;
+  } else if (type === 'all' || type === 'any') {
+    const conditions = cond.conditions || [
+      { type: 'consecutive_errors', count: 5 },
+      { type: 'request_count', window_secs: 60, max_requests: 100 }
+    ];
+    return `
+      <div class="field">
+        <label class="field-label">子条件 (JSON)</label>
+        <textarea class="input" id="cond-conditions" rows="8">${esc(JSON.stringify(conditions, null, 2))}</textarea>
+        <div class="field-help">子条件数组，格式与单个条件相同，可继续嵌套 all / any</div>
+      </div>
+    `;
+  } else if (type === 'key_sharing') {
+    return `
+      <div class="form-row">
+        <div class="field">
+          <label class="field-label">最多 IP 数</label>
+          <input type="number" class="input" id="cond-max-ips" value="${cond.max_ips ?? 10}"
+                 min="0" placeholder="10">
+          <div class="field-help">超过该数量的不同客户端 IP 时触发，0 表示不限制</div>
+        </div>
+        <div class="field">
+          <label class="field-label">最多网段数</label>
+          <input type="number" class="input" id="cond-max-subnets" value="${cond.max_subnets ?? 0}"
+                 min="0" placeholder="3">
+          <div class="field-help">IPv4 按 /24、IPv6 按 /64 归并，0 表示不限制</div>
+        </div>
+        <div class="field">
+          <label class="field-label">时间窗口 (秒)</label>
+          <input type="number" class="input" id="cond-window" value="${cond.window_secs || 3600}"
+                 min="10" step="10" placeholder="3600">
+        </div>
+      </div>
+    `;
   }
   return '';
 }
@@ -1805,7 +2314,47 @@ function saveBanRule(idx) {
     condition.window_secs = parseInt(document.getElementById('cond-window').value) || 60;
   } else if (type === 'consecutive_errors') {
     condition.count = parseInt(document.getElementById('cond-count').value) || 5;
+  } else if (type === 'status_code') {
+    condition.statuses = document.getElementById('cond-statuses').value
+      .split(',').map(s => s.trim()).filter(Boolean);
+    condition.max_count = parseInt(document.getElementById('cond-max-count').value) || 50;
+    condition.window_secs = parseInt(document.getElementById('cond-window').value) || 300;
+  } else if (type === 'latency') {
+    condition.p95_threshold_ms = parseInt(document.getElementById('cond-p95-threshold').value) || 30000;
+    condition.window_secs = parseInt(document.getElementById('cond-window').value) || 300;
+    condition.min_requests = parseInt(document.getElementById('cond-min-requests').value) || 20;
+  } else if (type === 'token_usage') {
+    condition.max_tokens = parseInt(document.getElementById('cond-max-tokens').value) || 1000000;
+    condition.window_secs = parseInt(document.getElementById('cond-window').value) || 3600;
+  } else if (type === 'spike') {
+    condition.metric = document.getElementById('cond-metric').value;
+    condition.multiplier = parseFloat(document.getElementById('cond-multiplier').value) || 10;
+    condition.min_value = parseInt(document.getElementById('cond-min-value').value) || 0;
+    condition.window_secs = parseInt(document.getElementById('cond-window').value) || 300;
+    condition.baseline_secs = parseInt(document.getElementById('cond-baseline').value) || 86400;
+  } else if (type === 'key_sharing') {
+    const maxIps = parseInt(document.getElementById('cond-max-ips').value) || 0;
+    const maxSubnets = parseInt(document.getElementById('cond-max-subnets').value) || 0;
+    if (!maxIps && !maxSubnets) {
+      Toast.show('请至少设置 IP 数或网段数上限', 'error');
+      return;
+    }
+    if (maxIps) condition.max_ips = maxIps;
+    if (maxSubnets) condition.max_subnets = maxSubnets;
+    condition.window_secs = parseInt(document.getElementById('cond-window').value) || 3600;
+  } else if (type === 'all' || type === 'any') {
+    try {
+      condition.conditions = JSON.parse(document.getElementById('cond-conditions').value);
+    } catch (err) {
+      Toast.show(`子条件 JSON 格式错误: ${err.message}`, 'error');
+      return;
+    }
+    if (!Array.isArray(condition.conditions) || condition.conditions.length === 0) {
+      Toast.show('请至少配置一个子条件', 'error');
+      return;
+    }
   }
+  const action = document.getElementById('banrule-action').value;
 
   const rule = {
     id: idx !== null ? cfg.api_keys.ban_rules[idx]?.id : 'rule_' + Date.now(),
@@ -1816,6 +2365,21 @@ function saveBanRule(idx) {
     trigger_count_threshold: parseInt(document.getElementById('banrule-trigger-count').value) || 1,
     trigger_window_secs: parseInt(document.getElementById('banrule-trigger-window').value) || 3600
   };
+  if (action === 'alert') rule.action = 'alert';
+  if (document.getElementById('banrule-mode').value === 'shadow') rule.mode = 'shadow';
+  const subject = document.getElementById('banrule-subject').value;
+  if (subject !== 'key') rule.subject = subject;
+  if (document.getElementById('banrule-escalation-enabled').checked) {
+    const escalation = {
+      multiplier: parseFloat(document.getElementById('banrule-escalation-multiplier').value) || 2,
+      decay_secs: parseInt(document.getElementById('banrule-escalation-decay').value) || 604800
+    };
+    const maxDuration = parseInt(document.getElementById('banrule-escalation-max').value) || 0;
+    const permanentAfter = parseInt(document.getElementById('banrule-escalation-permanent').value) || 0;
+    if (maxDuration) escalation.max_duration_secs = maxDuration;
+    if (permanentAfter) escalation.permanent_after = permanentAfter;
+    rule.escalation = escalation;
+  }
 
   if (!cfg.api_keys) cfg.api_keys = {};
   if (!cfg.api_keys.ban_rules) cfg.api_keys.ban_rules = [];
@@ -1882,28 +2446,31 @@ function renderBanLogs() {
   // 生成表格行
   const tableRows = paginatedLogs.map(log => {
     const isUnban = !!log.unbanned_at;
-    const actionClass = isUnban ? 'action-unban' : 'action-ban';
-    const actionText = isUnban ? '解封' : '封禁';
+    const actionClass = isUnban || log.shadow || log.alert ? 'action-unban' : 'action-ban';
+    const actionText = log.alert ? '告警' : (log.shadow ? '模拟封禁' : (isUnban ? '解封' : '封禁'));
     const actionIcon = isUnban
       ? '<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M12 2v20M2 12h20"/></svg>'
       : '<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M12 2v20M2 12h20"/></svg>';
 
-    // 查找 API Key 信息
-    const apiKey = apiKeysData.find(k => k.id === log.api_key_id);
-    const apiKeyDisplay = apiKey ? apiKey.key : log.api_key_id;
+    // 查找 API Key 信息（IP / 网段封禁直接展示对象）
+    const apiKey = !log.subject || log.subject === 'key' ? apiKeysData.find(k => k.id === log.api_key_id) : null;
+    const apiKeyDisplay = apiKey ? apiKey.key : describeBanTarget(log.subject, log.api_key_id);
 
     // 计算封禁时长
     const durationSecs = log.banned_until - log.banned_at;
-    const durationText = formatDurationSeconds(durationSecs);
+    let durationText = log.alert ? '-' : (log.permanent ? '永久' : formatDurationSeconds(durationSecs));
+    if (log.offense > 1) durationText += ` · 第 ${log.offense} 次`;
 
     // 格式化时间
     const bannedTime = formatUnixTime(log.banned_at);
+    const clientIps = log.metrics_snapshot?.client_ips;
+    const reasonTitle = clientIps?.length ? `${log.reason}\nIP: ${clientIps.join(', ')}` : log.reason;
 
     return `
       <tr>
         <td><code class="apikey-code" title="${esc(apiKey?.key || log.api_key_id)}">${esc(apiKeyDisplay)}</code></td>
         <td><span class="action-badge ${actionClass}">${actionIcon} ${actionText}</span></td>
-        <td class="reason-cell" title="${esc(log.reason)}">${esc(log.reason)}</td>
+        <td class="reason-cell" title="${esc(reasonTitle)}">${esc(log.reason)}</td>
         <td>${bannedTime}</td>
         <td>${durationText}</td>
         <td><span class="operator-badge system">system</span></td>
@@ -1912,8 +2479,10 @@ function renderBanLogs() {
   }).join('');
 
   // 统计（新结构）
-  const totalBans = banLogsData.filter(l => !l.unbanned_at).length;
+  const totalBans = banLogsData.filter(l => !l.unbanned_at && !l.shadow && !l.alert).length;
   const totalUnbans = banLogsData.filter(l => !!l.unbanned_at).length;
+  const totalShadow = banLogsData.filter(l => l.shadow).length;
+  const totalAlerts = banLogsData.filter(l => l.alert).length;
 
   // 生成分页
   const totalPages = Math.ceil(banLogPagination.total / banLogPagination.pageSize);
@@ -1927,6 +2496,8 @@ function renderBanLogs() {
           <span class="stat-item">总记录: <strong>${banLogsData.length}</strong></span>
           <span class="stat-item">封禁: <strong class="text-danger">${totalBans}</strong></span>
           <span class="stat-item">解封: <strong class="text-success">${totalUnbans}</strong></span>
+          <span class="stat-item">模拟封禁: <strong>${totalShadow}</strong></span>
+          <span class="stat-item">告警: <strong>${totalAlerts}</strong></span>
         </div>
       </div>
 
@@ -1972,6 +2543,150 @@ function renderBanLogs() {
       </div>
 
       ${paginationHtml}
+
+      ${renderBanAlerts()}
+
+      ${renderIpBans()}
+
+      ${renderAuthLockouts()}
+    </div>
+  `;
+}
+
+// 仅告警规则的触发记录
+function renderBanAlerts() {
+  if (!banAlertsData.length) return '';
+
+  const rows = banAlertsData.map(alert => {
+    const clientIps = alert.metrics_snapshot?.client_ips || [];
+    return `
+      <tr>
+        <td><code class="apikey-code">${esc(describeBanTarget(alert.subject, alert.api_key_id))}</code></td>
+        <td>${esc(alert.rule_id)}</td>
+        <td class="reason-cell" title="${esc(alert.reason)}">${esc(alert.reason)}</td>
+        <td class="reason-cell" title="${esc(clientIps.join(', '))}">${esc(clientIps.join(', ') || '-')}</td>
+        <td>${formatUnixTime(alert.triggered_at)}</td>
+      </tr>
+    `;
+  }).join('');
+
+  return `
+    <div class="banlogs-header">
+      <h2 class="banlogs-title">规则告警</h2>
+      <div class="banlogs-stats">
+        <span class="stat-item">最近告警: <strong class="text-danger">${banAlertsData.length}</strong></span>
+      </div>
+    </div>
+    <div class="banlogs-table-wrapper">
+      <table class="banlogs-table">
+        <thead>
+          <tr>
+            <th>API Key</th>
+            <th>规则</th>
+            <th>原因</th>
+            <th>客户端 IP</th>
+            <th>时间</th>
+          </tr>
+        </thead>
+        <tbody>${rows}</tbody>
+      </table>
+    </div>
+  `;
+}
+
+// 封禁对象的展示文本
+function describeBanTarget(subject, id) {
+  if (subject === 'ip') return `IP ${id}`;
+  if (subject === 'subnet') return `网段 ${id}`;
+  return id;
+}
+
+// 封禁中的 IP 与网段
+function renderIpBans() {
+  const rows = ipBansData.map(item => `
+    <tr>
+      <td><code class="apikey-code">${esc(describeBanTarget(item.subject, item.target))}</code></td>
+      <td>${esc(item.status.triggered_rule_id || '手动')}</td>
+      <td class="reason-cell" title="${esc(item.status.reason || '')}">${esc(item.status.reason || '-')}</td>
+      <td>${item.status.banned_at ? formatUnixTime(item.status.banned_at) : '-'}</td>
+      <td>${item.status.permanent ? '永久' : formatUnixTime(item.status.banned_until)}</td>
+      <td><button class="btn btn-sm btn-ghost" onclick="unbanIp('${esc(item.target)}')">解封</button></td>
+    </tr>
+  `).join('');
+
+  return `
+    <div class="banlogs-header">
+      <h2 class="banlogs-title">IP 封禁</h2>
+      <div class="banlogs-stats">
+        <span class="stat-item">封禁中: <strong class="text-danger">${ipBansData.length}</strong></span>
+      </div>
+    </div>
+    <div class="banlogs-toolbar">
+      <div class="filter-group">
+        <input type="text" class="input" id="ipban-target" placeholder="IP 或网段，如 10.0.0.1、10.0.0.0/24">
+        <input type="number" class="input" id="ipban-duration" placeholder="时长（秒，0 为永久）" min="0" value="3600">
+        <input type="text" class="input" id="ipban-reason" placeholder="封禁原因">
+        <button class="btn btn-primary btn-sm" onclick="banIpFromForm()">封禁</button>
+      </div>
+    </div>
+    <div class="banlogs-table-wrapper">
+      <table class="banlogs-table">
+        <thead>
+          <tr>
+            <th>对象</th>
+            <th>规则</th>
+            <th>原因</th>
+            <th>封禁时间</th>
+            <th>解封时间</th>
+            <th>操作</th>
+          </tr>
+        </thead>
+        <tbody>
+          ${rows || '<tr><td colspan="6" class="empty-cell">暂无封禁的 IP</td></tr>'}
+        </tbody>
+      </table>
+    </div>
+  `;
+}
+
+// 鉴权失败锁定的 IP 列表
+function renderAuthLockouts() {
+  if (!authLockoutsData.enabled) return '';
+
+  const rows = authLockoutsData.locked.map(item => `
+    <tr>
+      <td><code class="apikey-code">${esc(item.ip)}</code></td>
+      <td>${item.scope === 'admin' ? '管理接口' : '代理请求'}</td>
+      <td>${formatUnixTime(item.locked_at)}</td>
+      <td>${formatUnixTime(item.locked_until)}</td>
+      <td>${item.lockouts}</td>
+      <td><button class="btn btn-sm btn-ghost" onclick="unlockAuthLockout('${esc(item.ip)}')">解除</button></td>
+    </tr>
+  `).join('');
+
+  return `
+    <div class="banlogs-header">
+      <h2 class="banlogs-title">IP 锁定</h2>
+      <div class="banlogs-stats">
+        <span class="stat-item">锁定中: <strong class="text-danger">${authLockoutsData.locked.length}</strong></span>
+      </div>
+    </div>
+    <div class="banlogs-table-wrapper">
+      <table class="banlogs-table">
+        <thead>
+          <tr>
+            <th>IP</th>
+            <th>触发来源</th>
+            <th>锁定时间</th>
+            <th>解锁时间</th>
+            <th>累计锁定</th>
+            <th>操作</th>
+          </tr>
+        </thead>
+        <tbody>
+          ${rows || '<tr><td colspan="6" class="empty-cell">暂无锁定的 IP</td></tr>'}
+        </tbody>
+      </table>
     </div>
   `;
 }
@@ -2328,6 +3043,8 @@ function addRoute() {
 function parseTokenSource(i, value) {
   if (value.startsWith('Header:') || value.startsWith('header:')) {
     cfg.gateway_auth.token_sources[i] = { type: 'header', name: value.substring(7).trim() };
+  } else if (value.startsWith('Query:') || value.startsWith('query:')) {
+    cfg.gateway_auth.token_sources[i] = { type: 'query', name: value.substring(6).trim() };
   }
 }
 
@@ -2466,8 +3183,16 @@ function renderGatewayAuthSection() {
         <button class="btn btn-danger btn-sm" onclick="cfg.gateway_auth.token_sources.splice(${i},1);renderGateway()">删除</button>
       </div>`;
     }
+    if (s.type === 'jwt') {
+      // JWT 配置项较多，仅展示，需在配置文件中编辑
+      return `<div class="token-row">
+        <input class="input" value="JWT${s.issuer ? ': ' + esc(s.issuer) : ''}" readonly />
+        <button class="btn btn-danger btn-sm" onclick="cfg.gateway_auth.token_sources.splice(${i},1);renderGateway()">删除</button>
+      </div>`;
+    }
+    const label = s.type === 'query' ? 'Query' : 'Header';
     return `<div class="token-row">
-      <input class="input" value="Header: ${esc(s.name || '')}" onchange="parseTokenSource(${i}, this.value)" />
+      <input class="input" value="${label}: ${esc(s.name || '')}" onchange="parseTokenSource(${i}, this.value)" />
       <button class="btn btn-danger btn-sm" onclick="cfg.gateway_auth.token_sources.splice(${i},1);renderGateway()">删除</button>
     </div>`;
   }).join('');
@@ -2479,6 +3204,7 @@ function renderGatewayAuthSection() {
       <div style="display:flex;gap:var(--space-2);margin-top:var(--space-2)">
         <button class="btn btn-secondary btn-sm" onclick="cfg.gateway_auth.token_sources.push({type:'authorization_bearer'});renderGateway()">+ Authorization Bearer</button>
         <button class="btn btn-secondary btn-sm" onclick="cfg.gateway_auth.token_sources.push({type:'header',name:'x-gw-token'});renderGateway()">+ 自定义 Header</button>
+        <button class="btn btn-secondary btn-sm" onclick="cfg.gateway_auth.token_sources.push({type:'query',name:'key'});renderGateway()">+ 查询参数</button>
       </div>
     </div>
   `;
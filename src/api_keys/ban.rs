@@ -1,9 +1,15 @@
-pub use crate::config::{BanCondition, BanRule, BanStatus};
+pub use crate::config::{BanAction, BanCondition, BanEscalation, BanRule, BanRuleMode, BanStatus, BanSubject, SpikeMetric};
+use crate::api_keys::RequestResult;
+use crate::config::parse_status_pattern;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+
+/// 快照中最多记录的客户端 IP 数
+const MAX_SNAPSHOT_CLIENT_IPS: usize = 100;
 
 /// 封禁时的指标快照
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BanMetricsSnapshot {
     /// 时间窗口内的请求数
     pub requests: u64,
@@ -11,13 +17,58 @@ pub struct BanMetricsSnapshot {
     pub errors: u64,
     /// 错误率
     pub error_rate: f64,
+    /// 时间窗口内匹配状态码的响应数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_matches: Option<u64>,
+    /// 时间窗口内的 P95 延迟（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p95_latency_ms: Option<u64>,
+    /// 时间窗口内的 token 用量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u64>,
+    /// 突增检测的基线：基线期用量折算到当前窗口时长
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<f64>,
+    /// 时间窗口内的不同客户端 IP 数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distinct_ips: Option<u64>,
+    /// 时间窗口内的不同网段数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distinct_subnets: Option<u64>,
+    /// 时间窗口内的客户端 IP（最多记录 100 个）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ips: Option<Vec<String>>,
+}
+
+impl BanMetricsSnapshot {
+    /// 合并组合条件中另一个子条件的快照：请求数等公共字段保留先满足的子条件，附加字段取首个非空值
+    fn merge(&mut self, other: BanMetricsSnapshot) {
+        self.status_matches = self.status_matches.or(other.status_matches);
+        self.p95_latency_ms = self.p95_latency_ms.or(other.p95_latency_ms);
+        self.tokens = self.tokens.or(other.tokens);
+        self.baseline = self.baseline.or(other.baseline);
+        self.distinct_ips = self.distinct_ips.or(other.distinct_ips);
+        self.distinct_subnets = self.distinct_subnets.or(other.distinct_subnets);
+        self.client_ips = self.client_ips.take().or(other.client_ips);
+    }
+}
+
+/// 单次请求记录
+#[derive(Debug, Clone)]
+struct RequestRecord {
+    timestamp: u64,
+    success: bool,
+    status: u16,
+    latency_ms: u64,
+    tokens: u64,
+    client_ip: Option<String>,
 }
 
 /// 违规计数器（按时间窗口统计）
 #[derive(Debug)]
 pub struct ViolationCounter {
-    /// 请求记录（时间戳，是否成功）
-    requests: VecDeque<(u64, bool)>,
+    /// 请求记录
+    requests: VecDeque<RequestRecord>,
     /// 连续错误数
     consecutive_errors: u32,
     /// 最大窗口大小（用于清理过期数据）
@@ -34,8 +85,16 @@ impl ViolationCounter {
     }
 
     /// 记录一次请求
-    pub fn record(&mut self, timestamp: u64, success: bool) {
-        self.requests.push_back((timestamp, success));
+    pub fn record(&mut self, timestamp: u64, result: &RequestResult) {
+        let success = result.success;
+        self.requests.push_back(RequestRecord {
+            timestamp,
+            success,
+            status: result.response_status,
+            latency_ms: result.latency_ms,
+            tokens: result.tokens,
+            client_ip: result.client_ip.clone(),
+        });
 
         if success {
             self.consecutive_errors = 0;
@@ -49,23 +108,20 @@ impl ViolationCounter {
     /// 清理过期记录
     fn cleanup_old_records(&mut self, now: u64) {
         let cutoff = now.saturating_sub(self.max_window_secs);
-        while self.requests.front().map(|(t, _)| *t < cutoff).unwrap_or(false) {
+        while self.requests.front().map(|r| r.timestamp < cutoff).unwrap_or(false) {
             self.requests.pop_front();
         }
     }
 
     /// 获取时间窗口内的统计
     pub fn get_window_stats(&self, window_secs: u64, now: u64) -> WindowStats {
-        let cutoff = now.saturating_sub(window_secs);
         let mut total = 0u64;
         let mut errors = 0u64;
 
-        for (timestamp, success) in &self.requests {
-            if *timestamp >= cutoff {
-                total += 1;
-                if !success {
-                    errors += 1;
-                }
+        for record in self.window(window_secs, now) {
+            total += 1;
+            if !record.success {
+                errors += 1;
             }
         }
 
@@ -82,6 +138,54 @@ impl ViolationCounter {
             consecutive_errors: self.consecutive_errors,
         }
     }
+
+    /// 时间窗口内的请求记录
+    fn window(&self, window_secs: u64, now: u64) -> impl Iterator<Item = &RequestRecord> {
+        let cutoff = now.saturating_sub(window_secs);
+        self.requests.iter().filter(move |r| r.timestamp >= cutoff)
+    }
+
+    /// 时间窗口内状态码落在任一区间的响应数
+    pub fn count_statuses(&self, window_secs: u64, now: u64, ranges: &[(u16, u16)]) -> u64 {
+        self.window(window_secs, now)
+            .filter(|r| ranges.iter().any(|(low, high)| (*low..=*high).contains(&r.status)))
+            .count() as u64
+    }
+
+    /// 时间窗口内的 P95 延迟（毫秒），无请求时返回 None
+    pub fn p95_latency_ms(&self, window_secs: u64, now: u64) -> Option<u64> {
+        let mut latencies: Vec<u64> = self.window(window_secs, now).map(|r| r.latency_ms).collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let rank = (latencies.len() * 95).div_ceil(100);
+        Some(latencies[rank.saturating_sub(1)])
+    }
+
+    /// 时间窗口内的 token 用量
+    pub fn sum_tokens(&self, window_secs: u64, now: u64) -> u64 {
+        self.window(window_secs, now).map(|r| r.tokens).sum()
+    }
+
+    /// 时间窗口内的不同客户端 IP（排序后）及不同网段数
+    pub fn distinct_clients(&self, window_secs: u64, now: u64) -> (Vec<String>, u64) {
+        let ips: BTreeSet<&str> = self
+            .window(window_secs, now)
+            .filter_map(|r| r.client_ip.as_deref())
+            .collect();
+        let subnets: HashSet<String> = ips.iter().map(|ip| subnet_of(ip)).collect();
+        (ips.into_iter().map(str::to_string).collect(), subnets.len() as u64)
+    }
+
+    /// [from, to) 区间内的指标合计
+    fn metric_between(&self, metric: SpikeMetric, from: u64, to: u64) -> u64 {
+        let records = self.requests.iter().filter(|r| r.timestamp >= from && r.timestamp < to);
+        match metric {
+            SpikeMetric::Requests => records.count() as u64,
+            SpikeMetric::Tokens => records.map(|r| r.tokens).sum(),
+        }
+    }
 }
 
 /// 时间窗口统计
@@ -108,6 +212,10 @@ pub struct BanRuleEngine {
     counter: ViolationCounter,
     /// 记录每个规则的触发历史（用于多次触发才封禁）
     rule_triggers: Vec<RuleTrigger>,
+    /// 影子规则达到阈值的触发，由调用方取出记录
+    shadow_triggers: Vec<TriggeredRule>,
+    /// 影子规则模拟封禁的结束时间（`None` 为永久），期间不再重复触发
+    shadow_suppressed: HashMap<String, Option<u64>>,
 }
 
 impl BanRuleEngine {
@@ -115,24 +223,37 @@ impl BanRuleEngine {
         Self {
             counter: ViolationCounter::new(max_window_secs),
             rule_triggers: Vec::new(),
+            shadow_triggers: Vec::new(),
+            shadow_suppressed: HashMap::new(),
         }
     }
 
     /// 记录请求结果并检查是否触发封禁规则
+    ///
+    /// 影子模式的规则不会作为返回值，达到阈值后存入 [`Self::take_shadow_triggers`]。
     pub fn check_rules(
         &mut self,
         rules: &[BanRule],
         now: u64,
-        success: bool,
+        result: &RequestResult,
     ) -> Option<TriggeredRule> {
         // 记录请求
-        self.counter.record(now, success);
+        self.counter.record(now, result);
 
         // 清理过期的触发记录
         self.cleanup_old_triggers(now, rules);
 
         // 检查每个启用的规则
         for rule in rules.iter().filter(|r| r.enabled) {
+            let shadow = rule.mode == BanRuleMode::Shadow;
+            if shadow
+                && self
+                    .shadow_suppressed
+                    .get(&rule.id)
+                    .is_some_and(|until| until.is_none_or(|until| now < until))
+            {
+                continue;
+            }
             if let Some(triggered) = self.check_single_rule(rule, now) {
                 // 记录这次触发
                 self.rule_triggers.push(RuleTrigger {
@@ -145,6 +266,10 @@ impl BanRuleEngine {
                 if trigger_count >= rule.trigger_count_threshold {
                     // 达到阈值，执行封禁，并清除该规则的触发记录
                     self.clear_triggers_for_rule(&rule.id);
+                    if shadow {
+                        self.shadow_triggers.push(triggered);
+                        continue;
+                    }
                     return Some(triggered);
                 }
             }
@@ -153,6 +278,16 @@ impl BanRuleEngine {
         None
     }
 
+    /// 取出影子规则达到阈值的触发
+    pub fn take_shadow_triggers(&mut self) -> Vec<TriggeredRule> {
+        std::mem::take(&mut self.shadow_triggers)
+    }
+
+    /// 影子规则在模拟封禁期间（`until` 为空表示永久）不再重复触发
+    pub fn suppress_shadow_rule(&mut self, rule_id: &str, until: Option<u64>) {
+        self.shadow_suppressed.insert(rule_id.to_string(), until);
+    }
+
     /// 清理过期的触发记录
     fn cleanup_old_triggers(&mut self, now: u64, rules: &[BanRule]) {
         // 找到最大的触发窗口
@@ -181,7 +316,20 @@ impl BanRuleEngine {
 
     /// 检查单个规则
     fn check_single_rule(&self, rule: &BanRule, now: u64) -> Option<TriggeredRule> {
-        match &rule.condition {
+        let matched = self.evaluate(&rule.condition, now)?;
+        Some(TriggeredRule {
+            rule_id: rule.id.clone(),
+            rule_name: rule.name.clone(),
+            ban_duration_secs: rule.ban_duration_secs,
+            action: rule.action,
+            reason: matched.reason,
+            metrics_snapshot: matched.metrics_snapshot,
+        })
+    }
+
+    /// 检查条件是否满足
+    fn evaluate(&self, condition: &BanCondition, now: u64) -> Option<ConditionMatch> {
+        match condition {
             BanCondition::ErrorRate {
                 window_secs,
                 threshold,
@@ -189,10 +337,7 @@ impl BanRuleEngine {
             } => {
                 let stats = self.counter.get_window_stats(*window_secs, now);
                 if stats.total >= *min_requests && stats.error_rate >= *threshold {
-                    return Some(TriggeredRule {
-                        rule_id: rule.id.clone(),
-                        rule_name: rule.name.clone(),
-                        ban_duration_secs: rule.ban_duration_secs,
+                    return Some(ConditionMatch {
                         reason: format!(
                             "Error rate {:.2}% exceeded threshold {:.2}% in {} seconds",
                             stats.error_rate * 100.0,
@@ -203,6 +348,7 @@ impl BanRuleEngine {
                             requests: stats.total,
                             errors: stats.errors,
                             error_rate: stats.error_rate,
+                            ..Default::default()
                         },
                     });
                 }
@@ -213,10 +359,7 @@ impl BanRuleEngine {
             } => {
                 let stats = self.counter.get_window_stats(*window_secs, now);
                 if stats.total >= *max_requests {
-                    return Some(TriggeredRule {
-                        rule_id: rule.id.clone(),
-                        rule_name: rule.name.clone(),
-                        ban_duration_secs: rule.ban_duration_secs,
+                    return Some(ConditionMatch {
                         reason: format!(
                             "Request count {} exceeded limit {} in {} seconds",
                             stats.total, max_requests, window_secs
@@ -225,6 +368,7 @@ impl BanRuleEngine {
                             requests: stats.total,
                             errors: stats.errors,
                             error_rate: stats.error_rate,
+                            ..Default::default()
                         },
                     });
                 }
@@ -232,40 +376,324 @@ impl BanRuleEngine {
             BanCondition::ConsecutiveErrors { count } => {
                 let stats = self.counter.get_window_stats(self.counter.max_window_secs, now);
                 if stats.consecutive_errors >= *count {
-                    return Some(TriggeredRule {
-                        rule_id: rule.id.clone(),
-                        rule_name: rule.name.clone(),
-                        ban_duration_secs: rule.ban_duration_secs,
+                    return Some(ConditionMatch {
                         reason: format!("{} consecutive errors exceeded threshold {}",
                             stats.consecutive_errors, count),
                         metrics_snapshot: BanMetricsSnapshot {
                             requests: stats.total,
                             errors: stats.errors,
                             error_rate: stats.error_rate,
+                            ..Default::default()
+                        },
+                    });
+                }
+            }
+            BanCondition::StatusCode {
+                window_secs,
+                statuses,
+                max_count,
+            } => {
+                let ranges: Vec<(u16, u16)> = statuses.iter().filter_map(|s| parse_status_pattern(s)).collect();
+                let matches = self.counter.count_statuses(*window_secs, now, &ranges);
+                if matches >= *max_count {
+                    let stats = self.counter.get_window_stats(*window_secs, now);
+                    return Some(ConditionMatch {
+                        reason: format!(
+                            "{} responses with status {} exceeded limit {} in {} seconds",
+                            matches,
+                            statuses.join("/"),
+                            max_count,
+                            window_secs
+                        ),
+                        metrics_snapshot: BanMetricsSnapshot {
+                            requests: stats.total,
+                            errors: stats.errors,
+                            error_rate: stats.error_rate,
+                            status_matches: Some(matches),
+                            ..Default::default()
+                        },
+                    });
+                }
+            }
+            BanCondition::Latency {
+                window_secs,
+                p95_threshold_ms,
+                min_requests,
+            } => {
+                let stats = self.counter.get_window_stats(*window_secs, now);
+                if stats.total >= *min_requests
+                    && let Some(p95) = self.counter.p95_latency_ms(*window_secs, now)
+                    && p95 >= *p95_threshold_ms
+                {
+                    return Some(ConditionMatch {
+                        reason: format!(
+                            "P95 latency {}ms exceeded threshold {}ms in {} seconds",
+                            p95, p95_threshold_ms, window_secs
+                        ),
+                        metrics_snapshot: BanMetricsSnapshot {
+                            requests: stats.total,
+                            errors: stats.errors,
+                            error_rate: stats.error_rate,
+                            p95_latency_ms: Some(p95),
+                            ..Default::default()
+                        },
+                    });
+                }
+            }
+            BanCondition::TokenUsage {
+                window_secs,
+                max_tokens,
+            } => {
+                let tokens = self.counter.sum_tokens(*window_secs, now);
+                if tokens >= *max_tokens {
+                    let stats = self.counter.get_window_stats(*window_secs, now);
+                    return Some(ConditionMatch {
+                        reason: format!(
+                            "Token usage {} exceeded limit {} in {} seconds",
+                            tokens, max_tokens, window_secs
+                        ),
+                        metrics_snapshot: BanMetricsSnapshot {
+                            requests: stats.total,
+                            errors: stats.errors,
+                            error_rate: stats.error_rate,
+                            tokens: Some(tokens),
+                            ..Default::default()
                         },
                     });
                 }
             }
+            BanCondition::KeySharing {
+                window_secs,
+                max_ips,
+                max_subnets,
+            } => {
+                let (ips, subnets) = self.counter.distinct_clients(*window_secs, now);
+                let distinct_ips = ips.len() as u64;
+                let reason = if max_ips.is_some_and(|max| distinct_ips > max) {
+                    format!(
+                        "Used from {} distinct client IPs (limit {}) in {} seconds",
+                        distinct_ips,
+                        max_ips.unwrap_or_default(),
+                        window_secs
+                    )
+                } else if max_subnets.is_some_and(|max| subnets > max) {
+                    format!(
+                        "Used from {} distinct subnets (limit {}) in {} seconds",
+                        subnets,
+                        max_subnets.unwrap_or_default(),
+                        window_secs
+                    )
+                } else {
+                    return None;
+                };
+                let stats = self.counter.get_window_stats(*window_secs, now);
+                return Some(ConditionMatch {
+                    reason,
+                    metrics_snapshot: BanMetricsSnapshot {
+                        requests: stats.total,
+                        errors: stats.errors,
+                        error_rate: stats.error_rate,
+                        distinct_ips: Some(distinct_ips),
+                        distinct_subnets: Some(subnets),
+                        client_ips: Some(ips.into_iter().take(MAX_SNAPSHOT_CLIENT_IPS).collect()),
+                        ..Default::default()
+                    },
+                });
+            }
+            BanCondition::Spike {
+                metric,
+                window_secs,
+                baseline_secs,
+                multiplier,
+                min_value,
+            } => {
+                let window_start = now.saturating_sub(*window_secs);
+                let current = self.counter.metric_between(*metric, window_start, now.saturating_add(1));
+                let baseline_total = self.counter.metric_between(
+                    *metric,
+                    window_start.saturating_sub(*baseline_secs),
+                    window_start,
+                );
+                // 基线期没有用量时无从比较（如新 Key）
+                if current < *min_value || baseline_total == 0 {
+                    return None;
+                }
+                let baseline = baseline_total as f64 * *window_secs as f64 / *baseline_secs as f64;
+                if current as f64 >= baseline * multiplier {
+                    let stats = self.counter.get_window_stats(*window_secs, now);
+                    let metric_name = match metric {
+                        SpikeMetric::Requests => "Request count",
+                        SpikeMetric::Tokens => "Token usage",
+                    };
+                    return Some(ConditionMatch {
+                        reason: format!(
+                            "{} {} in {} seconds is {:.1}x the baseline {:.1} (threshold {:.1}x)",
+                            metric_name,
+                            current,
+                            window_secs,
+                            current as f64 / baseline,
+                            baseline,
+                            multiplier
+                        ),
+                        metrics_snapshot: BanMetricsSnapshot {
+                            requests: stats.total,
+                            errors: stats.errors,
+                            error_rate: stats.error_rate,
+                            tokens: (*metric == SpikeMetric::Tokens).then_some(current),
+                            baseline: Some(baseline),
+                            ..Default::default()
+                        },
+                    });
+                }
+            }
+            BanCondition::All { conditions } => {
+                let mut matches = conditions.iter().map(|condition| self.evaluate(condition, now));
+                let mut combined = matches.next()??;
+                for matched in matches {
+                    let matched = matched?;
+                    combined.reason = format!("{} AND {}", combined.reason, matched.reason);
+                    combined.metrics_snapshot.merge(matched.metrics_snapshot);
+                }
+                return Some(combined);
+            }
+            BanCondition::Any { conditions } => {
+                return conditions.iter().find_map(|condition| self.evaluate(condition, now));
+            }
         }
 
         None
     }
 }
 
+/// 满足的条件及其描述
+#[derive(Debug)]
+struct ConditionMatch {
+    reason: String,
+    metrics_snapshot: BanMetricsSnapshot,
+}
+
+/// 计算本次封禁的累计次数与时长，时长为 `None` 表示永久封禁
+///
+/// 规则配置了升级策略时，每次再犯的时长按倍数放大；距上次封禁结束超过静默期后重新计次。
+pub fn escalate(rule: &BanRule, prior: Option<&BanStatus>, now: u64) -> (u32, Option<u64>) {
+    let prior_count = prior.map_or(0, |status| status.ban_count);
+    let Some(escalation) = &rule.escalation else {
+        return (prior_count.saturating_add(1), Some(rule.ban_duration_secs));
+    };
+
+    let last_ended_at = prior
+        .and_then(|status| status.banned_until.or(status.banned_at))
+        .unwrap_or_default();
+    let offense = if last_ended_at.saturating_add(escalation.decay_secs) <= now {
+        1
+    } else {
+        prior_count.saturating_add(1)
+    };
+    if escalation.permanent_after.is_some_and(|after| offense >= after) {
+        return (offense, None);
+    }
+
+    let exponent = offense.saturating_sub(1).min(i32::MAX as u32) as i32;
+    let mut duration = (rule.ban_duration_secs as f64 * escalation.multiplier.powi(exponent)) as u64;
+    if let Some(max) = escalation.max_duration_secs {
+        duration = duration.min(max);
+    }
+    (offense, Some(duration))
+}
+
 /// 触发的规则信息
 #[derive(Debug, Clone)]
 pub struct TriggeredRule {
     pub rule_id: String,
     pub rule_name: String,
     pub ban_duration_secs: u64,
+    pub action: BanAction,
     pub reason: String,
     pub metrics_snapshot: BanMetricsSnapshot,
 }
 
+/// 客户端 IP 在指定封禁对象类型下的标识：IP 规范化后的地址或所在网段（Key 类型原样返回）
+pub fn ban_target(subject: BanSubject, ip: &str) -> String {
+    match subject {
+        BanSubject::Subnet => subnet_of(ip),
+        BanSubject::Ip | BanSubject::Key => ip
+            .parse::<IpAddr>()
+            .map_or_else(|_| ip.to_string(), |ip| ip.to_string()),
+    }
+}
+
+/// 解析手动封禁的对象：单个 IP，或 IPv4 /24、IPv6 /64 网段（与网段规则的统计粒度一致）
+///
+/// 其他前缀长度返回错误，而不是静默按 /24、/64 处理。
+pub fn parse_ban_target(value: &str) -> Result<(BanSubject, String), String> {
+    let value = value.trim();
+    match value.split_once('/') {
+        None => {
+            let ip = value
+                .parse::<IpAddr>()
+                .map_err(|_| format!("`{value}` is not an IP address or CIDR"))?;
+            Ok((BanSubject::Ip, ip.to_string()))
+        }
+        Some((addr, prefix)) => {
+            let ip = addr
+                .parse::<IpAddr>()
+                .map_err(|_| format!("`{value}` is not an IP address or CIDR"))?;
+            let expected = if ip.is_ipv4() { "24" } else { "64" };
+            if prefix != expected {
+                return Err(format!(
+                    "unsupported prefix /{prefix} in `{value}`: subnet bans support only /24 (IPv4) and /64 (IPv6)"
+                ));
+            }
+            Ok((BanSubject::Subnet, subnet_of(&ip.to_string())))
+        }
+    }
+}
+
+/// 客户端 IP 所在网段：IPv4 取 /24，IPv6 取 /64，无法解析时原样返回
+fn subnet_of(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        Ok(IpAddr::V6(v6)) => {
+            let [a, b, c, d, ..] = v6.segments();
+            format!("{a:x}:{b:x}:{c:x}:{d:x}::/64")
+        }
+        Err(_) => ip.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn result(success: bool) -> RequestResult {
+        RequestResult {
+            success,
+            latency_ms: 100,
+            response_status: if success { 200 } else { 500 },
+            tokens: 0,
+            client_ip: None,
+        }
+    }
+
+    fn rule(id: &str, condition: BanCondition) -> BanRule {
+        BanRule {
+            id: id.to_string(),
+            name: id.to_string(),
+            condition,
+            ban_duration_secs: 600,
+            enabled: true,
+            trigger_count_threshold: 1,
+            trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
+        }
+    }
+
     #[test]
     fn test_error_rate_rule() {
         let mut engine = BanRuleEngine::new(300);
@@ -283,12 +711,16 @@ mod tests {
             enabled: true,
             trigger_count_threshold: 1, // 立即触发
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 记录10个请求，6个错误（60%错误率）
         for i in 0..10 {
             let success = i < 4; // 前4个成功，后6个错误
-            let result = engine.check_rules(&rules, now + i, success);
+            let result = engine.check_rules(&rules, now + i, &result(success));
             if i < 9 {
                 assert!(result.is_none(), "Should not trigger at request {}", i);
             } else {
@@ -317,11 +749,15 @@ mod tests {
             enabled: true,
             trigger_count_threshold: 1, // 立即触发
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 记录5个请求，第5个应该触发
         for i in 0..5 {
-            let result = engine.check_rules(&rules, now + i, true);
+            let result = engine.check_rules(&rules, now + i, &result(true));
             if i < 4 {
                 assert!(result.is_none());
             } else {
@@ -346,11 +782,15 @@ mod tests {
             enabled: true,
             trigger_count_threshold: 1, // 立即触发
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 记录3个连续错误
         for i in 0..3 {
-            let result = engine.check_rules(&rules, now + i, false);
+            let result = engine.check_rules(&rules, now + i, &result(false));
             if i < 2 {
                 assert!(result.is_none());
             } else {
@@ -378,10 +818,14 @@ mod tests {
             enabled: false, // 禁用
             trigger_count_threshold: 1,
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 即使超过阈值，禁用的规则也不会触发
-        let result = engine.check_rules(&rules, now, true);
+        let result = engine.check_rules(&rules, now, &result(true));
         assert!(result.is_none());
     }
 
@@ -401,15 +845,19 @@ mod tests {
             enabled: true,
             trigger_count_threshold: 1,
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 记录5个请求
         for i in 0..5 {
-            engine.check_rules(&rules, now + i, true);
+            engine.check_rules(&rules, now + i, &result(true));
         }
 
         // 70秒后，之前的记录应该过期
-        let result = engine.check_rules(&rules, now + 70, true);
+        let result = engine.check_rules(&rules, now + 70, &result(true));
         assert!(result.is_none(), "Old records should be cleaned up");
     }
 
@@ -429,25 +877,29 @@ mod tests {
             enabled: true,
             trigger_count_threshold: 3, // 需要触发3次才封禁
             trigger_window_secs: 3600,
+            action: BanAction::Ban,
+            escalation: None,
+            mode: BanRuleMode::Enforce,
+            subject: BanSubject::Key,
         }];
 
         // 第一次满足条件，记录触发但不封禁
         for i in 0..5 {
-            let result = engine.check_rules(&rules, now + i, true);
+            let result = engine.check_rules(&rules, now + i, &result(true));
             assert!(result.is_none(), "Should not ban on first trigger");
         }
 
         // 等待一段时间后，第二次满足条件
         let now2 = now + 100;
         for i in 0..5 {
-            let result = engine.check_rules(&rules, now2 + i, true);
+            let result = engine.check_rules(&rules, now2 + i, &result(true));
             assert!(result.is_none(), "Should not ban on second trigger");
         }
 
         // 第三次满足条件，应该封禁
         let now3 = now + 200;
         for i in 0..5 {
-            let result = engine.check_rules(&rules, now3 + i, true);
+            let result = engine.check_rules(&rules, now3 + i, &result(true));
             if i < 4 {
                 assert!(result.is_none());
             } else {
@@ -457,4 +909,317 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_status_code_rule_matches_classes_and_codes() {
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "upstream_401",
+            BanCondition::StatusCode {
+                window_secs: 60,
+                statuses: vec!["401".to_string(), "429".to_string()],
+                max_count: 3,
+            },
+        )];
+        let status = |response_status: u16| RequestResult {
+            success: false,
+            latency_ms: 10,
+            response_status,
+            tokens: 0,
+            client_ip: None,
+        };
+
+        // 500 不在匹配范围内
+        assert!(engine.check_rules(&rules, 1_000, &status(401)).is_none());
+        assert!(engine.check_rules(&rules, 1_001, &status(500)).is_none());
+        assert!(engine.check_rules(&rules, 1_002, &status(429)).is_none());
+        let triggered = engine
+            .check_rules(&rules, 1_003, &status(401))
+            .expect("third matching response should trigger");
+        assert_eq!(triggered.metrics_snapshot.status_matches, Some(3));
+        assert_eq!(triggered.metrics_snapshot.requests, 4);
+
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "client_errors",
+            BanCondition::StatusCode {
+                window_secs: 60,
+                statuses: vec!["4xx".to_string()],
+                max_count: 2,
+            },
+        )];
+        assert!(engine.check_rules(&rules, 1_000, &status(404)).is_none());
+        assert!(engine.check_rules(&rules, 1_001, &status(403)).is_some());
+    }
+
+    #[test]
+    fn test_latency_rule_uses_p95() {
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "slow",
+            BanCondition::Latency {
+                window_secs: 60,
+                p95_threshold_ms: 5_000,
+                min_requests: 20,
+            },
+        )];
+        let latency = |latency_ms: u64| RequestResult {
+            success: true,
+            latency_ms,
+            response_status: 200,
+            tokens: 0,
+            client_ip: None,
+        };
+
+        // 20 个请求中 1 个慢请求，P95 仍为快请求
+        for i in 0..19 {
+            assert!(engine.check_rules(&rules, 1_000 + i, &latency(100)).is_none());
+        }
+        assert!(engine.check_rules(&rules, 1_019, &latency(9_000)).is_none());
+        // 第 2 个慢请求使 P95 超过阈值
+        let triggered = engine
+            .check_rules(&rules, 1_020, &latency(8_000))
+            .expect("p95 above threshold should trigger");
+        assert_eq!(triggered.metrics_snapshot.p95_latency_ms, Some(8_000));
+        assert!(triggered.reason.contains("P95 latency"));
+    }
+
+    #[test]
+    fn test_token_usage_rule() {
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "token_burn",
+            BanCondition::TokenUsage {
+                window_secs: 60,
+                max_tokens: 10_000,
+            },
+        )];
+        let tokens = |tokens: u64| RequestResult {
+            success: true,
+            latency_ms: 10,
+            response_status: 200,
+            tokens,
+            client_ip: None,
+        };
+
+        assert!(engine.check_rules(&rules, 1_000, &tokens(6_000)).is_none());
+        // 超出窗口的用量不计入
+        assert!(engine.check_rules(&rules, 1_070, &tokens(6_000)).is_none());
+        let triggered = engine
+            .check_rules(&rules, 1_080, &tokens(4_000))
+            .expect("token usage above limit should trigger");
+        assert_eq!(triggered.metrics_snapshot.tokens, Some(10_000));
+    }
+
+    #[test]
+    fn test_spike_rule_compares_against_own_baseline() {
+        let mut engine = BanRuleEngine::new(660);
+        let rules = vec![rule(
+            "spike",
+            BanCondition::Spike {
+                metric: SpikeMetric::Requests,
+                window_secs: 60,
+                baseline_secs: 600,
+                multiplier: 5.0,
+                min_value: 10,
+            },
+        )];
+
+        // 没有基线时不触发
+        for i in 0..20 {
+            assert!(engine.check_rules(&rules, 1_000 + i, &result(true)).is_none());
+        }
+
+        // 基线：600 秒内 20 个请求，折算每 60 秒 2 个
+        let now = 1_600;
+        for i in 0..9 {
+            assert!(engine.check_rules(&rules, now + i, &result(true)).is_none());
+        }
+        let triggered = engine
+            .check_rules(&rules, now + 9, &result(true))
+            .expect("5x the baseline should trigger");
+        assert_eq!(triggered.metrics_snapshot.requests, 10);
+        assert_eq!(triggered.metrics_snapshot.baseline, Some(2.0));
+        assert!(triggered.reason.contains("baseline"));
+    }
+
+    #[test]
+    fn test_key_sharing_rule_counts_distinct_ips_and_subnets() {
+        let from = |ip: &str| RequestResult {
+            client_ip: Some(ip.to_string()),
+            ..result(true)
+        };
+
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "sharing_ips",
+            BanCondition::KeySharing {
+                window_secs: 60,
+                max_ips: Some(2),
+                max_subnets: None,
+            },
+        )];
+        assert!(engine.check_rules(&rules, 1_000, &from("10.0.0.1")).is_none());
+        assert!(engine.check_rules(&rules, 1_001, &from("10.0.0.2")).is_none());
+        // 重复 IP 不增加计数
+        assert!(engine.check_rules(&rules, 1_002, &from("10.0.0.1")).is_none());
+        let triggered = engine
+            .check_rules(&rules, 1_003, &from("10.0.0.3"))
+            .expect("third distinct ip should trigger");
+        assert_eq!(triggered.action, BanAction::Ban);
+        assert_eq!(triggered.metrics_snapshot.distinct_ips, Some(3));
+        assert_eq!(triggered.metrics_snapshot.distinct_subnets, Some(1));
+        assert_eq!(
+            triggered.metrics_snapshot.client_ips,
+            Some(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string(), "10.0.0.3".to_string()])
+        );
+
+        let mut engine = BanRuleEngine::new(300);
+        let mut sharing = rule(
+            "sharing_subnets",
+            BanCondition::KeySharing {
+                window_secs: 60,
+                max_ips: None,
+                max_subnets: Some(1),
+            },
+        );
+        sharing.action = BanAction::Alert;
+        let rules = vec![sharing];
+        // 同一 /24 内的多个 IP 视为一个网段
+        for (i, ip) in ["192.0.2.1", "192.0.2.2", "192.0.2.3"].into_iter().enumerate() {
+            assert!(engine.check_rules(&rules, 1_000 + i as u64, &from(ip)).is_none());
+        }
+        let triggered = engine
+            .check_rules(&rules, 1_010, &from("198.51.100.7"))
+            .expect("second subnet should trigger");
+        assert_eq!(triggered.action, BanAction::Alert);
+        assert_eq!(triggered.metrics_snapshot.distinct_subnets, Some(2));
+        assert!(triggered.reason.contains("subnets"));
+
+        assert_eq!(subnet_of("2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::/64");
+    }
+
+    #[test]
+    fn test_ban_targets() {
+        assert_eq!(ban_target(BanSubject::Ip, "10.0.0.7"), "10.0.0.7");
+        assert_eq!(ban_target(BanSubject::Subnet, "10.0.0.7"), "10.0.0.0/24");
+        assert_eq!(ban_target(BanSubject::Ip, "2001:DB8::1"), "2001:db8::1");
+        assert_eq!(parse_ban_target(" 10.0.0.7 "), Ok((BanSubject::Ip, "10.0.0.7".to_string())));
+        assert_eq!(parse_ban_target("10.0.0.9/24"), Ok((BanSubject::Subnet, "10.0.0.0/24".to_string())));
+        assert_eq!(
+            parse_ban_target("2001:db8:1:2::/64"),
+            Ok((BanSubject::Subnet, "2001:db8:1:2::/64".to_string()))
+        );
+        let err = parse_ban_target("10.0.0.0/16").unwrap_err();
+        assert!(err.contains("/24 (IPv4) and /64 (IPv6)"), "{err}");
+        assert!(parse_ban_target("2001:db8::/48").is_err());
+        assert!(parse_ban_target("not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_composite_conditions() {
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "busy_and_failing",
+            BanCondition::All {
+                conditions: vec![
+                    BanCondition::RequestCount { window_secs: 60, max_requests: 5 },
+                    BanCondition::ErrorRate { window_secs: 60, threshold: 0.5, min_requests: 1 },
+                ],
+            },
+        )];
+        // 请求数达标但错误率不足时不触发
+        for i in 0..4 {
+            assert!(engine.check_rules(&rules, 1_000 + i, &result(true)).is_none());
+        }
+        for i in 4..7 {
+            assert!(engine.check_rules(&rules, 1_000 + i, &result(false)).is_none());
+        }
+        let triggered = engine
+            .check_rules(&rules, 1_007, &result(false))
+            .expect("both conditions should hold");
+        assert!(triggered.reason.contains(" AND "), "{}", triggered.reason);
+        assert_eq!(triggered.metrics_snapshot.requests, 8);
+
+        let mut engine = BanRuleEngine::new(300);
+        let rules = vec![rule(
+            "errors_or_tokens",
+            BanCondition::Any {
+                conditions: vec![
+                    BanCondition::ConsecutiveErrors { count: 3 },
+                    BanCondition::TokenUsage { window_secs: 60, max_tokens: 1_000 },
+                ],
+            },
+        )];
+        let heavy = RequestResult {
+            tokens: 1_500,
+            ..result(true)
+        };
+        let triggered = engine
+            .check_rules(&rules, 1_000, &heavy)
+            .expect("either condition should trigger");
+        assert!(triggered.reason.contains("Token usage"));
+        assert_eq!(triggered.metrics_snapshot.tokens, Some(1_500));
+    }
+
+    #[test]
+    fn test_shadow_rule_does_not_block_enforced_rules() {
+        let mut engine = BanRuleEngine::new(300);
+        let mut shadow = rule("shadow", BanCondition::RequestCount { window_secs: 60, max_requests: 2 });
+        shadow.mode = BanRuleMode::Shadow;
+        let enforced = rule("enforced", BanCondition::RequestCount { window_secs: 60, max_requests: 4 });
+        let rules = vec![shadow, enforced];
+
+        assert!(engine.check_rules(&rules, 1_000, &result(true)).is_none());
+        assert!(engine.check_rules(&rules, 1_001, &result(true)).is_none());
+        let shadowed = engine.take_shadow_triggers();
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].rule_id, "shadow");
+        engine.suppress_shadow_rule("shadow", Some(1_600));
+
+        // 模拟封禁期间影子规则不再触发，其余规则照常生效
+        assert!(engine.check_rules(&rules, 1_002, &result(true)).is_none());
+        let triggered = engine
+            .check_rules(&rules, 1_003, &result(true))
+            .expect("enforced rule should still trigger");
+        assert_eq!(triggered.rule_id, "enforced");
+        assert!(engine.take_shadow_triggers().is_empty());
+
+        // 模拟封禁结束后可再次触发
+        for i in 0..2 {
+            engine.check_rules(&rules, 1_600 + i, &result(true));
+        }
+        assert_eq!(engine.take_shadow_triggers().len(), 1);
+    }
+
+    #[test]
+    fn test_escalation_multiplies_duration_and_decays() {
+        let mut escalating = rule("escalating", BanCondition::ConsecutiveErrors { count: 3 });
+        escalating.escalation = Some(BanEscalation {
+            multiplier: 2.0,
+            max_duration_secs: Some(3_000),
+            permanent_after: Some(5),
+            decay_secs: 86_400,
+        });
+        let prior = |ban_count: u32| BanStatus {
+            banned_at: Some(400),
+            banned_until: Some(1_000),
+            ban_count,
+            ..Default::default()
+        };
+
+        assert_eq!(escalate(&escalating, None, 2_000), (1, Some(600)));
+        assert_eq!(escalate(&escalating, Some(&prior(1)), 2_000), (2, Some(1_200)));
+        assert_eq!(escalate(&escalating, Some(&prior(2)), 2_000), (3, Some(2_400)));
+        // 达到上限后不再增长
+        assert_eq!(escalate(&escalating, Some(&prior(3)), 2_000), (4, Some(3_000)));
+        // 第 5 次起永久封禁
+        assert_eq!(escalate(&escalating, Some(&prior(4)), 2_000), (5, None));
+        // 静默期后重新计次
+        assert_eq!(escalate(&escalating, Some(&prior(4)), 1_000 + 86_400), (1, Some(600)));
+
+        // 未配置升级策略时时长固定，次数照常累计
+        let fixed = rule("fixed", BanCondition::ConsecutiveErrors { count: 3 });
+        assert_eq!(escalate(&fixed, Some(&prior(4)), 2_000), (5, Some(600)));
+    }
 }
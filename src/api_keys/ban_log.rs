@@ -1,4 +1,4 @@
-use crate::api_keys::ban::BanMetricsSnapshot;
+use crate::api_keys::ban::{BanMetricsSnapshot, BanStatus, BanSubject};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePoolOptions;
@@ -11,8 +11,11 @@ use thiserror::Error;
 pub struct BanLogEntry {
     /// 日志ID
     pub id: String,
-    /// API Key ID
+    /// API Key ID（`subject` 为 ip / subnet 时为客户端 IP 或网段）
     pub api_key_id: String,
+    /// 封禁对象类型
+    #[serde(default)]
+    pub subject: BanSubject,
     /// 触发封禁的规则ID
     pub rule_id: String,
     /// 封禁原因
@@ -26,6 +29,52 @@ pub struct BanLogEntry {
     pub unbanned_at: Option<u64>,
     /// 触发时的指标快照
     pub metrics_snapshot: BanMetricsSnapshot,
+    /// 该 Key 的第几次封禁（升级策略静默期后重新计次）
+    #[serde(default)]
+    pub offense: u32,
+    /// 是否为永久封禁（此时 `banned_until` 等于 `banned_at`）
+    #[serde(default)]
+    pub permanent: bool,
+    /// 影子模式规则记录的模拟封禁（未实际执行）
+    #[serde(default)]
+    pub shadow: bool,
+    /// `action: alert` 规则的触发记录（未封禁，`banned_until` 等于 `banned_at`）
+    #[serde(default)]
+    pub alert: bool,
+}
+
+/// 影子规则的模拟封禁统计
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShadowBanCount {
+    pub rule_id: String,
+    /// 模拟封禁次数
+    pub count: u64,
+    /// 涉及的不同封禁对象数（Key、IP 或网段）
+    pub distinct_keys: u64,
+    /// 最近一次模拟封禁时间（Unix秒）
+    pub last_banned_at: u64,
+}
+
+impl BanLogEntry {
+    /// 还原为 Key 的封禁状态
+    pub fn to_ban_status(&self, now: u64) -> BanStatus {
+        let is_banned = self.unbanned_at.is_none() && (self.permanent || now < self.banned_until);
+        let banned_until = match (self.unbanned_at, self.permanent) {
+            (Some(unbanned_at), true) => Some(unbanned_at),
+            (Some(unbanned_at), false) => Some(unbanned_at.min(self.banned_until)),
+            (None, true) => None,
+            (None, false) => Some(self.banned_until),
+        };
+        BanStatus {
+            is_banned,
+            banned_at: Some(self.banned_at),
+            banned_until,
+            triggered_rule_id: (self.rule_id != "manual").then(|| self.rule_id.clone()),
+            reason: Some(self.reason.clone()),
+            ban_count: self.offense,
+            permanent: is_banned && self.permanent,
+        }
+    }
 }
 
 /// 封禁日志错误
@@ -48,7 +97,7 @@ pub trait BanLogStore: Send + Sync {
     /// 更新解封时间
     async fn mark_unbanned(&self, entry_id: &str, unbanned_at: u64) -> Result<(), BanLogError>;
 
-    /// 查询 API Key 的封禁历史
+    /// 查询 API Key 的封禁历史（不含 IP 与网段封禁）
     async fn query_by_api_key(
         &self,
         api_key_id: &str,
@@ -56,23 +105,41 @@ pub trait BanLogStore: Send + Sync {
         offset: usize,
     ) -> Result<Vec<BanLogEntry>, BanLogError>;
 
-    /// 查询最近的封禁日志（所有 API Keys）
+    /// 查询最近的封禁日志（所有封禁对象）
     async fn query_recent(
         &self,
         limit: usize,
         offset: usize,
     ) -> Result<Vec<BanLogEntry>, BanLogError>;
 
-    /// 查询活跃的封禁（未解封且未过期）
+    /// 查询活跃的封禁（未解封且未过期，不含影子与告警记录）
     async fn query_active_bans(
         &self,
         before: u64,
     ) -> Result<Vec<BanLogEntry>, BanLogError>;
 
+    /// 查询指定类型的每个封禁对象最近一次的封禁记录（不含影子与告警记录）
+    async fn query_latest_per_subject(&self, subject: BanSubject) -> Result<Vec<BanLogEntry>, BanLogError>;
+
+    /// 按规则统计 `since` 之后的影子模拟封禁
+    async fn count_shadow_bans(&self, since: u64) -> Result<Vec<ShadowBanCount>, BanLogError>;
+
+    /// 查询最近的告警记录（最新的在前）
+    async fn query_recent_alerts(&self, limit: usize) -> Result<Vec<BanLogEntry>, BanLogError>;
+
     /// 清理过期日志
     async fn cleanup_old_entries(&self, before: u64) -> Result<u64, BanLogError>;
 }
 
+fn subject_from_db(value: &str) -> Result<BanSubject, BanLogError> {
+    match value {
+        "key" => Ok(BanSubject::Key),
+        "ip" => Ok(BanSubject::Ip),
+        "subnet" => Ok(BanSubject::Subnet),
+        other => Err(BanLogError::InvalidData(format!("unknown ban subject: {other}"))),
+    }
+}
+
 /// SQLite 封禁日志存储实现
 pub struct SqliteBanLogStore {
     pool: Pool<Sqlite>,
@@ -148,6 +215,18 @@ impl SqliteBanLogStore {
                 metrics_requests INTEGER NOT NULL,
                 metrics_errors INTEGER NOT NULL,
                 metrics_error_rate REAL NOT NULL,
+                metrics_status_matches INTEGER,
+                metrics_p95_latency_ms INTEGER,
+                metrics_tokens INTEGER,
+                metrics_baseline REAL,
+                metrics_distinct_ips INTEGER,
+                metrics_distinct_subnets INTEGER,
+                metrics_client_ips TEXT,
+                offense INTEGER NOT NULL DEFAULT 1,
+                permanent INTEGER NOT NULL DEFAULT 0,
+                shadow INTEGER NOT NULL DEFAULT 0,
+                subject TEXT NOT NULL DEFAULT 'key',
+                alert INTEGER NOT NULL DEFAULT 0,
                 created_at INTEGER NOT NULL DEFAULT (unixepoch())
             )
             "#,
@@ -155,6 +234,31 @@ impl SqliteBanLogStore {
         .execute(&self.pool)
         .await?;
 
+        // 迁移：补齐旧版本缺少的指标快照与升级记录字段
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('ban_logs')")
+            .fetch_all(&self.pool)
+            .await?;
+        for (column, definition) in [
+            ("metrics_status_matches", "INTEGER"),
+            ("metrics_p95_latency_ms", "INTEGER"),
+            ("metrics_tokens", "INTEGER"),
+            ("metrics_baseline", "REAL"),
+            ("metrics_distinct_ips", "INTEGER"),
+            ("metrics_distinct_subnets", "INTEGER"),
+            ("metrics_client_ips", "TEXT"),
+            ("offense", "INTEGER NOT NULL DEFAULT 1"),
+            ("permanent", "INTEGER NOT NULL DEFAULT 0"),
+            ("shadow", "INTEGER NOT NULL DEFAULT 0"),
+            ("subject", "TEXT NOT NULL DEFAULT 'key'"),
+            ("alert", "INTEGER NOT NULL DEFAULT 0"),
+        ] {
+            if !columns.iter().any(|name| name == column) {
+                sqlx::query(&format!("ALTER TABLE ban_logs ADD COLUMN {column} {definition}"))
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
         // 创建索引
         sqlx::query(
             r#"
@@ -180,6 +284,23 @@ impl SqliteBanLogStore {
         .execute(&self.pool)
         .await?;
 
+        // 迁移：旧版本自动封禁记录的 ID 内嵌原始 Key，改写为 `ban_{api_key_id}_{banned_at}`
+        let migrated = sqlx::query(
+            r#"
+            UPDATE OR IGNORE ban_logs
+            SET id = 'ban_' || api_key_id || '_' || banned_at
+            WHERE id != 'ban_' || api_key_id || '_' || banned_at
+              AND id != 'ban_manual_' || api_key_id || '_' || banned_at
+              AND shadow = 0 AND alert = 0 AND subject = 'key'
+            "#,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if migrated > 0 {
+            tracing::info!("Rewrote {} legacy ban log ids", migrated);
+        }
+
         Ok(())
     }
 
@@ -204,6 +325,7 @@ impl SqliteBanLogStore {
         Ok(BanLogEntry {
             id: row.try_get("id")?,
             api_key_id: row.try_get("api_key_id")?,
+            subject: subject_from_db(&row.try_get::<String, _>("subject")?)?,
             rule_id: row.try_get("rule_id")?,
             reason: row.try_get("reason")?,
             banned_at: row.try_get::<i64, _>("banned_at")? as u64,
@@ -213,7 +335,22 @@ impl SqliteBanLogStore {
                 requests: row.try_get::<i64, _>("metrics_requests")? as u64,
                 errors: row.try_get::<i64, _>("metrics_errors")? as u64,
                 error_rate: row.try_get("metrics_error_rate")?,
+                status_matches: row.try_get::<Option<i64>, _>("metrics_status_matches")?.map(|v| v as u64),
+                p95_latency_ms: row.try_get::<Option<i64>, _>("metrics_p95_latency_ms")?.map(|v| v as u64),
+                tokens: row.try_get::<Option<i64>, _>("metrics_tokens")?.map(|v| v as u64),
+                baseline: row.try_get("metrics_baseline")?,
+                distinct_ips: row.try_get::<Option<i64>, _>("metrics_distinct_ips")?.map(|v| v as u64),
+                distinct_subnets: row.try_get::<Option<i64>, _>("metrics_distinct_subnets")?.map(|v| v as u64),
+                client_ips: row
+                    .try_get::<Option<String>, _>("metrics_client_ips")?
+                    .map(|ips| serde_json::from_str(&ips))
+                    .transpose()
+                    .map_err(|e| BanLogError::InvalidData(format!("metrics_client_ips: {e}")))?,
             },
+            offense: row.try_get::<i64, _>("offense")? as u32,
+            permanent: row.try_get("permanent")?,
+            shadow: row.try_get("shadow")?,
+            alert: row.try_get("alert")?,
         })
     }
 }
@@ -223,13 +360,17 @@ impl BanLogStore for SqliteBanLogStore {
     async fn insert(&self, entry: BanLogEntry) -> Result<(), BanLogError> {
         let (id, api_key_id, rule_id, reason, banned_at, banned_until, unbanned_at,
              metrics_requests, metrics_errors, metrics_error_rate) = Self::entry_to_row(&entry);
+        let snapshot = &entry.metrics_snapshot;
 
         sqlx::query(
             r#"
             INSERT INTO ban_logs (
                 id, api_key_id, rule_id, reason, banned_at, banned_until, unbanned_at,
-                metrics_requests, metrics_errors, metrics_error_rate
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                metrics_requests, metrics_errors, metrics_error_rate,
+                metrics_status_matches, metrics_p95_latency_ms, metrics_tokens, metrics_baseline,
+                metrics_distinct_ips, metrics_distinct_subnets, metrics_client_ips,
+                offense, permanent, shadow, subject, alert
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(id)
@@ -242,6 +383,18 @@ impl BanLogStore for SqliteBanLogStore {
         .bind(metrics_requests)
         .bind(metrics_errors)
         .bind(metrics_error_rate)
+        .bind(snapshot.status_matches.map(|v| v as i64))
+        .bind(snapshot.p95_latency_ms.map(|v| v as i64))
+        .bind(snapshot.tokens.map(|v| v as i64))
+        .bind(snapshot.baseline)
+        .bind(snapshot.distinct_ips.map(|v| v as i64))
+        .bind(snapshot.distinct_subnets.map(|v| v as i64))
+        .bind(snapshot.client_ips.as_ref().map(|ips| serde_json::json!(ips).to_string()))
+        .bind(entry.offense as i64)
+        .bind(entry.permanent)
+        .bind(entry.shadow)
+        .bind(entry.subject.as_str())
+        .bind(entry.alert)
         .execute(&self.pool)
         .await?;
 
@@ -275,7 +428,7 @@ impl BanLogStore for SqliteBanLogStore {
         let rows = sqlx::query(
             r#"
             SELECT * FROM ban_logs
-            WHERE api_key_id = ?
+            WHERE api_key_id = ? AND subject = 'key'
             ORDER BY banned_at DESC
             LIMIT ? OFFSET ?
             "#,
@@ -320,7 +473,7 @@ impl BanLogStore for SqliteBanLogStore {
         let rows = sqlx::query(
             r#"
             SELECT * FROM ban_logs
-            WHERE unbanned_at IS NULL AND banned_until > ?
+            WHERE unbanned_at IS NULL AND shadow = 0 AND alert = 0 AND (permanent = 1 OR banned_until > ?)
             ORDER BY banned_at DESC
             "#,
         )
@@ -333,6 +486,76 @@ impl BanLogStore for SqliteBanLogStore {
             .collect::<Result<Vec<_>, _>>()
     }
 
+    async fn query_latest_per_subject(&self, subject: BanSubject) -> Result<Vec<BanLogEntry>, BanLogError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM ban_logs AS b
+            WHERE shadow = 0 AND alert = 0 AND subject = ?
+              AND banned_at = (
+                  SELECT MAX(banned_at) FROM ban_logs
+                  WHERE api_key_id = b.api_key_id AND subject = b.subject AND shadow = 0 AND alert = 0
+              )
+            ORDER BY api_key_id, id
+            "#,
+        )
+        .bind(subject.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = rows
+            .iter()
+            .map(Self::row_to_entry)
+            .collect::<Result<Vec<_>, _>>()?;
+        // 同一秒内有多条记录时只保留一条
+        entries.dedup_by(|a, b| a.api_key_id == b.api_key_id);
+        Ok(entries)
+    }
+
+    async fn count_shadow_bans(&self, since: u64) -> Result<Vec<ShadowBanCount>, BanLogError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT rule_id, COUNT(*) AS count, COUNT(DISTINCT api_key_id) AS distinct_keys,
+                   MAX(banned_at) AS last_banned_at
+            FROM ban_logs
+            WHERE shadow = 1 AND banned_at >= ?
+            GROUP BY rule_id
+            ORDER BY rule_id
+            "#,
+        )
+        .bind(since as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(ShadowBanCount {
+                    rule_id: row.try_get("rule_id")?,
+                    count: row.try_get::<i64, _>("count")? as u64,
+                    distinct_keys: row.try_get::<i64, _>("distinct_keys")? as u64,
+                    last_banned_at: row.try_get::<i64, _>("last_banned_at")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn query_recent_alerts(&self, limit: usize) -> Result<Vec<BanLogEntry>, BanLogError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM ban_logs
+            WHERE alert = 1
+            ORDER BY banned_at DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(Self::row_to_entry)
+            .collect::<Result<Vec<_>, _>>()
+    }
+
     async fn cleanup_old_entries(&self, before: u64) -> Result<u64, BanLogError> {
         let result = sqlx::query(
             r#"
@@ -387,7 +610,7 @@ impl BanLogStore for InMemoryBanLogStore {
         let entries = self.entries.lock().unwrap();
         let filtered: Vec<_> = entries
             .iter()
-            .filter(|e| e.api_key_id == api_key_id)
+            .filter(|e| e.api_key_id == api_key_id && e.subject == BanSubject::Key)
             .cloned()
             .collect();
 
@@ -413,12 +636,53 @@ impl BanLogStore for InMemoryBanLogStore {
         let entries = self.entries.lock().unwrap();
         let filtered: Vec<_> = entries
             .iter()
-            .filter(|e| e.unbanned_at.is_none() && e.banned_until > before)
+            .filter(|e| e.unbanned_at.is_none() && !e.shadow && !e.alert && (e.permanent || e.banned_until > before))
             .cloned()
             .collect();
         Ok(filtered)
     }
 
+    async fn query_latest_per_subject(&self, subject: BanSubject) -> Result<Vec<BanLogEntry>, BanLogError> {
+        let entries = self.entries.lock().unwrap();
+        let mut latest: std::collections::HashMap<&str, &BanLogEntry> = std::collections::HashMap::new();
+        for entry in entries.iter().filter(|e| !e.shadow && !e.alert && e.subject == subject) {
+            let current = latest.entry(entry.api_key_id.as_str()).or_insert(entry);
+            if entry.banned_at > current.banned_at {
+                *current = entry;
+            }
+        }
+        Ok(latest.into_values().cloned().collect())
+    }
+
+    async fn count_shadow_bans(&self, since: u64) -> Result<Vec<ShadowBanCount>, BanLogError> {
+        let entries = self.entries.lock().unwrap();
+        let mut by_rule: std::collections::BTreeMap<&str, Vec<&BanLogEntry>> = std::collections::BTreeMap::new();
+        for entry in entries.iter().filter(|e| e.shadow && e.banned_at >= since) {
+            by_rule.entry(entry.rule_id.as_str()).or_default().push(entry);
+        }
+        Ok(by_rule
+            .into_iter()
+            .map(|(rule_id, entries)| ShadowBanCount {
+                rule_id: rule_id.to_string(),
+                count: entries.len() as u64,
+                distinct_keys: entries
+                    .iter()
+                    .map(|e| e.api_key_id.as_str())
+                    .collect::<std::collections::HashSet<_>>()
+                    .len() as u64,
+                last_banned_at: entries.iter().map(|e| e.banned_at).max().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn query_recent_alerts(&self, limit: usize) -> Result<Vec<BanLogEntry>, BanLogError> {
+        let entries = self.entries.lock().unwrap();
+        let mut alerts: Vec<_> = entries.iter().filter(|e| e.alert).cloned().collect();
+        alerts.sort_by_key(|e| std::cmp::Reverse(e.banned_at));
+        alerts.truncate(limit);
+        Ok(alerts)
+    }
+
     async fn cleanup_old_entries(&self, before: u64) -> Result<u64, BanLogError> {
         let mut entries = self.entries.lock().unwrap();
         let original_len = entries.len();
@@ -435,6 +699,7 @@ mod tests {
         BanLogEntry {
             id: id.to_string(),
             api_key_id: api_key_id.to_string(),
+            subject: BanSubject::Key,
             rule_id: "rule_001".to_string(),
             reason: "Test ban".to_string(),
             banned_at: 1000,
@@ -444,7 +709,12 @@ mod tests {
                 requests: 100,
                 errors: 50,
                 error_rate: 0.5,
+                ..Default::default()
             },
+            offense: 1,
+            permanent: false,
+            shadow: false,
+            alert: false,
         }
     }
 
@@ -474,4 +744,127 @@ mod tests {
         let deleted = store.cleanup_old_entries(1500).await.unwrap();
         assert_eq!(deleted, 1);
     }
+
+    #[tokio::test]
+    async fn test_sqlite_store_rewrites_legacy_ids() {
+        let dir = std::env::temp_dir().join(format!(
+            "ai-gw-lite-ban-log-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("time should move forward")
+                .as_nanos()
+        ));
+        let db_path = dir.join("ban_logs.db");
+        let db_path = db_path.to_str().unwrap();
+
+        let store = SqliteBanLogStore::new(db_path).await.unwrap();
+        // 旧版本的自动封禁 ID 内嵌原始 Key
+        store
+            .insert(create_test_entry("ban_sk-gw-raw-secret_1000", "ak_001"))
+            .await
+            .unwrap();
+        store
+            .insert(create_test_entry("ban_manual_ak_002_1000", "ak_002"))
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = SqliteBanLogStore::new(db_path).await.unwrap();
+        let results = store.query_by_api_key("ak_001", 10, 0).await.unwrap();
+        assert_eq!(results[0].id, "ban_ak_001_1000");
+        let results = store.query_by_api_key("ak_002", 10, 0).await.unwrap();
+        assert_eq!(results[0].id, "ban_manual_ak_002_1000");
+
+        // 新增的快照字段可写入并读回
+        let mut entry = create_test_entry("ban_ak_003_1000", "ak_003");
+        entry.metrics_snapshot.tokens = Some(120_000);
+        entry.metrics_snapshot.baseline = Some(2.5);
+        entry.metrics_snapshot.client_ips = Some(vec!["10.0.0.1".to_string(), "10.0.1.1".to_string()]);
+        store.insert(entry).await.unwrap();
+        let results = store.query_by_api_key("ak_003", 10, 0).await.unwrap();
+        assert_eq!(results[0].metrics_snapshot.tokens, Some(120_000));
+        assert_eq!(results[0].metrics_snapshot.baseline, Some(2.5));
+        assert_eq!(results[0].metrics_snapshot.p95_latency_ms, None);
+        assert_eq!(
+            results[0].metrics_snapshot.client_ips.as_deref(),
+            Some(&["10.0.0.1".to_string(), "10.0.1.1".to_string()][..])
+        );
+
+        // 升级记录：每个 Key 只返回最近一次封禁，可还原为封禁状态
+        let mut entry = create_test_entry("ban_ak_003_3000", "ak_003");
+        entry.banned_at = 3000;
+        entry.banned_until = 3000;
+        entry.offense = 4;
+        entry.permanent = true;
+        store.insert(entry).await.unwrap();
+        let latest = store.query_latest_per_subject(BanSubject::Key).await.unwrap();
+        assert_eq!(latest.len(), 3);
+        let latest = latest.iter().find(|e| e.api_key_id == "ak_003").unwrap();
+        assert_eq!((latest.offense, latest.permanent), (4, true));
+        let status = latest.to_ban_status(10_000);
+        assert!(status.is_banned && status.permanent);
+        assert_eq!((status.banned_until, status.ban_count), (None, 4));
+        assert_eq!(store.query_active_bans(10_000).await.unwrap().len(), 1);
+
+        store.mark_unbanned("ban_ak_003_3000", 5000).await.unwrap();
+        let latest = store.query_latest_per_subject(BanSubject::Key).await.unwrap();
+        let status = latest.iter().find(|e| e.api_key_id == "ak_003").unwrap().to_ban_status(10_000);
+        assert!(!status.is_banned && !status.permanent);
+        assert_eq!(status.banned_until, Some(5000));
+
+        // 影子记录保留原 ID，不参与封禁状态恢复，按规则统计
+        let mut entry = create_test_entry("ban_shadow_ak_004_rule_001_4000", "ak_004");
+        entry.banned_at = 4000;
+        entry.banned_until = 4600;
+        entry.shadow = true;
+        store.insert(entry).await.unwrap();
+        drop(store);
+        let store = SqliteBanLogStore::new(db_path).await.unwrap();
+        let results = store.query_by_api_key("ak_004", 10, 0).await.unwrap();
+        assert_eq!(results[0].id, "ban_shadow_ak_004_rule_001_4000");
+        assert!(results[0].shadow);
+        assert_eq!(store.query_latest_per_subject(BanSubject::Key).await.unwrap().len(), 3);
+        assert!(store.query_active_bans(4100).await.unwrap().iter().all(|e| !e.shadow));
+        assert_eq!(
+            store.count_shadow_bans(3500).await.unwrap(),
+            vec![ShadowBanCount {
+                rule_id: "rule_001".to_string(),
+                count: 1,
+                distinct_keys: 1,
+                last_banned_at: 4000,
+            }]
+        );
+        assert!(store.count_shadow_bans(4001).await.unwrap().is_empty());
+
+        // IP 封禁与 Key 封禁分开恢复，不出现在 Key 的封禁历史中
+        let mut entry = create_test_entry("ban_ip_10.0.0.1_5000", "10.0.0.1");
+        entry.subject = BanSubject::Ip;
+        entry.banned_at = 5000;
+        entry.banned_until = 5600;
+        store.insert(entry).await.unwrap();
+        drop(store);
+        let store = SqliteBanLogStore::new(db_path).await.unwrap();
+        assert!(store.query_by_api_key("10.0.0.1", 10, 0).await.unwrap().is_empty());
+        assert_eq!(store.query_latest_per_subject(BanSubject::Key).await.unwrap().len(), 3);
+        let latest = store.query_latest_per_subject(BanSubject::Ip).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!((latest[0].id.as_str(), latest[0].subject), ("ban_ip_10.0.0.1_5000", BanSubject::Ip));
+
+        // 告警记录落库但不参与封禁状态恢复
+        let mut entry = create_test_entry("ban_alert_key_ak_005_rule_002_6000", "ak_005");
+        entry.banned_at = 6000;
+        entry.banned_until = 6000;
+        entry.alert = true;
+        store.insert(entry).await.unwrap();
+        drop(store);
+        let store = SqliteBanLogStore::new(db_path).await.unwrap();
+        assert_eq!(store.query_latest_per_subject(BanSubject::Key).await.unwrap().len(), 3);
+        assert!(store.query_active_bans(5900).await.unwrap().iter().all(|e| !e.alert));
+        let alerts = store.query_recent_alerts(10).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].alert && alerts[0].id == "ban_alert_key_ak_005_rule_002_6000");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
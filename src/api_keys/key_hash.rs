@@ -0,0 +1,136 @@
+//! API Key 哈希存储
+//!
+//! 配置与运行时只保存加盐哈希（`sha256$<salt>$<digest>`）与展示前缀，
+//! 校验时先按展示前缀定位候选 Key，再以常量时间比较摘要。
+
+use sha2::{Digest, Sha256};
+
+const HASH_SCHEME: &str = "sha256";
+/// 展示前缀中保留的密钥字符数上限
+const KEY_PREFIX_SECRET_CHARS: usize = 4;
+/// 旧版本展示前缀的最大字符数
+const LEGACY_KEY_PREFIX_MAX_CHARS: usize = 10;
+
+/// 生成新的 API Key：`sk-<scope>-<32 位随机十六进制>`（122 位随机数）
+pub fn generate_key(scope: &str) -> String {
+    format!("sk-{scope}-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// 计算 API Key 的加盐哈希
+pub fn hash_key(key: &str) -> String {
+    let salt = uuid::Uuid::now_v7().simple().to_string();
+    let digest = salted_digest(&salt, key);
+    format!("{HASH_SCHEME}${salt}${digest}")
+}
+
+/// 校验 API Key 是否与存储的哈希匹配（常量时间比较摘要）
+pub fn verify_key(key: &str, key_hash: &str) -> bool {
+    let Some((salt, digest)) = parse_key_hash(key_hash) else {
+        return false;
+    };
+    constant_time_eq(salted_digest(salt, key).as_bytes(), digest.as_bytes())
+}
+
+/// 检查哈希格式是否有效
+pub fn is_valid_key_hash(key_hash: &str) -> bool {
+    parse_key_hash(key_hash).is_some()
+}
+
+/// API Key 展示前缀：公开部分 `sk-<scope>-` 加至多 4 个密钥字符，且不超过密钥部分的四分之一
+///
+/// 前缀同时作为查找索引，请求携带的 Key 按同样规则计算前缀后定位候选。
+pub fn key_prefix(key: &str) -> String {
+    let (public, secret) = key.split_at(public_part_len(key));
+    let shown = KEY_PREFIX_SECRET_CHARS.min(secret.chars().count() / 4);
+    public.chars().chain(secret.chars().take(shown)).collect()
+}
+
+/// 定位候选 Key 的前缀：当前规则与旧规则（旧版本保存的前缀最多 10 个字符、不超过 Key 长度的一半）
+pub fn lookup_prefixes(key: &str) -> Vec<String> {
+    let current = key_prefix(key);
+    let len = key.chars().count();
+    let legacy: String = key.chars().take(LEGACY_KEY_PREFIX_MAX_CHARS.min(len / 2)).collect();
+    if legacy == current { vec![current] } else { vec![current, legacy] }
+}
+
+/// `sk-<scope>-` 部分的字节长度，不符合该格式时为 0
+fn public_part_len(key: &str) -> usize {
+    key.strip_prefix("sk-")
+        .and_then(|rest| rest.find('-'))
+        .map_or(0, |scope_len| "sk-".len() + scope_len + 1)
+}
+
+/// 展示用的 Key 文本，如 `sk-gw-ab12…`
+pub fn display_key(key_prefix: &str) -> String {
+    format!("{key_prefix}…")
+}
+
+fn salted_digest(salt: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn parse_key_hash(key_hash: &str) -> Option<(&str, &str)> {
+    let mut parts = key_hash.splitn(3, '$');
+    if parts.next()? != HASH_SCHEME {
+        return None;
+    }
+    let salt = parts.next()?;
+    let digest = parts.next()?;
+    let valid = !salt.is_empty()
+        && digest.len() == 64
+        && digest.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    valid.then_some((salt, digest))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_and_verify_key() {
+        let hash1 = hash_key("sk-gw-ab12cd34ef56gh78");
+        let hash2 = hash_key("sk-gw-ab12cd34ef56gh78");
+
+        // 每次哈希使用不同的盐
+        assert_ne!(hash1, hash2);
+        assert!(is_valid_key_hash(&hash1));
+        assert!(verify_key("sk-gw-ab12cd34ef56gh78", &hash1));
+        assert!(verify_key("sk-gw-ab12cd34ef56gh78", &hash2));
+        assert!(!verify_key("sk-gw-ab12cd34ef56gh79", &hash1));
+        assert!(!verify_key("sk-gw-ab12cd34ef56gh78", "sk-gw-ab12cd34ef56gh78"));
+        assert!(!is_valid_key_hash("sha256$salt$not-a-digest"));
+    }
+
+    #[test]
+    fn key_prefix_never_exposes_whole_key() {
+        assert_eq!(key_prefix("sk-gw-ab12cd34ef56gh78"), "sk-gw-ab12");
+        assert_eq!(key_prefix("sk-gw-ab12cd"), "sk-gw-a");
+        assert_eq!(key_prefix("gw_token"), "gw");
+        assert_eq!(key_prefix("custom-secret-value-1234"), "cust");
+        assert_eq!(key_prefix("x"), "");
+        assert_eq!(display_key("sk-gw-ab12"), "sk-gw-ab12…");
+
+        // 旧版本保存的前缀仍可定位
+        assert_eq!(lookup_prefixes("sk-gw-ab12cd34ef56gh78"), vec!["sk-gw-ab12"]);
+        assert_eq!(lookup_prefixes("gw_token"), vec!["gw", "gw_t"]);
+    }
+
+    #[test]
+    fn generate_key_is_random() {
+        let key1 = generate_key("gw");
+        let key2 = generate_key("gw");
+
+        assert_ne!(key1, key2);
+        assert!(key1.starts_with("sk-gw-"));
+        assert_eq!(key1.len(), "sk-gw-".len() + 32);
+        assert!(key_prefix(&key1).starts_with("sk-gw-"));
+        assert_eq!(key_prefix(&key1).len(), 10);
+    }
+}
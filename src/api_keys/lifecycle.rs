@@ -0,0 +1,100 @@
+//! API Key 生命周期
+//!
+//! 根据 `not_before`、`expires_at` 与闲置过期天数（相对最近使用时间）计算 Key 当前所处阶段。
+
+use crate::config::ResolvedApiKey;
+use serde::Serialize;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Key 生命周期阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyLifecycleState {
+    /// 未到生效时间
+    Pending,
+    /// 正常可用
+    Active,
+    /// 可用，但距过期不足预警窗口
+    ExpiringSoon,
+    /// 已过 `expires_at`
+    Expired,
+    /// 闲置超过 `idle_expiry_days`
+    IdleExpired,
+}
+
+/// 单个 Key 的生命周期状态
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyLifecycleStatus {
+    pub key_id: String,
+    pub state: KeyLifecycleState,
+    /// 实际过期时间（`expires_at` 与闲置过期时间中较早者）
+    pub expires_at: Option<u64>,
+    /// 最近使用时间（从未使用时为首次同步到配置库的时间）
+    pub last_used_at: Option<u64>,
+}
+
+/// 闲置过期时间：最近使用时间（未知时为 0，不判定闲置）加上闲置天数
+pub fn idle_expires_at(key: &ResolvedApiKey, last_used_at: u64) -> Option<u64> {
+    let days = key.idle_expiry_days?;
+    (last_used_at > 0).then(|| last_used_at.saturating_add(days.saturating_mul(SECS_PER_DAY)))
+}
+
+/// 实际过期时间：`expires_at` 与闲置过期时间中较早者
+pub fn effective_expires_at(key: &ResolvedApiKey, last_used_at: u64) -> Option<u64> {
+    match (key.expires_at, idle_expires_at(key, last_used_at)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// 计算 Key 在 `now` 时刻的生命周期阶段
+pub fn evaluate(key: &ResolvedApiKey, last_used_at: u64, now: u64, warning_secs: u64) -> KeyLifecycleState {
+    if key.not_before.is_some_and(|not_before| now < not_before) {
+        return KeyLifecycleState::Pending;
+    }
+    if key.expires_at.is_some_and(|expires_at| now >= expires_at) {
+        return KeyLifecycleState::Expired;
+    }
+    if idle_expires_at(key, last_used_at).is_some_and(|idle_at| now >= idle_at) {
+        return KeyLifecycleState::IdleExpired;
+    }
+    match effective_expires_at(key, last_used_at) {
+        Some(expires_at) if expires_at.saturating_sub(now) <= warning_secs => KeyLifecycleState::ExpiringSoon,
+        _ => KeyLifecycleState::Active,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(not_before: Option<u64>, expires_at: Option<u64>, idle_expiry_days: Option<u64>) -> ResolvedApiKey {
+        let mut key = ResolvedApiKey::from_key_string("sk-gw-lifecycle-test");
+        key.not_before = not_before;
+        key.expires_at = expires_at;
+        key.idle_expiry_days = idle_expiry_days;
+        key
+    }
+
+    #[test]
+    fn evaluate_lifecycle_states() {
+        let warning = 7 * SECS_PER_DAY;
+        let now = 100 * SECS_PER_DAY;
+
+        assert_eq!(evaluate(&key(None, None, None), 0, now, warning), KeyLifecycleState::Active);
+        assert_eq!(evaluate(&key(Some(now + 1), None, None), 0, now, warning), KeyLifecycleState::Pending);
+        assert_eq!(evaluate(&key(None, Some(now), None), 0, now, warning), KeyLifecycleState::Expired);
+        assert_eq!(
+            evaluate(&key(None, Some(now + SECS_PER_DAY), None), 0, now, warning),
+            KeyLifecycleState::ExpiringSoon
+        );
+
+        // 闲置过期以最近使用时间为基准，使用时间未知时不判定
+        let idle = key(None, None, Some(90));
+        assert_eq!(evaluate(&idle, 0, now, warning), KeyLifecycleState::Active);
+        assert_eq!(evaluate(&idle, now - 90 * SECS_PER_DAY, now, warning), KeyLifecycleState::IdleExpired);
+        assert_eq!(evaluate(&idle, now - 85 * SECS_PER_DAY, now, warning), KeyLifecycleState::ExpiringSoon);
+        assert_eq!(evaluate(&idle, now - SECS_PER_DAY, now, warning), KeyLifecycleState::Active);
+    }
+}
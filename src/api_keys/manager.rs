@@ -1,22 +1,35 @@
-use crate::api_keys::ban::{BanRuleEngine, BanStatus, BanMetricsSnapshot, BanRule};
+use crate::api_keys::ban::{self, BanAction, BanRuleEngine, BanStatus, BanSubject, BanMetricsSnapshot, BanRule, TriggeredRule};
 use crate::api_keys::ban_log::{BanLogEntry, BanLogStore};
 use crate::api_keys::current_epoch_seconds;
-use crate::config::ResolvedApiKey;
-use crate::ratelimit::{RateLimitDecision, RateLimiter};
+use crate::api_keys::key_hash;
+use crate::api_keys::lifecycle::{self, KeyLifecycleState, KeyLifecycleStatus};
+use crate::config::{RateLimitConfig, ResolvedApiKey};
+use crate::jwt::{DynamicIdentity, JwtIdentity};
+use serde::Serialize;
+use crate::ratelimit::{
+    RateLimitCheck, RateLimitDimension, RateLimiter, RouteRateLimiters, check_combined, release_combined,
+};
+use crate::state_backend::SharedState;
 use crate::token_quota::{TokenQuotaChecker, CheckQuotaResult};
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
 
 #[derive(Debug)]
 pub enum ApiKeyError {
     KeyNotFound,
     KeyDisabled,
-    KeyBanned { until: u64, reason: Option<String> },
+    KeyNotYetValid { not_before: u64 },
+    KeyExpired { expired_at: u64 },
+    /// 封禁中；`until` 为空表示永久封禁
+    KeyBanned { until: Option<u64>, reason: Option<String> },
     RouteNotAllowed,
-    RateLimitExceeded { retry_after_secs: u64 },
+    RateLimitExceeded { retry_after_secs: u64, dimension: RateLimitDimension },
     ConcurrencyLimitExceeded,
     TokenQuotaExceeded { quota_type: String, limit: u64, used: u64 },
+    /// JWT 校验失败
+    InvalidJwt(String),
 }
 
 impl std::fmt::Display for ApiKeyError {
@@ -24,21 +37,27 @@ impl std::fmt::Display for ApiKeyError {
         match self {
             ApiKeyError::KeyNotFound => write!(f, "API key not found"),
             ApiKeyError::KeyDisabled => write!(f, "API key is disabled"),
+            ApiKeyError::KeyNotYetValid { not_before } => write!(f, "API key is not valid before {}", not_before),
+            ApiKeyError::KeyExpired { expired_at } => write!(f, "API key expired at {}", expired_at),
             ApiKeyError::KeyBanned { until, reason } => {
-                write!(f, "API key is banned until {}", until)?;
+                match until {
+                    Some(until) => write!(f, "API key is banned until {}", until)?,
+                    None => write!(f, "API key is permanently banned")?,
+                }
                 if let Some(r) = reason {
                     write!(f, ": {}", r)?;
                 }
                 Ok(())
             }
             ApiKeyError::RouteNotAllowed => write!(f, "API key not allowed for this route"),
-            ApiKeyError::RateLimitExceeded { retry_after_secs } => {
-                write!(f, "Rate limit exceeded, retry after {} seconds", retry_after_secs)
+            ApiKeyError::RateLimitExceeded { retry_after_secs, dimension } => {
+                write!(f, "Rate limit exceeded ({}), retry after {} seconds", dimension, retry_after_secs)
             }
             ApiKeyError::ConcurrencyLimitExceeded => write!(f, "Concurrency limit exceeded"),
             ApiKeyError::TokenQuotaExceeded { quota_type, limit, used } => {
                 write!(f, "Token quota exceeded: {} limit {}/{} tokens", quota_type, used, limit)
             }
+            ApiKeyError::InvalidJwt(reason) => write!(f, "Invalid JWT: {}", reason),
         }
     }
 }
@@ -56,18 +75,161 @@ pub struct RequestResult {
     pub success: bool,
     pub latency_ms: u64,
     pub response_status: u16,
+    /// 本次请求消耗的 token（输入 + 输出）
+    pub tokens: u64,
+    /// 客户端 IP（与 IP 统计使用同一来源）
+    pub client_ip: Option<String>,
+}
+
+/// 最多保留的封禁规则告警条数
+const MAX_BAN_ALERTS: usize = 200;
+
+/// 最多跟踪的 IP / 网段计数器数，超出时淘汰闲置的计数器
+const MAX_IP_BAN_ENGINES: usize = 10_000;
+
+/// 最多保留的 IP / 网段封禁状态数，超出时清理已结束的封禁
+const MAX_IP_BAN_STATUSES: usize = 10_000;
+
+/// 最多缓存的 JWT 动态身份数，超出时淘汰最久未使用的身份
+const MAX_DYNAMIC_KEYS: usize = 10_000;
+
+/// JWT 动态身份闲置超过该时长（秒）后淘汰，封禁中的身份保留到封禁结束
+const DYNAMIC_KEY_IDLE_TTL_SECS: u64 = 3_600;
+
+/// `action: alert` 的封禁规则触发记录
+#[derive(Debug, Clone, Serialize)]
+pub struct BanAlert {
+    /// API Key ID（`subject` 为 ip / subnet 时为客户端 IP 或网段）
+    pub api_key_id: String,
+    pub subject: BanSubject,
+    pub rule_id: String,
+    pub reason: String,
+    pub triggered_at: u64,
+    pub metrics_snapshot: BanMetricsSnapshot,
+}
+
+/// IP 或网段的封禁
+#[derive(Debug, Clone, Serialize)]
+pub struct IpBan {
+    pub subject: BanSubject,
+    /// IP 或网段（如 `10.0.0.0/24`）
+    pub target: String,
+    pub status: BanStatus,
+}
+
+/// 封禁规则作用的对象
+struct BanTarget<'a> {
+    subject: BanSubject,
+    /// API Key ID、IP 或网段
+    id: &'a str,
+    /// 此前的封禁状态（用于升级策略计次）
+    prior: Option<&'a BanStatus>,
+}
+
+/// 封禁日志 ID：Key 为 `ban_[manual_]{key_id}_{banned_at}`，IP / 网段为 `ban_[manual_]{subject}_{target}_{banned_at}`
+fn ban_log_id(subject: BanSubject, id: &str, banned_at: u64, manual: bool) -> String {
+    let manual = if manual { "manual_" } else { "" };
+    match subject {
+        BanSubject::Key => format!("ban_{manual}{id}_{banned_at}"),
+        BanSubject::Ip | BanSubject::Subnet => format!("ban_{manual}{}_{id}_{banned_at}", subject.as_str()),
+    }
+}
+
+/// 封禁是否仍在生效（永久封禁或未到期）
+fn ban_in_effect(status: &BanStatus, now: u64) -> bool {
+    status.is_banned && (status.permanent || status.banned_until.is_some_and(|until| now < until))
+}
+
+/// 淘汰超过最大统计窗口未再出现的计数器；仍然满额时淘汰最久未出现的一个
+fn evict_idle_ip_engines(
+    engines: &mut HashMap<(BanSubject, String), (BanRuleEngine, u64)>,
+    now: u64,
+    max_window_secs: u64,
+) {
+    engines.retain(|_, (_, last_seen)| last_seen.saturating_add(max_window_secs) > now);
+    if engines.len() >= MAX_IP_BAN_ENGINES
+        && let Some(oldest) = engines
+            .iter()
+            .min_by_key(|(_, (_, last_seen))| *last_seen)
+            .map(|(key, _)| key.clone())
+    {
+        engines.remove(&oldest);
+    }
+}
+
+/// 运行时 Key 表：配置中的 Key 与 JWT 动态身份分开保存
+///
+/// 按 ID 查找时两者都会命中；Key 列表、生命周期与最近使用时间只包含配置中的 Key。
+#[derive(Default)]
+struct KeyTable {
+    configured: HashMap<String, ApiKeyRuntimeInfo>,
+    /// 动态身份的有界缓存，闲置超过 TTL 或超出容量时淘汰
+    dynamic: HashMap<String, ApiKeyRuntimeInfo>,
+}
+
+impl KeyTable {
+    fn get(&self, key_id: &str) -> Option<&ApiKeyRuntimeInfo> {
+        self.configured.get(key_id).or_else(|| self.dynamic.get(key_id))
+    }
+
+    fn get_mut(&mut self, key_id: &str) -> Option<&mut ApiKeyRuntimeInfo> {
+        match self.configured.get_mut(key_id) {
+            Some(info) => Some(info),
+            None => self.dynamic.get_mut(key_id),
+        }
+    }
+
+    /// 淘汰闲置的动态身份；仍然满额时淘汰最久未使用的一个
+    fn evict_dynamic(&mut self, now: u64) {
+        self.dynamic.retain(|_, info| {
+            let last_used_at = info.last_used_at.load(Ordering::Relaxed);
+            last_used_at.saturating_add(DYNAMIC_KEY_IDLE_TTL_SECS) > now
+                || info.resolved.ban_status.as_ref().is_some_and(|status| ban_in_effect(status, now))
+        });
+        if self.dynamic.len() >= MAX_DYNAMIC_KEYS
+            && let Some(oldest) = self
+                .dynamic
+                .iter()
+                .min_by_key(|(_, info)| info.last_used_at.load(Ordering::Relaxed))
+                .map(|(key_id, _)| key_id.clone())
+        {
+            self.dynamic.remove(&oldest);
+        }
+    }
 }
 
 pub struct ApiKeyManager {
-    keys: RwLock<HashMap<String, ApiKeyRuntimeInfo>>,
-    id_index: RwLock<HashMap<String, String>>,
+    /// 运行时 Key 信息（key_id -> info）
+    keys: RwLock<KeyTable>,
+    /// 展示前缀索引（key_prefix -> key_id 列表），用于定位待校验哈希的候选 Key
+    prefix_index: std::sync::RwLock<HashMap<String, Vec<String>>>,
     ban_log_store: Option<Arc<dyn BanLogStore>>,
-    /// 全局封禁规则（对所有 API Key 生效）
+    /// 按 API Key 统计的全局封禁规则（对所有 API Key 生效）
     ban_rules: Vec<BanRule>,
+    /// 按客户端 IP 统计的封禁规则
+    ip_ban_rules: Vec<BanRule>,
+    /// 按客户端网段统计的封禁规则
+    subnet_ban_rules: Vec<BanRule>,
+    /// IP / 网段规则的计数器（(对象类型, IP 或网段) -> (规则引擎, 最近请求时间)）
+    ip_ban_engines: Mutex<HashMap<(BanSubject, String), (BanRuleEngine, u64)>>,
+    /// IP / 网段的封禁状态（含已结束的封禁，用于升级策略计次）
+    ip_bans: std::sync::RwLock<HashMap<(BanSubject, String), BanStatus>>,
     /// 封禁引擎的最大时间窗口（用于初始化每个 key 的计数器）
-    _ban_max_window_secs: u64,
+    ban_max_window_secs: u64,
     /// Token配额检查器
     token_quota_checker: Option<Arc<TokenQuotaChecker>>,
+    /// 共享状态后端（集群级限流与封禁状态同步）
+    shared_state: SharedState,
+    /// 本实例变更、尚未推送到共享状态后端的封禁状态（api_key_id）
+    dirty_ban_statuses: Mutex<HashSet<String>>,
+    /// 本实例变更、尚未推送到共享状态后端的 IP / 网段封禁状态
+    dirty_ip_bans: Mutex<HashSet<(BanSubject, String)>>,
+    /// 即将过期预警窗口（秒）
+    expiry_warning_secs: u64,
+    /// 后台任务标记的生命周期预警（即将过期、已过期、未生效的 Key）
+    lifecycle_alerts: Mutex<Vec<KeyLifecycleStatus>>,
+    /// 仅告警的封禁规则最近触发记录（按时间先后）
+    ban_alerts: Mutex<VecDeque<BanAlert>>,
 }
 
 #[derive(Debug)]
@@ -77,6 +239,106 @@ pub struct ApiKeyRuntimeInfo {
     pub concurrency_semaphore: Option<Arc<Semaphore>>,
     /// 封禁规则引擎（使用全局规则，但每个 key 有自己的计数器）
     pub ban_engine: BanRuleEngine,
+    /// 最近使用时间（Unix秒，0 表示未知），用于闲置过期判断
+    pub last_used_at: AtomicU64,
+    /// 轮换后旧 Key 的使用情况（旧 Key 哈希 -> 使用次数、最近使用时间）
+    previous_key_uses: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+/// 轮换后旧 Key 在宽限期内的使用情况，用于找出尚未迁移到新 Key 的调用方
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviousKeyUsage {
+    pub key_prefix: String,
+    pub rotated_at: u64,
+    pub expires_at: u64,
+    /// 是否仍在宽限期内
+    pub active: bool,
+    /// 使用次数（自本实例加载该 Key 起）
+    pub uses: u64,
+    pub last_used_at: Option<u64>,
+}
+
+impl ApiKeyRuntimeInfo {
+    fn new(resolved: ResolvedApiKey, ban_max_window_secs: u64) -> Self {
+        Self {
+            rate_limiter: Self::build_rate_limiter(&resolved),
+            concurrency_semaphore: Self::build_concurrency_semaphore(&resolved),
+            // 为每个 key 创建封禁引擎（使用全局最大窗口）
+            ban_engine: BanRuleEngine::new(ban_max_window_secs),
+            last_used_at: AtomicU64::new(0),
+            previous_key_uses: Mutex::new(HashMap::new()),
+            resolved,
+        }
+    }
+
+    /// 当前 Key 与旧 Key 的展示前缀（去重）
+    fn key_prefixes(resolved: &ResolvedApiKey) -> HashSet<String> {
+        std::iter::once(&resolved.key_prefix)
+            .filter(|_| !resolved.key_hash.is_empty())
+            .chain(resolved.previous_keys.iter().map(|previous| &previous.key_prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// 记录一次旧 Key 使用，返回是否为首次使用
+    fn record_previous_key_use(&self, key_hash: &str, now: u64) -> bool {
+        let mut uses = self.previous_key_uses.lock().unwrap();
+        let entry = uses.entry(key_hash.to_string()).or_default();
+        entry.0 += 1;
+        entry.1 = entry.1.max(now);
+        entry.0 == 1
+    }
+
+    /// 应用新配置：保留封禁状态与最近使用时间，限流/并发配置未变化时沿用原限制器
+    fn update(&mut self, mut resolved: ResolvedApiKey) {
+        if self.resolved.rate_limit.as_ref().map(|cfg| cfg.per_minute)
+            != resolved.rate_limit.as_ref().map(|cfg| cfg.per_minute)
+        {
+            self.rate_limiter = Self::build_rate_limiter(&resolved);
+        }
+        if self.resolved.concurrency.as_ref().and_then(|cfg| cfg.downstream_max_inflight)
+            != resolved.concurrency.as_ref().and_then(|cfg| cfg.downstream_max_inflight)
+        {
+            self.concurrency_semaphore = Self::build_concurrency_semaphore(&resolved);
+        }
+        resolved.ban_status = self.resolved.ban_status.take();
+        self.previous_key_uses
+            .lock()
+            .unwrap()
+            .retain(|hash, _| resolved.previous_keys.iter().any(|previous| &previous.key_hash == hash));
+        self.resolved = resolved;
+    }
+
+    fn build_rate_limiter(resolved: &ResolvedApiKey) -> Option<Arc<RateLimiter>> {
+        resolved
+            .rate_limit
+            .as_ref()
+            .map(|cfg| Arc::new(RateLimiter::new(cfg.per_minute)))
+    }
+
+    fn build_concurrency_semaphore(resolved: &ResolvedApiKey) -> Option<Arc<Semaphore>> {
+        resolved
+            .concurrency
+            .as_ref()
+            .and_then(|cfg| cfg.downstream_max_inflight)
+            .map(|limit| Arc::new(Semaphore::new(limit)))
+    }
+}
+
+fn add_to_prefix_index(prefix_index: &mut HashMap<String, Vec<String>>, key_prefix: String, key_id: &str) {
+    let ids = prefix_index.entry(key_prefix).or_default();
+    if !ids.iter().any(|id| id == key_id) {
+        ids.push(key_id.to_string());
+    }
+}
+
+fn remove_from_prefix_index(prefix_index: &mut HashMap<String, Vec<String>>, key_prefix: &str, key_id: &str) {
+    if let Some(ids) = prefix_index.get_mut(key_prefix) {
+        ids.retain(|id| id != key_id);
+        if ids.is_empty() {
+            prefix_index.remove(key_prefix);
+        }
+    }
 }
 
 impl ApiKeyManager {
@@ -85,23 +347,17 @@ impl ApiKeyManager {
         ban_log_store: Option<Arc<dyn BanLogStore>>,
         global_ban_rules: Vec<BanRule>,
         token_quota_checker: Option<Arc<TokenQuotaChecker>>,
+        shared_state: SharedState,
+        expiry_warning_secs: u64,
     ) -> Self {
         let mut keys = HashMap::new();
-        let mut id_index = HashMap::new();
+        let mut prefix_index: HashMap<String, Vec<String>> = HashMap::new();
 
         // 计算全局封禁规则的最大时间窗口
         let ban_max_window_secs = if !global_ban_rules.is_empty() {
             global_ban_rules
                 .iter()
-                .filter_map(|r| match &r.condition {
-                    crate::api_keys::ban::BanCondition::ErrorRate { window_secs, .. } => {
-                        Some(*window_secs)
-                    }
-                    crate::api_keys::ban::BanCondition::RequestCount { window_secs, .. } => {
-                        Some(*window_secs)
-                    }
-                    _ => Some(3600),
-                })
+                .map(|r| r.condition.max_window_secs().unwrap_or(3600))
                 .max()
                 .unwrap_or(3600)
         } else {
@@ -109,62 +365,221 @@ impl ApiKeyManager {
         };
 
         for resolved in resolved_keys {
-            let key_value = resolved.key.clone();
             let key_id = resolved.id.clone();
-
-            let rate_limiter = resolved.rate_limit.as_ref().map(|cfg| {
-                Arc::new(RateLimiter::new(cfg.per_minute))
-            });
-
-            let concurrency_semaphore = resolved.concurrency.as_ref()
-                .and_then(|cfg| cfg.downstream_max_inflight)
-                .map(|limit| Arc::new(Semaphore::new(limit)));
-
-            // 为每个 key 创建封禁引擎（使用全局最大窗口）
-            let ban_engine = BanRuleEngine::new(ban_max_window_secs);
-
-            let runtime_info = ApiKeyRuntimeInfo {
-                resolved,
-                rate_limiter,
-                concurrency_semaphore,
-                ban_engine,
-            };
-
-            id_index.insert(key_id, key_value.clone());
-            keys.insert(key_value, runtime_info);
+            for key_prefix in ApiKeyRuntimeInfo::key_prefixes(&resolved) {
+                add_to_prefix_index(&mut prefix_index, key_prefix, &key_id);
+            }
+            keys.insert(key_id, ApiKeyRuntimeInfo::new(resolved, ban_max_window_secs));
         }
+        let keys = KeyTable {
+            configured: keys,
+            dynamic: HashMap::new(),
+        };
+
+        let (ip_ban_rules, rules): (Vec<_>, Vec<_>) = global_ban_rules
+            .into_iter()
+            .partition(|rule| rule.subject == BanSubject::Ip);
+        let (subnet_ban_rules, ban_rules): (Vec<_>, Vec<_>) = rules
+            .into_iter()
+            .partition(|rule| rule.subject == BanSubject::Subnet);
 
         Self {
             keys: RwLock::new(keys),
-            id_index: RwLock::new(id_index),
+            prefix_index: std::sync::RwLock::new(prefix_index),
             ban_log_store,
-            ban_rules: global_ban_rules,
-            _ban_max_window_secs: ban_max_window_secs,
+            ban_rules,
+            ip_ban_rules,
+            subnet_ban_rules,
+            ip_ban_engines: Mutex::new(HashMap::new()),
+            ip_bans: std::sync::RwLock::new(HashMap::new()),
+            ban_max_window_secs,
             token_quota_checker,
+            shared_state,
+            dirty_ban_statuses: Mutex::new(HashSet::new()),
+            dirty_ip_bans: Mutex::new(HashSet::new()),
+            expiry_warning_secs,
+            lifecycle_alerts: Mutex::new(Vec::new()),
+            ban_alerts: Mutex::new(VecDeque::new()),
         }
     }
 
+    /// 按请求携带的 Key 查找 key_id：先按展示前缀定位候选，再逐个以常量时间校验哈希
+    pub async fn resolve_key_id(&self, key_value: &str) -> Option<String> {
+        self.resolve_key(key_value, current_epoch_seconds())
+            .await
+            .map(|(key_id, _)| key_id)
+    }
+
+    /// 查找 key_id，并返回命中的旧 Key 哈希（命中当前 Key 时为 None）
+    ///
+    /// 旧 Key 只在宽限期内有效。
+    async fn resolve_key(&self, key_value: &str, now: u64) -> Option<(String, Option<String>)> {
+        let candidates: Vec<String> = {
+            let prefix_index = self.prefix_index.read().unwrap();
+            key_hash::lookup_prefixes(key_value)
+                .iter()
+                .filter_map(|prefix| prefix_index.get(prefix))
+                .flatten()
+                .cloned()
+                .collect()
+        };
+        if candidates.is_empty() {
+            return None;
+        }
+        let keys = self.keys.read().await;
+        // 校验全部候选，耗时不随匹配位置变化
+        let mut matched = None;
+        for key_id in &candidates {
+            let Some(info) = keys.get(key_id) else {
+                continue;
+            };
+            if key_hash::verify_key(key_value, &info.resolved.key_hash) && matched.is_none() {
+                matched = Some((key_id.clone(), None));
+            }
+            for previous in &info.resolved.previous_keys {
+                let verified = key_hash::verify_key(key_value, &previous.key_hash);
+                if verified && now < previous.expires_at && matched.is_none() {
+                    matched = Some((key_id.clone(), Some(previous.key_hash.clone())));
+                }
+            }
+        }
+        matched
+    }
+
     pub async fn validate_key(&self, key_value: &str, route_id: &str) -> Result<ValidationResult, ApiKeyError> {
+        let now = current_epoch_seconds();
+        let (key_id, previous_key_hash) = self
+            .resolve_key(key_value, now)
+            .await
+            .ok_or(ApiKeyError::KeyNotFound)?;
         let keys = self.keys.read().await;
-        let info = keys.get(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get(&key_id).ok_or(ApiKeyError::KeyNotFound)?;
+        self.check_key_access(info, route_id, now)?;
+
+        info.last_used_at.fetch_max(now, Ordering::Relaxed);
+        if let Some(previous_key_hash) = previous_key_hash
+            && info.record_previous_key_use(&previous_key_hash, now)
+        {
+            tracing::warn!(
+                "API key {} is still used with its rotated-out secret; it stops working after the grace period",
+                info.resolved.id
+            );
+        }
+        Ok(ValidationResult {
+            key_id: info.resolved.id.clone(),
+            key: info.resolved.clone(),
+        })
+    }
 
-        if !info.resolved.enabled {
-            return Err(ApiKeyError::KeyDisabled);
+    /// 校验 JWT 映射出的身份：已有 Key 按 ID 查找，动态身份按需由模板 Key 派生
+    pub async fn validate_jwt(&self, identity: &JwtIdentity, route_id: &str) -> Result<ValidationResult, ApiKeyError> {
+        let now = current_epoch_seconds();
+        let key_id = match identity {
+            JwtIdentity::ApiKey(key_id) => key_id.clone(),
+            JwtIdentity::Dynamic(identity) => self.ensure_dynamic_key(identity).await?,
+        };
+        let keys = self.keys.read().await;
+        let info = keys.get(&key_id).ok_or(ApiKeyError::KeyNotFound)?;
+        self.check_key_access(info, route_id, now)?;
+
+        info.last_used_at.fetch_max(now, Ordering::Relaxed);
+        Ok(ValidationResult {
+            key_id: info.resolved.id.clone(),
+            key: info.resolved.clone(),
+        })
+    }
+
+    /// 动态身份对应的运行时 Key：复制模板 Key 并应用 claim 覆盖项，配置变化时才更新
+    ///
+    /// 动态 Key 不含密钥哈希，只能通过 JWT 使用；限流器、封禁计数等按身份独立。
+    /// 动态 Key 保存在有界缓存中，不出现在 Key 列表中。
+    async fn ensure_dynamic_key(&self, identity: &DynamicIdentity) -> Result<String, ApiKeyError> {
+        let key_id = identity.key_id();
+        let mut resolved = {
+            let keys = self.keys.read().await;
+            let template = keys
+                .configured
+                .get(&identity.template_key)
+                .ok_or(ApiKeyError::KeyNotFound)?;
+            template.resolved.clone()
+        };
+        resolved.id = key_id.clone();
+        resolved.key_hash = String::new();
+        resolved.key_prefix = String::new();
+        resolved.previous_keys = Vec::new();
+        resolved.ban_status = None;
+        if let Some(route_ids) = &identity.route_ids {
+            resolved.route_id = None;
+            resolved.route_ids = Some(route_ids.clone());
+        }
+        if let Some(per_minute) = identity.rate_limit_per_minute {
+            resolved.rate_limit = Some(RateLimitConfig { per_minute });
+        }
+        if identity.token_quota.is_some() {
+            resolved.token_quota = identity.token_quota.clone();
         }
 
-        if let Some(status) = &info.resolved.ban_status {
-            if status.is_banned {
-                if let Some(until) = status.banned_until {
+        let unchanged = self.keys.read().await.dynamic.get(&key_id).is_some_and(|info| {
+            info.resolved.route_id == resolved.route_id
+                && info.resolved.route_ids == resolved.route_ids
+                && info.resolved.rate_limit == resolved.rate_limit
+                && info.resolved.token_quota == resolved.token_quota
+                && info.resolved.enabled == resolved.enabled
+        });
+        if !unchanged {
+            if let Some(checker) = &self.token_quota_checker {
+                checker.manager().set_quota(&key_id, resolved.token_quota.clone());
+            }
+            let mut keys = self.keys.write().await;
+            match keys.dynamic.get_mut(&key_id) {
+                Some(info) => info.update(resolved),
+                None => {
                     let now = current_epoch_seconds();
-                    if now < until {
-                        return Err(ApiKeyError::KeyBanned {
-                            until,
-                            reason: status.reason.clone(),
-                        });
-                    }
+                    keys.evict_dynamic(now);
+                    let info = ApiKeyRuntimeInfo::new(resolved, self.ban_max_window_secs);
+                    info.last_used_at.store(now, Ordering::Relaxed);
+                    keys.dynamic.insert(key_id.clone(), info);
                 }
             }
         }
+        Ok(key_id)
+    }
+
+    /// 淘汰闲置的 JWT 动态身份（后台任务定期调用）
+    pub async fn evict_idle_dynamic_keys(&self) {
+        self.keys.write().await.evict_dynamic(current_epoch_seconds());
+    }
+
+    /// 启用状态、生命周期、封禁状态与路由权限检查
+    fn check_key_access(&self, info: &ApiKeyRuntimeInfo, route_id: &str, now: u64) -> Result<(), ApiKeyError> {
+        if !info.resolved.enabled {
+            return Err(ApiKeyError::KeyDisabled);
+        }
+
+        let last_used_at = info.last_used_at.load(Ordering::Relaxed);
+        match lifecycle::evaluate(&info.resolved, last_used_at, now, self.expiry_warning_secs) {
+            KeyLifecycleState::Pending => {
+                return Err(ApiKeyError::KeyNotYetValid {
+                    not_before: info.resolved.not_before.unwrap_or_default(),
+                });
+            }
+            KeyLifecycleState::Expired | KeyLifecycleState::IdleExpired => {
+                return Err(ApiKeyError::KeyExpired {
+                    expired_at: lifecycle::effective_expires_at(&info.resolved, last_used_at).unwrap_or(now),
+                });
+            }
+            KeyLifecycleState::Active | KeyLifecycleState::ExpiringSoon => {}
+        }
+
+        if let Some(status) = &info.resolved.ban_status
+            && status.is_banned
+            && (status.permanent || status.banned_until.is_some_and(|until| now < until))
+        {
+            return Err(ApiKeyError::KeyBanned {
+                until: status.banned_until.filter(|_| !status.permanent),
+                reason: status.reason.clone(),
+            });
+        }
 
         // 检查路由权限（优先使用 route_ids，兼容 route_id）
         if let Some(allowed_routes) = &info.resolved.route_ids {
@@ -178,34 +593,63 @@ impl ApiKeyManager {
                 return Err(ApiKeyError::RouteNotAllowed);
             }
         }
-
-        Ok(ValidationResult {
-            key_id: info.resolved.id.clone(),
-            key: info.resolved.clone(),
-        })
+        Ok(())
     }
 
-    pub async fn check_rate_limit(&self, key_value: &str, _route_id: &str) -> Result<(), ApiKeyError> {
+    /// 组合检查 API Key、路由、Key + 路由、客户端 IP 各维度的限流
+    ///
+    /// API Key 未配置限流时回退到全局限流器（按 token + route 计数）。
+    pub async fn check_rate_limit(
+        &self,
+        key_id: &str,
+        route_id: &str,
+        route_limiters: Option<&RouteRateLimiters>,
+        client_ip: Option<&str>,
+        global_limiter: Option<&RateLimiter>,
+    ) -> Result<(), ApiKeyError> {
         let keys = self.keys.read().await;
-        let info = keys.get(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get(key_id).ok_or(ApiKeyError::KeyNotFound)?;
 
+        let mut checks = Vec::new();
         if let Some(limiter) = &info.rate_limiter {
             // API Key 级别的限流是针对该 Key 的全局限制，不区分路由
             // 使用固定的 key 来统计该 API Key 的所有请求
-            match limiter.check(&info.resolved.id, "global") {
-                RateLimitDecision::Allowed => Ok(()),
-                RateLimitDecision::Rejected { retry_after_secs } => {
-                    Err(ApiKeyError::RateLimitExceeded { retry_after_secs })
-                }
-            }
-        } else {
-            Ok(())
+            checks.push(RateLimitCheck::new(
+                RateLimitDimension::ApiKey,
+                limiter.as_ref().clone(),
+                format!("global\n{}", info.resolved.id),
+            ));
+        } else if let Some(limiter) = global_limiter {
+            checks.push(RateLimitCheck::new(
+                RateLimitDimension::Global,
+                limiter.clone(),
+                format!("{route_id}\n{key_id}"),
+            ));
+        }
+        if let Some(route_limiters) = route_limiters {
+            checks.extend(route_limiters.checks(route_id, &info.resolved.id, client_ip));
         }
+
+        drop(keys);
+
+        // 本地检查通过后再按集群合计检查（共享状态后端不可用时只按本地限制）
+        let result = match check_combined(&checks) {
+            Ok(()) => self
+                .shared_state
+                .check_rate_limits(route_id, &checks)
+                .await
+                .inspect_err(|_| release_combined(&checks)),
+            Err(rejection) => Err(rejection),
+        };
+        result.map_err(|rejection| ApiKeyError::RateLimitExceeded {
+            retry_after_secs: rejection.retry_after_secs,
+            dimension: rejection.dimension,
+        })
     }
 
-    pub async fn acquire_concurrency_permit(&self, key_value: &str) -> Result<Option<OwnedSemaphorePermit>, ApiKeyError> {
+    pub async fn acquire_concurrency_permit(&self, key_id: &str) -> Result<Option<OwnedSemaphorePermit>, ApiKeyError> {
         let keys = self.keys.read().await;
-        let info = keys.get(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get(key_id).ok_or(ApiKeyError::KeyNotFound)?;
 
         if let Some(semaphore) = &info.concurrency_semaphore {
             match semaphore.clone().try_acquire_owned() {
@@ -219,147 +663,34 @@ impl ApiKeyManager {
 
     /// 检查Token配额
     pub fn check_token_quota(&self, key_value: &str) -> Result<CheckQuotaResult, ApiKeyError> {
-        if let Some(checker) = &self.token_quota_checker {
-            // 获取API Key ID
-            // 注意：这里我们使用key_value作为临时ID，实际应该使用key_id
-            // 但由于check_token_quota是同步方法，无法async获取key_id
-            // 我们将在validate_key之后调用此方法，传入key_id
-            let key_id = self.get_key_id_sync(key_value);
-            if let Some(id) = key_id {
-                let result = checker.check_quota(&id);
-                if !result.allowed {
-                    // 构造错误信息
-                    if let Some(limit) = result.daily_limit_total {
-                        if result.daily_used_total >= limit {
-                            return Err(ApiKeyError::TokenQuotaExceeded {
-                                quota_type: "daily_total".to_string(),
-                                limit,
-                                used: result.daily_used_total,
-                            });
-                        }
-                    }
-                    if let Some(limit) = result.daily_limit_input {
-                        if result.daily_used_input >= limit {
-                            return Err(ApiKeyError::TokenQuotaExceeded {
-                                quota_type: "daily_input".to_string(),
-                                limit,
-                                used: result.daily_used_input,
-                            });
-                        }
-                    }
-                    if let Some(limit) = result.daily_limit_output {
-                        if result.daily_used_output >= limit {
-                            return Err(ApiKeyError::TokenQuotaExceeded {
-                                quota_type: "daily_output".to_string(),
-                                limit,
-                                used: result.daily_used_output,
-                            });
-                        }
-                    }
-                    if let Some(limit) = result.weekly_limit_total {
-                        if result.weekly_used_total >= limit {
-                            return Err(ApiKeyError::TokenQuotaExceeded {
-                                quota_type: "weekly_total".to_string(),
-                                limit,
-                                used: result.weekly_used_total,
-                            });
-                        }
-                    }
-                }
-                return Ok(result);
+        if self.token_quota_checker.is_some() {
+            // 注意：check_token_quota是同步方法，这里通过同步查找拿到key_id
+            if let Some(id) = self.get_key_id_sync(key_value) {
+                return self.check_token_quota_by_id(&id, 0);
             }
         }
         // 无配额限制时返回默认允许的结果
-        Ok(CheckQuotaResult {
-            allowed: true,
-            daily_used_input: 0,
-            daily_used_output: 0,
-            daily_used_total: 0,
-            daily_limit_input: None,
-            daily_limit_output: None,
-            daily_limit_total: None,
-            daily_remaining_input: None,
-            daily_remaining_output: None,
-            daily_remaining_total: None,
-            weekly_used_input: 0,
-            weekly_used_output: 0,
-            weekly_used_total: 0,
-            weekly_limit_input: None,
-            weekly_limit_output: None,
-            weekly_limit_total: None,
-            weekly_remaining_input: None,
-            weekly_remaining_output: None,
-            weekly_remaining_total: None,
-            reason: None,
-        })
+        Ok(CheckQuotaResult::unlimited())
     }
 
-    /// 检查Token配额（使用key_id版本）
-    pub fn check_token_quota_by_id(&self, key_id: &str) -> Result<CheckQuotaResult, ApiKeyError> {
+    /// 检查Token配额（使用key_id版本），`estimated_input_tokens` 为本次请求的预估输入
+    pub fn check_token_quota_by_id(
+        &self,
+        key_id: &str,
+        estimated_input_tokens: u64,
+    ) -> Result<CheckQuotaResult, ApiKeyError> {
         if let Some(checker) = &self.token_quota_checker {
-            let result = checker.check_quota(key_id);
-            if !result.allowed {
-                if let Some(limit) = result.daily_limit_total {
-                    if result.daily_used_total >= limit {
-                        return Err(ApiKeyError::TokenQuotaExceeded {
-                            quota_type: "daily_total".to_string(),
-                            limit,
-                            used: result.daily_used_total,
-                        });
-                    }
-                }
-                if let Some(limit) = result.daily_limit_input {
-                    if result.daily_used_input >= limit {
-                        return Err(ApiKeyError::TokenQuotaExceeded {
-                            quota_type: "daily_input".to_string(),
-                            limit,
-                            used: result.daily_used_input,
-                        });
-                    }
-                }
-                if let Some(limit) = result.daily_limit_output {
-                    if result.daily_used_output >= limit {
-                        return Err(ApiKeyError::TokenQuotaExceeded {
-                            quota_type: "daily_output".to_string(),
-                            limit,
-                            used: result.daily_used_output,
-                        });
-                    }
-                }
-                if let Some(limit) = result.weekly_limit_total {
-                    if result.weekly_used_total >= limit {
-                        return Err(ApiKeyError::TokenQuotaExceeded {
-                            quota_type: "weekly_total".to_string(),
-                            limit,
-                            used: result.weekly_used_total,
-                        });
-                    }
-                }
+            let result = checker.check_quota_with_estimate(key_id, estimated_input_tokens);
+            if let Some((quota_type, limit, used)) = result.exceeded_quota() {
+                return Err(ApiKeyError::TokenQuotaExceeded {
+                    quota_type: quota_type.to_string(),
+                    limit,
+                    used,
+                });
             }
             return Ok(result);
         }
-        Ok(CheckQuotaResult {
-            allowed: true,
-            daily_used_input: 0,
-            daily_used_output: 0,
-            daily_used_total: 0,
-            daily_limit_input: None,
-            daily_limit_output: None,
-            daily_limit_total: None,
-            daily_remaining_input: None,
-            daily_remaining_output: None,
-            daily_remaining_total: None,
-            weekly_used_input: 0,
-            weekly_used_output: 0,
-            weekly_used_total: 0,
-            weekly_limit_input: None,
-            weekly_limit_output: None,
-            weekly_limit_total: None,
-            weekly_remaining_input: None,
-            weekly_remaining_output: None,
-            weekly_remaining_total: None,
-            reason: None,
-        })
+        Ok(CheckQuotaResult::unlimited())
     }
 
     /// 同步获取key_id（用于非async上下文）
@@ -376,67 +707,41 @@ impl ApiKeyManager {
 
     pub async fn report_request_result(
         &self,
-        key_value: &str,
+        key_id: &str,
         result: RequestResult,
     ) -> Option<BanStatus> {
+        self.report_ip_result(&result);
+
         let mut keys = self.keys.write().await;
-        let info = keys.get_mut(key_value)?;
+        let info = keys.get_mut(key_id)?;
 
         // 使用全局封禁规则检查
         if !self.ban_rules.is_empty() {
             let now = current_epoch_seconds();
 
-            if let Some(triggered) = info
-                .ban_engine
-                .check_rules(&self.ban_rules, now, result.success)
-            {
-                let ban_until = now + triggered.ban_duration_secs;
-                let new_status = BanStatus {
-                    is_banned: true,
-                    banned_at: Some(now),
-                    banned_until: Some(ban_until),
-                    triggered_rule_id: Some(triggered.rule_id.clone()),
-                    reason: Some(triggered.reason.clone()),
-                    ban_count: info
-                        .resolved
-                        .ban_status
-                        .as_ref()
-                        .map(|s| s.ban_count + 1)
-                        .unwrap_or(1),
+            let triggered = info.ban_engine.check_rules(&self.ban_rules, now, &result);
+            for shadow in info.ban_engine.take_shadow_triggers() {
+                let Some(rule) = self.ban_rules.iter().find(|rule| rule.id == shadow.rule_id) else {
+                    continue;
                 };
-
+                let target = BanTarget {
+                    subject: BanSubject::Key,
+                    id: &info.resolved.id,
+                    prior: info.resolved.ban_status.as_ref(),
+                };
+                let until = self.record_shadow_ban(target, rule, shadow, now);
+                info.ban_engine.suppress_shadow_rule(&rule.id, until);
+            }
+            if let Some(triggered) = triggered {
+                let rule = self.ban_rules.iter().find(|rule| rule.id == triggered.rule_id)?;
+                let target = BanTarget {
+                    subject: BanSubject::Key,
+                    id: &info.resolved.id,
+                    prior: info.resolved.ban_status.as_ref(),
+                };
+                let new_status = self.apply_triggered_rule(target, rule, triggered, now)?;
                 info.resolved.ban_status = Some(new_status.clone());
-
-                if let Some(store) = &self.ban_log_store {
-                    let entry = BanLogEntry {
-                        id: format!("ban_{}_{}", key_value, now),
-                        api_key_id: info.resolved.id.clone(),
-                        rule_id: triggered.rule_id.clone(),
-                        reason: triggered.reason.clone(),
-                        banned_at: now,
-                        banned_until: ban_until,
-                        unbanned_at: None,
-                        metrics_snapshot: triggered.metrics_snapshot.clone(),
-                    };
-
-                    tracing::info!(
-                        "Inserting ban log for key {} (rule: {}, until: {})",
-                        info.resolved.id,
-                        triggered.rule_id,
-                        ban_until
-                    );
-
-                    let store = Arc::clone(store);
-                    tokio::spawn(async move {
-                        match store.insert(entry).await {
-                            Ok(()) => tracing::info!("Ban log inserted successfully"),
-                            Err(e) => tracing::error!("Failed to insert ban log: {}", e),
-                        }
-                    });
-                } else {
-                    tracing::warn!("Ban log store not available, skipping ban log insertion");
-                }
-
+                self.mark_ban_status_dirty(&info.resolved.id);
                 return Some(new_status);
             }
         }
@@ -444,44 +749,379 @@ impl ApiKeyManager {
         None
     }
 
-    pub async fn ban_key(&self, key_value: &str, duration_secs: u64, reason: String) -> Result<BanStatus, ApiKeyError> {
+    /// 按客户端 IP 与网段统计请求结果，触发 IP / 网段规则时封禁该对象
+    ///
+    /// 鉴权失败等未关联 Key 的请求同样需要调用。
+    pub fn report_ip_result(&self, result: &RequestResult) {
+        let Some(ip) = result.client_ip.as_deref() else {
+            return;
+        };
+        let now = current_epoch_seconds();
+
+        for (subject, rules) in [
+            (BanSubject::Ip, &self.ip_ban_rules),
+            (BanSubject::Subnet, &self.subnet_ban_rules),
+        ] {
+            if rules.is_empty() {
+                continue;
+            }
+            let target_id = ban::ban_target(subject, ip);
+            let key = (subject, target_id.clone());
+
+            let mut engines = self.ip_ban_engines.lock().unwrap();
+            if !engines.contains_key(&key) && engines.len() >= MAX_IP_BAN_ENGINES {
+                evict_idle_ip_engines(&mut engines, now, self.ban_max_window_secs);
+            }
+            let (engine, last_seen) = engines
+                .entry(key.clone())
+                .or_insert_with(|| (BanRuleEngine::new(self.ban_max_window_secs), now));
+            *last_seen = now;
+
+            let triggered = engine.check_rules(rules, now, result);
+            let prior = self.ip_bans.read().unwrap().get(&key).cloned();
+            for shadow in engine.take_shadow_triggers() {
+                let Some(rule) = rules.iter().find(|rule| rule.id == shadow.rule_id) else {
+                    continue;
+                };
+                let target = BanTarget { subject, id: &target_id, prior: prior.as_ref() };
+                let until = self.record_shadow_ban(target, rule, shadow, now);
+                engine.suppress_shadow_rule(&rule.id, until);
+            }
+            drop(engines);
+
+            let Some(triggered) = triggered else {
+                continue;
+            };
+            let Some(rule) = rules.iter().find(|rule| rule.id == triggered.rule_id) else {
+                continue;
+            };
+            let target = BanTarget { subject, id: &target_id, prior: prior.as_ref() };
+            if let Some(status) = self.apply_triggered_rule(target, rule, triggered, now) {
+                self.set_ip_ban(key, status, now);
+            }
+        }
+    }
+
+    /// 处理达到阈值的规则：告警规则记录告警并写入封禁日志；封禁规则按升级策略生成新的封禁状态并写入封禁日志
+    fn apply_triggered_rule(
+        &self,
+        target: BanTarget<'_>,
+        rule: &BanRule,
+        triggered: TriggeredRule,
+        now: u64,
+    ) -> Option<BanStatus> {
+        if triggered.action == BanAction::Alert {
+            let quiet_secs = rule.trigger_window_secs;
+            let mut alerts = self.ban_alerts.lock().unwrap();
+            // 同一对象与规则在触发计数窗口内只告警一次
+            let recently_alerted = alerts.iter().any(|alert| {
+                alert.subject == target.subject
+                    && alert.api_key_id == target.id
+                    && alert.rule_id == triggered.rule_id
+                    && alert.triggered_at.saturating_add(quiet_secs) > now
+            });
+            if recently_alerted {
+                return None;
+            }
+            tracing::warn!(
+                subject = target.subject.as_str(),
+                api_key_id = %target.id,
+                rule_id = %triggered.rule_id,
+                "Ban rule alert: {}",
+                triggered.reason
+            );
+            if alerts.len() >= MAX_BAN_ALERTS {
+                alerts.pop_front();
+            }
+            alerts.push_back(BanAlert {
+                api_key_id: target.id.to_string(),
+                subject: target.subject,
+                rule_id: triggered.rule_id.clone(),
+                reason: triggered.reason.clone(),
+                triggered_at: now,
+                metrics_snapshot: triggered.metrics_snapshot.clone(),
+            });
+            drop(alerts);
+            // 告警落库（快照含涉及的客户端 IP），重启后仍可查
+            self.insert_ban_log(BanLogEntry {
+                id: format!("ban_alert_{}_{}_{}_{}", target.subject.as_str(), target.id, triggered.rule_id, now),
+                api_key_id: target.id.to_string(),
+                subject: target.subject,
+                rule_id: triggered.rule_id,
+                reason: triggered.reason,
+                banned_at: now,
+                banned_until: now,
+                unbanned_at: None,
+                metrics_snapshot: triggered.metrics_snapshot,
+                offense: 0,
+                permanent: false,
+                shadow: false,
+                alert: true,
+            });
+            return None;
+        }
+
+        let (ban_count, duration_secs) = ban::escalate(rule, target.prior, now);
+        let ban_until = duration_secs.map(|secs| now.saturating_add(secs));
+        let new_status = BanStatus {
+            is_banned: true,
+            banned_at: Some(now),
+            banned_until: ban_until,
+            triggered_rule_id: Some(triggered.rule_id.clone()),
+            reason: Some(triggered.reason.clone()),
+            ban_count,
+            permanent: ban_until.is_none(),
+        };
+
+        tracing::info!(
+            "Inserting ban log for {} {} (rule: {}, offense: {}, until: {:?})",
+            target.subject.as_str(),
+            target.id,
+            triggered.rule_id,
+            ban_count,
+            ban_until
+        );
+        self.insert_ban_log(BanLogEntry {
+            id: ban_log_id(target.subject, target.id, now, false),
+            api_key_id: target.id.to_string(),
+            subject: target.subject,
+            rule_id: triggered.rule_id,
+            reason: triggered.reason,
+            banned_at: now,
+            banned_until: ban_until.unwrap_or(now),
+            unbanned_at: None,
+            metrics_snapshot: triggered.metrics_snapshot,
+            offense: ban_count,
+            permanent: new_status.permanent,
+            shadow: false,
+            alert: false,
+        });
+
+        Some(new_status)
+    }
+
+    /// 记录影子规则本应执行的封禁：写入带影子标记的封禁日志，不修改封禁状态
+    ///
+    /// 返回模拟封禁的结束时间（`None` 为永久），供调用方在此期间抑制该规则。
+    fn record_shadow_ban(
+        &self,
+        target: BanTarget<'_>,
+        rule: &BanRule,
+        triggered: TriggeredRule,
+        now: u64,
+    ) -> Option<u64> {
+        let (offense, duration_secs) = ban::escalate(rule, target.prior, now);
+        let ban_until = duration_secs.map(|secs| now.saturating_add(secs));
+
+        tracing::info!(
+            subject = target.subject.as_str(),
+            api_key_id = %target.id,
+            rule_id = %rule.id,
+            "Shadow ban rule would ban (until: {:?}): {}",
+            ban_until,
+            triggered.reason
+        );
+
+        if self.ban_log_store.is_some() {
+            self.insert_ban_log(BanLogEntry {
+                id: format!("ban_shadow_{}_{}_{}", target.id, rule.id, now),
+                api_key_id: target.id.to_string(),
+                subject: target.subject,
+                rule_id: rule.id.clone(),
+                reason: triggered.reason,
+                banned_at: now,
+                banned_until: ban_until.unwrap_or(now),
+                unbanned_at: None,
+                metrics_snapshot: triggered.metrics_snapshot,
+                offense,
+                permanent: ban_until.is_none(),
+                shadow: true,
+                alert: false,
+            });
+        }
+        ban_until
+    }
+
+    /// 异步写入封禁日志
+    fn insert_ban_log(&self, entry: BanLogEntry) {
+        let Some(store) = &self.ban_log_store else {
+            tracing::warn!("Ban log store not available, skipping ban log insertion");
+            return;
+        };
+        let store = Arc::clone(store);
+        tokio::spawn(async move {
+            match store.insert(entry).await {
+                Ok(()) => tracing::debug!("Ban log inserted successfully"),
+                Err(e) => tracing::error!("Failed to insert ban log: {}", e),
+            }
+        });
+    }
+
+    /// 检查客户端 IP 及其所在网段是否处于封禁中
+    pub fn check_ip_ban(&self, ip: &str) -> Option<IpBan> {
+        let bans = self.ip_bans.read().unwrap();
+        if bans.is_empty() {
+            return None;
+        }
+        let now = current_epoch_seconds();
+        [BanSubject::Ip, BanSubject::Subnet].into_iter().find_map(|subject| {
+            let target = ban::ban_target(subject, ip);
+            let status = bans.get(&(subject, target.clone()))?;
+            ban_in_effect(status, now).then(|| IpBan { subject, target, status: status.clone() })
+        })
+    }
+
+    /// 手动封禁 IP 或网段（`target` 需已规范化），`duration_secs` 为空表示永久封禁
+    pub fn ban_ip(&self, subject: BanSubject, target: &str, duration_secs: Option<u64>, reason: String) -> BanStatus {
+        let now = current_epoch_seconds();
+        let ban_until = duration_secs.map(|secs| now.saturating_add(secs));
+        let key = (subject, target.to_string());
+        let prior_count = self.ip_bans.read().unwrap().get(&key).map_or(0, |status| status.ban_count);
+
+        let new_status = BanStatus {
+            is_banned: true,
+            banned_at: Some(now),
+            banned_until: ban_until,
+            triggered_rule_id: None,
+            reason: Some(reason.clone()),
+            ban_count: prior_count + 1,
+            permanent: ban_until.is_none(),
+        };
+
+        tracing::info!("Manual ban for {} {} (until: {:?})", subject.as_str(), target, ban_until);
+        self.insert_ban_log(BanLogEntry {
+            id: ban_log_id(subject, target, now, true),
+            api_key_id: target.to_string(),
+            subject,
+            rule_id: "manual".to_string(),
+            reason,
+            banned_at: now,
+            banned_until: ban_until.unwrap_or(now),
+            unbanned_at: None,
+            metrics_snapshot: BanMetricsSnapshot::default(),
+            offense: new_status.ban_count,
+            permanent: new_status.permanent,
+            shadow: false,
+            alert: false,
+        });
+        self.set_ip_ban(key, new_status.clone(), now);
+
+        new_status
+    }
+
+    /// 解除 IP 或网段的封禁，未处于封禁中时返回 `false`
+    pub fn unban_ip(&self, subject: BanSubject, target: &str) -> bool {
+        let now = current_epoch_seconds();
+        let mut bans = self.ip_bans.write().unwrap();
+        let Some(status) = bans.get_mut(&(subject, target.to_string())) else {
+            return false;
+        };
+        if !ban_in_effect(status, now) {
+            return false;
+        }
+        status.is_banned = false;
+        // 记录实际结束时间，升级策略的静默期由此起算
+        status.banned_until = Some(status.banned_until.map_or(now, |until| until.min(now)));
+        status.permanent = false;
+        self.mark_ip_ban_dirty(subject, target);
+
+        if let (Some(store), Some(banned_at)) = (&self.ban_log_store, status.banned_at) {
+            let entry_id = ban_log_id(subject, target, banned_at, status.triggered_rule_id.is_none());
+            let store = Arc::clone(store);
+            tokio::spawn(async move {
+                if let Err(e) = store.mark_unbanned(&entry_id, now).await {
+                    tracing::warn!("Failed to mark ban log {} as unbanned: {}", entry_id, e);
+                }
+            });
+        }
+        true
+    }
+
+    /// 处于封禁中的 IP 与网段
+    pub fn ip_bans(&self) -> Vec<IpBan> {
+        let now = current_epoch_seconds();
+        let bans = self.ip_bans.read().unwrap();
+        let mut active: Vec<IpBan> = bans
+            .iter()
+            .filter(|(_, status)| ban_in_effect(status, now))
+            .map(|((subject, target), status)| IpBan {
+                subject: *subject,
+                target: target.clone(),
+                status: status.clone(),
+            })
+            .collect();
+        active.sort_by(|a, b| a.target.cmp(&b.target));
+        active
+    }
+
+    fn set_ip_ban(&self, key: (BanSubject, String), status: BanStatus, now: u64) {
+        let mut bans = self.ip_bans.write().unwrap();
+        if !bans.contains_key(&key) && bans.len() >= MAX_IP_BAN_STATUSES {
+            // 只保留封禁中或仍在升级静默期内的记录
+            let retention_secs = self.ip_ban_retention_secs();
+            bans.retain(|_, status| {
+                ban_in_effect(status, now)
+                    || status
+                        .banned_until
+                        .is_some_and(|until| until.saturating_add(retention_secs) > now)
+            });
+        }
+        self.mark_ip_ban_dirty(key.0, &key.1);
+        bans.insert(key, status);
+    }
+
+    /// IP / 网段规则中最长的升级静默期
+    fn ip_ban_retention_secs(&self) -> u64 {
+        self.ip_ban_rules
+            .iter()
+            .chain(&self.subnet_ban_rules)
+            .filter_map(|rule| rule.escalation.as_ref().map(|escalation| escalation.decay_secs))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// 手动封禁，`duration_secs` 为空表示永久封禁
+    pub async fn ban_key(&self, key_id: &str, duration_secs: Option<u64>, reason: String) -> Result<BanStatus, ApiKeyError> {
         let mut keys = self.keys.write().await;
-        let info = keys.get_mut(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get_mut(key_id).ok_or(ApiKeyError::KeyNotFound)?;
 
         let now = current_epoch_seconds();
-        let ban_until = now + duration_secs;
+        let ban_until = duration_secs.map(|secs| now.saturating_add(secs));
 
         let new_status = BanStatus {
             is_banned: true,
             banned_at: Some(now),
-            banned_until: Some(ban_until),
+            banned_until: ban_until,
             triggered_rule_id: None,
             reason: Some(reason.clone()),
             ban_count: info.resolved.ban_status.as_ref()
                 .map(|s| s.ban_count + 1)
                 .unwrap_or(1),
+            permanent: ban_until.is_none(),
         };
 
         info.resolved.ban_status = Some(new_status.clone());
+        self.mark_ban_status_dirty(&info.resolved.id);
 
         if let Some(store) = &self.ban_log_store {
             let entry = BanLogEntry {
                 id: format!("ban_manual_{}_{}", info.resolved.id, now),
                 api_key_id: info.resolved.id.clone(),
+                subject: BanSubject::Key,
                 rule_id: "manual".to_string(),
                 reason: reason.clone(),
                 banned_at: now,
-                banned_until: ban_until,
+                banned_until: ban_until.unwrap_or(now),
                 unbanned_at: None,
-                metrics_snapshot: BanMetricsSnapshot {
-                    requests: 0,
-                    errors: 0,
-                    error_rate: 0.0,
-                },
+                metrics_snapshot: BanMetricsSnapshot::default(),
+                offense: new_status.ban_count,
+                permanent: new_status.permanent,
+                shadow: false,
+                alert: false,
             };
 
             tracing::info!(
-                "Inserting manual ban log for key {} (until: {})",
+                "Inserting manual ban log for key {} (until: {:?})",
                 info.resolved.id,
                 ban_until
             );
@@ -500,32 +1140,38 @@ impl ApiKeyManager {
         Ok(new_status)
     }
 
-    pub async fn unban_key(&self, key_value: &str) -> Result<(), ApiKeyError> {
+    pub async fn unban_key(&self, key_id: &str) -> Result<(), ApiKeyError> {
         let mut keys = self.keys.write().await;
-        let info = keys.get_mut(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get_mut(key_id).ok_or(ApiKeyError::KeyNotFound)?;
 
         if let Some(status) = &mut info.resolved.ban_status {
             let now = current_epoch_seconds();
             let was_banned = status.is_banned;
             status.is_banned = false;
+            if was_banned {
+                // 记录实际结束时间，升级策略的静默期由此起算
+                status.banned_until = Some(status.banned_until.map_or(now, |until| until.min(now)));
+                status.permanent = false;
+            }
+            self.mark_ban_status_dirty(&info.resolved.id);
 
             // 如果之前是封禁状态，尝试更新封禁日志的解封时间
             if was_banned {
                 if let Some(store) = &self.ban_log_store {
                     if let Some(banned_at) = status.banned_at {
                         // 尝试两种可能的 entry_id 格式：
-                        // 1. 自动封禁: ban_{key_value}_{banned_at}
+                        // 1. 自动封禁: ban_{key_id}_{banned_at}
                         // 2. 手动封禁: ban_manual_{key_id}_{banned_at}
                         let entry_ids = if let Some(_rule_id) = &status.triggered_rule_id {
                             // 自动封禁：只有一个可能的 ID
-                            vec![format!("ban_{}_{}", key_value, banned_at)]
+                            vec![format!("ban_{}_{}", key_id, banned_at)]
                         } else {
                             // 手动封禁：只有一个可能的 ID
                             vec![format!("ban_manual_{}_{}", info.resolved.id, banned_at)]
                         };
 
                         let store = Arc::clone(store);
-                        let key_value_owned = key_value.to_string();
+                        let key_id_owned = key_id.to_string();
                         tokio::spawn(async move {
                             for entry_id in entry_ids {
                                 if let Ok(()) = store.mark_unbanned(&entry_id, now).await {
@@ -533,7 +1179,7 @@ impl ApiKeyManager {
                                     return;
                                 }
                             }
-                            tracing::warn!("Failed to find ban log entry to mark as unbanned for key: {}", key_value_owned);
+                            tracing::warn!("Failed to find ban log entry to mark as unbanned for key: {}", key_id_owned);
                         });
                     }
                 }
@@ -543,35 +1189,264 @@ impl ApiKeyManager {
         Ok(())
     }
 
-    pub async fn get_key_by_id(&self, id: &str) -> Option<String> {
-        let id_index = self.id_index.read().await;
-        id_index.get(id).cloned()
-    }
-
     pub async fn get_all_keys(&self) -> Vec<ResolvedApiKey> {
         let keys = self.keys.read().await;
-        keys.values()
+        keys.configured
+            .values()
             .map(|info| info.resolved.clone())
             .collect()
     }
 
-    pub async fn get_key_info(&self, key_value: &str) -> Option<ResolvedApiKey> {
+    pub async fn get_key_info(&self, key_id: &str) -> Option<ResolvedApiKey> {
         let keys = self.keys.read().await;
-        keys.get(key_value).map(|info| info.resolved.clone())
+        keys.get(key_id).map(|info| info.resolved.clone())
     }
 
     pub fn ban_log_store(&self) -> Option<Arc<dyn BanLogStore>> {
         self.ban_log_store.clone()
     }
 
+    /// 标记封禁状态待推送到共享状态后端
+    pub fn mark_ban_status_dirty(&self, api_key_id: &str) {
+        if self.shared_state.is_shared() {
+            self.dirty_ban_statuses.lock().unwrap().insert(api_key_id.to_string());
+        }
+    }
+
+    /// 取出待推送的封禁状态
+    pub async fn take_dirty_ban_statuses(&self) -> Vec<(String, BanStatus)> {
+        let dirty = std::mem::take(&mut *self.dirty_ban_statuses.lock().unwrap());
+        if dirty.is_empty() {
+            return Vec::new();
+        }
+        let keys = self.keys.read().await;
+        dirty
+            .into_iter()
+            .filter_map(|id| {
+                let status = keys.get(&id)?.resolved.ban_status.clone()?;
+                Some((id, status))
+            })
+            .collect()
+    }
+
+    /// 应用其他实例写入共享状态后端的封禁状态（本实例尚未推送的变更优先）
+    pub async fn apply_shared_ban_statuses(&self, statuses: &HashMap<String, BanStatus>) {
+        let mut keys = self.keys.write().await;
+        let dirty = self.dirty_ban_statuses.lock().unwrap().clone();
+        for (id, status) in statuses {
+            if dirty.contains(id) {
+                continue;
+            }
+            let Some(info) = keys.get_mut(id) else {
+                continue;
+            };
+            if info.resolved.ban_status.as_ref() == Some(status) {
+                continue;
+            }
+            tracing::info!(
+                "Applying shared ban status for key {} (banned: {}, until: {:?})",
+                id,
+                status.is_banned,
+                status.banned_until
+            );
+            info.resolved.ban_status = Some(status.clone());
+        }
+    }
+
+    /// 标记 IP / 网段封禁状态待推送到共享状态后端
+    pub fn mark_ip_ban_dirty(&self, subject: BanSubject, target: &str) {
+        if self.shared_state.is_shared() {
+            self.dirty_ip_bans.lock().unwrap().insert((subject, target.to_string()));
+        }
+    }
+
+    /// 取出待推送的 IP / 网段封禁状态
+    pub fn take_dirty_ip_bans(&self) -> Vec<((BanSubject, String), BanStatus)> {
+        let dirty = std::mem::take(&mut *self.dirty_ip_bans.lock().unwrap());
+        if dirty.is_empty() {
+            return Vec::new();
+        }
+        let bans = self.ip_bans.read().unwrap();
+        dirty
+            .into_iter()
+            .filter_map(|key| {
+                let status = bans.get(&key)?.clone();
+                Some((key, status))
+            })
+            .collect()
+    }
+
+    /// 应用其他实例写入共享状态后端的 IP / 网段封禁状态（本实例尚未推送的变更优先）
+    pub fn apply_shared_ip_bans(&self, statuses: &HashMap<(BanSubject, String), BanStatus>) {
+        let dirty = self.dirty_ip_bans.lock().unwrap().clone();
+        let mut bans = self.ip_bans.write().unwrap();
+        for (key, status) in statuses {
+            if dirty.contains(key) || bans.get(key) == Some(status) {
+                continue;
+            }
+            tracing::info!(
+                "Applying shared ban status for {} {} (banned: {}, until: {:?})",
+                key.0.as_str(),
+                key.1,
+                status.is_banned,
+                status.banned_until
+            );
+            bans.insert(key.clone(), status.clone());
+        }
+    }
+
+    /// 新增或更新单个 Key
+    ///
+    /// 已有 Key 保留封禁引擎、运行时封禁状态与最近使用时间，限流/并发配置未变化时沿用原限制器；
+    /// 其他 Key 不受影响。
+    pub async fn upsert_key(&self, resolved: ResolvedApiKey) {
+        let key_id = resolved.id.clone();
+        let mut keys = self.keys.write().await;
+        let old_prefixes = match keys.configured.get_mut(&key_id) {
+            Some(info) => {
+                let old_prefixes = ApiKeyRuntimeInfo::key_prefixes(&info.resolved);
+                info.update(resolved);
+                old_prefixes
+            }
+            None => {
+                keys.configured
+                    .insert(key_id.clone(), ApiKeyRuntimeInfo::new(resolved, self.ban_max_window_secs));
+                HashSet::new()
+            }
+        };
+
+        let new_prefixes = ApiKeyRuntimeInfo::key_prefixes(&keys.configured[&key_id].resolved);
+        if old_prefixes != new_prefixes {
+            let mut prefix_index = self.prefix_index.write().unwrap();
+            for old_prefix in old_prefixes.difference(&new_prefixes) {
+                remove_from_prefix_index(&mut prefix_index, old_prefix, &key_id);
+            }
+            for new_prefix in new_prefixes.difference(&old_prefixes) {
+                add_to_prefix_index(&mut prefix_index, new_prefix.clone(), &key_id);
+            }
+        }
+    }
+
+    /// 删除单个 Key，返回 Key 是否存在
+    pub async fn remove_key(&self, key_id: &str) -> bool {
+        let Some(info) = self.keys.write().await.configured.remove(key_id) else {
+            return false;
+        };
+        let mut prefix_index = self.prefix_index.write().unwrap();
+        for key_prefix in ApiKeyRuntimeInfo::key_prefixes(&info.resolved) {
+            remove_from_prefix_index(&mut prefix_index, &key_prefix, key_id);
+        }
+        true
+    }
+
+    /// 轮换后旧 Key 的使用情况
+    pub async fn previous_key_usage(&self, key_id: &str) -> Option<Vec<PreviousKeyUsage>> {
+        let now = current_epoch_seconds();
+        let keys = self.keys.read().await;
+        let info = keys.get(key_id)?;
+        let uses = info.previous_key_uses.lock().unwrap();
+        Some(
+            info.resolved
+                .previous_keys
+                .iter()
+                .map(|previous| {
+                    let (count, last_used_at) = uses.get(&previous.key_hash).copied().unwrap_or_default();
+                    PreviousKeyUsage {
+                        key_prefix: previous.key_prefix.clone(),
+                        rotated_at: previous.rotated_at,
+                        expires_at: previous.expires_at,
+                        active: now < previous.expires_at,
+                        uses: count,
+                        last_used_at: (last_used_at > 0).then_some(last_used_at),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// 计算全部 Key 当前的生命周期状态
+    pub async fn lifecycle_statuses(&self) -> Vec<KeyLifecycleStatus> {
+        let now = current_epoch_seconds();
+        let keys = self.keys.read().await;
+        keys.configured
+            .values()
+            .map(|info| {
+                let last_used_at = info.last_used_at.load(Ordering::Relaxed);
+                KeyLifecycleStatus {
+                    key_id: info.resolved.id.clone(),
+                    state: lifecycle::evaluate(&info.resolved, last_used_at, now, self.expiry_warning_secs),
+                    expires_at: lifecycle::effective_expires_at(&info.resolved, last_used_at),
+                    last_used_at: (last_used_at > 0).then_some(last_used_at),
+                }
+            })
+            .collect()
+    }
+
+    /// 重新计算生命周期预警（后台任务定期调用），新进入预警的 Key 记录日志
+    pub async fn refresh_lifecycle_alerts(&self) {
+        let mut alerts: Vec<KeyLifecycleStatus> = self
+            .lifecycle_statuses()
+            .await
+            .into_iter()
+            .filter(|status| status.state != KeyLifecycleState::Active)
+            .collect();
+        alerts.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+
+        let mut current = self.lifecycle_alerts.lock().unwrap();
+        for alert in &alerts {
+            let is_new = !current
+                .iter()
+                .any(|old| old.key_id == alert.key_id && old.state == alert.state);
+            if is_new {
+                tracing::warn!(
+                    "API key {} lifecycle state is {:?} (expires_at: {:?})",
+                    alert.key_id,
+                    alert.state,
+                    alert.expires_at
+                );
+            }
+        }
+        *current = alerts;
+    }
+
+    /// 最近一次后台任务标记的生命周期预警
+    pub fn lifecycle_alerts(&self) -> Vec<KeyLifecycleStatus> {
+        self.lifecycle_alerts.lock().unwrap().clone()
+    }
+
+    /// 仅告警的封禁规则最近触发记录，最新的在前
+    pub fn ban_alerts(&self) -> Vec<BanAlert> {
+        self.ban_alerts.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    /// 已知的 Key 最近使用时间（key_id, Unix秒）
+    pub async fn last_used_snapshot(&self) -> Vec<(String, u64)> {
+        let keys = self.keys.read().await;
+        keys.configured
+            .iter()
+            .map(|(key_id, info)| (key_id.clone(), info.last_used_at.load(Ordering::Relaxed)))
+            .filter(|(_, last_used_at)| *last_used_at > 0)
+            .collect()
+    }
+
+    /// 恢复最近使用时间（取已有值与给定值中较晚者）
+    pub async fn restore_last_used(&self, last_used: &HashMap<String, u64>) {
+        let keys = self.keys.read().await;
+        for (key_id, used_at) in last_used {
+            if let Some(info) = keys.get(key_id) {
+                info.last_used_at.fetch_max(*used_at, Ordering::Relaxed);
+            }
+        }
+    }
+
     /// 恢复封禁状态（用于配置热更新时迁移状态）
     pub async fn restore_ban_status(
         &self,
-        key_value: &str,
+        key_id: &str,
         ban_status: BanStatus,
     ) -> Result<(), ApiKeyError> {
         let mut keys = self.keys.write().await;
-        let info = keys.get_mut(key_value).ok_or(ApiKeyError::KeyNotFound)?;
+        let info = keys.get_mut(key_id).ok_or(ApiKeyError::KeyNotFound)?;
         info.resolved.ban_status = Some(ban_status);
         Ok(())
     }
@@ -581,6 +1456,7 @@ pub async fn create_api_key_manager(
     config: &crate::config::AppConfig,
     old_manager: Option<&ApiKeyManager>,
     token_quota_checker: Option<Arc<TokenQuotaChecker>>,
+    shared_state: SharedState,
 ) -> Option<ApiKeyManager> {
     let resolved_keys = config.resolved_api_keys();
     if resolved_keys.is_empty() {
@@ -608,49 +1484,145 @@ pub async fn create_api_key_manager(
             }
         };
 
-        let new_manager = ApiKeyManager::new(resolved_keys, ban_log_store, global_ban_rules, token_quota_checker);
-
-        // 如果有旧的 manager，迁移封禁状态
+        let new_manager = ApiKeyManager::new(
+            resolved_keys,
+            ban_log_store,
+            global_ban_rules,
+            token_quota_checker,
+            shared_state,
+            config
+                .api_keys
+                .as_ref()
+                .map_or(0, |ak| ak.expiry_warning_days.saturating_mul(24 * 60 * 60)),
+        );
+
+        // 如果有旧的 manager，迁移封禁状态、最近使用时间与旧 Key 使用记录；否则从封禁日志恢复
         if let Some(old) = old_manager {
             migrate_ban_status(&new_manager, old).await;
+            migrate_previous_key_uses(&new_manager, old).await;
+            new_manager.ip_bans.write().unwrap().clone_from(&old.ip_bans.read().unwrap());
+            new_manager.ban_alerts.lock().unwrap().clone_from(&old.ban_alerts.lock().unwrap());
+            // 动态身份按请求重新应用模板，限流器与封禁状态随缓存一并沿用
+            new_manager.keys.write().await.dynamic = std::mem::take(&mut old.keys.write().await.dynamic);
+            let last_used: HashMap<String, u64> = old.last_used_snapshot().await.into_iter().collect();
+            new_manager.restore_last_used(&last_used).await;
+        } else {
+            restore_ban_history(&new_manager).await;
         }
 
         Some(new_manager)
     }
 }
 
+/// 迁移旧 Key 使用记录（仅保留新配置中仍存在的旧 Key）
+async fn migrate_previous_key_uses(new_manager: &ApiKeyManager, old_manager: &ApiKeyManager) {
+    let old_keys = old_manager.keys.read().await;
+    let new_keys = new_manager.keys.read().await;
+    for (key_id, new_info) in &new_keys.configured {
+        let Some(old_info) = old_keys.get(key_id) else {
+            continue;
+        };
+        let old_uses = old_info.previous_key_uses.lock().unwrap();
+        let mut new_uses = new_info.previous_key_uses.lock().unwrap();
+        for previous in &new_info.resolved.previous_keys {
+            if let Some(usage) = old_uses.get(&previous.key_hash) {
+                new_uses.insert(previous.key_hash.clone(), *usage);
+            }
+        }
+    }
+}
+
 /// 从旧的 ApiKeyManager 迁移封禁状态到新的 Manager
+///
+/// 已过期的封禁也一并迁移，以保留升级策略所需的累计封禁次数。
 async fn migrate_ban_status(new_manager: &ApiKeyManager, old_manager: &ApiKeyManager) {
     // 获取旧 manager 中的所有 key 及其封禁状态
     let old_keys = old_manager.get_all_keys().await;
 
     for old_key in old_keys {
-        if let Some(ban_status) = &old_key.ban_status {
-            // 只迁移处于封禁状态且未过期的
-            if ban_status.is_banned {
-                if let Some(until) = ban_status.banned_until {
-                    let now = crate::api_keys::current_epoch_seconds();
-                    if now < until {
-                        // 封禁仍然有效，迁移状态
-                        if let Err(e) = new_manager
-                            .restore_ban_status(&old_key.key, ban_status.clone())
-                            .await
-                        {
-                            tracing::warn!(
-                                "Failed to migrate ban status for key {}: {}",
-                                old_key.id,
-                                e
-                            );
-                        } else {
-                            tracing::info!(
-                                "Migrated ban status for key {} (banned until {})",
-                                old_key.id,
-                                until
-                            );
-                        }
-                    }
-                }
+        let Some(ban_status) = old_key.ban_status else {
+            continue;
+        };
+        let is_banned = ban_status.is_banned;
+        let until = ban_status.banned_until;
+        match new_manager.restore_ban_status(&old_key.id, ban_status).await {
+            Err(e) => tracing::warn!("Failed to migrate ban status for key {}: {}", old_key.id, e),
+            Ok(()) if is_banned => {
+                tracing::info!("Migrated ban status for key {} (banned until {:?})", old_key.id, until)
+            }
+            Ok(()) => {}
+        }
+    }
+}
+
+/// 从封禁日志恢复各 Key、IP 与网段最近一次封禁的状态与累计次数，以及最近的告警（进程重启后）
+async fn restore_ban_history(manager: &ApiKeyManager) {
+    let Some(store) = manager.ban_log_store() else {
+        return;
+    };
+    let now = current_epoch_seconds();
+    match store.query_recent_alerts(MAX_BAN_ALERTS).await {
+        Ok(entries) => {
+            let mut alerts = manager.ban_alerts.lock().unwrap();
+            alerts.extend(entries.into_iter().rev().map(|entry| BanAlert {
+                api_key_id: entry.api_key_id,
+                subject: entry.subject,
+                rule_id: entry.rule_id,
+                reason: entry.reason,
+                triggered_at: entry.banned_at,
+                metrics_snapshot: entry.metrics_snapshot,
+            }));
+        }
+        Err(e) => tracing::warn!("Failed to restore ban alerts from ban log: {}", e),
+    }
+    for subject in [BanSubject::Ip, BanSubject::Subnet] {
+        let entries = match store.query_latest_per_subject(subject).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Failed to restore {} bans from ban log: {}", subject.as_str(), e);
+                continue;
+            }
+        };
+        let mut bans = manager.ip_bans.write().unwrap();
+        for entry in entries {
+            let status = entry.to_ban_status(now);
+            if status.is_banned {
+                tracing::info!(
+                    "Restored ban for {} {} (until: {:?}, permanent: {})",
+                    subject.as_str(),
+                    entry.api_key_id,
+                    status.banned_until,
+                    status.permanent
+                );
+            }
+            bans.insert((subject, entry.api_key_id), status);
+        }
+    }
+
+    let entries = match store.query_latest_per_subject(BanSubject::Key).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Failed to restore ban history from ban log: {}", e);
+            return;
+        }
+    };
+
+    let mut keys = manager.keys.write().await;
+    for entry in entries {
+        let Some(info) = keys.get_mut(&entry.api_key_id) else {
+            continue;
+        };
+        if info.resolved.ban_status.is_none() {
+            let status = entry.to_ban_status(now);
+            if status.is_banned {
+                tracing::info!(
+                    "Restored ban for key {} (until: {:?}, permanent: {})",
+                    entry.api_key_id,
+                    status.banned_until,
+                    status.permanent
+                );
             }
+            info.resolved.ban_status = Some(status);
         }
     }
 }
@@ -1,10 +1,13 @@
 pub mod ban;
 pub mod ban_log;
+pub mod key_hash;
+pub mod lifecycle;
 pub mod manager;
 
-pub use ban::{BanCondition, BanRule, BanStatus, BanMetricsSnapshot, BanRuleEngine, TriggeredRule};
-pub use ban_log::{BanLogEntry, BanLogStore, SqliteBanLogStore, InMemoryBanLogStore};
-pub use manager::{ApiKeyManager, ApiKeyError, ApiKeyRuntimeInfo, ValidationResult, RequestResult, create_api_key_manager};
+pub use ban::{BanCondition, BanRule, BanStatus, BanSubject, BanMetricsSnapshot, BanRuleEngine, TriggeredRule};
+pub use ban_log::{BanLogEntry, BanLogStore, ShadowBanCount, SqliteBanLogStore, InMemoryBanLogStore};
+pub use lifecycle::{KeyLifecycleState, KeyLifecycleStatus};
+pub use manager::{ApiKeyManager, ApiKeyError, BanAlert, ApiKeyRuntimeInfo, IpBan, PreviousKeyUsage, ValidationResult, RequestResult, create_api_key_manager};
 
 /// 生成 API Key ID（从 key 值生成）
 pub fn generate_key_id(key: &str) -> String {
@@ -1,33 +1,183 @@
-use crate::config::TokenSourceConfig;
+use crate::config::{GatewayAuthConfig, TokenSourceConfig};
+use crate::jwt::{self, JwtError, JwtIdentity, JwtVerifier};
 use http::header::AUTHORIZATION;
 use http::{HeaderMap, HeaderName};
 
-pub fn extract_token(headers: &HeaderMap, token_sources: &[TokenSourceConfig]) -> Option<String> {
-    for source in token_sources {
-        match source {
-            TokenSourceConfig::AuthorizationBearer => {
-                if let Some(value) = headers.get(AUTHORIZATION)
-                    && let Ok(text) = value.to_str()
-                    && let Some(token) = parse_bearer_token(text)
-                {
-                    return Some(token.to_string());
-                }
+/// 请求携带的凭证
+pub enum Credential {
+    /// 不透明 API Key
+    ApiKey(String),
+    /// JWT 及其校验结果
+    Jwt {
+        token: String,
+        identity: Result<JwtIdentity, JwtError>,
+    },
+}
+
+impl Credential {
+    pub fn token(&self) -> &str {
+        match self {
+            Credential::ApiKey(token) | Credential::Jwt { token, .. } => token,
+        }
+    }
+}
+
+/// 凭证在请求中的位置，转发前从请求中移除，避免网关 Key 泄露给上游
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialLocation {
+    Header(HeaderName),
+    Query(String),
+}
+
+impl CredentialLocation {
+    /// 从请求头或查询串中移除凭证，返回移除后的查询串
+    pub fn strip(&self, headers: &mut HeaderMap, query: Option<&str>) -> Option<String> {
+        match self {
+            CredentialLocation::Header(name) => {
+                headers.remove(name);
+                query.map(str::to_string)
             }
-            TokenSourceConfig::Header { name } => {
-                if let Ok(header_name) = HeaderName::from_bytes(name.as_bytes())
-                    && let Some(value) = headers.get(header_name)
-                    && let Ok(text) = value.to_str()
-                    && !text.trim().is_empty()
-                {
-                    return Some(text.trim().to_string());
+            CredentialLocation::Query(name) => query.and_then(|query| strip_query_param(query, name)),
+        }
+    }
+}
+
+/// 按 `gateway_auth.token_sources` 顺序提取凭证
+///
+/// `jwt` 来源只认领形如 JWT 的值，其余值继续交给后续来源，便于与同一请求头上的 API Key 共存。
+pub struct Authenticator {
+    sources: Vec<AuthSource>,
+}
+
+enum AuthSource {
+    Opaque(TokenSourceConfig),
+    Jwt(Box<JwtVerifier>),
+}
+
+impl Authenticator {
+    pub fn from_config(config: &GatewayAuthConfig) -> Result<Self, String> {
+        Self::from_sources(&config.token_sources)
+    }
+
+    pub fn from_sources(token_sources: &[TokenSourceConfig]) -> Result<Self, String> {
+        let sources = token_sources
+            .iter()
+            .map(|source| match source {
+                TokenSourceConfig::Jwt(jwt) => JwtVerifier::from_config(jwt).map(|verifier| AuthSource::Jwt(Box::new(verifier))),
+                other => Ok(AuthSource::Opaque(other.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { sources })
+    }
+
+    pub fn extract(&self, headers: &HeaderMap, query: Option<&str>) -> Option<(Credential, CredentialLocation)> {
+        for source in &self.sources {
+            match source {
+                AuthSource::Opaque(source) => {
+                    if let Some((token, location)) = extract_token(headers, query, std::slice::from_ref(source)) {
+                        return Some((Credential::ApiKey(token), location));
+                    }
+                }
+                AuthSource::Jwt(verifier) => {
+                    let token = match verifier.header() {
+                        Some(name) => header_token(headers, name),
+                        None => bearer_token(headers),
+                    };
+                    if let Some((token, location)) = token
+                        && jwt::looks_like_jwt(&token)
+                    {
+                        let identity = verifier.verify(&token);
+                        return Some((Credential::Jwt { token, identity }, location));
+                    }
                 }
             }
         }
+
+        None
+    }
+}
+
+/// 按不透明 API Key 来源提取 token 及其位置（忽略 `jwt` 来源）
+pub fn extract_token(
+    headers: &HeaderMap,
+    query: Option<&str>,
+    token_sources: &[TokenSourceConfig],
+) -> Option<(String, CredentialLocation)> {
+    for source in token_sources {
+        let token = match source {
+            TokenSourceConfig::AuthorizationBearer => bearer_token(headers),
+            TokenSourceConfig::Header { name } => header_token(headers, name),
+            TokenSourceConfig::Query { name } => query.and_then(|query| query_token(query, name)),
+            TokenSourceConfig::Jwt(_) => None,
+        };
+        if token.is_some() {
+            return token;
+        }
     }
 
     None
 }
 
+fn bearer_token(headers: &HeaderMap) -> Option<(String, CredentialLocation)> {
+    let text = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let token = parse_bearer_token(text)?;
+    Some((token.to_string(), CredentialLocation::Header(AUTHORIZATION)))
+}
+
+fn header_token(headers: &HeaderMap, name: &str) -> Option<(String, CredentialLocation)> {
+    let header_name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+    let text = headers.get(&header_name)?.to_str().ok()?.trim();
+    (!text.is_empty()).then(|| (text.to_string(), CredentialLocation::Header(header_name)))
+}
+
+fn query_token(query: &str, name: &str) -> Option<(String, CredentialLocation)> {
+    query_pairs(query)
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(|value| (value, CredentialLocation::Query(name.to_string())))
+}
+
+/// 移除查询串中的指定参数，其余参数保持原样；移除后为空时返回 None
+fn strip_query_param(query: &str, name: &str) -> Option<String> {
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && percent_decode(pair.split_once('=').map_or(*pair, |(key, _)| key)) != name)
+        .collect();
+    (!kept.is_empty()).then(|| kept.join("&"))
+}
+
+fn query_pairs(query: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    query.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (percent_decode(key), percent_decode(value))
+    })
+}
+
+/// application/x-www-form-urlencoded 解码（`+` 视为空格，非法转义原样保留）
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 fn parse_bearer_token(value: &str) -> Option<&str> {
     let (scheme, token) = value.trim().split_once(' ')?;
 
@@ -45,8 +195,9 @@ fn parse_bearer_token(value: &str) -> Option<&str> {
 
 #[cfg(test)]
 mod tests {
-    use super::extract_token;
-    use crate::config::TokenSourceConfig;
+    use super::{Authenticator, Credential, CredentialLocation, extract_token};
+    use crate::config::{GatewayAuthConfig, TokenSourceConfig};
+    use crate::jwt::JwtError;
     use http::header::AUTHORIZATION;
     use http::{HeaderMap, HeaderValue};
 
@@ -55,8 +206,11 @@ mod tests {
         let mut headers = HeaderMap::new();
         headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer gw_token"));
 
-        let token = extract_token(&headers, &[TokenSourceConfig::AuthorizationBearer]);
-        assert_eq!(token.as_deref(), Some("gw_token"));
+        let token = extract_token(&headers, None, &[TokenSourceConfig::AuthorizationBearer]);
+        assert_eq!(
+            token,
+            Some(("gw_token".to_string(), CredentialLocation::Header(AUTHORIZATION)))
+        );
     }
 
     #[test]
@@ -66,6 +220,7 @@ mod tests {
 
         let token = extract_token(
             &headers,
+            None,
             &[
                 TokenSourceConfig::AuthorizationBearer,
                 TokenSourceConfig::Header {
@@ -74,7 +229,7 @@ mod tests {
             ],
         );
 
-        assert_eq!(token.as_deref(), Some("fallback_token"));
+        assert_eq!(token.map(|(token, _)| token).as_deref(), Some("fallback_token"));
     }
 
     #[test]
@@ -84,19 +239,88 @@ mod tests {
 
         let token = extract_token(
             &headers,
+            None,
             &[TokenSourceConfig::Header {
                 name: "x-api-key".to_string(),
             }],
         );
 
-        assert_eq!(token.as_deref(), Some("api_key_value"));
+        assert_eq!(token.map(|(token, _)| token).as_deref(), Some("api_key_value"));
+    }
+
+    #[test]
+    fn jwt_source_leaves_opaque_keys_to_later_sources() {
+        let config: GatewayAuthConfig = serde_yaml::from_str(
+            r#"
+token_sources:
+  - type: jwt
+    keys:
+      - alg: HS256
+        secret: "jwt_secret"
+    key_id_claim: "gw_key"
+  - type: authorization_bearer
+"#,
+        )
+        .unwrap();
+        let authenticator = Authenticator::from_config(&config).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer gw_token"));
+        assert!(matches!(authenticator.extract(&headers, None), Some((Credential::ApiKey(token), _)) if token == "gw_token"));
+
+        // 签名无效的 JWT 不会回落为 API Key
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer eyJhbGciOiJIUzI1NiJ9.e30.c2ln"));
+        assert!(matches!(
+            authenticator.extract(&headers, None),
+            Some((Credential::Jwt { identity: Err(JwtError::InvalidSignature), .. }, _))
+        ));
+    }
+
+    #[test]
+    fn query_token_is_decoded_and_stripped() {
+        let headers = HeaderMap::new();
+        let query = Some("alt=sse&key=gw%5Ftoken&x=1");
+
+        let (token, location) = extract_token(
+            &headers,
+            query,
+            &[TokenSourceConfig::Query {
+                name: "key".to_string(),
+            }],
+        )
+        .expect("query token should be extracted");
+        assert_eq!(token, "gw_token");
+        assert_eq!(location, CredentialLocation::Query("key".to_string()));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(location.strip(&mut headers, query).as_deref(), Some("alt=sse&x=1"));
+        assert_eq!(location.strip(&mut headers, Some("key=gw_token")), None);
+    }
+
+    #[test]
+    fn header_location_is_removed() {
+        let mut headers = HeaderMap::new();
+        headers.insert("api-key", HeaderValue::from_static("gw_token"));
+        headers.insert("x-other", HeaderValue::from_static("kept"));
+
+        let (_, location) = extract_token(
+            &headers,
+            Some("a=1"),
+            &[TokenSourceConfig::Header {
+                name: "Api-Key".to_string(),
+            }],
+        )
+        .expect("header token should be extracted");
+        assert_eq!(location.strip(&mut headers, Some("a=1")).as_deref(), Some("a=1"));
+        assert!(!headers.contains_key("api-key"));
+        assert!(headers.contains_key("x-other"));
     }
 
     #[test]
     fn no_token_found() {
         let headers = HeaderMap::new();
 
-        let token = extract_token(&headers, &[TokenSourceConfig::AuthorizationBearer]);
+        let token = extract_token(&headers, None, &[TokenSourceConfig::AuthorizationBearer]);
         assert_eq!(token, None);
     }
 }
@@ -0,0 +1,269 @@
+//! 鉴权失败防护
+//!
+//! 按客户端 IP 统计窗口内的鉴权失败（未知 API Key、无效 JWT、错误的管理 Token）：
+//! 失败响应按次数递增延迟，达到上限后临时锁定该 IP，锁定期间的鉴权请求直接拒绝。
+//! 鉴权成功不清零计数，避免持有一个有效 Key 的调用方穿插成功请求来无限试探。
+
+use crate::config::AuthProtectionConfig;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// 跟踪的 IP 数上限，达到时先清理已过期的条目，仍满则不再跟踪新 IP
+const MAX_TRACKED_CLIENTS: usize = 100_000;
+
+/// 鉴权失败来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScope {
+    /// 代理请求携带的 API Key / JWT
+    Proxy,
+    /// 管理接口 Token
+    Admin,
+}
+
+impl AuthScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthScope::Proxy => "proxy",
+            AuthScope::Admin => "admin",
+        }
+    }
+}
+
+/// 记录一次失败后的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureOutcome {
+    /// 延迟后返回 401
+    Delay(Duration),
+    /// 本次失败触发锁定，锁定到该时间（Unix 秒）
+    Locked { until: u64 },
+}
+
+/// 锁定中的客户端
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockedClient {
+    pub ip: String,
+    pub scope: AuthScope,
+    pub locked_at: u64,
+    pub locked_until: u64,
+    /// 累计锁定次数
+    pub lockouts: u32,
+}
+
+#[derive(Debug, Default)]
+struct ClientFailures {
+    failures: VecDeque<u64>,
+    locked: Option<(AuthScope, u64, u64)>,
+    lockouts: u32,
+}
+
+impl ClientFailures {
+    fn prune(&mut self, window_secs: u64, now: u64) {
+        while self.failures.front().is_some_and(|at| at.saturating_add(window_secs) <= now) {
+            self.failures.pop_front();
+        }
+        if self.locked.is_some_and(|(_, _, until)| until <= now) {
+            self.locked = None;
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.failures.is_empty() && self.locked.is_none()
+    }
+}
+
+/// 按客户端 IP 统计鉴权失败，跨配置热重载保留
+#[derive(Debug)]
+pub struct AuthFailureTracker {
+    clients: DashMap<String, ClientFailures>,
+    max_clients: usize,
+}
+
+impl Default for AuthFailureTracker {
+    fn default() -> Self {
+        Self::with_max_clients(MAX_TRACKED_CLIENTS)
+    }
+}
+
+impl AuthFailureTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_max_clients(max_clients: usize) -> Self {
+        Self {
+            clients: DashMap::new(),
+            max_clients,
+        }
+    }
+
+    /// 该 IP 处于锁定中时返回剩余秒数
+    pub fn locked_for(&self, ip: &str, now: u64) -> Option<u64> {
+        let client = self.clients.get(ip)?;
+        let (_, _, until) = client.locked?;
+        (until > now).then(|| until - now)
+    }
+
+    pub fn record_failure(&self, config: &AuthProtectionConfig, ip: &str, scope: AuthScope, now: u64) -> FailureOutcome {
+        if self.clients.len() >= self.max_clients && !self.clients.contains_key(ip) {
+            self.clients.retain(|_, client| {
+                client.prune(config.window_secs, now);
+                !client.is_idle()
+            });
+            // 全部条目仍活跃：不淘汰已有记录（避免刷量挤掉锁定），新 IP 直接按最大延迟处理
+            if self.clients.len() >= self.max_clients {
+                return FailureOutcome::Delay(Duration::from_millis(config.max_delay_ms));
+            }
+        }
+
+        let mut client = self.clients.entry(ip.to_string()).or_default();
+        client.prune(config.window_secs, now);
+        client.failures.push_back(now);
+
+        let failures = client.failures.len() as u64;
+        if failures >= u64::from(config.max_failures) {
+            let until = now.saturating_add(config.lockout_secs);
+            client.failures.clear();
+            client.locked = Some((scope, now, until));
+            client.lockouts = client.lockouts.saturating_add(1);
+            return FailureOutcome::Locked { until };
+        }
+
+        let delay_ms = config.delay_ms.saturating_mul(failures - 1).min(config.max_delay_ms);
+        FailureOutcome::Delay(Duration::from_millis(delay_ms))
+    }
+
+    /// 当前锁定中的客户端，按解锁时间排序
+    pub fn locked_clients(&self, now: u64) -> Vec<LockedClient> {
+        let mut locked: Vec<LockedClient> = self
+            .clients
+            .iter()
+            .filter_map(|entry| {
+                let (scope, locked_at, locked_until) = entry.locked?;
+                (locked_until > now).then(|| LockedClient {
+                    ip: entry.key().clone(),
+                    scope,
+                    locked_at,
+                    locked_until,
+                    lockouts: entry.lockouts,
+                })
+            })
+            .collect();
+        locked.sort_by(|a, b| a.locked_until.cmp(&b.locked_until).then_with(|| a.ip.cmp(&b.ip)));
+        locked
+    }
+
+    /// 手动解除锁定并清空失败计数，返回该 IP 此前是否处于锁定中
+    pub fn unlock(&self, ip: &str, now: u64) -> bool {
+        self.clients
+            .remove(ip)
+            .is_some_and(|(_, client)| client.locked.is_some_and(|(_, _, until)| until > now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AuthProtectionConfig {
+        AuthProtectionConfig {
+            max_failures: 3,
+            window_secs: 60,
+            lockout_secs: 300,
+            delay_ms: 100,
+            max_delay_ms: 150,
+        }
+    }
+
+    #[test]
+    fn failures_delay_progressively_then_lock() {
+        let tracker = AuthFailureTracker::new();
+        let config = config();
+
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_000),
+            FailureOutcome::Delay(Duration::ZERO)
+        );
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_001),
+            FailureOutcome::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(tracker.locked_for("10.0.0.1", 1_002), None);
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Admin, 1_002),
+            FailureOutcome::Locked { until: 1_302 }
+        );
+        assert_eq!(tracker.locked_for("10.0.0.1", 1_010), Some(292));
+        assert_eq!(tracker.locked_for("10.0.0.2", 1_010), None);
+
+        let locked = tracker.locked_clients(1_010);
+        assert_eq!(locked.len(), 1);
+        assert_eq!(locked[0].scope, AuthScope::Admin);
+        assert_eq!(locked[0].lockouts, 1);
+
+        // 锁定到期后自动解除
+        assert_eq!(tracker.locked_for("10.0.0.1", 1_302), None);
+        assert!(tracker.locked_clients(1_302).is_empty());
+    }
+
+    #[test]
+    fn failures_outside_window_expire_and_unlock_clears_state() {
+        let tracker = AuthFailureTracker::new();
+        let config = config();
+
+        tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_000);
+        tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_001);
+        // 前两次失败已滑出窗口
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_061),
+            FailureOutcome::Delay(Duration::from_millis(0))
+        );
+
+        for now in 1_062..1_064 {
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, now);
+        }
+        assert!(tracker.locked_for("10.0.0.1", 1_064).is_some());
+        assert!(tracker.unlock("10.0.0.1", 1_064));
+        assert!(!tracker.unlock("10.0.0.1", 1_064));
+        assert_eq!(tracker.locked_for("10.0.0.1", 1_064), None);
+    }
+
+    #[test]
+    fn tracked_clients_stay_bounded_when_all_are_active() {
+        let tracker = AuthFailureTracker::with_max_clients(4);
+        let config = config();
+
+        for i in 0..4 {
+            tracker.record_failure(&config, &format!("10.0.0.{i}"), AuthScope::Proxy, 1_000);
+        }
+        tracker.record_failure(&config, "10.0.0.0", AuthScope::Proxy, 1_001);
+        tracker.record_failure(&config, "10.0.0.0", AuthScope::Proxy, 1_002);
+        assert!(tracker.locked_for("10.0.0.0", 1_002).is_some());
+
+        // 超出上限的新 IP 不被跟踪，按最大延迟响应，已有锁定不受影响
+        for i in 4..100 {
+            assert_eq!(
+                tracker.record_failure(&config, &format!("10.0.0.{i}"), AuthScope::Proxy, 1_010),
+                FailureOutcome::Delay(Duration::from_millis(150))
+            );
+        }
+        assert_eq!(tracker.clients.len(), 4);
+        assert!(tracker.locked_for("10.0.0.0", 1_010).is_some());
+        // 已跟踪的 IP 继续累计
+        tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_010);
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.0.1", AuthScope::Proxy, 1_010),
+            FailureOutcome::Locked { until: 1_310 }
+        );
+
+        // 旧失败滑出窗口后腾出空间，新 IP 恢复跟踪
+        assert_eq!(
+            tracker.record_failure(&config, "10.0.1.1", AuthScope::Proxy, 1_061),
+            FailureOutcome::Delay(Duration::ZERO)
+        );
+        assert!(tracker.clients.contains_key("10.0.1.1"));
+        assert!(tracker.clients.len() <= 4);
+    }
+}
@@ -0,0 +1,376 @@
+//! 费用预算（按价格表核算的花费）
+//!
+//! 支持 API Key 级与全局两级预算，窗口为 UTC 自然日、最近 7 天与 UTC 自然月。
+//! 达到软阈值时输出告警日志，达到上限后拒绝请求。
+
+use crate::config::{BudgetConfig, ResolvedApiKey};
+use chrono::{DateTime, Datelike, NaiveDate};
+use dashmap::DashMap;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Mutex, RwLock};
+
+/// 天级花费保留天数（覆盖自然月与最近 7 天）
+const SPEND_RETENTION_DAYS: u64 = 32;
+
+/// 预算管理器
+pub struct BudgetManager {
+    /// api_key_id -> 预算配置
+    key_budgets: DashMap<String, BudgetConfig>,
+    /// 全局预算配置
+    global_budget: RwLock<Option<BudgetConfig>>,
+    /// api_key_id -> 天级花费
+    key_spend: DashMap<String, SpendWindow>,
+    /// 全部 API Key 合计的天级花费
+    global_spend: Mutex<SpendWindow>,
+    /// 已发出的软阈值告警（scope, id, window, 窗口起点），避免重复告警
+    alerted: Mutex<HashSet<(BudgetScope, String, BudgetWindow, u64)>>,
+}
+
+/// 天级花费窗口
+#[derive(Debug, Default)]
+pub struct SpendWindow {
+    daily: VecDeque<DailySpend>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DailySpend {
+    day_epoch: u64,
+    cost: f64,
+}
+
+/// 预算的作用维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetScope {
+    ApiKey,
+    Global,
+}
+
+impl BudgetScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetScope::ApiKey => "api_key",
+            BudgetScope::Global => "global",
+        }
+    }
+}
+
+/// 预算的时间窗口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetWindow {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl BudgetWindow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetWindow::Daily => "daily",
+            BudgetWindow::Weekly => "weekly",
+            BudgetWindow::Monthly => "monthly",
+        }
+    }
+}
+
+/// 预算超限信息
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+    pub scope: BudgetScope,
+    pub window: BudgetWindow,
+    pub limit: f64,
+    pub spent: f64,
+    /// 响应状态码（402 或 429）
+    pub status: u16,
+}
+
+/// 单个窗口的预算使用情况
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetUsage {
+    pub window: BudgetWindow,
+    pub limit: f64,
+    pub spent: f64,
+}
+
+impl BudgetManager {
+    pub fn new() -> Self {
+        Self {
+            key_budgets: DashMap::new(),
+            global_budget: RwLock::new(None),
+            key_spend: DashMap::new(),
+            global_spend: Mutex::new(SpendWindow::default()),
+            alerted: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// 按最新配置替换 API Key 与全局预算
+    pub fn set_budgets(&self, keys: &[ResolvedApiKey], global: Option<&BudgetConfig>) {
+        self.key_budgets.clear();
+        for key in keys {
+            if let Some(budget) = &key.budget {
+                self.key_budgets.insert(key.id.clone(), budget.clone());
+            }
+        }
+        *self.global_budget.write().unwrap() = global.cloned();
+    }
+
+    /// 是否配置了任意预算
+    pub fn has_budget(&self, api_key_id: &str) -> bool {
+        self.key_budgets.contains_key(api_key_id) || self.global_budget.read().unwrap().is_some()
+    }
+
+    /// 记录一次花费（在请求完成后调用）
+    pub fn record_spend(&self, api_key_id: &str, cost: f64, now: u64) {
+        if cost <= 0.0 {
+            return;
+        }
+        self.key_spend
+            .entry(api_key_id.to_string())
+            .or_default()
+            .add(truncate_to_day(now), cost, now);
+        self.global_spend.lock().unwrap().add(truncate_to_day(now), cost, now);
+
+        if let Some(budget) = self.key_budgets.get(api_key_id)
+            && let Some(spend) = self.key_spend.get(api_key_id)
+        {
+            self.alert_soft_threshold(BudgetScope::ApiKey, api_key_id, &budget, &spend, now);
+        }
+        if let Some(budget) = self.global_budget.read().unwrap().as_ref() {
+            let spend = self.global_spend.lock().unwrap();
+            self.alert_soft_threshold(BudgetScope::Global, "", budget, &spend, now);
+        }
+    }
+
+    /// 合并一条从SQLite恢复的天级花费
+    pub fn restore_spend(&self, api_key_id: &str, day_epoch: u64, cost: f64, now: u64) {
+        self.key_spend
+            .entry(api_key_id.to_string())
+            .or_default()
+            .add(day_epoch, cost, now);
+        self.global_spend.lock().unwrap().add(day_epoch, cost, now);
+    }
+
+    /// 检查 API Key 与全局预算，任一窗口已达上限即返回超限信息
+    pub fn check(&self, api_key_id: &str, now: u64) -> Result<(), BudgetExceeded> {
+        if let Some(budget) = self.key_budgets.get(api_key_id) {
+            let spend = self.key_spend.get(api_key_id);
+            check_budget(BudgetScope::ApiKey, &budget, spend.as_deref(), now)?;
+        }
+        if let Some(budget) = self.global_budget.read().unwrap().as_ref() {
+            let spend = self.global_spend.lock().unwrap();
+            check_budget(BudgetScope::Global, budget, Some(&spend), now)?;
+        }
+        Ok(())
+    }
+
+    /// API Key 各窗口的预算使用情况，未配置预算时返回 `None`
+    pub fn key_usage(&self, api_key_id: &str, now: u64) -> Option<Vec<BudgetUsage>> {
+        let budget = self.key_budgets.get(api_key_id)?;
+        let spend = self.key_spend.get(api_key_id);
+        Some(budget_usage(&budget, spend.as_deref(), now))
+    }
+
+    /// 全局各窗口的预算使用情况，未配置预算时返回 `None`
+    pub fn global_usage(&self, now: u64) -> Option<Vec<BudgetUsage>> {
+        let budget = self.global_budget.read().unwrap();
+        let spend = self.global_spend.lock().unwrap();
+        Some(budget_usage(budget.as_ref()?, Some(&spend), now))
+    }
+
+    fn alert_soft_threshold(
+        &self,
+        scope: BudgetScope,
+        id: &str,
+        budget: &BudgetConfig,
+        spend: &SpendWindow,
+        now: u64,
+    ) {
+        let Some(threshold) = budget.soft_threshold else {
+            return;
+        };
+        for usage in budget_usage(budget, Some(spend), now) {
+            if usage.spent < usage.limit * threshold {
+                continue;
+            }
+            let window_start = window_start(usage.window, now);
+            let newly_alerted = {
+                let mut alerted = self.alerted.lock().unwrap();
+                // 清理已结束窗口的告警记录
+                let oldest = restore_start(now);
+                alerted.retain(|(_, _, _, start)| *start >= oldest);
+                alerted.insert((scope, id.to_string(), usage.window, window_start))
+            };
+            if newly_alerted {
+                tracing::warn!(
+                    scope = scope.as_str(),
+                    api_key_id = id,
+                    window = usage.window.as_str(),
+                    limit = usage.limit,
+                    spent = usage.spent,
+                    "Spend budget soft threshold reached"
+                );
+            }
+        }
+    }
+}
+
+impl Default for BudgetManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpendWindow {
+    fn add(&mut self, day_epoch: u64, cost: f64, now: u64) {
+        if let Some(daily) = self.daily.iter_mut().find(|d| d.day_epoch == day_epoch) {
+            daily.cost += cost;
+        } else {
+            self.daily.push_back(DailySpend { day_epoch, cost });
+            self.daily.make_contiguous().sort_by_key(|d| d.day_epoch);
+        }
+        // 清理过期数据
+        let oldest = truncate_to_day(now).saturating_sub(SPEND_RETENTION_DAYS * 86400);
+        while self.daily.front().is_some_and(|d| d.day_epoch < oldest) {
+            self.daily.pop_front();
+        }
+    }
+
+    fn spent_since(&self, start: u64) -> f64 {
+        self.daily
+            .iter()
+            .filter(|d| d.day_epoch >= start)
+            .map(|d| d.cost)
+            .sum()
+    }
+}
+
+fn budget_usage(budget: &BudgetConfig, spend: Option<&SpendWindow>, now: u64) -> Vec<BudgetUsage> {
+    [
+        (BudgetWindow::Daily, budget.daily_limit),
+        (BudgetWindow::Weekly, budget.weekly_limit),
+        (BudgetWindow::Monthly, budget.monthly_limit),
+    ]
+    .into_iter()
+    .filter_map(|(window, limit)| {
+        Some(BudgetUsage {
+            window,
+            limit: limit?,
+            spent: spend.map_or(0.0, |s| s.spent_since(window_start(window, now))),
+        })
+    })
+    .collect()
+}
+
+fn check_budget(
+    scope: BudgetScope,
+    budget: &BudgetConfig,
+    spend: Option<&SpendWindow>,
+    now: u64,
+) -> Result<(), BudgetExceeded> {
+    match budget_usage(budget, spend, now)
+        .into_iter()
+        .find(|usage| usage.spent >= usage.limit)
+    {
+        Some(usage) => Err(BudgetExceeded {
+            scope,
+            window: usage.window,
+            limit: usage.limit,
+            spent: usage.spent,
+            status: budget.exceeded_status,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// 窗口起点（UTC 天的起始时间戳）
+fn window_start(window: BudgetWindow, now: u64) -> u64 {
+    match window {
+        BudgetWindow::Daily => truncate_to_day(now),
+        BudgetWindow::Weekly => truncate_to_day(now.saturating_sub(6 * 86400)),
+        BudgetWindow::Monthly => month_start(now),
+    }
+}
+
+/// 天级花费需要从SQLite恢复的起始时间（覆盖所有窗口）
+pub fn restore_start(now: u64) -> u64 {
+    window_start(BudgetWindow::Weekly, now).min(window_start(BudgetWindow::Monthly, now))
+}
+
+/// UTC 自然月的起始时间戳
+fn month_start(now: u64) -> u64 {
+    DateTime::from_timestamp(now as i64, 0)
+        .and_then(|dt| NaiveDate::from_ymd_opt(dt.year(), dt.month(), 1))
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map_or(0, |dt| dt.and_utc().timestamp() as u64)
+}
+
+fn truncate_to_day(timestamp: u64) -> u64 {
+    (timestamp / 86400) * 86400
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-10-15T12:00:00Z
+    const NOW: u64 = 1_728_993_600;
+
+    fn budget(daily: Option<f64>, monthly: Option<f64>) -> BudgetConfig {
+        BudgetConfig {
+            daily_limit: daily,
+            weekly_limit: None,
+            monthly_limit: monthly,
+            soft_threshold: Some(0.8),
+            exceeded_status: 402,
+        }
+    }
+
+    fn key(id: &str, budget: Option<BudgetConfig>) -> ResolvedApiKey {
+        ResolvedApiKey {
+            id: id.to_string(),
+            budget,
+            ..ResolvedApiKey::from_key_string(&format!("sk-{id}"))
+        }
+    }
+
+    #[test]
+    fn rejects_once_daily_limit_is_spent() {
+        let manager = BudgetManager::new();
+        manager.set_budgets(&[key("k1", Some(budget(Some(1.0), None)))], None);
+
+        manager.record_spend("k1", 0.6, NOW);
+        assert!(manager.check("k1", NOW).is_ok());
+        manager.record_spend("k1", 0.4, NOW);
+        let exceeded = manager.check("k1", NOW).expect_err("daily budget spent");
+        assert_eq!(exceeded.scope, BudgetScope::ApiKey);
+        assert_eq!(exceeded.window, BudgetWindow::Daily);
+        assert_eq!(exceeded.status, 402);
+
+        // 次日恢复
+        assert!(manager.check("k1", NOW + 86400).is_ok());
+    }
+
+    #[test]
+    fn monthly_window_follows_calendar_month() {
+        let manager = BudgetManager::new();
+        manager.set_budgets(&[key("k1", Some(budget(None, Some(10.0))))], None);
+
+        // 2024-10-01 与 2024-09-30 的花费
+        manager.restore_spend("k1", 1_727_740_800, 6.0, NOW);
+        manager.restore_spend("k1", 1_727_654_400, 6.0, NOW);
+        let usage = manager.key_usage("k1", NOW).expect("budget configured");
+        assert_eq!(usage[0].window, BudgetWindow::Monthly);
+        assert!((usage[0].spent - 6.0).abs() < 1e-9);
+        assert!(manager.check("k1", NOW).is_ok());
+    }
+
+    #[test]
+    fn global_budget_applies_across_keys() {
+        let manager = BudgetManager::new();
+        manager.set_budgets(&[key("k1", None), key("k2", None)], Some(&budget(Some(1.0), None)));
+
+        manager.record_spend("k1", 0.5, NOW);
+        manager.record_spend("k2", 0.5, NOW);
+        let exceeded = manager.check("k3", NOW).expect_err("global budget spent");
+        assert_eq!(exceeded.scope, BudgetScope::Global);
+    }
+}
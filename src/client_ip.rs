@@ -0,0 +1,199 @@
+//! 客户端 IP 解析
+//!
+//! 默认使用 TCP 连接的对端地址；只有对端属于 `trusted_proxies` 时才读取转发头，
+//! 避免客户端伪造 `X-Forwarded-For` 等请求头绕过按 IP 的限流、锁定与封禁。
+
+use http::HeaderMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+/// 单值转发头，按优先级检查（`X-Forwarded-For` 优先于这些请求头）
+const SINGLE_VALUE_HEADERS: [&str; 3] = ["x-real-ip", "cf-connecting-ip", "true-client-ip"];
+
+/// IP 网段（CIDR），单个 IP 视为 /32 或 /128
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// 解析 `10.0.0.0/8`、`2001:db8::/32` 或单个 IP；地址中超出前缀的位会被清零
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr = normalize(addr.parse::<IpAddr>().ok()?);
+                let prefix = prefix.parse::<u8>().ok()?;
+                (addr, prefix)
+            }
+            None => {
+                let addr = normalize(value.parse::<IpAddr>().ok()?);
+                (addr, max_prefix(addr))
+            }
+        };
+        if prefix > max_prefix(addr) {
+            return None;
+        }
+        Some(Self { addr: mask(addr, prefix), prefix })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// 是否为单个 IP（/32 或 /128）
+    pub fn is_host(&self) -> bool {
+        self.prefix == max_prefix(self.addr)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = normalize(ip);
+        ip.is_ipv4() == self.addr.is_ipv4() && mask(ip, self.prefix) == self.addr
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// IPv4 映射的 IPv6 地址（`::ffff:a.b.c.d`）按 IPv4 处理
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+fn max_prefix(ip: IpAddr) -> u8 {
+    if ip.is_ipv4() { 32 } else { 128 }
+}
+
+fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4((bits & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6((bits & mask).into())
+        }
+    }
+}
+
+/// 解析 `trusted_proxies` 配置，任一项无法解析时返回该项
+pub fn parse_trusted_proxies(values: &[String]) -> Result<Vec<IpNetwork>, String> {
+    values
+        .iter()
+        .map(|value| IpNetwork::parse(value).ok_or_else(|| value.clone()))
+        .collect()
+}
+
+/// 解析客户端 IP
+///
+/// 对端不在可信代理列表中时直接返回对端地址。否则从右向左遍历 `X-Forwarded-For`，
+/// 跳过可信代理，取第一个不可信的地址；没有该请求头时依次读取 `X-Real-IP` 等单值请求头。
+/// 转发头中无法解析为 IP 的值一律忽略。
+pub fn resolve_client_ip(headers: &HeaderMap, peer: SocketAddr, trusted_proxies: &[IpNetwork]) -> IpAddr {
+    let peer = normalize(peer.ip());
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|network| network.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+
+    if let Some(forwarded_for) = header_value(headers, "x-forwarded-for") {
+        let mut client = None;
+        for hop in forwarded_for.rsplit(',') {
+            let Some(ip) = parse_ip(hop) else {
+                break;
+            };
+            client = Some(ip);
+            if !is_trusted(ip) {
+                break;
+            }
+        }
+        if let Some(client) = client {
+            return client;
+        }
+    }
+
+    SINGLE_VALUE_HEADERS
+        .iter()
+        .find_map(|name| header_value(headers, name).and_then(parse_ip))
+        .unwrap_or(peer)
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value.trim().parse::<IpAddr>().ok().map(normalize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn peer(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 40000)
+    }
+
+    #[test]
+    fn parses_networks_and_matches_addresses() {
+        let network = IpNetwork::parse("10.1.2.3/16").unwrap();
+        assert_eq!(network.to_string(), "10.1.0.0/16");
+        assert!(network.contains("10.1.200.7".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.0.9".parse().unwrap()));
+        assert!(IpNetwork::parse("2001:db8::1").unwrap().is_host());
+        assert_eq!(IpNetwork::parse("2001:db8:1::/48").unwrap().prefix(), 48);
+        assert!(IpNetwork::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert_eq!(IpNetwork::parse("10.0.0.0/33"), None);
+        assert_eq!(IpNetwork::parse("proxy.local"), None);
+    }
+
+    #[test]
+    fn ignores_forwarding_headers_from_untrusted_peers() {
+        let headers = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "5.6.7.8")]);
+        let ip = resolve_client_ip(&headers, peer("203.0.113.9"), &[]);
+        assert_eq!(ip.to_string(), "203.0.113.9");
+    }
+
+    #[test]
+    fn walks_forwarded_for_from_the_right_skipping_trusted_hops() {
+        let trusted = parse_trusted_proxies(&["127.0.0.1".to_string(), "10.0.0.0/8".to_string()]).unwrap();
+        // 最左侧的值由客户端自行填写，不可信
+        let headers = headers(&[("x-forwarded-for", "6.6.6.6, 198.51.100.7, 10.0.0.2")]);
+        let ip = resolve_client_ip(&headers, peer("127.0.0.1"), &trusted);
+        assert_eq!(ip.to_string(), "198.51.100.7");
+
+        let headers = self::headers(&[("x-forwarded-for", "garbage")]);
+        let ip = resolve_client_ip(&headers, peer("127.0.0.1"), &trusted);
+        assert_eq!(ip.to_string(), "127.0.0.1");
+
+        let headers = self::headers(&[("x-real-ip", " 198.51.100.8 ")]);
+        let ip = resolve_client_ip(&headers, peer("::ffff:127.0.0.1"), &trusted);
+        assert_eq!(ip.to_string(), "198.51.100.8");
+
+        assert!(parse_trusted_proxies(&["not-an-ip".to_string()]).is_err());
+    }
+}
@@ -25,6 +25,7 @@ struct SemaphoreEntry {
 pub struct ConcurrencyController {
     /// 全局下游并发限制
     downstream_semaphore: Option<Arc<Semaphore>>,
+    downstream_limit: Option<usize>,
     /// 全局上游默认限制
     upstream_default_limit: Option<usize>,
     /// 上游并发信号量（按 key），带访问时间戳，使用 DashMap 实现细粒度锁
@@ -79,6 +80,7 @@ impl ConcurrencyController {
 
         Some(Self {
             downstream_semaphore: downstream_limit.map(|limit| Arc::new(Semaphore::new(limit))),
+            downstream_limit,
             upstream_default_limit,
             upstream_semaphores: DashMap::new(),
             api_key_configs,
@@ -163,22 +165,31 @@ impl ConcurrencyController {
         self.acquire_downstream()
     }
 
-    pub fn acquire_upstream(
-        &self,
-        route: &RouteConfig,
-    ) -> Result<Option<OwnedSemaphorePermit>, ConcurrencyError> {
-        let Some(limit) = route
+    /// 全局下游并发上限
+    pub fn downstream_limit(&self) -> Option<usize> {
+        self.downstream_limit
+    }
+
+    /// 路由上游 key 的并发维度：(信号量 key, 上限)
+    pub fn upstream_scope(&self, route: &RouteConfig) -> Option<(String, usize)> {
+        let limit = route
             .upstream
             .upstream_key_max_inflight
-            .or(self.upstream_default_limit)
-        else {
-            return Ok(None);
-        };
+            .or(self.upstream_default_limit)?;
 
         let key_material = extract_upstream_key_from_injected_headers(route)
             .unwrap_or_else(|| "default".to_string());
         let key_fingerprint = fingerprint(&key_material);
-        let semaphore_key = format!("{}:{key_fingerprint:016x}", route.id);
+        Some((format!("{}:{key_fingerprint:016x}", route.id), limit))
+    }
+
+    pub fn acquire_upstream(
+        &self,
+        route: &RouteConfig,
+    ) -> Result<Option<OwnedSemaphorePermit>, ConcurrencyError> {
+        let Some((semaphore_key, limit)) = self.upstream_scope(route) else {
+            return Ok(None);
+        };
 
         let semaphore = self
             .upstream_semaphores
@@ -391,6 +402,10 @@ mod tests {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         });
         let controller = ConcurrencyController::new(&config).expect("controller should exist");
         let routes = config.routes.as_mut().unwrap();
@@ -575,6 +590,10 @@ mod tests {
                     upstream_key_max_inflight: route_upstream_limit,
                     user_agent: None,
                 },
+                rate_limit: None,
+                token_rate_limit: None,
+                forward_auth: None,
+                auth: None,
             }]),
             api_keys: None,
             inbound_tls: None,
@@ -588,6 +607,12 @@ mod tests {
             admin: None,
             config_db_path: "./data/config.db".to_string(),
             token_stats: None,
+            tokenizer: None,
+            pricing: None,
+            global_budget: None,
+            state_backend: None,
+            auth_protection: None,
+            trusted_proxies: Vec::new(),
         }
     }
 
@@ -604,13 +629,22 @@ mod tests {
                 route_id: None,
                 route_ids: None,
                 key: key.to_string(),
+                key_hash: String::new(),
+                key_prefix: String::new(),
+                previous_keys: Vec::new(),
                 enabled: true,
+                not_before: None,
+                expires_at: None,
+                idle_expiry_days: None,
                 remark: String::new(),
                 rate_limit: None,
                 concurrency: Some(concurrency),
                 token_quota: None,
+                token_rate_limit: None,
                 ban_rules: Vec::new(),
                 ban_status: None,
+                budget: None,
+                max_output_tokens_per_request: None,
             })
             .collect();
 
@@ -635,11 +669,17 @@ mod tests {
                     upstream_key_max_inflight: None,
                     user_agent: None,
                 },
+                rate_limit: None,
+                token_rate_limit: None,
+                forward_auth: None,
+                auth: None,
             }]),
             api_keys: Some(ApiKeysGlobalConfig {
                 keys: api_key_configs,
                 ban_rules: vec![],
                 sqlite: None,
+                expiry_warning_days: 7,
+                rotation_grace_secs: 86_400,
             }),
             inbound_tls: None,
             cors: None,
@@ -652,6 +692,12 @@ mod tests {
             admin: None,
             config_db_path: "./data/config.db".to_string(),
             token_stats: None,
+            tokenizer: None,
+            pricing: None,
+            global_budget: None,
+            state_backend: None,
+            auth_protection: None,
+            trusted_proxies: Vec::new(),
         }
     }
 }
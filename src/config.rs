@@ -1,4 +1,5 @@
-use http::HeaderValue;
+use crate::api_keys::key_hash;
+use http::{HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::env;
@@ -39,19 +40,181 @@ pub struct AppConfig {
     /// Token 统计配置
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub token_stats: Option<TokenStatsConfig>,
+    /// 内置分词器配置（用于预估请求输入 token）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<TokenizerConfig>,
+    /// 模型价格表（用于费用核算）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<PricingConfig>,
+    /// 全局费用上限（所有 API Key 合计）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_budget: Option<BudgetConfig>,
+    /// 共享状态后端（多实例部署时共享限流、并发、配额与封禁状态）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_backend: Option<StateBackendConfig>,
+    /// 鉴权失败防护（按客户端 IP 延迟与临时锁定）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_protection: Option<AuthProtectionConfig>,
+    /// 可信反向代理（IP 或 CIDR）：只有来自这些地址的连接才读取 `X-Forwarded-For` 等转发头
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GatewayAuthConfig {
     #[serde(default = "default_token_sources")]
     pub token_sources: Vec<TokenSourceConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 校验入站凭证来源，错误信息以 `owner` 开头
+fn validate_token_sources(
+    owner: &str,
+    token_sources: &[TokenSourceConfig],
+    api_keys: Option<&ApiKeysGlobalConfig>,
+) -> Result<(), ConfigError> {
+    for source in token_sources {
+        match source {
+            TokenSourceConfig::AuthorizationBearer => {}
+            TokenSourceConfig::Header { name } => {
+                if HeaderName::from_bytes(name.trim().as_bytes()).is_err() {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: header token source `{name}` must be a valid header name"
+                    )));
+                }
+            }
+            TokenSourceConfig::Query { name } => {
+                if name.trim().is_empty() {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: query token source `name` must not be empty"
+                    )));
+                }
+            }
+            TokenSourceConfig::Jwt(jwt) => jwt.validate(owner, api_keys)?,
+        }
+    }
+        Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum TokenSourceConfig {
     AuthorizationBearer,
     Header { name: String },
+    /// 查询参数（如 Gemini 的 `?key=`），转发前从查询串中移除
+    Query { name: String },
+    /// JWT：校验签名与标准 claim 后映射为已有 API Key 或动态身份
+    Jwt(Box<JwtConfig>),
+}
+
+/// JWT 认证配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    /// 读取 JWT 的请求头，缺省为 `Authorization: Bearer`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// 静态验签密钥
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<JwtKeyConfig>,
+    /// 本地 JWKS 文件路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_path: Option<String>,
+    /// JWKS 文件重新加载间隔（秒）
+    #[serde(default = "default_jwks_reload_secs")]
+    pub jwks_reload_secs: u64,
+    /// 要求的 `iss`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// 可接受的 `aud`（任一匹配即可），为空时不校验
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audiences: Vec<String>,
+    /// `exp`/`nbf` 允许的时钟偏差（秒）
+    #[serde(default = "default_jwt_leeway_secs")]
+    pub leeway_secs: u64,
+    /// 值为已有 API Key ID 的 claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id_claim: Option<String>,
+    /// 动态身份 claim（未映射到已有 Key 时使用）
+    #[serde(default = "default_jwt_identity_claim")]
+    pub identity_claim: String,
+    /// 动态身份的模板 Key ID，限流、配额、路由权限等默认取自该 Key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_key: Option<String>,
+    /// 覆盖动态身份可访问路由的 claim（字符串或字符串数组）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_ids_claim: Option<String>,
+    /// 覆盖动态身份每分钟请求数的 claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_claim: Option<String>,
+    /// 覆盖动态身份 Token 配额的 claim（结构同 `token_quota`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_quota_claim: Option<String>,
+}
+
+impl JwtConfig {
+    fn validate(&self, owner: &str, api_keys: Option<&ApiKeysGlobalConfig>) -> Result<(), ConfigError> {
+        if self.keys.is_empty() && self.jwks_path.is_none() {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: jwt token source requires `keys` or `jwks_path`"
+            )));
+        }
+        for key in &self.keys {
+            let kid = key.kid.as_deref().unwrap_or("<none>");
+            let has_material = match key.alg {
+                JwtAlgorithm::HS256 => key.secret.as_deref().is_some_and(|secret| !secret.is_empty()),
+                JwtAlgorithm::RS256 | JwtAlgorithm::ES256 => key.public_key_pem.is_some(),
+            };
+            if !has_material {
+                return Err(ConfigError::Validation(format!(
+                    "{owner}: jwt key `{kid}`: {:?} requires `{}`",
+                    key.alg,
+                    if key.alg == JwtAlgorithm::HS256 { "secret" } else { "public_key_pem" }
+                )));
+            }
+        }
+        if self.jwks_reload_secs == 0 {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: jwt `jwks_reload_secs` must be > 0"
+            )));
+        }
+        if self.key_id_claim.is_none() && self.template_key.is_none() {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: jwt token source requires `key_id_claim` or `template_key`"
+            )));
+        }
+        if let Some(template_key) = &self.template_key
+            && !api_keys.is_some_and(|global| global.keys.iter().any(|key| &key.id == template_key))
+        {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: jwt `template_key` `{template_key}` does not match any api_key id"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// JWT 静态验签密钥
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtKeyConfig {
+    /// 对应 JWT 头部的 `kid`，为空时匹配任意 `kid`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    pub alg: JwtAlgorithm,
+    /// HS256 共享密钥
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// RS256/ES256 公钥（PEM）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_pem: Option<String>,
+}
+
+/// 支持的 JWT 签名算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JwtAlgorithm {
+    HS256,
+    RS256,
+    ES256,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +222,190 @@ pub struct RouteConfig {
     pub id: String,
     pub prefix: String,
     pub upstream: UpstreamConfig,
+    /// 路由级限流配置（路由总量、Key+路由、客户端 IP）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RouteRateLimitConfig>,
+    /// 路由级 TPM/TPD 限制（该路由所有请求合计）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_rate_limit: Option<TokenRateLimitConfig>,
+    /// 外部授权：转发前调用授权服务决定是否放行
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forward_auth: Option<ForwardAuthConfig>,
+    /// 路由级鉴权策略（兼容旧字段名 `gateway_auth`）
+    #[serde(default, alias = "gateway_auth", skip_serializing_if = "Option::is_none")]
+    pub auth: Option<RouteAuthConfig>,
+}
+
+/// 路由级鉴权策略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteAuthConfig {
+    #[serde(default)]
+    pub mode: RouteAuthMode,
+    /// 覆盖全局 `gateway_auth.token_sources`（如 Anthropic 路由使用 `x-api-key`），为空时沿用全局配置
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_sources: Vec<TokenSourceConfig>,
+    /// 要求 TLS 握手时出示由 `inbound_tls.client_ca_path` 签发的客户端证书
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub require_client_cert: bool,
+}
+
+/// 路由鉴权模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteAuthMode {
+    /// 必须携带有效凭证
+    #[default]
+    Required,
+    /// 携带凭证时校验，未携带时按匿名身份放行
+    Optional,
+    /// 不读取凭证，一律按匿名身份放行
+    None,
+}
+
+/// 外部授权（forward-auth）配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForwardAuthConfig {
+    /// 授权服务地址（POST JSON：method、path、query、route_id、key_id、headers）
+    pub url: String,
+    /// 调用超时（毫秒）
+    #[serde(default = "default_forward_auth_timeout_ms")]
+    pub timeout_ms: u64,
+    /// 转发给授权服务的请求头
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forward_headers: Vec<String>,
+    /// 放行时从授权响应复制到上游请求的身份头；客户端自带的同名头一律移除
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identity_headers: Vec<String>,
+    /// 授权结果缓存时间（秒），0 表示不缓存
+    #[serde(default)]
+    pub cache_ttl_secs: u64,
+    /// 授权服务不可用（超时、连接失败、非预期状态码）时是否放行
+    #[serde(default)]
+    pub fail_open: bool,
+}
+
+fn default_forward_auth_timeout_ms() -> u64 {
+    1_000
+}
+
+impl RouteConfig {
+    pub fn auth_mode(&self) -> RouteAuthMode {
+        self.auth.as_ref().map_or(RouteAuthMode::Required, |auth| auth.mode)
+    }
+
+    /// 逐字段校验单个路由，返回全部错误（不含跨路由的 ID/前缀唯一性检查）
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        let upstream = &self.upstream;
+
+        if self.id.trim().is_empty() {
+            errors.push(FieldError::new("id", "must not be empty"));
+        }
+        if !self.prefix.starts_with('/') {
+            errors.push(FieldError::new("prefix", "must start with `/`"));
+        } else if self.prefix.len() > 1 && self.prefix.ends_with('/') {
+            errors.push(FieldError::new("prefix", "must not end with `/`"));
+        }
+
+        if upstream.base_url.trim().is_empty() {
+            errors.push(FieldError::new("upstream.base_url", "must not be empty"));
+        }
+        if upstream.connect_timeout_ms == 0 {
+            errors.push(FieldError::new("upstream.connect_timeout_ms", "must be > 0"));
+        }
+        if upstream.request_timeout_ms == 0 {
+            errors.push(FieldError::new("upstream.request_timeout_ms", "must be > 0"));
+        }
+        if let Some(user_agent) = upstream.user_agent.as_deref() {
+            if user_agent.trim().is_empty() {
+                errors.push(FieldError::new("upstream.user_agent", "must not be empty when provided"));
+            } else if HeaderValue::from_str(user_agent).is_err() {
+                errors.push(FieldError::new("upstream.user_agent", "must be a valid header value"));
+            }
+        }
+        if upstream.upstream_key_max_inflight == Some(0) {
+            errors.push(FieldError::new(
+                "upstream.upstream_key_max_inflight",
+                "must be > 0 when provided",
+            ));
+        }
+
+        if let Some(proxy) = &upstream.proxy {
+            if proxy.address.trim().is_empty() {
+                errors.push(FieldError::new("upstream.proxy.address", "must not be empty"));
+            }
+            match (&proxy.username, &proxy.password) {
+                (Some(username), Some(password))
+                    if username.trim().is_empty() || password.trim().is_empty() =>
+                {
+                    errors.push(FieldError::new("upstream.proxy.username/password", "must not be empty"));
+                }
+                (Some(_), Some(_)) | (None, None) => {}
+                _ => errors.push(FieldError::new(
+                    "upstream.proxy.username and upstream.proxy.password",
+                    "must be set together",
+                )),
+            }
+        }
+
+        for (index, header) in upstream.inject_headers.iter().enumerate() {
+            if header.name.trim().is_empty() {
+                errors.push(FieldError::new(
+                    format!("upstream.inject_headers[{index}].name"),
+                    "must not be empty",
+                ));
+            }
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            for (field, value) in [
+                ("per_minute", rate_limit.per_minute),
+                ("per_key_per_minute", rate_limit.per_key_per_minute),
+                ("per_ip_per_minute", rate_limit.per_ip_per_minute),
+            ] {
+                if value == Some(0) {
+                    errors.push(FieldError::new(
+                        format!("rate_limit.{field}"),
+                        "must be > 0 when provided",
+                    ));
+                }
+            }
+        }
+
+        if let Some(field) = self.token_rate_limit.as_ref().and_then(TokenRateLimitConfig::zero_field) {
+            errors.push(FieldError::new(
+                format!("token_rate_limit.{field}"),
+                "must be > 0 when provided",
+            ));
+        }
+
+        if let Some(forward_auth) = &self.forward_auth {
+            let url = forward_auth.url.trim();
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                errors.push(FieldError::new("forward_auth.url", "must be an http(s) URL"));
+            }
+            if forward_auth.timeout_ms == 0 {
+                errors.push(FieldError::new("forward_auth.timeout_ms", "must be > 0"));
+            }
+            for (field, names) in [
+                ("forward_headers", &forward_auth.forward_headers),
+                ("identity_headers", &forward_auth.identity_headers),
+            ] {
+                for (index, name) in names.iter().enumerate() {
+                    if HeaderName::from_bytes(name.trim().as_bytes()).is_err() {
+                        errors.push(FieldError::new(
+                            format!("forward_auth.{field}[{index}]"),
+                            "must be a valid header name",
+                        ));
+                    }
+                }
+            }
+        }
+
+        errors
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,6 +465,9 @@ pub struct InboundTlsConfig {
     pub self_signed_cert_path: String,
     #[serde(default = "default_self_signed_key_path")]
     pub self_signed_key_path: String,
+    /// 客户端证书 CA（PEM）；配置后握手时请求客户端证书，供路由 `auth.require_client_cert` 使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -134,11 +484,67 @@ pub struct CorsConfig {
     pub expose_headers: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RateLimitConfig {
     pub per_minute: u64,
 }
 
+/// 鉴权失败防护配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthProtectionConfig {
+    /// 窗口内失败次数达到该值时锁定客户端 IP
+    #[serde(default = "default_auth_max_failures")]
+    pub max_failures: u32,
+    /// 失败计数窗口（秒）
+    #[serde(default = "default_auth_failure_window_secs")]
+    pub window_secs: u64,
+    /// 锁定时长（秒）
+    #[serde(default = "default_auth_lockout_secs")]
+    pub lockout_secs: u64,
+    /// 每次失败递增的响应延迟（毫秒），窗口内第 n 次失败延迟 `(n - 1) * delay_ms`
+    #[serde(default = "default_auth_failure_delay_ms")]
+    pub delay_ms: u64,
+    /// 响应延迟上限（毫秒）
+    #[serde(default = "default_auth_failure_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_auth_max_failures() -> u32 {
+    10
+}
+
+fn default_auth_failure_window_secs() -> u64 {
+    300
+}
+
+fn default_auth_lockout_secs() -> u64 {
+    900
+}
+
+fn default_auth_failure_delay_ms() -> u64 {
+    200
+}
+
+fn default_auth_failure_max_delay_ms() -> u64 {
+    2_000
+}
+
+/// 路由级限流配置，各维度独立计数，同时生效
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteRateLimitConfig {
+    /// 该路由所有请求合计每分钟上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_minute: Option<u64>,
+    /// 每个 API Key 在该路由上的每分钟上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_key_per_minute: Option<u64>,
+    /// 每个客户端 IP 在该路由上的每分钟上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_ip_per_minute: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConcurrencyConfig {
@@ -277,6 +683,32 @@ pub struct ApiKeysGlobalConfig {
     /// 封禁日志存储配置
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sqlite: Option<ApiKeysSqliteConfig>,
+    /// 距过期不足该天数的 Key 标记为即将过期
+    #[serde(default = "default_expiry_warning_days")]
+    pub expiry_warning_days: u64,
+    /// 轮换 Key 时旧 Key 的默认宽限期（秒）
+    #[serde(default = "default_rotation_grace_secs")]
+    pub rotation_grace_secs: u64,
+}
+
+fn default_expiry_warning_days() -> u64 {
+    7
+}
+
+fn default_rotation_grace_secs() -> u64 {
+    24 * 60 * 60
+}
+
+impl Default for ApiKeysGlobalConfig {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            ban_rules: Vec::new(),
+            sqlite: None,
+            expiry_warning_days: default_expiry_warning_days(),
+            rotation_grace_secs: default_rotation_grace_secs(),
+        }
+    }
 }
 
 /// API Key 封禁日志 SQLite 配置
@@ -307,11 +739,30 @@ pub struct ApiKeyConfig {
     /// 关联的多个路由ID（None 表示所有路由）
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub route_ids: Option<Vec<String>>,
-    /// API Key 值
+    /// API Key 明文（仅用于导入，加载后转换为 `key_hash` 与 `key_prefix`）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub key: String,
+    /// API Key 加盐哈希（`sha256$<salt>$<digest>`）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_hash: String,
+    /// API Key 展示前缀（同时用于查找候选 Key）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_prefix: String,
+    /// 轮换前的旧 Key，宽限期内仍可使用，并计入同一 Key ID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_keys: Vec<PreviousKeyConfig>,
     /// 是否启用
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// 生效时间（Unix秒），此前的请求返回 `api_key_not_yet_valid`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    /// 过期时间（Unix秒），此后的请求返回 `api_key_expired`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// 闲置过期天数：连续该天数未使用的 Key 视为过期
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_expiry_days: Option<u64>,
     /// 备注说明
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub remark: String,
@@ -324,6 +775,15 @@ pub struct ApiKeyConfig {
     /// Token 配额配置
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub token_quota: Option<TokenQuotaConfig>,
+    /// TPM/TPD 限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_rate_limit: Option<TokenRateLimitConfig>,
+    /// 费用预算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetConfig>,
+    /// 单次请求的输出 token 上限（转发前收紧 `max_tokens`，流式响应超出时截断）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens_per_request: Option<u64>,
     /// 封禁规则
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub ban_rules: Vec<BanRule>,
@@ -332,6 +792,19 @@ pub struct ApiKeyConfig {
     pub ban_status: Option<BanStatus>,
 }
 
+/// 轮换后处于宽限期的旧 Key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviousKeyConfig {
+    /// 旧 Key 加盐哈希
+    pub key_hash: String,
+    /// 旧 Key 展示前缀
+    pub key_prefix: String,
+    /// 轮换时间（Unix秒）
+    pub rotated_at: u64,
+    /// 宽限期结束时间（Unix秒），此后旧 Key 失效
+    pub expires_at: u64,
+}
+
 /// 封禁规则
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BanRule {
@@ -352,6 +825,96 @@ pub struct BanRule {
     /// 触发计数窗口（秒）：统计触发次数的时间窗口
     #[serde(default = "default_trigger_window")]
     pub trigger_window_secs: u64,
+    /// 触发后的动作
+    #[serde(default, skip_serializing_if = "BanAction::is_ban")]
+    pub action: BanAction,
+    /// 重复封禁的升级策略（未配置时每次封禁时长固定为 `ban_duration_secs`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation: Option<BanEscalation>,
+    /// 运行模式
+    #[serde(default, skip_serializing_if = "BanRuleMode::is_enforce")]
+    pub mode: BanRuleMode,
+    /// 统计与封禁的对象
+    #[serde(default, skip_serializing_if = "BanSubject::is_key")]
+    pub subject: BanSubject,
+}
+
+/// 封禁规则的对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BanSubject {
+    /// 按 API Key 统计与封禁
+    #[default]
+    Key,
+    /// 按客户端 IP 统计与封禁，鉴权前拦截
+    Ip,
+    /// 按客户端网段（IPv4 /24、IPv6 /64）统计与封禁，鉴权前拦截
+    Subnet,
+}
+
+impl BanSubject {
+    fn is_key(&self) -> bool {
+        *self == BanSubject::Key
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanSubject::Key => "key",
+            BanSubject::Ip => "ip",
+            BanSubject::Subnet => "subnet",
+        }
+    }
+}
+
+/// 封禁规则运行模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BanRuleMode {
+    /// 正常生效
+    #[default]
+    Enforce,
+    /// 影子模式：只在封禁日志中记录本应执行的封禁，不拦截请求
+    Shadow,
+}
+
+impl BanRuleMode {
+    fn is_enforce(&self) -> bool {
+        *self == BanRuleMode::Enforce
+    }
+}
+
+/// 重复封禁的升级策略
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEscalation {
+    /// 每次再犯时封禁时长相对上一次的倍数
+    #[serde(default = "default_escalation_multiplier")]
+    pub multiplier: f64,
+    /// 封禁时长上限（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_secs: Option<u64>,
+    /// 第 N 次封禁起改为永久封禁（需手动解封）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permanent_after: Option<u32>,
+    /// 静默期（秒）：距上次封禁结束超过该时长后重新计次
+    #[serde(default = "default_escalation_decay_secs")]
+    pub decay_secs: u64,
+}
+
+/// 封禁规则触发后的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BanAction {
+    /// 封禁 Key
+    #[default]
+    Ban,
+    /// 仅记录告警，不封禁
+    Alert,
+}
+
+impl BanAction {
+    fn is_ban(&self) -> bool {
+        *self == BanAction::Ban
+    }
 }
 
 /// 封禁触发条件
@@ -379,10 +942,205 @@ pub enum BanCondition {
         /// 连续错误数阈值
         count: u32,
     },
+    /// 指定状态码的响应数超过阈值
+    StatusCode {
+        /// 时间窗口（秒）
+        window_secs: u64,
+        /// 匹配的状态码：具体状态码（如 `401`）或状态码类别（如 `4xx`）
+        statuses: Vec<String>,
+        /// 最大匹配数
+        max_count: u64,
+    },
+    /// P95 延迟超过阈值
+    Latency {
+        /// 时间窗口（秒）
+        window_secs: u64,
+        /// P95 延迟阈值（毫秒）
+        p95_threshold_ms: u64,
+        /// 最小请求数（避免样本过少）
+        min_requests: u64,
+    },
+    /// Token 用量超过阈值
+    TokenUsage {
+        /// 时间窗口（秒）
+        window_secs: u64,
+        /// 最大 token 数（输入 + 输出）
+        max_tokens: u64,
+    },
+    /// 相对自身基线的突增
+    Spike {
+        /// 比较的指标
+        #[serde(default)]
+        metric: SpikeMetric,
+        /// 当前窗口（秒）
+        window_secs: u64,
+        /// 基线窗口（秒），取当前窗口之前的这段时间
+        baseline_secs: u64,
+        /// 当前窗口用量达到基线同等时长用量的倍数时触发
+        multiplier: f64,
+        /// 当前窗口的最小用量（避免低流量时误判）
+        min_value: u64,
+    },
+    /// 同一 Key 来自过多的客户端 IP / 网段（疑似共享）
+    KeySharing {
+        /// 时间窗口（秒）
+        window_secs: u64,
+        /// 最多允许的不同 IP 数
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_ips: Option<u64>,
+        /// 最多允许的不同网段数（IPv4 按 /24，IPv6 按 /64）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_subnets: Option<u64>,
+    },
+    /// 所有子条件同时满足
+    All {
+        conditions: Vec<BanCondition>,
+    },
+    /// 任一子条件满足
+    Any {
+        conditions: Vec<BanCondition>,
+    },
+}
+
+/// 突增检测比较的指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SpikeMetric {
+    #[default]
+    Requests,
+    Tokens,
+}
+
+impl BanCondition {
+    /// 统计所需的最长时间窗口（秒）
+    pub fn max_window_secs(&self) -> Option<u64> {
+        match self {
+            BanCondition::ErrorRate { window_secs, .. }
+            | BanCondition::RequestCount { window_secs, .. }
+            | BanCondition::StatusCode { window_secs, .. }
+            | BanCondition::Latency { window_secs, .. }
+            | BanCondition::TokenUsage { window_secs, .. }
+            | BanCondition::KeySharing { window_secs, .. } => Some(*window_secs),
+            BanCondition::Spike { window_secs, baseline_secs, .. } => {
+                Some(window_secs.saturating_add(*baseline_secs))
+            }
+            BanCondition::ConsecutiveErrors { .. } => None,
+            BanCondition::All { conditions } | BanCondition::Any { conditions } => {
+                conditions.iter().filter_map(BanCondition::max_window_secs).max()
+            }
+        }
+    }
+
+    fn validate(&self, owner: &str) -> Result<(), ConfigError> {
+        match self {
+            BanCondition::StatusCode { window_secs, statuses, max_count } => {
+                if *window_secs == 0 || *max_count == 0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: status_code `window_secs` and `max_count` must be > 0"
+                    )));
+                }
+                if statuses.is_empty() || !statuses.iter().all(|status| parse_status_pattern(status).is_some()) {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: status_code `statuses` must list status codes like `401` or classes like `4xx`"
+                    )));
+                }
+            }
+            BanCondition::Latency { window_secs, p95_threshold_ms, .. } => {
+                if *window_secs == 0 || *p95_threshold_ms == 0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: latency `window_secs` and `p95_threshold_ms` must be > 0"
+                    )));
+                }
+            }
+            BanCondition::TokenUsage { window_secs, max_tokens } => {
+                if *window_secs == 0 || *max_tokens == 0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: token_usage `window_secs` and `max_tokens` must be > 0"
+                    )));
+                }
+            }
+            BanCondition::Spike { window_secs, baseline_secs, multiplier, .. } => {
+                if *window_secs == 0 || *baseline_secs < *window_secs {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: spike `window_secs` must be > 0 and `baseline_secs` must be >= `window_secs`"
+                    )));
+                }
+                if multiplier.is_nan() || *multiplier <= 1.0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: spike `multiplier` must be > 1"
+                    )));
+                }
+            }
+            BanCondition::KeySharing { window_secs, max_ips, max_subnets } => {
+                if *window_secs == 0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: key_sharing `window_secs` must be > 0"
+                    )));
+                }
+                if (max_ips.is_none() && max_subnets.is_none()) || *max_ips == Some(0) || *max_subnets == Some(0) {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: key_sharing must configure `max_ips` or `max_subnets` > 0"
+                    )));
+                }
+            }
+            BanCondition::All { conditions } | BanCondition::Any { conditions } => {
+                if conditions.is_empty() {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: composite condition `conditions` must not be empty"
+                    )));
+                }
+                for condition in conditions {
+                    condition.validate(owner)?;
+                }
+            }
+            BanCondition::ErrorRate { .. }
+            | BanCondition::RequestCount { .. }
+            | BanCondition::ConsecutiveErrors { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// 是否包含 `key_sharing` 条件（含组合条件的子条件）
+    fn contains_key_sharing(&self) -> bool {
+        match self {
+            BanCondition::KeySharing { .. } => true,
+            BanCondition::All { conditions } | BanCondition::Any { conditions } => {
+                conditions.iter().any(BanCondition::contains_key_sharing)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl BanEscalation {
+    fn validate(&self, owner: &str) -> Result<(), ConfigError> {
+        if self.multiplier.is_nan() || self.multiplier < 1.0 {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: escalation `multiplier` must be >= 1"
+            )));
+        }
+        if self.max_duration_secs == Some(0) || self.permanent_after == Some(0) {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: escalation `max_duration_secs` and `permanent_after` must be > 0"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 解析状态码匹配规则：`401` 返回 (401, 401)，`4xx` 返回 (400, 499)
+pub fn parse_status_pattern(pattern: &str) -> Option<(u16, u16)> {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    if let Some(class) = pattern.strip_suffix("xx") {
+        let class: u16 = class.parse().ok()?;
+        return (1..=5).contains(&class).then_some((class * 100, class * 100 + 99));
+    }
+    let status: u16 = pattern.parse().ok()?;
+    (100..=599).contains(&status).then_some((status, status))
 }
 
 /// 封禁状态
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct BanStatus {
     /// 是否被封禁
     pub is_banned: bool,
@@ -394,8 +1152,11 @@ pub struct BanStatus {
     pub triggered_rule_id: Option<String>,
     /// 封禁原因
     pub reason: Option<String>,
-    /// 历史封禁次数
+    /// 历史封禁次数（配置了升级策略时，静默期后重新计次）
     pub ban_count: u32,
+    /// 是否为永久封禁（`banned_until` 为空，需手动解封）
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub permanent: bool,
 }
 
 /// API Key 级别的并发配置
@@ -410,7 +1171,7 @@ pub struct ApiKeyConcurrencyConfig {
 }
 
 /// Token 配额配置
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TokenQuotaConfig {
     /// 每日总token上限（input + output）
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -423,13 +1184,320 @@ pub struct TokenQuotaConfig {
     pub daily_output_limit: Option<u64>,
     /// 每周总token上限
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub weekly_total_limit: Option<u64>,
-    /// 每周input token上限
+    pub weekly_total_limit: Option<u64>,
+    /// 每周input token上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_input_limit: Option<u64>,
+    /// 每周output token上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_output_limit: Option<u64>,
+    /// 每月总token上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_total_limit: Option<u64>,
+    /// 每月input token上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_input_limit: Option<u64>,
+    /// 每月output token上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_output_limit: Option<u64>,
+    /// 周窗口：最近 7 天或自然周（周一起）
+    #[serde(default, skip_serializing_if = "QuotaWindowMode::is_rolling")]
+    pub weekly_window: QuotaWindowMode,
+    /// 月窗口：最近 30 天或自然月
+    #[serde(default, skip_serializing_if = "QuotaWindowMode::is_rolling")]
+    pub monthly_window: QuotaWindowMode,
+    /// 日/周/月的重置时区（UTC 偏移，如 `+08:00`、`+05:30`），默认 UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset_timezone: Option<String>,
+    /// 滚动 N 小时窗口限制
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rolling_windows: Vec<RollingQuotaConfig>,
+}
+
+/// 周/月配额窗口的计算方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaWindowMode {
+    /// 滚动窗口（周为最近 7 天，月为最近 30 天，均含当天）
+    #[default]
+    Rolling,
+    /// 自然周 / 自然月
+    Calendar,
+}
+
+impl QuotaWindowMode {
+    fn is_rolling(&self) -> bool {
+        *self == QuotaWindowMode::Rolling
+    }
+}
+
+/// 滚动 N 小时配额窗口（按整点小时统计，含当前小时）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RollingQuotaConfig {
+    pub hours: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_limit: Option<u64>,
+}
+
+/// 滚动窗口的最大小时数（与内存中的用量保留时长一致）
+pub const MAX_ROLLING_QUOTA_HOURS: u32 = 720;
+
+impl TokenQuotaConfig {
+    /// 重置时区相对 UTC 的偏移（秒），未配置时为 0
+    pub fn reset_offset_secs(&self) -> i64 {
+        self.reset_timezone
+            .as_deref()
+            .and_then(parse_utc_offset)
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn validate(&self, owner: &str) -> Result<(), ConfigError> {
+        if let Some(timezone) = &self.reset_timezone
+            && parse_utc_offset(timezone).is_none()
+        {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: token_quota.reset_timezone must be `UTC` or an offset like `+08:00` or `+05:30`"
+            )));
+        }
+        for window in &self.rolling_windows {
+            if window.hours == 0 || window.hours > MAX_ROLLING_QUOTA_HOURS {
+                return Err(ConfigError::Validation(format!(
+                    "{owner}: token_quota.rolling_windows.hours must be between 1 and {MAX_ROLLING_QUOTA_HOURS}"
+                )));
+            }
+            let limits = [window.total_limit, window.input_limit, window.output_limit];
+            if limits.iter().all(Option::is_none) || limits.contains(&Some(0)) {
+                return Err(ConfigError::Validation(format!(
+                    "{owner}: token_quota.rolling_windows ({}h) must configure limits > 0",
+                    window.hours
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 解析 `UTC`、`Z`、`+08:00`、`+05:30`、`+0800`、`-05` 形式的 UTC 偏移（秒），范围 ±14:00
+fn parse_utc_offset(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let value = value.strip_prefix("UTC").unwrap_or(value);
+    if value.is_empty() || value == "Z" {
+        return Some(0);
+    }
+    let sign = match value.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = value[1..].replacen(':', "", 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.as_str(), "00"),
+        3 | 4 => digits.split_at(digits.len() - 2),
+        _ => return None,
+    };
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    let offset = hours * 3600 + minutes * 60;
+    (minutes < 60 && offset <= 14 * 3600).then_some(sign * offset)
+}
+
+/// TPM/TPD 限制配置，请求按预估输入 token 准入，完成后按实际用量校正
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenRateLimitConfig {
+    /// 每分钟 token 上限（input + output）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u64>,
+    /// 每日 token 上限（input + output，按 UTC 自然日）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_day: Option<u64>,
+}
+
+impl TokenRateLimitConfig {
+    fn validate(&self, owner: &str) -> Result<(), ConfigError> {
+        match self.zero_field() {
+            Some(field) => Err(ConfigError::Validation(format!(
+                "{owner}: token_rate_limit.{field} must be > 0 when provided"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// 第一个配置为 0 的限制字段
+    fn zero_field(&self) -> Option<&'static str> {
+        [
+            ("tokens_per_minute", self.tokens_per_minute),
+            ("tokens_per_day", self.tokens_per_day),
+        ]
+        .into_iter()
+        .find_map(|(field, value)| (value == Some(0)).then_some(field))
+    }
+}
+
+/// 费用预算，金额单位同 `pricing.currency`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    /// 每日费用上限（UTC 自然日）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_limit: Option<f64>,
+    /// 每周费用上限（最近 7 天，含当天）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_limit: Option<f64>,
+    /// 每月费用上限（UTC 自然月）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_limit: Option<f64>,
+    /// 软阈值（上限的比例，0~1），达到后输出告警日志
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soft_threshold: Option<f64>,
+    /// 达到上限时的响应状态码（402 或 429）
+    #[serde(default = "default_budget_exceeded_status")]
+    pub exceeded_status: u16,
+}
+
+impl BudgetConfig {
+    fn validate(&self, owner: &str) -> Result<(), ConfigError> {
+        let limits = [
+            ("daily_limit", self.daily_limit),
+            ("weekly_limit", self.weekly_limit),
+            ("monthly_limit", self.monthly_limit),
+        ];
+        if limits.iter().all(|(_, value)| value.is_none()) {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: budget must configure at least one of `daily_limit`, `weekly_limit` or `monthly_limit`"
+            )));
+        }
+        for (field, value) in limits {
+            if value.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+                return Err(ConfigError::Validation(format!(
+                    "{owner}: budget.{field} must be > 0 when provided"
+                )));
+            }
+        }
+        if self.soft_threshold.is_some_and(|t| !(t > 0.0 && t < 1.0)) {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: budget.soft_threshold must be between 0 and 1"
+            )));
+        }
+        if !matches!(self.exceeded_status, 402 | 429) {
+            return Err(ConfigError::Validation(format!(
+                "{owner}: budget.exceeded_status must be 402 or 429"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 内置分词器配置，词表为 tiktoken 格式的本地文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenizerConfig {
+    /// cl100k_base 词表路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cl100k_base_path: Option<String>,
+    /// o200k_base 词表路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub o200k_base_path: Option<String>,
+    /// 请求未指定模型时使用的编码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_encoding: Option<TokenizerEncoding>,
+}
+
+/// 共享状态后端
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StateBackendConfig {
+    /// 进程内存（默认，状态不在实例间共享）
+    Memory,
+    /// Redis 协议后端
+    Redis(RedisStateBackendConfig),
+}
+
+/// Redis 共享状态后端配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedisStateBackendConfig {
+    /// 连接地址，如 `redis://127.0.0.1:6379/0`
+    pub url: String,
+    /// 键名前缀
+    #[serde(default = "default_state_key_prefix")]
+    pub key_prefix: String,
+    /// 单次操作超时（毫秒），超时视为后端不可用
+    #[serde(default = "default_state_timeout_ms")]
+    pub timeout_ms: u64,
+    /// 配额用量与封禁状态的同步间隔（毫秒）
+    #[serde(default = "default_state_sync_interval_ms")]
+    pub sync_interval_ms: u64,
+    /// 并发槽位租约时长（秒），请求进行中自动续期
+    #[serde(default = "default_state_slot_lease_secs")]
+    pub slot_lease_secs: u64,
+}
+
+fn default_state_key_prefix() -> String {
+    "ai-gw:".to_string()
+}
+
+fn default_state_timeout_ms() -> u64 {
+    200
+}
+
+fn default_state_sync_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_state_slot_lease_secs() -> u64 {
+    30
+}
+
+/// 分词器编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerEncoding {
+    Cl100kBase,
+    O200kBase,
+}
+
+/// 模型价格表，价格单位为每 100 万 token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfig {
+    /// 货币单位，仅用于展示
+    #[serde(default = "default_pricing_currency")]
+    pub currency: String,
+    pub models: Vec<ModelPricingConfig>,
+}
+
+/// 单个模型的价格历史
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPricingConfig {
+    /// 模型名，末尾为 `*` 时按前缀匹配
+    pub model: String,
+    pub prices: Vec<ModelPriceConfig>,
+}
+
+/// 某一生效日期起的模型价格
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPriceConfig {
+    /// 生效日期（UTC，`YYYY-MM-DD`），缺省表示一直有效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_from: Option<chrono::NaiveDate>,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// 缓存命中输入价格，缺省按 `input_per_million` 计
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub weekly_input_limit: Option<u64>,
-    /// 每周output token上限
+    pub cached_input_per_million: Option<f64>,
+    /// 推理 token 价格，缺省按 `output_per_million` 计
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub weekly_output_limit: Option<u64>,
+    pub reasoning_per_million: Option<f64>,
 }
 
 /// Token 统计存储配置
@@ -478,14 +1546,22 @@ fn default_token_stats_retention_days() -> u32 {
 #[derive(Debug, Clone)]
 pub struct ResolvedApiKey {
     pub id: String,
-    pub key: String,
+    pub key_hash: String,
+    pub key_prefix: String,
+    pub previous_keys: Vec<PreviousKeyConfig>,
     pub route_id: Option<String>,
     pub route_ids: Option<Vec<String>>,
     pub enabled: bool,
+    pub not_before: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub idle_expiry_days: Option<u64>,
     pub remark: String,
     pub rate_limit: Option<RateLimitConfig>,
     pub concurrency: Option<ApiKeyConcurrencyConfig>,
     pub token_quota: Option<TokenQuotaConfig>,
+    pub token_rate_limit: Option<TokenRateLimitConfig>,
+    pub budget: Option<BudgetConfig>,
+    pub max_output_tokens_per_request: Option<u64>,
     pub ban_rules: Vec<BanRule>,
     pub ban_status: Option<BanStatus>,
 }
@@ -522,6 +1598,29 @@ impl Default for TracingConfig {
     }
 }
 
+/// 字段级校验错误
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// 字段路径，如 `upstream.base_url`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
     Io(std::io::Error),
@@ -542,16 +1641,29 @@ fn generate_key_id(key: &str) -> String {
 impl ResolvedApiKey {
     /// 从配置创建
     pub fn from_config(config: &ApiKeyConfig) -> Self {
+        let (key_hash, key_prefix) = if config.key.is_empty() {
+            (config.key_hash.clone(), config.key_prefix.clone())
+        } else {
+            (key_hash::hash_key(&config.key), key_hash::key_prefix(&config.key))
+        };
         Self {
             id: config.id.clone(),
-            key: config.key.clone(),
+            key_hash,
+            key_prefix,
+            previous_keys: config.previous_keys.clone(),
             route_id: config.route_id.clone(),
             route_ids: config.route_ids.clone(),
             enabled: config.enabled,
+            not_before: config.not_before,
+            expires_at: config.expires_at,
+            idle_expiry_days: config.idle_expiry_days,
             remark: config.remark.clone(),
             rate_limit: config.rate_limit.clone(),
             concurrency: config.concurrency.clone(),
             token_quota: config.token_quota.clone(),
+            token_rate_limit: config.token_rate_limit.clone(),
+            budget: config.budget.clone(),
+            max_output_tokens_per_request: config.max_output_tokens_per_request,
             ban_rules: config.ban_rules.clone(),
             ban_status: config.ban_status.clone(),
         }
@@ -561,36 +1673,97 @@ impl ResolvedApiKey {
     pub fn from_key_string(key: &str) -> Self {
         Self {
             id: generate_key_id(key),
-            key: key.to_string(),
+            key_hash: key_hash::hash_key(key),
+            key_prefix: key_hash::key_prefix(key),
+            previous_keys: Vec::new(),
             route_id: None,
             route_ids: None,
             enabled: true,
+            not_before: None,
+            expires_at: None,
+            idle_expiry_days: None,
             remark: String::new(),
             rate_limit: None,
             concurrency: None,
             token_quota: None,
+            token_rate_limit: None,
+            budget: None,
+            max_output_tokens_per_request: None,
             ban_rules: Vec::new(),
             ban_status: None,
         }
     }
 }
 
-impl AppConfig {
-    /// 获取所有解析后的 API Key 配置
-    pub fn resolved_api_keys(&self) -> Vec<ResolvedApiKey> {
-        let mut resolved = Vec::new();
-        let mut seen_keys = HashSet::new();
+impl ApiKeyConfig {
+    /// 将明文 Key 转换为加盐哈希与展示前缀，返回是否发生转换
+    pub fn hash_plaintext_key(&mut self) -> bool {
+        if self.key.is_empty() {
+            return false;
+        }
+        let key = std::mem::take(&mut self.key);
+        self.key_hash = key_hash::hash_key(&key);
+        self.key_prefix = key_hash::key_prefix(&key);
+        true
+    }
 
-        // 处理 api_keys.keys（全局配置）
-        if let Some(global) = &self.api_keys {
-            for config in &global.keys {
-                if seen_keys.insert(config.key.clone()) {
-                    resolved.push(ResolvedApiKey::from_config(config));
-                }
-            }
+    /// 明文 Key 是否与本 Key（含轮换宽限期内的旧 Key）相同
+    fn matches_secret(&self, secret: &str) -> bool {
+        if !self.key.is_empty() {
+            return key_hash::constant_time_eq(self.key.as_bytes(), secret.as_bytes());
+        }
+        key_hash::verify_key(secret, &self.key_hash)
+            || self
+                .previous_keys
+                .iter()
+                .any(|previous| key_hash::verify_key(secret, &previous.key_hash))
+    }
+}
+
+/// 检查第 `index` 个 Key 的明文是否与其他 Key 重复
+///
+/// 加盐哈希无法相互比较，只能在明文转换为哈希前逐一校验其他 Key 的哈希。
+fn check_duplicate_secret(keys: &[ApiKeyConfig], index: usize) -> Result<(), ConfigError> {
+    let key = &keys[index];
+    if key.key.is_empty() {
+        return Ok(());
+    }
+    match keys
+        .iter()
+        .enumerate()
+        .find(|(other_index, other)| *other_index != index && other.matches_secret(&key.key))
+    {
+        Some((_, other)) => Err(ConfigError::Validation(format!(
+            "api_key {}: key duplicates api_key {}",
+            key.id, other.id
+        ))),
+        None => Ok(()),
+    }
+}
+
+impl AppConfig {
+    /// 将所有明文 API Key 转换为哈希存储，返回转换的数量；明文与其他 Key 重复时报错
+    pub fn hash_plaintext_api_keys(&mut self) -> Result<usize, ConfigError> {
+        let Some(api_keys) = self.api_keys.as_mut() else {
+            return Ok(0);
+        };
+        for index in 0..api_keys.keys.len() {
+            check_duplicate_secret(&api_keys.keys, index)?;
         }
+        Ok(api_keys
+            .keys
+            .iter_mut()
+            .filter_map(|key| key.hash_plaintext_key().then_some(()))
+            .count())
+    }
 
-        resolved
+    /// 获取所有解析后的 API Key 配置
+    pub fn resolved_api_keys(&self) -> Vec<ResolvedApiKey> {
+        // 重复的 Key 在明文转换为哈希时已被拒绝
+        self.api_keys
+            .as_ref()
+            .map(|global| global.keys.iter().map(ResolvedApiKey::from_config).collect())
+            .unwrap_or_default()
     }
 
     /// 获取指定 API Key 的有效限流配置（继承机制：api_key级 > 全局级）
@@ -684,13 +1857,24 @@ impl AppConfig {
             }
         }
 
+        // 主配置内联或通过环境变量注入的明文 Key 只在内存中转换，保存配置后落盘为哈希
+        let hashed_in_memory = config.hash_plaintext_api_keys()?;
+        if hashed_in_memory > 0 {
+            eprintln!(
+                "warning: {} api key(s) are configured in plaintext and were hashed in memory only; \
+                 save the config from the admin console to persist hashed keys.",
+                hashed_in_memory
+            );
+        }
+
         config.validate()?;
         Ok(config)
     }
 
     pub fn from_yaml_str(yaml: &str) -> Result<Self, ConfigError> {
         let interpolated = interpolate_env_vars(yaml)?;
-        let config: Self = serde_yaml::from_str(&interpolated).map_err(ConfigError::Yaml)?;
+        let mut config: Self = serde_yaml::from_str(&interpolated).map_err(ConfigError::Yaml)?;
+        config.hash_plaintext_api_keys()?;
         config.validate()?;
         Ok(config)
     }
@@ -730,12 +1914,28 @@ impl AppConfig {
                         key_config.id
                     )));
                 }
-                if key_config.key.trim().is_empty() {
+                if key_config.key.trim().is_empty() && key_config.key_hash.is_empty() {
                     return Err(ConfigError::Validation(format!(
                         "api_key {}: key must not be empty",
                         key_config.id
                     )));
                 }
+                if !key_config.key_hash.is_empty() && !key_hash::is_valid_key_hash(&key_config.key_hash) {
+                    return Err(ConfigError::Validation(format!(
+                        "api_key {}: key_hash must be in `sha256$<salt>$<digest>` format",
+                        key_config.id
+                    )));
+                }
+                if key_config
+                    .previous_keys
+                    .iter()
+                    .any(|previous| !key_hash::is_valid_key_hash(&previous.key_hash))
+                {
+                    return Err(ConfigError::Validation(format!(
+                        "api_key {}: previous_keys.key_hash must be in `sha256$<salt>$<digest>` format",
+                        key_config.id
+                    )));
+                }
                 // 验证限流配置
                 if let Some(rate_limit) = &key_config.rate_limit {
                     if rate_limit.per_minute == 0 {
@@ -745,6 +1945,35 @@ impl AppConfig {
                         )));
                     }
                 }
+                if let Some(token_rate_limit) = &key_config.token_rate_limit {
+                    token_rate_limit.validate(&format!("api_key {}", key_config.id))?;
+                }
+                if let Some(token_quota) = &key_config.token_quota {
+                    token_quota.validate(&format!("api_key {}", key_config.id))?;
+                }
+                if let Some(budget) = &key_config.budget {
+                    budget.validate(&format!("api_key {}", key_config.id))?;
+                }
+                if let (Some(not_before), Some(expires_at)) = (key_config.not_before, key_config.expires_at)
+                    && not_before >= expires_at
+                {
+                    return Err(ConfigError::Validation(format!(
+                        "api_key {}: `not_before` must be earlier than `expires_at`",
+                        key_config.id
+                    )));
+                }
+                if key_config.idle_expiry_days == Some(0) {
+                    return Err(ConfigError::Validation(format!(
+                        "api_key {}: `idle_expiry_days` must be > 0 when provided",
+                        key_config.id
+                    )));
+                }
+                if key_config.max_output_tokens_per_request == Some(0) {
+                    return Err(ConfigError::Validation(format!(
+                        "api_key {}: max_output_tokens_per_request must be > 0 when provided",
+                        key_config.id
+                    )));
+                }
                 // 验证并发配置
                 if let Some(concurrency) = &key_config.concurrency {
                     if let Some(limit) = concurrency.downstream_max_inflight {
@@ -765,8 +1994,22 @@ impl AppConfig {
                     }
                 }
             }
+            for rule in &global.ban_rules {
+                let owner = format!("ban_rule {}", rule.id);
+                rule.condition.validate(&owner)?;
+                if rule.subject != BanSubject::Key && rule.condition.contains_key_sharing() {
+                    return Err(ConfigError::Validation(format!(
+                        "{owner}: key_sharing condition requires `subject: key`"
+                    )));
+                }
+                if let Some(escalation) = &rule.escalation {
+                    escalation.validate(&owner)?;
+                }
+            }
         }
 
+        validate_token_sources("gateway_auth", &self.gateway_auth.token_sources, self.api_keys.as_ref())?;
+
         let routes = self.routes.as_deref().unwrap_or_default();
         if routes.is_empty() {
             return Err(ConfigError::Validation(
@@ -792,20 +2035,6 @@ impl AppConfig {
                 )));
             }
 
-            if !route.prefix.starts_with('/') {
-                return Err(ConfigError::Validation(format!(
-                    "route `{}` prefix must start with `/`",
-                    route.id
-                )));
-            }
-
-            if route.prefix.len() > 1 && route.prefix.ends_with('/') {
-                return Err(ConfigError::Validation(format!(
-                    "route `{}` prefix must not end with `/`",
-                    route.id
-                )));
-            }
-
             if !prefixes.insert(route.prefix.clone()) {
                 return Err(ConfigError::Validation(format!(
                     "duplicate route prefix `{}`",
@@ -813,88 +2042,26 @@ impl AppConfig {
                 )));
             }
 
-            if route.upstream.base_url.trim().is_empty() {
-                return Err(ConfigError::Validation(format!(
-                    "route `{}` upstream.base_url must not be empty",
-                    route.id
-                )));
-            }
-
-            if route.upstream.connect_timeout_ms == 0 {
-                return Err(ConfigError::Validation(format!(
-                    "route `{}` upstream.connect_timeout_ms must be > 0",
-                    route.id
-                )));
-            }
+            has_route_upstream_key_concurrency |= route.upstream.upstream_key_max_inflight.is_some();
 
-            if route.upstream.request_timeout_ms == 0 {
+            if let Some(err) = route.field_errors().first() {
                 return Err(ConfigError::Validation(format!(
-                    "route `{}` upstream.request_timeout_ms must be > 0",
+                    "route `{}` {err}",
                     route.id
                 )));
             }
 
-            if let Some(user_agent) = route.upstream.user_agent.as_deref() {
-                if user_agent.trim().is_empty() {
-                    return Err(ConfigError::Validation(format!(
-                        "route `{}` upstream.user_agent must not be empty when provided",
-                        route.id
-                    )));
-                }
-                if HeaderValue::from_str(user_agent).is_err() {
-                    return Err(ConfigError::Validation(format!(
-                        "route `{}` upstream.user_agent must be a valid header value",
-                        route.id
-                    )));
-                }
-            }
-
-            if let Some(limit) = route.upstream.upstream_key_max_inflight {
-                has_route_upstream_key_concurrency = true;
-                if limit == 0 {
-                    return Err(ConfigError::Validation(format!(
-                        "route `{}` upstream.upstream_key_max_inflight must be > 0 when provided",
-                        route.id
-                    )));
-                }
-            }
-
-            if let Some(proxy) = &route.upstream.proxy {
-                if proxy.address.trim().is_empty() {
-                    return Err(ConfigError::Validation(format!(
-                        "route `{}` upstream.proxy.address must not be empty",
-                        route.id
-                    )));
-                }
-
-                match (&proxy.username, &proxy.password) {
-                    (Some(username), Some(password))
-                        if username.trim().is_empty() || password.trim().is_empty() =>
-                    {
-                        return Err(ConfigError::Validation(format!(
-                            "route `{}` upstream.proxy.username/password must not be empty",
-                            route.id
-                        )));
-                    }
-                    (Some(_), Some(_)) | (None, None) => {}
-                    _ => {
-                        return Err(ConfigError::Validation(format!(
-                            "route `{}` upstream.proxy.username and upstream.proxy.password must be set together",
-                            route.id
-                        )));
-                    }
-                }
-            }
-
-            for header in &route.upstream.inject_headers {
-                if header.name.trim().is_empty() {
+            if let Some(auth) = &route.auth {
+                validate_token_sources(&format!("route `{}` auth", route.id), &auth.token_sources, self.api_keys.as_ref())?;
+                if auth.require_client_cert
+                    && self.inbound_tls.as_ref().and_then(|tls| tls.client_ca_path.as_ref()).is_none()
+                {
                     return Err(ConfigError::Validation(format!(
-                        "route `{}` has empty inject_headers.name",
+                        "route `{}` `auth.require_client_cert` requires `inbound_tls.client_ca_path`",
                         route.id
                     )));
                 }
             }
-
         }
 
         let mut has_global_upstream_key_concurrency = false;
@@ -906,6 +2073,26 @@ impl AppConfig {
             ));
         }
 
+        if let Err(value) = crate::client_ip::parse_trusted_proxies(&self.trusted_proxies) {
+            return Err(ConfigError::Validation(format!(
+                "`trusted_proxies` entry `{value}` must be an IP address or CIDR"
+            )));
+        }
+
+        if let Some(protection) = &self.auth_protection {
+            for (field, value) in [
+                ("max_failures", u64::from(protection.max_failures)),
+                ("window_secs", protection.window_secs),
+                ("lockout_secs", protection.lockout_secs),
+            ] {
+                if value == 0 {
+                    return Err(ConfigError::Validation(format!(
+                        "`auth_protection.{field}` must be > 0"
+                    )));
+                }
+            }
+        }
+
         if let Some(concurrency) = &self.concurrency {
             if let Some(limit) = concurrency.downstream_max_inflight
                 && limit == 0
@@ -944,6 +2131,110 @@ impl AppConfig {
             }
         }
 
+        if let Some(tokenizer) = &self.tokenizer {
+            let paths = [
+                ("cl100k_base_path", tokenizer.cl100k_base_path.as_deref()),
+                ("o200k_base_path", tokenizer.o200k_base_path.as_deref()),
+            ];
+            if paths.iter().all(|(_, path)| path.is_none()) {
+                return Err(ConfigError::Validation(
+                    "`tokenizer` must configure at least one of `cl100k_base_path` or `o200k_base_path`"
+                        .to_string(),
+                ));
+            }
+            for (field, path) in paths {
+                if path.is_some_and(|p| p.trim().is_empty()) {
+                    return Err(ConfigError::Validation(format!(
+                        "`tokenizer.{field}` must not be empty when provided"
+                    )));
+                }
+            }
+            let default_path = match tokenizer.default_encoding {
+                Some(TokenizerEncoding::Cl100kBase) => Some(tokenizer.cl100k_base_path.as_ref()),
+                Some(TokenizerEncoding::O200kBase) => Some(tokenizer.o200k_base_path.as_ref()),
+                None => None,
+            };
+            if default_path.is_some_and(|path| path.is_none()) {
+                return Err(ConfigError::Validation(
+                    "`tokenizer.default_encoding` requires its vocabulary path to be configured"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if let Some(budget) = &self.global_budget {
+            budget.validate("global_budget")?;
+        }
+
+        if let Some(StateBackendConfig::Redis(redis)) = &self.state_backend {
+            if !redis.url.starts_with("redis://") && !redis.url.starts_with("redis+unix://") {
+                return Err(ConfigError::Validation(
+                    "`state_backend.url` must start with `redis://` or `redis+unix://`".to_string(),
+                ));
+            }
+            if redis.timeout_ms == 0 {
+                return Err(ConfigError::Validation(
+                    "`state_backend.timeout_ms` must be > 0".to_string(),
+                ));
+            }
+            if redis.sync_interval_ms < 100 {
+                return Err(ConfigError::Validation(
+                    "`state_backend.sync_interval_ms` must be >= 100".to_string(),
+                ));
+            }
+            if redis.slot_lease_secs < 3 {
+                return Err(ConfigError::Validation(
+                    "`state_backend.slot_lease_secs` must be >= 3".to_string(),
+                ));
+            }
+        }
+
+        if let Some(pricing) = &self.pricing {
+            let mut models = HashSet::new();
+            for model in &pricing.models {
+                if model.model.trim().is_empty() {
+                    return Err(ConfigError::Validation(
+                        "`pricing.models[].model` must not be empty".to_string(),
+                    ));
+                }
+                if !models.insert(model.model.as_str()) {
+                    return Err(ConfigError::Validation(format!(
+                        "duplicated pricing model `{}`",
+                        model.model
+                    )));
+                }
+                if model.prices.is_empty() {
+                    return Err(ConfigError::Validation(format!(
+                        "pricing model `{}` must configure at least one price",
+                        model.model
+                    )));
+                }
+                let mut effective_dates = HashSet::new();
+                for price in &model.prices {
+                    if !effective_dates.insert(price.effective_from) {
+                        return Err(ConfigError::Validation(format!(
+                            "pricing model `{}` has duplicated `effective_from`",
+                            model.model
+                        )));
+                    }
+                    let fields = [
+                        ("input_per_million", Some(price.input_per_million)),
+                        ("output_per_million", Some(price.output_per_million)),
+                        ("cached_input_per_million", price.cached_input_per_million),
+                        ("reasoning_per_million", price.reasoning_per_million),
+                    ];
+                    for (field, value) in fields {
+                        if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                            return Err(ConfigError::Validation(format!(
+                                "pricing model `{}`: `{field}` must be a non-negative number",
+                                model.model
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+
         if let Some(tls) = &self.inbound_tls {
             validate_optional_path(
                 tls.cert_path.as_deref(),
@@ -1127,6 +2418,34 @@ fn default_trigger_window() -> u64 {
     3600 // 默认1小时
 }
 
+fn default_escalation_multiplier() -> f64 {
+    2.0
+}
+
+fn default_escalation_decay_secs() -> u64 {
+    7 * 24 * 3600 // 默认7天
+}
+
+fn default_budget_exceeded_status() -> u16 {
+    402
+}
+
+fn default_pricing_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_jwks_reload_secs() -> u64 {
+    300
+}
+
+fn default_jwt_leeway_secs() -> u64 {
+    60
+}
+
+fn default_jwt_identity_claim() -> String {
+    "sub".to_string()
+}
+
 fn default_token_sources() -> Vec<TokenSourceConfig> {
     vec![TokenSourceConfig::AuthorizationBearer]
 }
@@ -1280,6 +2599,31 @@ struct BanRulesFileConfig {
     pub rules: Vec<BanRule>,
 }
 
+/// 将明文 apikey 文件迁移为哈希存储（写入失败时仅在内存中转换）
+fn migrate_plaintext_key_file(path: &Path, key: &mut ApiKeyConfig) {
+    key.hash_plaintext_key();
+    let result = serde_yaml::to_string(&*key)
+        .map_err(|e| e.to_string())
+        .and_then(|yaml| {
+            let temp_path = path.with_extension("yaml.tmp");
+            fs::write(&temp_path, yaml)
+                .and_then(|()| fs::rename(&temp_path, path))
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(()) => eprintln!(
+            "info: migrated plaintext api key '{}' in '{}' to hashed storage",
+            key.id,
+            path.display()
+        ),
+        Err(e) => eprintln!(
+            "warning: failed to migrate plaintext api key file '{}': {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
 /// 从 data/apikeys/ 目录加载所有 apikey 配置
 fn load_api_keys_from_dir(
     data_path: &Path,
@@ -1290,6 +2634,7 @@ fn load_api_keys_from_dir(
     }
 
     let mut keys = vec![];
+    let mut files = vec![];
     for entry in fs::read_dir(&keys_dir).map_err(ConfigError::Io)? {
         let entry = entry.map_err(ConfigError::Io)?;
         let path = entry.path();
@@ -1308,7 +2653,12 @@ fn load_api_keys_from_dir(
                 ))
             })?;
             match serde_yaml::from_str::<ApiKeyConfig>(&interpolated) {
-                Ok(key) => keys.push(key),
+                Ok(key) => {
+                    // 含环境变量的文件不回写，避免把注入的值落盘
+                    let migrate = !key.key.is_empty() && interpolated == raw;
+                    keys.push(key);
+                    files.push((path, migrate));
+                }
                 Err(e) => {
                     let err_str = e.to_string();
                     if err_str.contains("missing field") {
@@ -1328,6 +2678,16 @@ fn load_api_keys_from_dir(
         }
     }
 
+    // 所有文件读取后再检查重复，明文一旦回写为哈希就无法再比较
+    for index in 0..keys.len() {
+        check_duplicate_secret(&keys, index)?;
+    }
+    for (key, (path, migrate)) in keys.iter_mut().zip(&files) {
+        if *migrate {
+            migrate_plaintext_key_file(path, key);
+        }
+    }
+
     // 加载 ban_rules
     let ban_rules_path = data_path.join("ban_rules.yaml");
     let ban_rules = if ban_rules_path.exists() {
@@ -1362,24 +2722,84 @@ fn load_api_keys_from_dir(
         keys,
         ban_rules,
         sqlite: None, // sqlite 配置从主配置继承
+        expiry_warning_days: default_expiry_warning_days(),
+        rotation_grace_secs: default_rotation_grace_secs(),
     }))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{AppConfig, LogFormat, LogRotation, ProxyProtocol};
+    use super::{
+        AppConfig, BanAction, BanCondition, BanRuleMode, BanSubject, LogFormat, LogRotation, ProxyProtocol, QuotaWindowMode, RouteAuthMode,
+        SpikeMetric, StateBackendConfig, TokenSourceConfig, parse_status_pattern, parse_utc_offset,
+    };
+
+    #[test]
+    fn parse_minimal_config() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
+        assert_eq!(config.routes.as_ref().map_or(0, |r| r.len()), 1);
+        assert!(config.cors.is_none());
+        assert!(config.rate_limit.is_none());
+        assert!(config.concurrency.is_none());
+    }
+
+    #[test]
+    fn parse_config_with_env_interpolation() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: '${PATH}'
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
+        let key = &config.api_keys.as_ref().unwrap().keys[0];
+        // 插值后的明文 Key 在加载时转换为哈希
+        assert!(key.key.is_empty());
+        let path = std::env::var("PATH").expect("PATH should be set");
+        assert!(crate::api_keys::key_hash::verify_key(&path, &key.key_hash));
+    }
 
     #[test]
-    fn parse_minimal_config() {
+    fn parse_and_validate_api_key_lifecycle() {
         let yaml = r#"
 listen: "127.0.0.1:8080"
 gateway_auth:
   token_sources:
     - type: "authorization_bearer"
 api_keys:
+  expiry_warning_days: 14
   keys:
     - id: "default"
       key: "gw_token"
+      not_before: 1700000000
+      expires_at: 1800000000
+      idle_expiry_days: 90
 routes:
   - id: "openai"
     prefix: "/openai"
@@ -1388,32 +2808,49 @@ routes:
 "#;
 
         let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
-        assert_eq!(config.routes.as_ref().map_or(0, |r| r.len()), 1);
-        assert!(config.cors.is_none());
-        assert!(config.rate_limit.is_none());
-        assert!(config.concurrency.is_none());
+        let api_keys = config.api_keys.as_ref().unwrap();
+        assert_eq!(api_keys.expiry_warning_days, 14);
+        let resolved = &config.resolved_api_keys()[0];
+        assert_eq!(resolved.not_before, Some(1700000000));
+        assert_eq!(resolved.expires_at, Some(1800000000));
+        assert_eq!(resolved.idle_expiry_days, Some(90));
+
+        let invalid = yaml.replace("not_before: 1700000000", "not_before: 1900000000");
+        let err = AppConfig::from_yaml_str(&invalid).expect_err("not_before after expires_at should be rejected");
+        assert!(err.to_string().contains("`not_before` must be earlier than `expires_at`"));
     }
 
     #[test]
-    fn parse_config_with_env_interpolation() {
-        let yaml = r#"
+    fn rejects_duplicate_plaintext_and_hashed_keys() {
+        let yaml = format!(
+            r#"
 listen: "127.0.0.1:8080"
 gateway_auth:
   token_sources:
     - type: "authorization_bearer"
 api_keys:
   keys:
-    - id: "default"
-      key: '${PATH}'
+    - id: "stored"
+      key_hash: "{}"
+      key_prefix: "gw"
+    - id: "inline"
+      key: "gw_token"
 routes:
   - id: "openai"
     prefix: "/openai"
     upstream:
       base_url: "https://api.openai.com"
-"#;
+"#,
+            crate::api_keys::key_hash::hash_key("gw_token")
+        );
 
-        let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
-        assert!(!config.api_keys.as_ref().unwrap().keys[0].key.is_empty());
+        // 加盐哈希互不相同，只能用明文校验已有哈希来发现重复
+        let err = AppConfig::from_yaml_str(&yaml).expect_err("duplicate key should be rejected");
+        assert!(err.to_string().contains("api_key inline: key duplicates api_key stored"));
+
+        let distinct = yaml.replace("key: \"gw_token\"", "key: \"gw_token_2\"");
+        let config = AppConfig::from_yaml_str(&distinct).expect("distinct keys should parse");
+        assert_eq!(config.resolved_api_keys().len(), 2);
     }
 
     #[test]
@@ -1834,6 +3271,311 @@ rate_limit:
         );
     }
 
+    #[test]
+    fn parse_route_rate_limit() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+    rate_limit:
+      per_minute: 100
+      per_key_per_minute: 10
+      per_ip_per_minute: 20
+"#;
+
+        let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
+        let route = &config.routes.as_ref().expect("routes")[0];
+        let rate_limit = route.rate_limit.as_ref().expect("route rate limit");
+        assert_eq!(rate_limit.per_minute, Some(100));
+        assert_eq!(rate_limit.per_key_per_minute, Some(10));
+        assert_eq!(rate_limit.per_ip_per_minute, Some(20));
+    }
+
+    #[test]
+    fn parse_and_validate_token_rate_limit() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+      token_rate_limit:
+        tokens_per_minute: 10000
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+    token_rate_limit:
+      tokens_per_day: 0
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("route `openai` token_rate_limit.tokens_per_day must be > 0")
+        );
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("tokens_per_day: 0", "tokens_per_day: 500000"))
+            .expect("config should parse");
+        let key = &config.resolved_api_keys()[0];
+        assert_eq!(
+            key.token_rate_limit.as_ref().and_then(|l| l.tokens_per_minute),
+            Some(10000)
+        );
+        let route = &config.routes.as_ref().expect("routes")[0];
+        assert_eq!(
+            route.token_rate_limit.as_ref().and_then(|l| l.tokens_per_day),
+            Some(500000)
+        );
+    }
+
+    #[test]
+    fn parse_token_quota_reset_timezone() {
+        assert_eq!(parse_utc_offset("UTC"), Some(0));
+        assert_eq!(parse_utc_offset("+08:00"), Some(8 * 3600));
+        assert_eq!(parse_utc_offset("-0500"), Some(-5 * 3600));
+        assert_eq!(parse_utc_offset("UTC+9"), Some(9 * 3600));
+        assert_eq!(parse_utc_offset("+05:30"), Some(5 * 3600 + 30 * 60));
+        assert_eq!(parse_utc_offset("+0930"), Some(9 * 3600 + 30 * 60));
+        assert_eq!(parse_utc_offset("-03:30"), Some(-(3 * 3600 + 30 * 60)));
+        assert_eq!(parse_utc_offset("+05:60"), None);
+        assert_eq!(parse_utc_offset("+14:30"), None);
+        assert_eq!(parse_utc_offset("+15:00"), None);
+        assert_eq!(parse_utc_offset("Asia/Shanghai"), None);
+
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+      token_quota:
+        monthly_total_limit: 1000000
+        monthly_window: calendar
+        reset_timezone: "+08:00"
+        rolling_windows:
+          - hours: 5
+            total_limit: 0
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(error.to_string().contains("rolling_windows (5h) must configure limits > 0"));
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("total_limit: 0", "total_limit: 50000"))
+            .expect("config should parse");
+        let quota = config.resolved_api_keys()[0].token_quota.clone().expect("token quota");
+        assert_eq!(quota.monthly_window, QuotaWindowMode::Calendar);
+        assert_eq!(quota.weekly_window, QuotaWindowMode::Rolling);
+        assert_eq!(quota.reset_offset_secs(), 8 * 3600);
+    }
+
+    #[test]
+    fn parse_and_validate_budget() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+      budget:
+        daily_limit: 5.0
+        soft_threshold: 0.8
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+global_budget:
+  monthly_limit: 100.0
+  exceeded_status: 403
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("global_budget: budget.exceeded_status must be 402 or 429")
+        );
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("exceeded_status: 403", "exceeded_status: 429"))
+            .expect("config should parse");
+        let budget = config.resolved_api_keys()[0].budget.clone().expect("key budget");
+        assert_eq!(budget.daily_limit, Some(5.0));
+        assert_eq!(budget.exceeded_status, 402);
+        assert_eq!(config.global_budget.map(|b| b.exceeded_status), Some(429));
+    }
+
+    #[test]
+    fn parse_and_validate_max_output_tokens_per_request() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+      max_output_tokens_per_request: 0
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("api_key default: max_output_tokens_per_request must be > 0")
+        );
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("per_request: 0", "per_request: 512"))
+            .expect("config should parse");
+        assert_eq!(config.resolved_api_keys()[0].max_output_tokens_per_request, Some(512));
+    }
+
+    #[test]
+    fn parse_and_validate_state_backend() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+state_backend:
+  type: redis
+  url: "http://127.0.0.1:6379"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(error.to_string().contains("`state_backend.url` must start with"));
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("http://", "redis://"))
+            .expect("config should parse");
+        match config.state_backend {
+            Some(StateBackendConfig::Redis(redis)) => {
+                assert_eq!(redis.key_prefix, "ai-gw:");
+                assert_eq!(redis.timeout_ms, 200);
+                assert_eq!(redis.sync_interval_ms, 1000);
+                assert_eq!(redis.slot_lease_secs, 30);
+            }
+            other => panic!("unexpected state backend: {other:?}"),
+        }
+
+        let error = AppConfig::from_yaml_str(
+            &yaml.replace("http://127.0.0.1:6379\"", "redis://127.0.0.1:6379\"\n  slot_lease_secs: 1"),
+        )
+        .expect_err("config should fail");
+        assert!(error.to_string().contains("`state_backend.slot_lease_secs` must be >= 3"));
+    }
+
+    #[test]
+    fn parse_and_validate_pricing() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+pricing:
+  models:
+    - model: "gpt-4o"
+      prices:
+        - input_per_million: 5.0
+          output_per_million: 15.0
+        - effective_from: "2024-10-01"
+          input_per_million: 2.5
+          output_per_million: -10.0
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("pricing model `gpt-4o`: `output_per_million` must be a non-negative number")
+        );
+
+        let config = AppConfig::from_yaml_str(&yaml.replace("-10.0", "10.0")).expect("config should parse");
+        let pricing = config.pricing.expect("pricing");
+        assert_eq!(pricing.currency, "USD");
+        assert_eq!(
+            pricing.models[0].prices[1].effective_from,
+            chrono::NaiveDate::from_ymd_opt(2024, 10, 1)
+        );
+    }
+
+    #[test]
+    fn reject_zero_route_rate_limit() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+    rate_limit:
+      per_ip_per_minute: 0
+"#;
+
+        let error = AppConfig::from_yaml_str(yaml).expect_err("config should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("route `openai` rate_limit.per_ip_per_minute must be > 0")
+        );
+    }
+
     #[test]
     fn reject_removed_upstream_key_headers_field() {
         let yaml = r#"
@@ -1928,6 +3670,204 @@ routes:
         );
     }
 
+    #[test]
+    fn route_auth_accepts_legacy_gateway_auth_and_checks_client_ca() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+routes:
+  - id: "anthropic"
+    prefix: "/anthropic"
+    upstream:
+      base_url: "https://api.anthropic.com"
+    gateway_auth:
+      token_sources:
+        - type: "header"
+          name: "x-api-key"
+  - id: "internal"
+    prefix: "/internal"
+    upstream:
+      base_url: "http://127.0.0.1:9000"
+    auth:
+      mode: none
+      require_client_cert: true
+"#;
+
+        let err = AppConfig::from_yaml_str(yaml).expect_err("client cert requires a client ca");
+        assert!(err.to_string().contains("inbound_tls.client_ca_path"), "{err}");
+
+        let config = AppConfig::from_yaml_str(&format!(
+            "{yaml}inbound_tls:\n  cert_path: server.crt\n  key_path: server.key\n  client_ca_path: ca.crt\n"
+        ))
+        .expect("config should parse");
+        let routes = config.routes.as_ref().unwrap();
+        let anthropic = routes[0].auth.as_ref().unwrap();
+        assert_eq!(anthropic.mode, RouteAuthMode::Required);
+        assert_eq!(
+            anthropic.token_sources,
+            vec![TokenSourceConfig::Header {
+                name: "x-api-key".to_string()
+            }]
+        );
+        assert_eq!(routes[1].auth_mode(), RouteAuthMode::None);
+        let serialized = serde_yaml::to_string(&config).expect("should serialize");
+        let reparsed = AppConfig::from_yaml_str(&serialized).expect("should reparse");
+        let auths = |config: &AppConfig| config.routes.iter().flatten().map(|route| route.auth.clone()).collect::<Vec<_>>();
+        assert_eq!(auths(&reparsed), auths(&config));
+    }
+
+    #[test]
+    fn ban_conditions_parse_and_validate() {
+        let yaml = r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "default"
+      key: "gw_token"
+  ban_rules:
+    - id: "upstream_auth"
+      name: "Upstream 401"
+      condition:
+        type: status_code
+        window_secs: 300
+        statuses: ["401", "4xx"]
+        max_count: 20
+      ban_duration_secs: 600
+    - id: "burst"
+      name: "Token spike"
+      condition:
+        type: spike
+        metric: tokens
+        window_secs: 300
+        baseline_secs: 86400
+        multiplier: 10
+        min_value: 50000
+      ban_duration_secs: 600
+    - id: "shared"
+      name: "Key sharing"
+      condition:
+        type: key_sharing
+        window_secs: 3600
+        max_subnets: 3
+      ban_duration_secs: 600
+      action: alert
+      mode: shadow
+    - id: "abuse"
+      name: "Failing while busy"
+      condition:
+        type: all
+        conditions:
+          - type: consecutive_errors
+            count: 5
+          - type: token_usage
+            window_secs: 600
+            max_tokens: 100000
+      ban_duration_secs: 600
+      escalation:
+        multiplier: 4
+        permanent_after: 3
+    - id: "scanner"
+      name: "Invalid credentials"
+      subject: subnet
+      condition:
+        type: status_code
+        window_secs: 60
+        statuses: ["401"]
+        max_count: 30
+      ban_duration_secs: 3600
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#;
+
+        let config = AppConfig::from_yaml_str(yaml).expect("config should parse");
+        let rules = &config.api_keys.as_ref().unwrap().ban_rules;
+        assert!(matches!(
+            rules[1].condition,
+            BanCondition::Spike { metric: SpikeMetric::Tokens, .. }
+        ));
+        assert_eq!(rules[1].condition.max_window_secs(), Some(86_700));
+        assert_eq!(rules[1].action, BanAction::Ban);
+        assert_eq!(rules[2].action, BanAction::Alert);
+        assert_eq!((rules[1].mode, rules[2].mode), (BanRuleMode::Enforce, BanRuleMode::Shadow));
+        assert_eq!(rules[3].condition.max_window_secs(), Some(600));
+        let escalation = rules[3].escalation.as_ref().expect("escalation should parse");
+        assert_eq!(escalation.permanent_after, Some(3));
+        assert_eq!(escalation.decay_secs, 7 * 24 * 3600);
+        assert_eq!((rules[0].subject, rules[4].subject), (BanSubject::Key, BanSubject::Subnet));
+        assert_eq!(parse_status_pattern("4xx"), Some((400, 499)));
+        assert_eq!(parse_status_pattern("6xx"), None);
+
+        let err = AppConfig::from_yaml_str(&yaml.replace("\"4xx\"", "\"4x\"")).expect_err("bad status");
+        assert!(err.to_string().contains("statuses"), "{err}");
+        let err = AppConfig::from_yaml_str(&yaml.replace("multiplier: 10", "multiplier: 1")).expect_err("bad multiplier");
+        assert!(err.to_string().contains("multiplier"), "{err}");
+        let err = AppConfig::from_yaml_str(&yaml.replace("max_subnets: 3", "max_subnets: 0")).expect_err("bad sharing limit");
+        assert!(err.to_string().contains("max_subnets"), "{err}");
+        let err = AppConfig::from_yaml_str(&yaml.replace("max_tokens: 100000", "max_tokens: 0")).expect_err("bad nested condition");
+        assert!(err.to_string().contains("ban_rule abuse"), "{err}");
+        let err = AppConfig::from_yaml_str(&yaml.replace("multiplier: 4", "multiplier: 0.5")).expect_err("bad escalation");
+        assert!(err.to_string().contains("escalation"), "{err}");
+        let err = AppConfig::from_yaml_str(&yaml.replace("      action: alert\n", "      action: alert\n      subject: ip\n"))
+            .expect_err("key sharing by ip");
+        assert!(err.to_string().contains("subject: key"), "{err}");
+    }
+
+    #[test]
+    fn load_from_file_migrates_plaintext_key_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "ai-gw-lite-keys-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("time should move forward")
+                .as_nanos()
+        ));
+        let keys_dir = dir.join("data/apikeys");
+        let routes_dir = dir.join("data/routes");
+        std::fs::create_dir_all(&keys_dir).expect("keys dir should be created");
+        std::fs::create_dir_all(&routes_dir).expect("routes dir should be created");
+        std::fs::write(
+            dir.join("conf.yaml"),
+            "listen: \"127.0.0.1:8080\"\ngateway_auth:\n  token_sources:\n    - type: \"authorization_bearer\"\n",
+        )
+        .expect("main config should be written");
+        std::fs::write(
+            routes_dir.join("openai.yaml"),
+            "id: \"openai\"\nprefix: \"/openai\"\nupstream:\n  base_url: \"https://api.openai.com\"\n",
+        )
+        .expect("route should be written");
+        let key_path = keys_dir.join("default.yaml");
+        std::fs::write(&key_path, "id: \"default\"\nkey: \"sk-gw-ab12cd34ef56gh78\"\n")
+            .expect("key should be written");
+
+        let config = AppConfig::load_from_file(dir.join("conf.yaml")).expect("config should load");
+        let key = &config.api_keys.as_ref().unwrap().keys[0];
+        assert!(key.key.is_empty());
+        assert_eq!(key.key_prefix, "sk-gw-ab12");
+        assert!(crate::api_keys::key_hash::verify_key("sk-gw-ab12cd34ef56gh78", &key.key_hash));
+
+        // 文件已改写为哈希存储，再次加载结果一致
+        let migrated = std::fs::read_to_string(&key_path).expect("key file should exist");
+        assert!(!migrated.contains("sk-gw-ab12cd34ef56gh78"));
+        assert!(migrated.contains(&key.key_hash));
+        let reloaded = AppConfig::load_from_file(dir.join("conf.yaml")).expect("config should reload");
+        assert_eq!(reloaded.api_keys.as_ref().unwrap().keys[0].key_hash, key.key_hash);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn test_load_config_with_data_dir() {
         // 使用测试配置文件测试分散配置加载
@@ -1,5 +1,7 @@
+use crate::api_keys::key_hash;
 use crate::config::AppConfig;
 use crate::config::ResolvedApiKey;
+use crate::config::QuotaWindowMode;
 use sha2::{Digest, Sha256};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Pool, Sqlite};
@@ -39,8 +41,8 @@ pub struct ConfigStorage {
     pool: Pool<Sqlite>,
     /// In-memory cache: currently valid route IDs
     valid_routes: RwLock<HashSet<String>>,
-    /// In-memory cache: currently valid API Key hashes (key_hash -> key_id)
-    valid_api_keys: RwLock<HashMap<String, String>>,
+    /// In-memory cache: currently valid API Keys (key_prefix -> [(key_hash, key_id)])
+    valid_api_keys: RwLock<HashMap<String, Vec<(String, String)>>>,
 }
 
 impl ConfigStorage {
@@ -119,6 +121,34 @@ impl ConfigStorage {
         .await
         .map_err(|e| format!("Failed to create api_keys table: {}", e))?;
 
+        // Key 最近使用时间（闲置过期判断），兼容旧版本数据库
+        let has_last_used = sqlx::query_scalar::<_, String>("SELECT name FROM pragma_table_info('api_keys')")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to inspect api_keys table: {}", e))?
+            .iter()
+            .any(|name| name == "last_used_at");
+        if !has_last_used {
+            sqlx::query("ALTER TABLE api_keys ADD COLUMN last_used_at INTEGER")
+                .execute(pool)
+                .await
+                .map_err(|e| format!("Failed to add api_keys.last_used_at column: {}", e))?;
+        }
+
+        // Gateway-wide persistent settings (e.g. the token fingerprint key)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settings (
+                name TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to create settings table: {}", e))?;
+
         // Create indexes
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_routes_deleted ON routes(is_deleted)"
@@ -158,14 +188,17 @@ impl ConfigStorage {
         let route_set: HashSet<String> = routes.into_iter().map(|r| r.0).collect();
 
         // Load valid API keys
-        let keys: Vec<(String, String)> = sqlx::query_as(
-            "SELECT key_hash, id FROM api_keys WHERE is_deleted = 0 AND is_enabled = 1"
+        let keys: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT key_preview, key_hash, id FROM api_keys WHERE is_deleted = 0 AND is_enabled = 1"
         )
         .fetch_all(&self.pool)
         .await
         .map_err(|e| format!("Failed to load api_keys from database: {}", e))?;
 
-        let key_map: HashMap<String, String> = keys.into_iter().collect();
+        let mut key_map: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for (key_prefix, key_hash, key_id) in keys {
+            key_map.entry(key_prefix).or_default().push((key_hash, key_id));
+        }
 
         // Update caches
         {
@@ -311,11 +344,9 @@ impl ConfigStorage {
             .into_iter()
             .map(|key| {
                 let hash = compute_api_key_config_hash(&key);
-                let key_hash = compute_key_hash(&key.key);
-                let key_preview = key.key.chars().take(8).collect::<String>();
                 (
                     key.id.clone(),
-                    (key_hash, key_preview, hash, key.enabled),
+                    (key.key_hash, key.key_prefix, hash, key.enabled),
                 )
             })
             .collect();
@@ -428,30 +459,31 @@ impl ConfigStorage {
         Ok(result)
     }
 
-    /// Validate API Key by its value (SHA256 hash lookup)
+    /// Validate API Key by its value (prefix lookup + salted hash verification)
     ///
     /// Returns ConfigValidationResult indicating the key's status
     pub async fn validate_api_key(&self, key_value: &str) -> ConfigValidationResult {
-        let key_hash = compute_key_hash(key_value);
+        let prefixes = key_hash::lookup_prefixes(key_value);
 
         // Check in-memory cache first
-        {
-            let cache = self.valid_api_keys.read().await;
-            if let Some(key_id) = cache.get(&key_hash) {
-                return ConfigValidationResult::Valid {
-                    key_id: key_id.clone(),
-                };
-            }
+        if let Some(key_id) = self.get_api_key_id(key_value).await {
+            return ConfigValidationResult::Valid { key_id };
         }
 
         // Not in cache, check database (might be deleted or not exist)
-        match sqlx::query_as::<_, (String, bool, bool)>(
-            "SELECT id, is_deleted, is_enabled FROM api_keys WHERE key_hash = ?1"
+        let rows = sqlx::query_as::<_, (String, String, bool, bool)>(
+            "SELECT id, key_hash, is_deleted, is_enabled FROM api_keys WHERE key_preview IN (?1, ?2)"
         )
-        .bind(&key_hash)
-        .fetch_optional(&self.pool)
+        .bind(&prefixes[0])
+        .bind(prefixes.last())
+        .fetch_all(&self.pool)
         .await
-        {
+        .map(|rows| {
+            rows.into_iter()
+                .find(|(_, key_hash, _, _)| key_hash::verify_key(key_value, key_hash))
+                .map(|(key_id, _, is_deleted, is_enabled)| (key_id, is_deleted, is_enabled))
+        });
+        match rows {
             Ok(Some((key_id, is_deleted, is_enabled))) => {
                 if is_deleted {
                     ConfigValidationResult::Deleted { key_id }
@@ -506,23 +538,33 @@ impl ConfigStorage {
     /// Get all valid API Key IDs (for filtering)
     pub async fn get_valid_api_key_ids(&self) -> HashSet<String> {
         let cache = self.valid_api_keys.read().await;
-        cache.values().cloned().collect()
-    }
-
-    /// Get all valid API Key value hashes (for filtering metrics by key value)
-    pub async fn get_valid_api_key_hashes(&self) -> HashSet<String> {
-        let cache = self.valid_api_keys.read().await;
-        cache.keys().cloned().collect()
+        cache
+            .values()
+            .flatten()
+            .map(|(_, key_id)| key_id.clone())
+            .collect()
     }
 
     /// Get API Key ID by key value (for lookup)
     pub async fn get_api_key_id(&self, key_value: &str) -> Option<String> {
-        let key_hash = compute_key_hash(key_value);
-        self.valid_api_keys.read().await.get(&key_hash).cloned()
+        let cache = self.valid_api_keys.read().await;
+        key_hash::lookup_prefixes(key_value)
+            .iter()
+            .filter_map(|prefix| cache.get(prefix))
+            .find_map(|candidates| find_verified(key_value, candidates))
     }
 
     /// Check if an API Key ID is valid (by ID, not by key value)
     pub async fn is_api_key_id_valid(&self, key_id: &str) -> bool {
+        // Check in-memory cache first
+        {
+            let cache = self.valid_api_keys.read().await;
+            if cache.values().flatten().any(|(_, id)| id == key_id) {
+                return true;
+            }
+        }
+
+        // Not in cache, check database
         match sqlx::query_scalar::<_, bool>(
             "SELECT EXISTS(SELECT 1 FROM api_keys WHERE id = ?1 AND is_deleted = 0 AND is_enabled = 1)"
         )
@@ -538,6 +580,51 @@ impl ConfigStorage {
         }
     }
 
+    /// Record API Key last-used timestamps (keeps the later of stored and given values)
+    pub async fn record_api_key_last_used(&self, last_used: &[(String, u64)]) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| format!("Failed to begin transaction: {}", e))?;
+        for (key_id, used_at) in last_used {
+            sqlx::query("UPDATE api_keys SET last_used_at = MAX(COALESCE(last_used_at, 0), ?1) WHERE id = ?2")
+                .bind(*used_at as i64)
+                .bind(key_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to record api_key last_used_at: {}", e))?;
+        }
+        tx.commit().await.map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(())
+    }
+
+    /// Load API Key activity timestamps (last used, or creation time for never-used keys)
+    pub async fn load_api_key_last_used(&self) -> Result<HashMap<String, u64>, String> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT id, COALESCE(last_used_at, created_at) FROM api_keys WHERE is_deleted = 0"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to load api_key last_used_at: {}", e))?;
+        Ok(rows
+            .into_iter()
+            .map(|(key_id, used_at)| (key_id, used_at.max(0) as u64))
+            .collect())
+    }
+
+    /// Load a persistent setting, storing `value` first if it does not exist yet
+    pub async fn load_or_insert_setting(&self, name: &str, value: &str) -> Result<String, String> {
+        sqlx::query("INSERT OR IGNORE INTO settings (name, value, created_at) VALUES (?1, ?2, ?3)")
+            .bind(name)
+            .bind(value)
+            .bind(current_epoch_seconds() as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to store setting {}: {}", name, e))?;
+        sqlx::query_scalar("SELECT value FROM settings WHERE name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to load setting {}: {}", name, e))
+    }
+
     /// Check if an API Key value is valid (by key value, not by ID)
     /// Returns true if the key exists, is not deleted, and is enabled
     pub async fn is_api_key_value_valid(&self, key_value: &str) -> bool {
@@ -548,11 +635,12 @@ impl ConfigStorage {
     }
 }
 
-/// Compute SHA256 hash of a string
-fn compute_key_hash(key: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(key.as_bytes());
-    format!("{:x}", hasher.finalize())
+/// Find the key_id whose salted hash matches the key value
+fn find_verified(key_value: &str, candidates: &[(String, String)]) -> Option<String> {
+    candidates
+        .iter()
+        .find(|(key_hash, _)| key_hash::verify_key(key_value, key_hash))
+        .map(|(_, key_id)| key_id.clone())
 }
 
 /// Compute configuration hash for a RouteConfig
@@ -585,9 +673,10 @@ fn compute_api_key_config_hash(key: &ResolvedApiKey) -> String {
     // Hash based on key fields that define the API key configuration
     let mut hasher = Sha256::new();
     hasher.update(key.id.as_bytes());
-    hasher.update(key.key.as_bytes());
+    hasher.update(key.key_hash.as_bytes());
     hasher.update(key.enabled.to_string().as_bytes());
     hasher.update(key.remark.as_bytes());
+    hasher.update(format!("{:?}{:?}{:?}", key.not_before, key.expires_at, key.idle_expiry_days).as_bytes());
 
     // Include route permissions in hash
     if let Some(route_id) = &key.route_id {
@@ -633,6 +722,33 @@ fn compute_api_key_config_hash(key: &ResolvedApiKey) -> String {
         if let Some(limit) = tq.weekly_output_limit {
             hasher.update(format!("weekly_output:{}", limit).as_bytes());
         }
+        if let Some(limit) = tq.monthly_total_limit {
+            hasher.update(format!("monthly_total:{}", limit).as_bytes());
+        }
+        if let Some(limit) = tq.monthly_input_limit {
+            hasher.update(format!("monthly_input:{}", limit).as_bytes());
+        }
+        if let Some(limit) = tq.monthly_output_limit {
+            hasher.update(format!("monthly_output:{}", limit).as_bytes());
+        }
+        if tq.weekly_window == QuotaWindowMode::Calendar {
+            hasher.update(b"weekly_window:calendar");
+        }
+        if tq.monthly_window == QuotaWindowMode::Calendar {
+            hasher.update(b"monthly_window:calendar");
+        }
+        if let Some(timezone) = &tq.reset_timezone {
+            hasher.update(format!("reset_timezone:{}", timezone).as_bytes());
+        }
+        for window in &tq.rolling_windows {
+            hasher.update(
+                format!(
+                    "rolling:{}:{:?}:{:?}:{:?}",
+                    window.hours, window.total_limit, window.input_limit, window.output_limit
+                )
+                .as_bytes(),
+            );
+        }
     }
 
     format!("{:x}", hasher.finalize())
@@ -651,14 +767,81 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_compute_key_hash() {
-        let hash1 = compute_key_hash("test-key-123");
-        let hash2 = compute_key_hash("test-key-123");
-        let hash3 = compute_key_hash("different-key");
+    fn test_find_verified() {
+        let candidates = vec![
+            (key_hash::hash_key("test-key-456"), "key-2".to_string()),
+            (key_hash::hash_key("test-key-123"), "key-1".to_string()),
+        ];
+
+        assert_eq!(find_verified("test-key-123", &candidates).as_deref(), Some("key-1"));
+        assert_eq!(find_verified("test-key-456", &candidates).as_deref(), Some("key-2"));
+        assert_eq!(find_verified("test-key-789", &candidates), None);
+    }
 
-        assert_eq!(hash1, hash2);
-        assert_ne!(hash1, hash3);
-        assert_eq!(hash1.len(), 64); // SHA256 hex string length
+    #[tokio::test]
+    async fn test_api_key_last_used_round_trip() {
+        let dir = std::env::temp_dir().join(format!(
+            "ai-gw-lite-config-storage-{}-{}",
+            std::process::id(),
+            current_epoch_seconds()
+        ));
+        let db_path = dir.join("config.db");
+        let storage = ConfigStorage::new(db_path.to_str().unwrap()).await.unwrap();
+        let config = AppConfig::from_yaml_str(
+            r#"
+listen: "127.0.0.1:8080"
+gateway_auth:
+  token_sources:
+    - type: "authorization_bearer"
+api_keys:
+  keys:
+    - id: "key-1"
+      key: "test-key-123"
+routes:
+  - id: "openai"
+    prefix: "/openai"
+    upstream:
+      base_url: "https://api.openai.com"
+"#,
+        )
+        .unwrap();
+        storage.sync_config(&config).await.unwrap();
+
+        // 从未使用的 Key 以同步时间为基准
+        let created = storage.load_api_key_last_used().await.unwrap()["key-1"];
+        assert!(created > 0);
+
+        storage
+            .record_api_key_last_used(&[("key-1".to_string(), created + 100)])
+            .await
+            .unwrap();
+        // 只保留较晚的时间
+        storage
+            .record_api_key_last_used(&[("key-1".to_string(), created + 50)])
+            .await
+            .unwrap();
+        assert_eq!(storage.load_api_key_last_used().await.unwrap()["key-1"], created + 100);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_setting_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!(
+            "ai-gw-lite-config-settings-{}-{}",
+            std::process::id(),
+            current_epoch_seconds()
+        ));
+        let db_path = dir.join("config.db");
+        let storage = ConfigStorage::new(db_path.to_str().unwrap()).await.unwrap();
+        assert_eq!(storage.load_or_insert_setting("fp", "first").await.unwrap(), "first");
+        drop(storage);
+
+        // 重新打开后沿用已保存的值，不被新生成的值覆盖
+        let storage = ConfigStorage::new(db_path.to_str().unwrap()).await.unwrap();
+        assert_eq!(storage.load_or_insert_setting("fp", "second").await.unwrap(), "first");
+
+        let _ = std::fs::remove_dir_all(dir);
     }
 
     #[test]
@@ -681,6 +864,10 @@ mod tests {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         };
         let route2 = RouteConfig {
             id: "test-route".to_string(),
@@ -697,6 +884,10 @@ mod tests {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         };
         let route3 = RouteConfig {
             id: "test-route".to_string(),
@@ -713,6 +904,10 @@ mod tests {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         };
 
         let hash1 = compute_route_config_hash(&route1);
@@ -727,42 +922,66 @@ mod tests {
     fn test_compute_api_key_config_hash() {
         let key1 = ResolvedApiKey {
             id: "key-1".to_string(),
-            key: "secret-key-123".to_string(),
+            key_hash: "sha256$salt1$digest1".to_string(),
+            key_prefix: "secret-k".to_string(),
+            previous_keys: Vec::new(),
             route_id: Some("route-1".to_string()),
             route_ids: None,
             enabled: true,
+            not_before: None,
+            expires_at: None,
+            idle_expiry_days: None,
             remark: "Test key".to_string(),
             rate_limit: None,
             concurrency: None,
             token_quota: None,
+            token_rate_limit: None,
+            budget: None,
             ban_rules: vec![],
             ban_status: None,
+            max_output_tokens_per_request: None,
         };
         let key2 = ResolvedApiKey {
             id: "key-1".to_string(),
-            key: "secret-key-123".to_string(),
+            key_hash: "sha256$salt1$digest1".to_string(),
+            key_prefix: "secret-k".to_string(),
+            previous_keys: Vec::new(),
             route_id: Some("route-1".to_string()),
             route_ids: None,
             enabled: true,
+            not_before: None,
+            expires_at: None,
+            idle_expiry_days: None,
             remark: "Test key".to_string(),
             rate_limit: None,
             concurrency: None,
             token_quota: None,
+            token_rate_limit: None,
+            budget: None,
             ban_rules: vec![],
             ban_status: None,
+            max_output_tokens_per_request: None,
         };
         let key3 = ResolvedApiKey {
             id: "key-1".to_string(),
-            key: "different-key".to_string(),
+            key_hash: "sha256$salt2$digest2".to_string(),
+            key_prefix: "differ".to_string(),
+            previous_keys: Vec::new(),
             route_id: Some("route-1".to_string()),
             route_ids: None,
             enabled: true,
+            not_before: None,
+            expires_at: None,
+            idle_expiry_days: None,
             remark: "Test key".to_string(),
             rate_limit: None,
             concurrency: None,
             token_quota: None,
+            token_rate_limit: None,
+            budget: None,
             ban_rules: vec![],
             ban_status: None,
+            max_output_tokens_per_request: None,
         };
 
         let hash1 = compute_api_key_config_hash(&key1);
@@ -0,0 +1,249 @@
+//! 外部授权（forward-auth）
+//!
+//! 转发前以 POST JSON 调用路由配置的授权服务：2xx 放行并复制身份头，401/403 拒绝，
+//! 其他状态码、超时与连接失败视为授权服务不可用，按 `fail_open` 放行或拒绝。
+//! 明确的放行/拒绝结果按 `cache_ttl_secs` 缓存。
+
+use crate::config::ForwardAuthConfig;
+use dashmap::DashMap;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// 缓存条目上限，超出时先清理过期条目，仍满则不再缓存新结果
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+/// 授权决定
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForwardAuthDecision {
+    /// 放行，附带需写入上游请求的身份头
+    Allow(Vec<(HeaderName, HeaderValue)>),
+    /// 拒绝，返回授权服务给出的状态码（401/403）
+    Deny(StatusCode),
+    /// 授权服务不可用且配置为 fail-closed
+    Unavailable,
+}
+
+/// 发给授权服务的请求内容
+#[derive(Debug, Serialize)]
+pub struct ForwardAuthRequest<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<&'a str>,
+    pub route_id: &'a str,
+    pub key_id: &'a str,
+    pub headers: BTreeMap<String, String>,
+}
+
+struct CachedDecision {
+    decision: ForwardAuthDecision,
+    expires_at: Instant,
+}
+
+/// 单个路由的授权客户端与结果缓存
+pub struct ForwardAuth {
+    config: ForwardAuthConfig,
+    client: reqwest::Client,
+    forward_headers: Vec<HeaderName>,
+    identity_headers: Vec<HeaderName>,
+    cache: DashMap<String, CachedDecision>,
+}
+
+impl ForwardAuth {
+    pub fn from_config(config: &ForwardAuthConfig) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .map_err(|err| format!("failed to build forward_auth client: {err}"))?;
+        Ok(Self {
+            config: config.clone(),
+            client,
+            forward_headers: parse_header_names(&config.forward_headers)?,
+            identity_headers: parse_header_names(&config.identity_headers)?,
+            cache: DashMap::new(),
+        })
+    }
+
+    /// 移除客户端自带的身份头，避免伪造授权服务下发的身份
+    pub fn strip_identity_headers(&self, headers: &mut HeaderMap) {
+        for name in &self.identity_headers {
+            headers.remove(name);
+        }
+    }
+
+    /// 按配置挑选转发给授权服务的请求头
+    pub fn selected_headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        self.forward_headers
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(name)?.to_str().ok()?;
+                Some((name.as_str().to_string(), value.to_string()))
+            })
+            .collect()
+    }
+
+    pub async fn authorize(&self, request: &ForwardAuthRequest<'_>) -> ForwardAuthDecision {
+        let cache_key = (self.config.cache_ttl_secs > 0).then(|| cache_key(request));
+        if let Some(cache_key) = &cache_key
+            && let Some(cached) = self.cache.get(cache_key)
+            && cached.expires_at > Instant::now()
+        {
+            return cached.decision.clone();
+        }
+
+        let decision = match self.call(request).await {
+            Ok(decision) => decision,
+            Err(err) => {
+                warn!(
+                    route_id = %request.route_id,
+                    error = %err,
+                    fail_open = self.config.fail_open,
+                    "forward_auth: authorization service unavailable"
+                );
+                return if self.config.fail_open {
+                    ForwardAuthDecision::Allow(Vec::new())
+                } else {
+                    ForwardAuthDecision::Unavailable
+                };
+            }
+        };
+
+        if let Some(cache_key) = cache_key {
+            self.store(cache_key, decision.clone());
+        }
+        decision
+    }
+
+    async fn call(&self, request: &ForwardAuthRequest<'_>) -> Result<ForwardAuthDecision, String> {
+        let body = serde_json::to_vec(request).map_err(|err| err.to_string())?;
+        let response = self
+            .client
+            .post(&self.config.url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let status = response.status();
+        if status.is_success() {
+            let headers = self
+                .identity_headers
+                .iter()
+                .filter_map(|name| Some((name.clone(), response.headers().get(name)?.clone())))
+                .collect();
+            Ok(ForwardAuthDecision::Allow(headers))
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Ok(ForwardAuthDecision::Deny(status))
+        } else {
+            Err(format!("unexpected status {status}"))
+        }
+    }
+
+    fn store(&self, cache_key: String, decision: ForwardAuthDecision) {
+        if self.cache.len() >= MAX_CACHE_ENTRIES {
+            let now = Instant::now();
+            self.cache.retain(|_, cached| cached.expires_at > now);
+            if self.cache.len() >= MAX_CACHE_ENTRIES {
+                return;
+            }
+        }
+        self.cache.insert(
+            cache_key,
+            CachedDecision {
+                decision,
+                expires_at: Instant::now() + Duration::from_secs(self.config.cache_ttl_secs),
+            },
+        );
+    }
+}
+
+fn parse_header_names(names: &[String]) -> Result<Vec<HeaderName>, String> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("invalid forward_auth header name `{name}`"))
+        })
+        .collect()
+}
+
+/// 缓存键覆盖发给授权服务的全部内容
+fn cache_key(request: &ForwardAuthRequest<'_>) -> String {
+    let mut hasher = Sha256::new();
+    for part in [request.method, request.path, request.query.unwrap_or(""), request.route_id, request.key_id] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    for (name, value) in &request.headers {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request<'a>(path: &'a str, headers: &[(&str, &str)]) -> ForwardAuthRequest<'a> {
+        ForwardAuthRequest {
+            method: "POST",
+            path,
+            query: None,
+            route_id: "openai",
+            key_id: "default",
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn cache_key_covers_path_and_selected_headers() {
+        let base = cache_key(&request("/openai/v1/chat", &[("x-tenant", "a")]));
+        assert_eq!(base, cache_key(&request("/openai/v1/chat", &[("x-tenant", "a")])));
+        assert_ne!(base, cache_key(&request("/openai/v1/models", &[("x-tenant", "a")])));
+        assert_ne!(base, cache_key(&request("/openai/v1/chat", &[("x-tenant", "b")])));
+    }
+
+    #[tokio::test]
+    async fn unreachable_service_follows_fail_mode() {
+        let mut config = ForwardAuthConfig {
+            url: "http://127.0.0.1:1/authz".to_string(),
+            timeout_ms: 200,
+            forward_headers: vec!["X-Tenant".to_string()],
+            identity_headers: vec!["x-user".to_string()],
+            cache_ttl_secs: 60,
+            fail_open: false,
+        };
+        let forward_auth = ForwardAuth::from_config(&config).unwrap();
+        assert_eq!(
+            forward_auth.authorize(&request("/openai/v1/chat", &[])).await,
+            ForwardAuthDecision::Unavailable
+        );
+        // 不可用结果不缓存
+        assert!(forward_auth.cache.is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant", HeaderValue::from_static("acme"));
+        headers.insert("x-user", HeaderValue::from_static("spoofed"));
+        assert_eq!(
+            forward_auth.selected_headers(&headers),
+            BTreeMap::from([("x-tenant".to_string(), "acme".to_string())])
+        );
+        forward_auth.strip_identity_headers(&mut headers);
+        assert!(!headers.contains_key("x-user"));
+
+        config.fail_open = true;
+        let forward_auth = ForwardAuth::from_config(&config).unwrap();
+        assert_eq!(
+            forward_auth.authorize(&request("/openai/v1/chat", &[])).await,
+            ForwardAuthDecision::Allow(Vec::new())
+        );
+    }
+}
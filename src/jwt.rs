@@ -0,0 +1,766 @@
+//! JWT 校验
+//!
+//! 支持 HS256/RS256/ES256，验签密钥来自配置中的静态密钥或本地 JWKS 文件（后台任务按间隔检查修改时间后重新加载）。
+//! 校验 `exp`/`nbf`/`iss`/`aud` 后，按配置的 claim 映射为已有 API Key 或以模板 Key 为基础的动态身份。
+
+use crate::api_keys::current_epoch_seconds;
+use crate::config::{JwtAlgorithm, JwtConfig, JwtKeyConfig, TokenQuotaConfig};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::{hmac, signature};
+use serde::Deserialize;
+use arc_swap::ArcSwap;
+use serde_json::Value;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum JwtError {
+    #[error("malformed token: {0}")]
+    Malformed(&'static str),
+    #[error("unsupported algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+    #[error("no verification key matches the token")]
+    UnknownKey,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("token expired")]
+    Expired,
+    #[error("token not yet valid")]
+    NotYetValid,
+    #[error("issuer mismatch")]
+    InvalidIssuer,
+    #[error("audience mismatch")]
+    InvalidAudience,
+    #[error("missing claim `{0}`")]
+    MissingClaim(String),
+    #[error("invalid claim `{0}`")]
+    InvalidClaim(String),
+}
+
+/// JWT 映射出的身份
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwtIdentity {
+    /// 已有 API Key
+    ApiKey(String),
+    /// 以模板 Key 为基础的动态身份
+    Dynamic(Box<DynamicIdentity>),
+}
+
+/// 动态身份，覆盖项为 None 时沿用模板 Key 的配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicIdentity {
+    pub template_key: String,
+    pub subject: String,
+    pub route_ids: Option<Vec<String>>,
+    pub rate_limit_per_minute: Option<u64>,
+    pub token_quota: Option<TokenQuotaConfig>,
+}
+
+impl DynamicIdentity {
+    /// 动态身份的 Key ID：`<模板 Key ID>:<身份>`
+    pub fn key_id(&self) -> String {
+        format!("{}:{}", self.template_key, self.subject)
+    }
+}
+
+/// 按单个 `jwt` token source 配置校验 JWT
+pub struct JwtVerifier {
+    config: JwtConfig,
+    keys: Vec<VerifyKey>,
+    jwks: Option<JwksFile>,
+}
+
+impl JwtVerifier {
+    pub fn from_config(config: &JwtConfig) -> Result<Self, String> {
+        let keys = config
+            .keys
+            .iter()
+            .map(VerifyKey::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        let jwks = config
+            .jwks_path
+            .as_ref()
+            .map(|path| JwksFile::open(path, Duration::from_secs(config.jwks_reload_secs)))
+            .transpose()?;
+        Ok(Self {
+            config: config.clone(),
+            keys,
+            jwks,
+        })
+    }
+
+    /// 读取 JWT 的请求头（None 表示 `Authorization: Bearer`）
+    pub fn header(&self) -> Option<&str> {
+        self.config.header.as_deref()
+    }
+
+    pub fn verify(&self, token: &str) -> Result<JwtIdentity, JwtError> {
+        self.verify_at(token, current_epoch_seconds())
+    }
+
+    fn verify_at(&self, token: &str, now: u64) -> Result<JwtIdentity, JwtError> {
+        let mut segments = token.split('.');
+        let (Some(header_segment), Some(payload_segment), Some(signature_segment), None) =
+            (segments.next(), segments.next(), segments.next(), segments.next())
+        else {
+            return Err(JwtError::Malformed("expected three segments"));
+        };
+
+        let header: JwtHeader = decode_segment(header_segment)?;
+        let alg = match header.alg.as_str() {
+            "HS256" => JwtAlgorithm::HS256,
+            "RS256" => JwtAlgorithm::RS256,
+            "ES256" => JwtAlgorithm::ES256,
+            other => return Err(JwtError::UnsupportedAlgorithm(other.to_string())),
+        };
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature_segment)
+            .map_err(|_| JwtError::Malformed("signature is not base64url"))?;
+        let signing_input = &token[..header_segment.len() + 1 + payload_segment.len()];
+        self.verify_signature(alg, header.kid.as_deref(), signing_input.as_bytes(), &signature)?;
+
+        let claims: Value = decode_segment(payload_segment)?;
+        self.check_claims(&claims, now)?;
+        self.map_identity(&claims)
+    }
+
+    /// 只尝试算法一致、`kid` 匹配的密钥，避免算法混淆
+    fn verify_signature(
+        &self,
+        alg: JwtAlgorithm,
+        kid: Option<&str>,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), JwtError> {
+        let jwks_keys = self.jwks.as_ref().map(JwksFile::keys);
+        let mut candidates = self
+            .keys
+            .iter()
+            .chain(jwks_keys.iter().flat_map(|keys| keys.iter()))
+            .filter(|key| key.alg == alg && (kid.is_none() || key.kid.is_none() || key.kid.as_deref() == kid))
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(JwtError::UnknownKey);
+        }
+        if candidates.any(|key| key.verify(message, signature)) {
+            Ok(())
+        } else {
+            Err(JwtError::InvalidSignature)
+        }
+    }
+
+    fn check_claims(&self, claims: &Value, now: u64) -> Result<(), JwtError> {
+        let leeway = self.config.leeway_secs;
+        let exp = numeric_claim(claims, "exp")?.ok_or_else(|| JwtError::MissingClaim("exp".to_string()))?;
+        if now >= exp.saturating_add(leeway) {
+            return Err(JwtError::Expired);
+        }
+        if let Some(nbf) = numeric_claim(claims, "nbf")?
+            && now.saturating_add(leeway) < nbf
+        {
+            return Err(JwtError::NotYetValid);
+        }
+        if let Some(issuer) = &self.config.issuer
+            && claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str())
+        {
+            return Err(JwtError::InvalidIssuer);
+        }
+        if !self.config.audiences.is_empty() {
+            let accepted = |aud: &str| self.config.audiences.iter().any(|expected| expected == aud);
+            let matched = match claims.get("aud") {
+                Some(Value::String(aud)) => accepted(aud),
+                Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).any(accepted),
+                _ => false,
+            };
+            if !matched {
+                return Err(JwtError::InvalidAudience);
+            }
+        }
+        Ok(())
+    }
+
+    /// 优先映射到 `key_id_claim` 指定的已有 Key，否则按模板 Key 生成动态身份
+    fn map_identity(&self, claims: &Value) -> Result<JwtIdentity, JwtError> {
+        if let Some(claim) = &self.config.key_id_claim
+            && let Some(value) = claim_value(claims, claim)
+        {
+            return match value.as_str() {
+                Some(key_id) if !key_id.is_empty() => Ok(JwtIdentity::ApiKey(key_id.to_string())),
+                _ => Err(JwtError::InvalidClaim(claim.clone())),
+            };
+        }
+
+        let Some(template_key) = &self.config.template_key else {
+            return Err(JwtError::MissingClaim(self.config.key_id_claim.clone().unwrap_or_default()));
+        };
+
+        let identity_claim = &self.config.identity_claim;
+        let subject = match claim_value(claims, identity_claim) {
+            Some(Value::String(subject)) if !subject.is_empty() => subject.clone(),
+            Some(Value::Number(subject)) => subject.to_string(),
+            Some(_) => return Err(JwtError::InvalidClaim(identity_claim.clone())),
+            None => return Err(JwtError::MissingClaim(identity_claim.clone())),
+        };
+
+        let route_ids = match self.config.route_ids_claim.as_deref() {
+            Some(claim) => match claim_value(claims, claim) {
+                None => None,
+                Some(Value::String(route_id)) => Some(vec![route_id.clone()]),
+                Some(Value::Array(items)) => Some(
+                    items
+                        .iter()
+                        .map(|item| item.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| JwtError::InvalidClaim(claim.to_string()))?,
+                ),
+                Some(_) => return Err(JwtError::InvalidClaim(claim.to_string())),
+            },
+            None => None,
+        };
+
+        let rate_limit_per_minute = match self.config.rate_limit_claim.as_deref() {
+            Some(claim) => match claim_value(claims, claim) {
+                None => None,
+                Some(value) => Some(
+                    value
+                        .as_u64()
+                        .filter(|per_minute| *per_minute > 0)
+                        .ok_or_else(|| JwtError::InvalidClaim(claim.to_string()))?,
+                ),
+            },
+            None => None,
+        };
+
+        let token_quota = match self.config.token_quota_claim.as_deref() {
+            Some(claim) => match claim_value(claims, claim) {
+                None => None,
+                Some(value) => {
+                    let quota = serde_json::from_value::<TokenQuotaConfig>(value.clone())
+                        .map_err(|_| JwtError::InvalidClaim(claim.to_string()))?;
+                    quota
+                        .validate(claim)
+                        .map_err(|_| JwtError::InvalidClaim(claim.to_string()))?;
+                    Some(quota)
+                }
+            },
+            None => None,
+        };
+
+        Ok(JwtIdentity::Dynamic(Box::new(DynamicIdentity {
+            template_key: template_key.clone(),
+            subject,
+            route_ids,
+            rate_limit_per_minute,
+            token_quota,
+        })))
+    }
+}
+
+/// 形如 JWT（三段、以 JSON 头部开头）的凭证，用于与不透明 API Key 区分
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.starts_with("eyJ") && token.matches('.').count() == 2
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(segment: &str) -> Result<T, JwtError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| JwtError::Malformed("segment is not base64url"))?;
+    serde_json::from_slice(&bytes).map_err(|_| JwtError::Malformed("segment is not a JSON object"))
+}
+
+/// 按 `.` 分隔的路径读取嵌套 claim
+fn claim_value<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(claims, |value, part| value.get(part))
+}
+
+fn numeric_claim(claims: &Value, name: &str) -> Result<Option<u64>, JwtError> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .or_else(|| value.as_f64().filter(|v| *v >= 0.0).map(|v| v as u64))
+            .map(Some)
+            .ok_or_else(|| JwtError::InvalidClaim(name.to_string())),
+    }
+}
+
+// ==================== 验签密钥 ====================
+
+struct VerifyKey {
+    kid: Option<String>,
+    alg: JwtAlgorithm,
+    material: KeyMaterial,
+}
+
+enum KeyMaterial {
+    Hmac(hmac::Key),
+    /// PKCS#1 RSAPublicKey（DER）
+    RsaDer(Vec<u8>),
+    /// JWKS 中的模数与指数
+    RsaComponents { n: Vec<u8>, e: Vec<u8> },
+    /// P-256 非压缩点（0x04 || x || y）
+    EcPoint(Vec<u8>),
+}
+
+impl VerifyKey {
+    fn from_config(key: &JwtKeyConfig) -> Result<Self, String> {
+        let kid = key.kid.as_deref().unwrap_or("<none>");
+        let material = match key.alg {
+            JwtAlgorithm::HS256 => {
+                let secret = key
+                    .secret
+                    .as_deref()
+                    .ok_or_else(|| format!("jwt key `{kid}`: HS256 requires `secret`"))?;
+                KeyMaterial::Hmac(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+            }
+            JwtAlgorithm::RS256 | JwtAlgorithm::ES256 => {
+                let pem = key
+                    .public_key_pem
+                    .as_deref()
+                    .ok_or_else(|| format!("jwt key `{kid}`: {:?} requires `public_key_pem`", key.alg))?;
+                let der = public_key_der(pem).map_err(|err| format!("jwt key `{kid}`: {err}"))?;
+                if key.alg == JwtAlgorithm::RS256 {
+                    KeyMaterial::RsaDer(der)
+                } else {
+                    KeyMaterial::EcPoint(der)
+                }
+            }
+        };
+        Ok(Self {
+            kid: key.kid.clone(),
+            alg: key.alg,
+            material,
+        })
+    }
+
+    fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match &self.material {
+            KeyMaterial::Hmac(key) => hmac::verify(key, message, sig).is_ok(),
+            KeyMaterial::RsaDer(der) => {
+                signature::UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, der)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+            KeyMaterial::RsaComponents { n, e } => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+                .is_ok(),
+            KeyMaterial::EcPoint(point) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+/// 从 PEM 公钥取出 ring 所需的原始公钥：`PUBLIC KEY`（SPKI）取其中的公钥位串，`RSA PUBLIC KEY` 原样使用
+fn public_key_der(pem_text: &str) -> Result<Vec<u8>, String> {
+    let parsed = pem::parse(pem_text).map_err(|err| format!("invalid PEM: {err}"))?;
+    match parsed.tag() {
+        "PUBLIC KEY" => spki_public_key(parsed.contents())
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| "invalid SubjectPublicKeyInfo".to_string()),
+        "RSA PUBLIC KEY" => Ok(parsed.contents().to_vec()),
+        other => Err(format!("unsupported PEM type `{other}`")),
+    }
+}
+
+/// SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }
+fn spki_public_key(der: &[u8]) -> Option<&[u8]> {
+    let (spki, _) = der_element(der, 0x30)?;
+    let (_, rest) = der_element(spki, 0x30)?;
+    let (bits, _) = der_element(rest, 0x03)?;
+    match bits.split_first()? {
+        (0, key) => Some(key),
+        _ => None,
+    }
+}
+
+/// 读取一个 DER 元素，返回（内容，剩余字节）
+fn der_element(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual_tag, rest) = input.split_first()?;
+    if actual_tag != tag {
+        return None;
+    }
+    let (&len_byte, rest) = rest.split_first()?;
+    let (len, rest) = if len_byte < 0x80 {
+        (len_byte as usize, rest)
+    } else {
+        let count = (len_byte & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+// ==================== JWKS 文件 ====================
+
+/// JWKS 文件中的密钥，请求路径只读取当前快照，重新加载由后台任务完成
+struct JwksFile {
+    keys: Arc<ArcSwap<Vec<VerifyKey>>>,
+}
+
+/// 后台重新加载任务的状态
+struct JwksReloader {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(default, rename = "use")]
+    key_use: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+    #[serde(default)]
+    k: Option<String>,
+}
+
+impl JwksFile {
+    /// 启动时加载失败直接报错；之后的重新加载失败只告警并沿用旧密钥
+    ///
+    /// 在 tokio 运行时内创建时启动后台重新加载任务，校验器被释放（如配置热更新）后任务随之退出。
+    fn open(path: &str, reload_interval: Duration) -> Result<Self, String> {
+        let modified = file_modified(path);
+        let keys = Arc::new(ArcSwap::from_pointee(load_jwks(path)?));
+        let reloader = JwksReloader {
+            path: path.to_string(),
+            modified,
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(reload_jwks_periodically(Arc::downgrade(&keys), reloader, reload_interval));
+            }
+            Err(_) => warn!(path = %path, "jwt: no async runtime, JWKS will not be reloaded"),
+        }
+        Ok(Self { keys })
+    }
+
+    fn keys(&self) -> Arc<Vec<VerifyKey>> {
+        self.keys.load_full()
+    }
+}
+
+impl JwksReloader {
+    /// 文件修改时间变化时重新加载
+    fn reload_if_modified(&mut self, keys: &ArcSwap<Vec<VerifyKey>>) {
+        let modified = file_modified(&self.path);
+        if modified.is_some() && modified == self.modified {
+            return;
+        }
+        match load_jwks(&self.path) {
+            Ok(loaded) => {
+                info!(path = %self.path, keys = loaded.len(), "jwt: JWKS reloaded");
+                keys.store(Arc::new(loaded));
+                self.modified = modified;
+            }
+            Err(err) => warn!(path = %self.path, error = %err, "jwt: failed to reload JWKS, keeping previous keys"),
+        }
+    }
+}
+
+async fn reload_jwks_periodically(keys: Weak<ArcSwap<Vec<VerifyKey>>>, mut reloader: JwksReloader, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(keys) = keys.upgrade() else {
+            return;
+        };
+        // 文件读取放到阻塞线程池，不占用异步工作线程
+        let result = tokio::task::spawn_blocking(move || {
+            reloader.reload_if_modified(&keys);
+            reloader
+        })
+        .await;
+        match result {
+            Ok(returned) => reloader = returned,
+            Err(err) => {
+                warn!(error = %err, "jwt: JWKS reload task failed");
+                return;
+            }
+        }
+    }
+}
+
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// 加载 JWKS，跳过加密用途与不支持的密钥类型
+fn load_jwks(path: &str) -> Result<Vec<VerifyKey>, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("failed to read JWKS `{path}`: {err}"))?;
+    let jwks: Jwks = serde_json::from_str(&content).map_err(|err| format!("invalid JWKS `{path}`: {err}"))?;
+    let decode = |value: &Option<String>, field: &str, kid: &str| {
+        value
+            .as_deref()
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+            .ok_or_else(|| format!("JWKS `{path}` key `{kid}`: missing or invalid `{field}`"))
+    };
+
+    let mut keys = Vec::new();
+    for jwk in &jwks.keys {
+        if jwk.key_use.as_deref() == Some("enc") {
+            continue;
+        }
+        let kid = jwk.kid.as_deref().unwrap_or("<none>");
+        let (alg, material) = match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => (
+                JwtAlgorithm::RS256,
+                KeyMaterial::RsaComponents {
+                    n: decode(&jwk.n, "n", kid)?,
+                    e: decode(&jwk.e, "e", kid)?,
+                },
+            ),
+            ("EC", Some("P-256")) => {
+                let mut point = vec![0x04];
+                point.extend(decode(&jwk.x, "x", kid)?);
+                point.extend(decode(&jwk.y, "y", kid)?);
+                (JwtAlgorithm::ES256, KeyMaterial::EcPoint(point))
+            }
+            ("oct", _) => {
+                let secret = decode(&jwk.k, "k", kid)?;
+                (JwtAlgorithm::HS256, KeyMaterial::Hmac(hmac::Key::new(hmac::HMAC_SHA256, &secret)))
+            }
+            _ => continue,
+        };
+        if jwk.alg.as_deref().is_some_and(|declared| declared != format!("{alg:?}")) {
+            continue;
+        }
+        keys.push(VerifyKey {
+            kid: jwk.kid.clone(),
+            alg,
+            material,
+        });
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+
+    const NOW: u64 = 1_800_000_000;
+
+    fn hs256_config() -> JwtConfig {
+        serde_yaml::from_str(
+            r#"
+keys:
+  - kid: "k1"
+    alg: HS256
+    secret: "jwt_secret"
+issuer: "https://sso.example.com"
+audiences: ["ai-gw"]
+key_id_claim: "gw_key"
+template_key: "sso"
+route_ids_claim: "gw.routes"
+rate_limit_claim: "gw.rpm"
+"#,
+        )
+        .expect("jwt config should parse")
+    }
+
+    fn encode(value: &Value) -> String {
+        URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn sign_hs256(header: &Value, claims: &Value, secret: &str) -> String {
+        let input = format!("{}.{}", encode(header), encode(claims));
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let tag = hmac::sign(&key, input.as_bytes());
+        format!("{input}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    fn hs256(claims: Value) -> String {
+        sign_hs256(&serde_json::json!({"alg":"HS256","typ":"JWT","kid":"k1"}), &claims, "jwt_secret")
+    }
+
+    fn base_claims() -> Value {
+        serde_json::json!({
+            "sub": "alice",
+            "iss": "https://sso.example.com",
+            "aud": ["ai-gw", "other"],
+            "exp": NOW + 300,
+        })
+    }
+
+    #[test]
+    fn hs256_token_maps_to_existing_key() {
+        let verifier = JwtVerifier::from_config(&hs256_config()).unwrap();
+        let mut claims = base_claims();
+        claims["gw_key"] = Value::from("default");
+        let token = hs256(claims);
+
+        assert!(looks_like_jwt(&token));
+        assert_eq!(verifier.verify_at(&token, NOW), Ok(JwtIdentity::ApiKey("default".to_string())));
+    }
+
+    #[test]
+    fn dynamic_identity_takes_overrides_from_claims() {
+        let verifier = JwtVerifier::from_config(&hs256_config()).unwrap();
+        let mut claims = base_claims();
+        claims["gw"] = serde_json::json!({"routes": ["openai"], "rpm": 30});
+
+        let Ok(JwtIdentity::Dynamic(identity)) = verifier.verify_at(&hs256(claims), NOW) else {
+            panic!("expected a dynamic identity");
+        };
+        assert_eq!(identity.key_id(), "sso:alice");
+        assert_eq!(identity.route_ids, Some(vec!["openai".to_string()]));
+        assert_eq!(identity.rate_limit_per_minute, Some(30));
+        assert_eq!(identity.token_quota, None);
+    }
+
+    #[test]
+    fn rejects_bad_signature_and_claims() {
+        let verifier = JwtVerifier::from_config(&hs256_config()).unwrap();
+
+        let forged = sign_hs256(&serde_json::json!({"alg":"HS256","kid":"k1"}), &base_claims(), "wrong");
+        assert_eq!(verifier.verify_at(&forged, NOW), Err(JwtError::InvalidSignature));
+
+        let unsigned = format!("{}.{}.", encode(&serde_json::json!({"alg":"none"})), encode(&base_claims()));
+        assert_eq!(
+            verifier.verify_at(&unsigned, NOW),
+            Err(JwtError::UnsupportedAlgorithm("none".to_string()))
+        );
+
+        let unknown_kid = sign_hs256(&serde_json::json!({"alg":"HS256","kid":"k2"}), &base_claims(), "jwt_secret");
+        assert_eq!(verifier.verify_at(&unknown_kid, NOW), Err(JwtError::UnknownKey));
+
+        let token = hs256(base_claims());
+        assert_eq!(verifier.verify_at(&token, NOW + 300 + 60), Err(JwtError::Expired));
+        // 默认 60 秒时钟偏差
+        assert!(verifier.verify_at(&token, NOW + 330).is_ok());
+
+        let mut claims = base_claims();
+        claims["nbf"] = Value::from(NOW + 120);
+        assert_eq!(verifier.verify_at(&hs256(claims), NOW), Err(JwtError::NotYetValid));
+
+        let mut claims = base_claims();
+        claims["aud"] = Value::from("other");
+        assert_eq!(verifier.verify_at(&hs256(claims), NOW), Err(JwtError::InvalidAudience));
+
+        let mut claims = base_claims();
+        claims["iss"] = Value::from("https://evil.example.com");
+        assert_eq!(verifier.verify_at(&hs256(claims), NOW), Err(JwtError::InvalidIssuer));
+
+        let mut claims = base_claims();
+        claims.as_object_mut().unwrap().remove("exp");
+        assert_eq!(verifier.verify_at(&hs256(claims), NOW), Err(JwtError::MissingClaim("exp".to_string())));
+    }
+
+    fn es256_key_pair() -> EcdsaKeyPair {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap()
+    }
+
+    fn sign_es256(key_pair: &EcdsaKeyPair, kid: &str, claims: &Value) -> String {
+        let input = format!("{}.{}", encode(&serde_json::json!({"alg":"ES256","kid":kid})), encode(claims));
+        let sig = key_pair.sign(&SystemRandom::new(), input.as_bytes()).unwrap();
+        format!("{input}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    #[test]
+    fn es256_verifies_with_pem_public_key() {
+        let key_pair = es256_key_pair();
+        // P-256 SubjectPublicKeyInfo 固定前缀
+        let mut spki = vec![
+            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+            0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+        ];
+        spki.extend_from_slice(key_pair.public_key().as_ref());
+        let config = JwtConfig {
+            keys: vec![JwtKeyConfig {
+                kid: None,
+                alg: JwtAlgorithm::ES256,
+                secret: None,
+                public_key_pem: Some(pem::encode(&pem::Pem::new("PUBLIC KEY", spki))),
+            }],
+            ..hs256_config()
+        };
+        let verifier = JwtVerifier::from_config(&config).unwrap();
+
+        let identity = verifier.verify_at(&sign_es256(&key_pair, "any", &base_claims()), NOW).unwrap();
+        assert!(matches!(identity, JwtIdentity::Dynamic(identity) if identity.key_id() == "sso:alice"));
+
+        let other = es256_key_pair();
+        assert_eq!(
+            verifier.verify_at(&sign_es256(&other, "any", &base_claims()), NOW),
+            Err(JwtError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn jwks_file_is_reloaded_after_change() {
+        let path = std::env::temp_dir().join(format!("ai-gw-jwks-{}.json", uuid::Uuid::new_v4()));
+        let write_jwks = |key_pair: &EcdsaKeyPair, kid: &str| {
+            let point = key_pair.public_key().as_ref();
+            let jwks = serde_json::json!({"keys": [{
+                "kty": "EC", "crv": "P-256", "kid": kid, "alg": "ES256", "use": "sig",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            }]});
+            std::fs::write(&path, jwks.to_string()).unwrap();
+        };
+
+        let first = es256_key_pair();
+        write_jwks(&first, "first");
+        let config = JwtConfig {
+            keys: Vec::new(),
+            jwks_path: Some(path.to_string_lossy().into_owned()),
+            jwks_reload_secs: 300,
+            ..hs256_config()
+        };
+        let verifier = JwtVerifier::from_config(&config).unwrap();
+        assert!(verifier.verify_at(&sign_es256(&first, "first", &base_claims()), NOW).is_ok());
+
+        let second = es256_key_pair();
+        write_jwks(&second, "second");
+        // 部分文件系统的修改时间精度较粗，强制视为已修改
+        let mut reloader = JwksReloader {
+            path: path.to_string_lossy().into_owned(),
+            modified: None,
+        };
+        reloader.reload_if_modified(&verifier.jwks.as_ref().unwrap().keys);
+        assert!(verifier.verify_at(&sign_es256(&second, "second", &base_claims()), NOW).is_ok());
+        assert_eq!(
+            verifier.verify_at(&sign_es256(&first, "first", &base_claims()), NOW),
+            Err(JwtError::UnknownKey)
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
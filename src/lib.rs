@@ -1,16 +1,24 @@
 pub mod admin;
 pub mod api_keys;
 pub mod auth;
+pub mod auth_protection;
+pub mod budget;
+pub mod client_ip;
 pub mod concurrency;
 pub mod config;
 pub mod config_storage;
+pub mod forward_auth;
 pub mod install;
+pub mod jwt;
 pub mod observability;
+pub mod pricing;
 pub mod proxy;
 pub mod ratelimit;
 pub mod server;
+pub mod state_backend;
 pub mod tls;
 pub mod token_extractor;
 pub mod token_quota;
 pub mod token_stats;
 pub mod token_stats_storage;
+pub mod tokenizer;
@@ -21,6 +21,7 @@ use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
 use prometheus_client::registry::Registry;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Pool, Sqlite};
 use std::collections::{HashMap, VecDeque};
@@ -201,6 +202,9 @@ pub struct GatewayMetrics {
     upstream_duration_seconds: Family<UpstreamDurationLabels, Histogram>,
     inflight_requests: Family<RouteLabels, Gauge>,
     sse_streams_inflight: Family<RouteLabels, Gauge>,
+    auth_failures_total: Family<AuthScopeLabels, Counter>,
+    auth_lockouts_total: Family<AuthScopeLabels, Counter>,
+    auth_locked_rejections_total: Family<AuthScopeLabels, Counter>,
     // Use DashMap for fine-grained concurrent access instead of Mutex<SummaryState>
     route_stats: DashMap<String, RouteStats>,
     route_token_stats: DashMap<String, RouteTokenStats>,
@@ -223,6 +227,9 @@ impl GatewayMetrics {
             });
         let inflight_requests = Family::<RouteLabels, Gauge>::default();
         let sse_streams_inflight = Family::<RouteLabels, Gauge>::default();
+        let auth_failures_total = Family::<AuthScopeLabels, Counter>::default();
+        let auth_lockouts_total = Family::<AuthScopeLabels, Counter>::default();
+        let auth_locked_rejections_total = Family::<AuthScopeLabels, Counter>::default();
 
         let mut registry = Registry::default();
         registry.register(
@@ -250,6 +257,21 @@ impl GatewayMetrics {
             "Current number of in-flight SSE streams.",
             sse_streams_inflight.clone(),
         );
+        registry.register(
+            "gateway_auth_failures",
+            "Total number of failed authentication attempts.",
+            auth_failures_total.clone(),
+        );
+        registry.register(
+            "gateway_auth_lockouts",
+            "Total number of client IP lockouts triggered by authentication failures.",
+            auth_lockouts_total.clone(),
+        );
+        registry.register(
+            "gateway_auth_locked_rejections",
+            "Total number of requests rejected because the client IP is locked out.",
+            auth_locked_rejections_total.clone(),
+        );
 
         Self {
             registry: RwLock::new(registry),
@@ -258,6 +280,9 @@ impl GatewayMetrics {
             upstream_duration_seconds,
             inflight_requests,
             sse_streams_inflight,
+            auth_failures_total,
+            auth_lockouts_total,
+            auth_locked_rejections_total,
             route_stats: DashMap::new(),
             route_token_stats: DashMap::new(),
             ip_stats: DashMap::new(),
@@ -268,6 +293,15 @@ impl GatewayMetrics {
 
     /// Asynchronous constructor that loads historical data from SQLite
     pub async fn new(storage: Option<Arc<MetricsStorage>>, config_storage: Option<Arc<ConfigStorage>>) -> Self {
+        // Rewrite legacy raw-key token labels before loading history
+        if let (Some(storage), Some(config_storage)) = (&storage, &config_storage) {
+            match storage.migrate_token_labels(config_storage).await {
+                Ok(0) => {}
+                Ok(rewritten) => info!("Migrated {} metrics records to key id / fingerprint token labels", rewritten),
+                Err(e) => warn!("Failed to migrate metrics token labels: {}", e),
+            }
+        }
+
         // Load historical data first (before creating the instance)
         // to avoid holding MutexGuard across await points
         let historical_data = if let Some(storage) = &storage {
@@ -328,13 +362,13 @@ impl GatewayMetrics {
                 return; // Skip recording for invalid routes
             }
 
-            // Check if API key is valid (if token_label is provided)
+            // Check if API key is valid (if token_label is provided, it is the key ID)
             if let Some(label) = token_label {
-                let validation_result = tokio::task::block_in_place(|| {
+                let is_key_valid = tokio::task::block_in_place(|| {
                     tokio::runtime::Handle::current()
-                        .block_on(config_storage.validate_api_key(label))
+                        .block_on(config_storage.is_api_key_id_valid(label))
                 });
-                if !matches!(validation_result, ConfigValidationResult::Valid { .. }) {
+                if !is_key_valid {
                     return; // Skip recording for invalid API keys
                 }
             }
@@ -449,6 +483,25 @@ impl GatewayMetrics {
         stats.current_inflight = stats.current_inflight.saturating_sub(1);
     }
 
+    /// 记录一次鉴权失败，`locked` 表示该次失败触发了 IP 锁定
+    pub fn observe_auth_failure(&self, scope: &str, locked: bool) {
+        let labels = AuthScopeLabels {
+            scope: scope.to_string(),
+        };
+        self.auth_failures_total.get_or_create(&labels).inc();
+        if locked {
+            self.auth_lockouts_total.get_or_create(&labels).inc();
+        }
+    }
+
+    pub fn inc_auth_locked_rejection(&self, scope: &str) {
+        self.auth_locked_rejections_total
+            .get_or_create(&AuthScopeLabels {
+                scope: scope.to_string(),
+            })
+            .inc();
+    }
+
     pub fn inc_sse_inflight(&self, route_id: &str) {
         self.sse_streams_inflight
             .get_or_create(&RouteLabels {
@@ -813,6 +866,11 @@ struct RouteLabels {
     route_id: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AuthScopeLabels {
+    scope: String,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 struct RequestCounterLabels {
     route_id: String,
@@ -1000,8 +1058,49 @@ fn epoch_millis_now() -> u64 {
         .unwrap_or(0)
 }
 
+/// token_label 改为 Key ID / 指纹后的 metrics 库版本
+const TOKEN_LABEL_SCHEMA_VERSION: i64 = 1;
+
+/// 未识别 Key 的指标标签前缀
+const TOKEN_FINGERPRINT_PREFIX: &str = "fp_";
+
+/// 配置库中保存指纹密钥的设置项
+const TOKEN_FINGERPRINT_KEY_SETTING: &str = "token_fingerprint_key";
+
+static TOKEN_FINGERPRINT_SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// 从配置库加载未识别令牌的指纹密钥（首次启动时生成并保存），重启后同一令牌的标签保持不变
+///
+/// 需在计算任何 [`token_label`] 之前调用；已初始化时保留当前密钥。
+pub async fn init_token_fingerprint_key(config_storage: &ConfigStorage) -> Result<(), String> {
+    let generated = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let key = config_storage
+        .load_or_insert_setting(TOKEN_FINGERPRINT_KEY_SETTING, &generated)
+        .await?;
+    if TOKEN_FINGERPRINT_SECRET.set(key.into_bytes()).is_err() {
+        tracing::debug!("Token fingerprint key already initialized");
+    }
+    Ok(())
+}
+
+/// 未能识别为 API Key 的令牌在指标中的标签：带持久化密钥的哈希指纹
+///
+/// 已识别的请求直接使用 API Key ID 作为标签，原始令牌不会进入指标与存储。
 pub fn token_label(token: &str) -> String {
-    token.trim().to_string()
+    let mut hasher = Sha256::new();
+    hasher.update(token_fingerprint_secret());
+    hasher.update(token.trim().as_bytes());
+    let digest = format!("{:x}", hasher.finalize());
+    format!("{TOKEN_FINGERPRINT_PREFIX}{}", &digest[..16])
+}
+
+/// 未调用 [`init_token_fingerprint_key`] 时（如单元测试）退化为进程级随机密钥
+fn token_fingerprint_secret() -> &'static [u8] {
+    TOKEN_FINGERPRINT_SECRET.get_or_init(|| {
+        let mut secret = uuid::Uuid::now_v7().into_bytes().to_vec();
+        secret.extend_from_slice(uuid::Uuid::now_v7().as_bytes());
+        secret
+    })
 }
 
 pub fn extract_or_generate_request_id(headers: &HeaderMap) -> String {
@@ -1366,6 +1465,61 @@ impl MetricsStorage {
         Ok(())
     }
 
+    /// 迁移旧版本以原始 API Key 作为 `token_label` 的记录
+    ///
+    /// 可识别的 Key 改写为 Key ID，无法识别的改写为指纹；
+    /// 通过 `PRAGMA user_version` 记录，仅执行一次。返回改写的记录数。
+    pub(crate) async fn migrate_token_labels(&self, config_storage: &ConfigStorage) -> Result<u64, String> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to read metrics schema version: {}", e))?;
+        if version >= TOKEN_LABEL_SCHEMA_VERSION {
+            return Ok(0);
+        }
+
+        let labels: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT token_label FROM metrics_requests WHERE token_label IS NOT NULL AND token_label != ''",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to load token labels: {}", e))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to begin migration: {}", e))?;
+        let mut rewritten = 0;
+        for label in labels {
+            let new_label = match config_storage.validate_api_key(&label).await {
+                ConfigValidationResult::Valid { key_id } | ConfigValidationResult::Deleted { key_id } => key_id,
+                _ => token_label(&label),
+            };
+            rewritten += sqlx::query("UPDATE metrics_requests SET token_label = ?1 WHERE token_label = ?2")
+                .bind(&new_label)
+                .bind(&label)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to rewrite token label: {}", e))?
+                .rows_affected();
+        }
+        // 聚合表中的 tokens 列未再写入，直接清空旧数据
+        sqlx::query("UPDATE metrics_ip_stats SET tokens = NULL WHERE tokens IS NOT NULL")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to clear IP stats tokens: {}", e))?;
+        sqlx::query(&format!("PRAGMA user_version = {TOKEN_LABEL_SCHEMA_VERSION}"))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to update metrics schema version: {}", e))?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to commit migration: {}", e))?;
+
+        Ok(rewritten)
+    }
+
     /// Queue a record for batch insertion
     pub(crate) fn queue_record(&self, record: MetricsRecord) {
         let _ = self.sender.send(record);
@@ -1721,7 +1875,7 @@ impl MetricsStorageHandle {
 
 #[cfg(test)]
 mod tests {
-    use super::{extract_or_generate_request_id, is_sensitive_header_name, tracing_rotation};
+    use super::{extract_or_generate_request_id, is_sensitive_header_name, token_label, tracing_rotation};
     use crate::config::LogRotation;
     use axum::http::{HeaderMap, HeaderValue};
 
@@ -1744,6 +1898,15 @@ mod tests {
         assert_ne!(request_id, "bad request id");
     }
 
+    #[test]
+    fn token_label_is_fingerprint_not_raw_token() {
+        let label = token_label("sk-gw-ab12cd34ef56gh78");
+        assert!(label.starts_with("fp_"));
+        assert!(!label.contains("ab12cd34"));
+        assert_eq!(label, token_label(" sk-gw-ab12cd34ef56gh78 "));
+        assert_ne!(label, token_label("sk-gw-ab12cd34ef56gh79"));
+    }
+
     #[test]
     fn sensitive_header_detection_works() {
         assert!(is_sensitive_header_name("authorization"));
@@ -0,0 +1,189 @@
+//! 模型价格表与费用核算
+//!
+//! 价格按每 100 万 token 配置，同一模型可配置多条带生效日期的价格，
+//! 按用量发生时间选取当时生效的价格。
+
+use crate::config::{ModelPriceConfig, PricingConfig};
+use crate::token_extractor::TokenUsage;
+
+/// 每 100 万 token
+const TOKENS_PER_UNIT: f64 = 1_000_000.0;
+
+/// 编译后的价格表
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    currency: String,
+    models: Vec<ModelPrices>,
+}
+
+#[derive(Debug, Clone)]
+struct ModelPrices {
+    /// 模型名（前缀匹配时不含末尾的 `*`）
+    name: String,
+    prefix: bool,
+    /// 按生效时间升序排列
+    prices: Vec<ModelPrice>,
+}
+
+/// 某一时间点生效的模型价格（每 100 万 token）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    /// 生效时间（Unix 秒）
+    pub effective_from: i64,
+    pub input: f64,
+    pub output: f64,
+    pub cached_input: f64,
+    pub reasoning: f64,
+}
+
+impl PriceTable {
+    pub fn from_config(config: &PricingConfig) -> Self {
+        let models = config
+            .models
+            .iter()
+            .map(|model| {
+                let (name, prefix) = match model.model.strip_suffix('*') {
+                    Some(name) => (name.to_string(), true),
+                    None => (model.model.clone(), false),
+                };
+                let mut prices: Vec<ModelPrice> = model.prices.iter().map(ModelPrice::from_config).collect();
+                prices.sort_by_key(|price| price.effective_from);
+                ModelPrices { name, prefix, prices }
+            })
+            .collect();
+
+        Self {
+            currency: config.currency.clone(),
+            models,
+        }
+    }
+
+    /// 货币单位
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// 查找模型在指定时间生效的价格
+    ///
+    /// 优先精确匹配，其次取最长的前缀匹配。
+    pub fn price_at(&self, model: &str, timestamp: u64) -> Option<ModelPrice> {
+        let model_prices = self
+            .models
+            .iter()
+            .find(|m| !m.prefix && m.name == model)
+            .or_else(|| {
+                self.models
+                    .iter()
+                    .filter(|m| m.prefix && model.starts_with(m.name.as_str()))
+                    .max_by_key(|m| m.name.len())
+            })?;
+
+        model_prices
+            .prices
+            .iter()
+            .rev()
+            .find(|price| price.effective_from <= timestamp as i64)
+            .copied()
+    }
+
+    /// 计算一次请求的费用，模型未配置价格时返回 `None`
+    pub fn cost(&self, model: &str, usage: &TokenUsage, timestamp: u64) -> Option<f64> {
+        self.price_at(model, timestamp).map(|price| price.cost(usage))
+    }
+}
+
+impl ModelPrice {
+    fn from_config(config: &ModelPriceConfig) -> Self {
+        Self {
+            effective_from: config
+                .effective_from
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map_or(i64::MIN, |datetime| datetime.and_utc().timestamp()),
+            input: config.input_per_million,
+            output: config.output_per_million,
+            cached_input: config.cached_input_per_million.unwrap_or(config.input_per_million),
+            reasoning: config.reasoning_per_million.unwrap_or(config.output_per_million),
+        }
+    }
+
+    /// 按用量计算费用，缓存命中与推理 token 分别按各自价格计费
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let reasoning = usage.reasoning_tokens.min(usage.output_tokens);
+        ((usage.input_tokens - cached) as f64 * self.input
+            + cached as f64 * self.cached_input
+            + (usage.output_tokens - reasoning) as f64 * self.output
+            + reasoning as f64 * self.reasoning)
+            / TOKENS_PER_UNIT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelPricingConfig;
+
+    fn price(effective_from: Option<&str>, input: f64, output: f64) -> ModelPriceConfig {
+        ModelPriceConfig {
+            effective_from: effective_from.map(|d| d.parse().expect("valid date")),
+            input_per_million: input,
+            output_per_million: output,
+            cached_input_per_million: None,
+            reasoning_per_million: None,
+        }
+    }
+
+    fn table() -> PriceTable {
+        PriceTable::from_config(&PricingConfig {
+            currency: "USD".to_string(),
+            models: vec![
+                ModelPricingConfig {
+                    model: "gpt-4o".to_string(),
+                    prices: vec![price(Some("2024-10-01"), 2.5, 10.0), price(None, 5.0, 15.0)],
+                },
+                ModelPricingConfig {
+                    model: "gpt-4*".to_string(),
+                    prices: vec![price(None, 30.0, 60.0)],
+                },
+                ModelPricingConfig {
+                    model: "gpt-4o-mini*".to_string(),
+                    prices: vec![ModelPriceConfig {
+                        cached_input_per_million: Some(0.075),
+                        ..price(None, 0.15, 0.6)
+                    }],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn select_price_by_effective_date() {
+        let table = table();
+        // 2024-09-30T00:00:00Z 与 2024-10-01T00:00:00Z
+        assert_eq!(table.price_at("gpt-4o", 1_727_654_400).map(|p| p.input), Some(5.0));
+        assert_eq!(table.price_at("gpt-4o", 1_727_740_800).map(|p| p.input), Some(2.5));
+    }
+
+    #[test]
+    fn prefer_exact_then_longest_prefix() {
+        let table = table();
+        assert_eq!(table.price_at("gpt-4-turbo", 0).map(|p| p.input), Some(30.0));
+        assert_eq!(table.price_at("gpt-4o-mini-2024-07-18", 0).map(|p| p.input), Some(0.15));
+        assert!(table.price_at("claude-3-opus", 0).is_none());
+    }
+
+    #[test]
+    fn cost_splits_cached_and_reasoning_tokens() {
+        let table = table();
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            total_tokens: 2_000_000,
+            cached_input_tokens: 500_000,
+            reasoning_tokens: 200_000,
+        };
+        let cost = table.cost("gpt-4o-mini", &usage, 0).expect("priced model");
+        // 0.5M * 0.15 + 0.5M * 0.075 + 1M * 0.6
+        assert!((cost - 0.7125).abs() < 1e-9);
+    }
+}
@@ -220,11 +220,19 @@ mod tests {
                 id: "root".to_string(),
                 prefix: "/openai".to_string(),
                 upstream: minimal_upstream(),
+                rate_limit: None,
+                token_rate_limit: None,
+                forward_auth: None,
+                auth: None,
             },
             RouteConfig {
                 id: "nested".to_string(),
                 prefix: "/openai/v1".to_string(),
                 upstream: minimal_upstream(),
+                rate_limit: None,
+                token_rate_limit: None,
+                forward_auth: None,
+                auth: None,
             },
         ];
 
@@ -238,6 +246,10 @@ mod tests {
             id: "openai".to_string(),
             prefix: "/openai".to_string(),
             upstream: minimal_upstream(),
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         };
 
         assert!(build_upstream_url_for_route(&route, "/openai/v1/models", None).is_some());
@@ -1,6 +1,7 @@
-use crate::config::RateLimitConfig;
+use crate::config::{RateLimitConfig, RouteRateLimitConfig};
 use dashmap::DashMap;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -9,6 +10,182 @@ pub enum RateLimitDecision {
     Rejected { retry_after_secs: u64 },
 }
 
+/// 限流维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDimension {
+    /// 全局默认限流（token + route）
+    Global,
+    /// API Key 级别限流（跨路由共享）
+    ApiKey,
+    /// 路由合计限流
+    Route,
+    /// API Key + 路由限流
+    ApiKeyRoute,
+    /// 客户端 IP 限流
+    ClientIp,
+}
+
+impl RateLimitDimension {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitDimension::Global => "global",
+            RateLimitDimension::ApiKey => "api_key",
+            RateLimitDimension::Route => "route",
+            RateLimitDimension::ApiKeyRoute => "api_key_route",
+            RateLimitDimension::ClientIp => "client_ip",
+        }
+    }
+}
+
+impl fmt::Display for RateLimitDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 单个维度的限流检查项
+#[derive(Debug, Clone)]
+pub struct RateLimitCheck {
+    pub dimension: RateLimitDimension,
+    limiter: RateLimiter,
+    key: String,
+}
+
+impl RateLimitCheck {
+    pub fn new(dimension: RateLimitDimension, limiter: RateLimiter, key: impl Into<String>) -> Self {
+        Self {
+            dimension,
+            limiter,
+            key: key.into(),
+        }
+    }
+
+    /// 限流器内的计数 key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// 每分钟上限
+    pub fn per_minute(&self) -> u64 {
+        self.limiter.per_minute
+    }
+}
+
+/// 组合限流的拒绝结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitRejection {
+    pub dimension: RateLimitDimension,
+    pub retry_after_secs: u64,
+}
+
+/// 组合检查多个维度：全部通过才计数，否则返回最严格的拒绝
+///
+/// 每个维度在各自的锁内检查并计数；任一维度拒绝时回退已计入的维度，
+/// 被拒绝的请求不消耗任何额度。最严格指等待时间最长者；等待时间相同时取每分钟上限最小者。
+pub fn check_combined(checks: &[RateLimitCheck]) -> Result<(), RateLimitRejection> {
+    check_combined_at_epoch_seconds(checks, current_epoch_seconds())
+}
+
+/// 回退一次已通过 [`check_combined`] 计入的请求（如集群合计检查拒绝时）
+pub fn release_combined(checks: &[RateLimitCheck]) {
+    let epoch_seconds = current_epoch_seconds();
+    for check in checks {
+        check.limiter.release_at_epoch_seconds(&check.key, epoch_seconds);
+    }
+}
+
+fn check_combined_at_epoch_seconds(
+    checks: &[RateLimitCheck],
+    epoch_seconds: u64,
+) -> Result<(), RateLimitRejection> {
+    let mut strictest: Option<(RateLimitRejection, u64)> = None;
+    let mut counted = Vec::with_capacity(checks.len());
+    for check in checks {
+        let RateLimitDecision::Rejected { retry_after_secs } =
+            check.limiter.check_at_epoch_seconds(&check.key, epoch_seconds)
+        else {
+            counted.push(check);
+            continue;
+        };
+        let per_minute = check.limiter.per_minute;
+        let is_stricter = match &strictest {
+            None => true,
+            Some((current, current_per_minute)) => {
+                retry_after_secs > current.retry_after_secs
+                    || (retry_after_secs == current.retry_after_secs
+                        && per_minute < *current_per_minute)
+            }
+        };
+        if is_stricter {
+            strictest = Some((
+                RateLimitRejection {
+                    dimension: check.dimension,
+                    retry_after_secs,
+                },
+                per_minute,
+            ));
+        }
+    }
+
+    if let Some((rejection, _)) = strictest {
+        for check in counted {
+            check.limiter.release_at_epoch_seconds(&check.key, epoch_seconds);
+        }
+        return Err(rejection);
+    }
+    Ok(())
+}
+
+/// 路由级限流器集合（路由合计、Key + 路由、客户端 IP）
+#[derive(Debug, Clone, Default)]
+pub struct RouteRateLimiters {
+    route: Option<RateLimiter>,
+    per_key: Option<RateLimiter>,
+    per_ip: Option<RateLimiter>,
+}
+
+impl RouteRateLimiters {
+    pub fn from_config(config: &RouteRateLimitConfig) -> Self {
+        Self {
+            route: config.per_minute.map(RateLimiter::new),
+            per_key: config.per_key_per_minute.map(RateLimiter::new),
+            per_ip: config.per_ip_per_minute.map(RateLimiter::new),
+        }
+    }
+
+    /// 生成该路由需要检查的限流项
+    pub fn checks(
+        &self,
+        route_id: &str,
+        api_key_id: &str,
+        client_ip: Option<&str>,
+    ) -> Vec<RateLimitCheck> {
+        let mut checks = Vec::new();
+        if let Some(limiter) = &self.route {
+            checks.push(RateLimitCheck::new(
+                RateLimitDimension::Route,
+                limiter.clone(),
+                route_id,
+            ));
+        }
+        if let Some(limiter) = &self.per_key {
+            checks.push(RateLimitCheck::new(
+                RateLimitDimension::ApiKeyRoute,
+                limiter.clone(),
+                api_key_id,
+            ));
+        }
+        if let (Some(limiter), Some(ip)) = (&self.per_ip, client_ip) {
+            checks.push(RateLimitCheck::new(
+                RateLimitDimension::ClientIp,
+                limiter.clone(),
+                ip,
+            ));
+        }
+        checks
+    }
+}
+
 /// 限流器管理器，支持多级别限流配置
 pub struct RateLimiterManager {
     /// 全局默认限流配置
@@ -125,6 +302,21 @@ impl RateLimiter {
         self.check_at_epoch_seconds(key, epoch_seconds)
     }
 
+    /// 回退一次计数；窗口已轮转时无需回退
+    fn release_at_epoch_seconds(&self, key: &str, epoch_seconds: u64) {
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if state.minute_bucket != epoch_seconds / 60 {
+            return;
+        }
+        if let Some(counter) = state.counters.get_mut(key) {
+            *counter = counter.saturating_sub(1);
+        }
+    }
+
     fn check_at_epoch_seconds(&self, key: &str, epoch_seconds: u64) -> RateLimitDecision {
         let minute_bucket = epoch_seconds / 60;
         let mut state = match self.state.lock() {
@@ -156,15 +348,18 @@ fn current_epoch_seconds() -> u64 {
         .map_or(0, |duration| duration.as_secs())
 }
 
-fn retry_after_seconds(epoch_seconds: u64) -> u64 {
+pub(crate) fn retry_after_seconds(epoch_seconds: u64) -> u64 {
     let remaining = 60 - (epoch_seconds % 60);
     if remaining == 0 { 60 } else { remaining }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{RateLimitDecision, RateLimiter, RateLimiterManager};
-    use crate::config::RateLimitConfig;
+    use super::{
+        RateLimitCheck, RateLimitDecision, RateLimitDimension, RateLimitRejection, RateLimiter,
+        RateLimiterManager, RouteRateLimiters, check_combined_at_epoch_seconds,
+    };
+    use crate::config::{RateLimitConfig, RouteRateLimitConfig};
 
     #[test]
     fn allows_until_limit_then_rejects() {
@@ -342,4 +537,113 @@ mod tests {
             RateLimitDecision::Rejected { .. }
         ));
     }
+
+    #[test]
+    fn combined_check_only_counts_when_all_dimensions_allow() {
+        let key_limiter = RateLimiter::new(5);
+        let route_limiter = RateLimiter::new(1);
+        let now = 1_700_000_040;
+        let checks = vec![
+            RateLimitCheck::new(RateLimitDimension::ApiKey, key_limiter.clone(), "key_a"),
+            RateLimitCheck::new(RateLimitDimension::Route, route_limiter, "openai"),
+        ];
+
+        assert!(check_combined_at_epoch_seconds(&checks, now).is_ok());
+        let rejection = check_combined_at_epoch_seconds(&checks, now).expect_err("route exhausted");
+        assert_eq!(rejection.dimension, RateLimitDimension::Route);
+
+        // 被拒绝的请求不应消耗其他维度的额度
+        for _ in 0..4 {
+            assert!(matches!(
+                key_limiter.check_at_epoch_seconds("key_a", now),
+                RateLimitDecision::Allowed
+            ));
+        }
+        assert!(matches!(
+            key_limiter.check_at_epoch_seconds("key_a", now),
+            RateLimitDecision::Rejected { .. }
+        ));
+    }
+
+    #[test]
+    fn combined_check_is_atomic_under_concurrency() {
+        let key_limiter = RateLimiter::new(10);
+        let route_limiter = RateLimiter::new(1_000);
+        let now = 1_700_000_040;
+        let checks = vec![
+            RateLimitCheck::new(RateLimitDimension::Route, route_limiter.clone(), "openai"),
+            RateLimitCheck::new(RateLimitDimension::ApiKey, key_limiter, "key_a"),
+        ];
+
+        let allowed: usize = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..10)
+                            .filter(|_| check_combined_at_epoch_seconds(&checks, now).is_ok())
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+        });
+        assert_eq!(allowed, 10);
+
+        // 被 Key 维度拒绝的请求已从路由维度回退
+        for _ in 0..990 {
+            assert!(matches!(
+                route_limiter.check_at_epoch_seconds("openai", now),
+                RateLimitDecision::Allowed
+            ));
+        }
+        assert!(matches!(
+            route_limiter.check_at_epoch_seconds("openai", now),
+            RateLimitDecision::Rejected { .. }
+        ));
+    }
+
+    #[test]
+    fn combined_check_reports_most_restrictive_dimension() {
+        let now = 1_700_000_040;
+        let ip_limiter = RateLimiter::new(3);
+        let key_route_limiter = RateLimiter::new(1);
+        for _ in 0..3 {
+            ip_limiter.check_at_epoch_seconds("10.0.0.1", now);
+        }
+        key_route_limiter.check_at_epoch_seconds("key_a", now);
+
+        let checks = vec![
+            RateLimitCheck::new(RateLimitDimension::ClientIp, ip_limiter, "10.0.0.1"),
+            RateLimitCheck::new(RateLimitDimension::ApiKeyRoute, key_route_limiter, "key_a"),
+        ];
+        assert_eq!(
+            check_combined_at_epoch_seconds(&checks, now),
+            Err(RateLimitRejection {
+                dimension: RateLimitDimension::ApiKeyRoute,
+                retry_after_secs: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn route_limiters_build_checks_for_configured_dimensions() {
+        let limiters = RouteRateLimiters::from_config(&RouteRateLimitConfig {
+            per_minute: Some(100),
+            per_key_per_minute: None,
+            per_ip_per_minute: Some(10),
+        });
+
+        let dimensions: Vec<_> = limiters
+            .checks("openai", "key_a", Some("10.0.0.1"))
+            .into_iter()
+            .map(|check| check.dimension)
+            .collect();
+        assert_eq!(
+            dimensions,
+            vec![RateLimitDimension::Route, RateLimitDimension::ClientIp]
+        );
+
+        // 无法识别客户端 IP 时跳过 IP 维度
+        assert_eq!(limiters.checks("openai", "key_a", None).len(), 1);
+    }
 }
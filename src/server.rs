@@ -1,16 +1,24 @@
-use crate::api_keys::{ApiKeyManager, create_api_key_manager};
+use crate::api_keys::{ApiKeyManager, create_api_key_manager, current_epoch_seconds};
 use crate::auth;
+use crate::auth_protection::{AuthFailureTracker, AuthScope, FailureOutcome};
+use crate::client_ip::IpNetwork;
 use crate::concurrency::ConcurrencyController;
 use crate::config::{
-    AppConfig, CorsConfig, ProxyProtocol, RouteConfig, UpstreamConfig, UpstreamProxyConfig,
+    AppConfig, CorsConfig, ProxyProtocol, RouteAuthMode, RouteConfig, UpstreamConfig, UpstreamProxyConfig,
 };
 use crate::config_storage::ConfigStorage;
+use crate::forward_auth::{ForwardAuth, ForwardAuthDecision, ForwardAuthRequest};
 use crate::observability;
+use crate::pricing::PriceTable;
 use crate::proxy;
-use crate::ratelimit::{RateLimitDecision, RateLimiter};
+use crate::ratelimit::{
+    RateLimitCheck, RateLimitDimension, RateLimiter, RouteRateLimiters, check_combined, release_combined,
+};
+use crate::state_backend::{SharedState, SlotAcquisition, SlotLease};
 use crate::tls;
-use crate::token_extractor::TokenExtractor;
-use crate::token_quota::TokenQuotaChecker;
+use crate::tokenizer::{self, Tokenizer};
+use crate::token_extractor::{SseOutputCounter, TokenExtractor, TokenUsage};
+use crate::token_quota::{QuotaReservation, TokenQuotaChecker, TokenRateLimitExceeded, TokenRateReservation};
 use crate::token_stats::TokenStatsCollector;
 use arc_swap::ArcSwap;
 use axum::body::{Body, Bytes};
@@ -19,7 +27,8 @@ use axum::http::{HeaderMap, Method, Request, Response, StatusCode};
 use axum::routing::{any, get};
 use axum::{Json, Router, response::IntoResponse};
 use futures_util::{Stream, StreamExt, TryStreamExt};
-use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use std::collections::HashMap;
 use std::io;
 use std::net::SocketAddr;
@@ -37,11 +46,27 @@ pub struct RuntimeState {
     pub config: Arc<AppConfig>,
     pub upstream_clients: HashMap<String, reqwest::Client>,
     pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// 路由级限流器（按 route id）
+    pub route_rate_limiters: HashMap<String, RouteRateLimiters>,
+    /// 路由级外部授权（按 route id，仅配置了 `forward_auth` 的路由）
+    pub forward_auth: HashMap<String, Arc<ForwardAuth>>,
     pub concurrency: Option<Arc<ConcurrencyController>>,
     /// API Key 管理器（支持 API Key 级别的限流和并发控制）
     pub api_key_manager: Option<Arc<ApiKeyManager>>,
     /// Token配额检查器（存储在ApiKeyManager中，这里仅用于热重载传递）
     pub _token_quota_checker: Option<Arc<TokenQuotaChecker>>,
+    /// 内置分词器（用于预估请求输入 token）
+    pub tokenizer: Option<Arc<Tokenizer>>,
+    /// 模型价格表（用于费用核算）
+    pub price_table: Option<Arc<PriceTable>>,
+    /// 共享状态后端（多实例间共享限流、并发、配额与封禁状态）
+    pub shared_state: SharedState,
+    /// 按 `gateway_auth` 提取并校验请求凭证
+    pub authenticator: Arc<auth::Authenticator>,
+    /// 路由级凭证来源（按 route id，仅配置了 `auth.token_sources` 的路由）
+    pub route_authenticators: HashMap<String, Arc<auth::Authenticator>>,
+    /// 可信反向代理，只有来自这些地址的连接才读取转发头
+    pub trusted_proxies: Vec<IpNetwork>,
 }
 
 #[derive(Clone)]
@@ -52,6 +77,132 @@ pub struct AppState {
     pub admin_token: Option<String>,
     pub admin_path_prefix: Option<String>,
     pub config_storage: Arc<ConfigStorage>,
+    /// 串行化管理接口的配置变更（整体应用与单个 Key 增删改）
+    pub config_lock: Arc<tokio::sync::Mutex<()>>,
+    /// 按客户端 IP 统计的鉴权失败（配置热重载后保留）
+    pub auth_failures: Arc<AuthFailureTracker>,
+}
+
+impl RuntimeState {
+    /// 仅替换配置，其余运行时状态（客户端、限流器、Key 管理器等）沿用当前实例
+    pub fn with_config(&self, config: Arc<AppConfig>) -> Self {
+        Self {
+            config,
+            upstream_clients: self.upstream_clients.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            route_rate_limiters: self.route_rate_limiters.clone(),
+            forward_auth: self.forward_auth.clone(),
+            concurrency: self.concurrency.clone(),
+            api_key_manager: self.api_key_manager.clone(),
+            _token_quota_checker: self._token_quota_checker.clone(),
+            tokenizer: self.tokenizer.clone(),
+            price_table: self.price_table.clone(),
+            shared_state: self.shared_state.clone(),
+            authenticator: self.authenticator.clone(),
+            route_authenticators: self.route_authenticators.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+        }
+    }
+
+    /// 路由生效的鉴权：有路由级覆盖时使用覆盖，否则使用全局配置
+    pub fn authenticator_for(&self, route_id: &str) -> &auth::Authenticator {
+        self.route_authenticators
+            .get(route_id)
+            .unwrap_or(&self.authenticator)
+    }
+
+    /// 替换配置并只重建指定路由的上游客户端与路由级限流器，其他路由的连接池与计数保持不变
+    ///
+    /// 新配置中不存在该路由时视为删除。
+    pub fn with_route(&self, config: Arc<AppConfig>, route_id: &str) -> Result<Self, String> {
+        let route = config
+            .routes
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|route| route.id == route_id);
+        let old_route = self
+            .config
+            .routes
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|route| route.id == route_id);
+
+        let mut upstream_clients = self.upstream_clients.clone();
+        let mut route_rate_limiters = self.route_rate_limiters.clone();
+        let mut forward_auth = self.forward_auth.clone();
+        let mut route_authenticators = self.route_authenticators.clone();
+        match route {
+            Some(route) => {
+                let client = build_upstream_client(&route.upstream).map_err(|err| {
+                    format!("failed to build upstream client for route `{route_id}`: {err}")
+                })?;
+                upstream_clients.insert(route.id.clone(), client);
+
+                // 限流配置未变化时保留已有计数
+                let rate_limit_unchanged = old_route.is_some_and(|old| old.rate_limit == route.rate_limit);
+                if !rate_limit_unchanged {
+                    match &route.rate_limit {
+                        Some(rate_limit) => {
+                            route_rate_limiters
+                                .insert(route.id.clone(), RouteRateLimiters::from_config(rate_limit));
+                        }
+                        None => {
+                            route_rate_limiters.remove(route_id);
+                        }
+                    }
+                }
+
+                // 授权配置未变化时保留已缓存的授权结果
+                if !old_route.is_some_and(|old| old.forward_auth == route.forward_auth) {
+                    match &route.forward_auth {
+                        Some(config) => {
+                            forward_auth.insert(route.id.clone(), Arc::new(ForwardAuth::from_config(config)?));
+                        }
+                        None => {
+                            forward_auth.remove(route_id);
+                        }
+                    }
+                }
+
+                if !old_route.is_some_and(|old| old.auth == route.auth) {
+                    match route.auth.as_ref().filter(|auth| !auth.token_sources.is_empty()) {
+                        Some(auth) => {
+                            route_authenticators.insert(
+                                route.id.clone(),
+                                Arc::new(auth::Authenticator::from_sources(&auth.token_sources)?),
+                            );
+                        }
+                        None => {
+                            route_authenticators.remove(route_id);
+                        }
+                    }
+                }
+            }
+            None => {
+                upstream_clients.remove(route_id);
+                route_rate_limiters.remove(route_id);
+                forward_auth.remove(route_id);
+                route_authenticators.remove(route_id);
+            }
+        }
+
+        // 路由新增上游并发限制而此前未启用并发控制时补建控制器
+        let concurrency = match &self.concurrency {
+            Some(concurrency) => Some(concurrency.clone()),
+            None => build_concurrency_controller(&config),
+        };
+
+        Ok(Self {
+            upstream_clients,
+            route_rate_limiters,
+            forward_auth,
+            route_authenticators,
+            concurrency,
+            ..self.with_config(config)
+        })
+    }
 }
 
 impl AppState {
@@ -67,6 +218,49 @@ impl AppState {
             .as_ref()
             .map(|m| Arc::new(TokenQuotaChecker::new(m.clone())))
     }
+
+    /// 客户端 IP 因鉴权失败被锁定时返回 429 响应（未启用 `auth_protection` 时始终放行）
+    pub(crate) fn auth_lockout_response(&self, client_ip: Option<&str>, scope: AuthScope) -> Option<Response<Body>> {
+        self.runtime.load().config.auth_protection.as_ref()?;
+        let retry_after_secs = self.auth_failures.locked_for(client_ip?, current_epoch_seconds())?;
+        if let Some(metrics) = &self.observability.metrics {
+            metrics.inc_auth_locked_rejection(scope.as_str());
+        }
+        let mut response = json_error(StatusCode::TOO_MANY_REQUESTS, "auth_locked");
+        set_header(response.headers_mut(), "retry-after", &retry_after_secs.to_string());
+        Some(response)
+    }
+
+    /// 记录一次鉴权失败，并按窗口内失败次数延迟返回
+    pub(crate) async fn record_auth_failure(&self, client_ip: Option<&str>, scope: AuthScope) {
+        let Some(config) = self.runtime.load().config.auth_protection.clone() else {
+            return;
+        };
+        let Some(client_ip) = client_ip else {
+            return;
+        };
+        let outcome = self
+            .auth_failures
+            .record_failure(&config, client_ip, scope, current_epoch_seconds());
+        if let Some(metrics) = &self.observability.metrics {
+            metrics.observe_auth_failure(scope.as_str(), matches!(outcome, FailureOutcome::Locked { .. }));
+        }
+        match outcome {
+            FailureOutcome::Delay(delay) => {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            FailureOutcome::Locked { until } => {
+                warn!(
+                    client_ip,
+                    scope = scope.as_str(),
+                    locked_until = until,
+                    "auth_protection: client locked out after repeated authentication failures"
+                );
+            }
+        }
+    }
 }
 
 pub async fn build_runtime_state(
@@ -79,33 +273,69 @@ pub async fn build_runtime_state(
         .rate_limit
         .as_ref()
         .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit.per_minute)));
-    let concurrency = ConcurrencyController::new(&config).map(Arc::new);
+    let route_rate_limiters = build_route_rate_limiters(&config);
+    let forward_auth = build_forward_auth(&config)?;
+    let concurrency = build_concurrency_controller(&config);
+    let authenticator = Arc::new(auth::Authenticator::from_config(&config.gateway_auth)?);
+    let route_authenticators = build_route_authenticators(&config)?;
+    let trusted_proxies = crate::client_ip::parse_trusted_proxies(&config.trusted_proxies)
+        .map_err(|value| format!("invalid trusted proxy `{value}`"))?;
+
+    // 分词器配置未变化时复用已加载的词表
+    let tokenizer = match (&config.tokenizer, old_runtime) {
+        (None, _) => None,
+        (Some(_), Some(old)) if old.config.tokenizer == config.tokenizer => old.tokenizer.clone(),
+        (Some(tokenizer_config), _) => Some(Arc::new(Tokenizer::from_config(tokenizer_config)?)),
+    };
 
-    // 启动信号量清理任务（仅在首次创建时）
-    if let Some(ref ctrl) = concurrency {
-        let ctrl_clone = Arc::clone(ctrl);
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(60));
-            loop {
-                interval.tick().await;
-                ctrl_clone.cleanup_unused_semaphores();
-            }
-        });
+    let price_table = config.pricing.as_ref().map(|pricing| Arc::new(PriceTable::from_config(pricing)));
+
+    // 共享状态后端配置未变化时复用已建立的连接
+    let shared_state = SharedState::reuse_or_build(
+        old_runtime.map(|old| &old.shared_state),
+        config.state_backend.as_ref(),
+    )?;
+
+    // 刷新 TPM/TPD 限制与费用预算
+    if let Some(checker) = &token_quota_checker {
+        let resolved_keys = config.resolved_api_keys();
+        checker.manager().set_token_rate_limits(
+            &resolved_keys,
+            config.routes.as_deref().unwrap_or_default(),
+        );
+        checker
+            .manager()
+            .budget()
+            .set_budgets(&resolved_keys, config.global_budget.as_ref());
+        checker.manager().set_usage_sync(shared_state.is_shared());
     }
 
     // 获取旧的 api_key_manager（如果存在）
     let old_manager = old_runtime.and_then(|r| r.api_key_manager.clone());
     // 创建 API Key 管理器（支持 API Key 级别的限流和并发控制）
-    let api_key_manager = create_api_key_manager(&config, old_manager.as_deref(), token_quota_checker)
-        .await
-        .map(Arc::new);
+    let api_key_manager = create_api_key_manager(
+        &config,
+        old_manager.as_deref(),
+        token_quota_checker,
+        shared_state.clone(),
+    )
+    .await
+    .map(Arc::new);
     Ok(RuntimeState {
         config,
         upstream_clients,
         rate_limiter,
+        route_rate_limiters,
+        forward_auth,
         concurrency,
         api_key_manager,
         _token_quota_checker: None, // quota_checker is owned by api_key_manager
+        tokenizer,
+        price_table,
+        shared_state,
+        authenticator,
+        route_authenticators,
+        trusted_proxies,
     })
 }
 
@@ -115,6 +345,7 @@ pub async fn build_app(config: Arc<AppConfig>, config_path: Option<PathBuf>) ->
 
     // Create ConfigStorage first (before GatewayMetrics)
     let config_storage = Arc::new(ConfigStorage::new(config_db_path).await?);
+    observability::init_token_fingerprint_key(&config_storage).await?;
 
     // Sync config to database
     config_storage.sync_config(&config).await?;
@@ -155,8 +386,47 @@ pub async fn build_app(config: Arc<AppConfig>, config_path: Option<PathBuf>) ->
         admin_token,
         admin_path_prefix: admin_path_prefix.clone(),
         config_storage,
+        config_lock: Arc::new(tokio::sync::Mutex::new(())),
+        auth_failures: Arc::new(AuthFailureTracker::new()),
     };
 
+    // 与共享状态后端同步 Token 配额用量与封禁状态（热重载后按最新配置生效）
+    {
+        let runtime = Arc::clone(&state.runtime);
+        let observability = Arc::clone(&state.observability);
+        tokio::spawn(async move {
+            loop {
+                let current = runtime.load_full();
+                current
+                    .shared_state
+                    .sync(
+                        observability.token_quota_manager.as_deref(),
+                        current.api_key_manager.as_deref(),
+                    )
+                    .await;
+                let interval = current.shared_state.sync_interval();
+                drop(current);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    // API Key 生命周期：持久化最近使用时间，并标记即将过期的 Key
+    {
+        let runtime = Arc::clone(&state.runtime);
+        let config_storage = Arc::clone(&state.config_storage);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(API_KEY_LIFECYCLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let current = runtime.load_full();
+                if let Some(manager) = current.api_key_manager.as_deref() {
+                    sync_api_key_lifecycle(manager, &config_storage).await;
+                }
+            }
+        });
+    }
+
     let mut router = Router::new().route("/healthz", get(healthz_handler));
     if let Some(metrics_path) = state.observability.metrics_path() {
         router = router.route(metrics_path, get(metrics_handler));
@@ -168,11 +438,27 @@ pub async fn build_app(config: Arc<AppConfig>, config_path: Option<PathBuf>) ->
         router = router.route(metrics_summary_path, get(metrics_summary_handler));
     }
     if let Some(prefix) = &admin_path_prefix {
-        router = crate::admin::register_admin_routes(router, prefix);
+        router = crate::admin::register_admin_routes(router, prefix, &state);
     }
     Ok(router.fallback(any(proxy_handler)).with_state(state))
 }
 
+/// 同步 Key 最近使用时间到配置库，刷新生命周期预警并淘汰闲置的 JWT 动态身份
+async fn sync_api_key_lifecycle(manager: &ApiKeyManager, config_storage: &ConfigStorage) {
+    if let Err(e) = config_storage
+        .record_api_key_last_used(&manager.last_used_snapshot().await)
+        .await
+    {
+        warn!("Failed to persist api key last used time: {}", e);
+    }
+    match config_storage.load_api_key_last_used().await {
+        Ok(last_used) => manager.restore_last_used(&last_used).await,
+        Err(e) => warn!("Failed to load api key last used time: {}", e),
+    }
+    manager.refresh_lifecycle_alerts().await;
+    manager.evict_idle_dynamic_keys().await;
+}
+
 pub async fn run_server(config: Arc<AppConfig>, config_path: Option<String>) -> Result<(), String> {
     let listen_addr: SocketAddr = config
         .listen
@@ -183,20 +469,26 @@ pub async fn run_server(config: Arc<AppConfig>, config_path: Option<String>) ->
     if let Some(tls_config) = &config.inbound_tls {
         install_rustls_crypto_provider();
         let (tls_paths, _) = tls::resolve_tls_paths(tls_config, listen_addr)?;
-        let rustls_config = axum_server::tls_rustls::RustlsConfig::from_pem_file(
-            &tls_paths.cert_path,
-            &tls_paths.key_path,
-        )
-        .await
-        .map_err(|err| {
-            format!(
-                "failed to load inbound tls cert/key (`{}` / `{}`): {err}",
-                tls_paths.cert_path.display(),
-                tls_paths.key_path.display()
+        let rustls_config = match &tls_config.client_ca_path {
+            Some(client_ca_path) => axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(
+                tls::build_server_config_with_client_ca(&tls_paths, client_ca_path)?,
+            )),
+            None => axum_server::tls_rustls::RustlsConfig::from_pem_file(
+                &tls_paths.cert_path,
+                &tls_paths.key_path,
             )
-        })?;
+            .await
+            .map_err(|err| {
+                format!(
+                    "failed to load inbound tls cert/key (`{}` / `{}`): {err}",
+                    tls_paths.cert_path.display(),
+                    tls_paths.key_path.display()
+                )
+            })?,
+        };
 
-        axum_server::bind_rustls(listen_addr, rustls_config)
+        axum_server::bind(listen_addr)
+            .acceptor(tls::ClientCertAcceptor::new(rustls_config))
             .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .map_err(|err| format!("server error: {err}"))
@@ -492,20 +784,20 @@ fn metrics_dashboard_html(summary_path: &str) -> String {
 async fn proxy_handler(
     State(state): State<AppState>,
     ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
-    request: Request<Body>,
+    mut request: Request<Body>,
 ) -> Response<Body> {
     let runtime = state.runtime.load();
     let request_started_at = tokio::time::Instant::now();
     let method = request.method().clone();
     let path = request.uri().path().to_string();
-    let query = request.uri().query().map(ToString::to_string);
+    let mut query = request.uri().query().map(ToString::to_string);
     let request_origin = extract_origin(request.headers());
     let request_id = observability::extract_or_generate_request_id(request.headers());
     let cors_config = runtime.config.cors.as_ref().filter(|cors| cors.enabled);
     let metrics = state.observability.metrics.clone();
 
     // 获取客户端 IP（优先从转发头，否则从 TCP 连接）
-    let client_ip = extract_client_ip(request.headers(), client_addr);
+    let client_ip = extract_client_ip(request.headers(), client_addr, &runtime.trusted_proxies);
 
     // 检查是否是 admin 路径，如果是则不记录监控统计
     let is_admin_path = state
@@ -567,10 +859,13 @@ async fn proxy_handler(
         );
     }
 
-    // Extract token from headers using configured token sources
-    let Some(token) = auth::extract_token(request.headers(), &runtime.config.gateway_auth.token_sources) else {
+    // 封禁中的客户端 IP / 网段在鉴权前直接拒绝
+    if let (Some(api_key_manager), Some(ip)) = (&runtime.api_key_manager, client_ip.as_deref())
+        && let Some(ban) = api_key_manager.check_ip_ban(ip)
+    {
+        tracing::debug!(subject = ban.subject.as_str(), target = %ban.target, "Rejected request from banned client");
         return finalize_observed_proxy_response(
-            json_error(StatusCode::UNAUTHORIZED, "unauthorized"),
+            json_error(StatusCode::FORBIDDEN, "ip_banned"),
             cors_config,
             request_origin.as_deref(),
             request_observation(
@@ -581,15 +876,19 @@ async fn proxy_handler(
                 &request_id,
                 request_started_at,
             ),
-            "unauthorized",
+            "ip_banned",
         );
-    };
-    let token_label = observability::token_label(&token);
+    }
 
-    // API Key Manager is required for authentication
-    let Some(api_key_manager) = &runtime.api_key_manager else {
+    // mTLS：路由要求客户端证书时，连接必须出示经 CA 校验的证书
+    if route.auth.as_ref().is_some_and(|auth| auth.require_client_cert)
+        && !request
+            .extensions()
+            .get::<tls::ClientCertificate>()
+            .is_some_and(|cert| cert.verified)
+    {
         return finalize_observed_proxy_response(
-            json_error(StatusCode::UNAUTHORIZED, "unauthorized"),
+            json_error(StatusCode::FORBIDDEN, "client_certificate_required"),
             cors_config,
             request_origin.as_deref(),
             request_observation(
@@ -600,49 +899,269 @@ async fn proxy_handler(
                 &request_id,
                 request_started_at,
             ),
-            "unauthorized",
+            "client_certificate_required",
         );
-    };
+    }
 
-    // Validate the API key using ApiKeyManager
-    if let Err(e) = api_key_manager.validate_key(&token, &route.id).await {
-        let error_code = match e {
-            crate::api_keys::ApiKeyError::KeyDisabled => "api_key_disabled",
-            crate::api_keys::ApiKeyError::RouteNotAllowed => "api_key_route_not_allowed",
-            _ => "unauthorized",
-        };
+    // Extract credential from headers using configured token sources
+    let auth_mode = route.auth_mode();
+    let credential = match auth_mode {
+        RouteAuthMode::None => None,
+        RouteAuthMode::Required | RouteAuthMode::Optional => runtime
+            .authenticator_for(&route.id)
+            .extract(request.headers(), query.as_deref()),
+    };
+    if credential.is_none() && auth_mode == RouteAuthMode::Required {
+        report_rejected_request(
+            runtime.api_key_manager.as_ref(),
+            client_ip.as_deref(),
+            StatusCode::UNAUTHORIZED,
+            request_started_at,
+        );
         return finalize_observed_proxy_response(
-            json_error(StatusCode::UNAUTHORIZED, error_code),
+            json_error(StatusCode::UNAUTHORIZED, "unauthorized"),
             cors_config,
             request_origin.as_deref(),
-            request_observation_with_token(
+            request_observation(
                 metrics.as_ref(),
                 route.id.as_str(),
                 &method,
                 &path,
-                Some(token_label.as_str()),
                 &request_id,
                 request_started_at,
             ),
-            error_code,
+            "unauthorized",
         );
     }
 
+    // 携带凭证时按 API Key 校验（optional 模式下无效凭证同样拒绝）；未携带凭证的请求以匿名身份计量
+    let api_key_manager = match credential {
+        Some((credential, credential_location)) => {
+            // 因鉴权失败被锁定的客户端 IP 不再校验任何凭证，避免继续试探
+            if let Some(response) = state.auth_lockout_response(client_ip.as_deref(), AuthScope::Proxy) {
+                return finalize_observed_proxy_response(
+                    response,
+                    cors_config,
+                    request_origin.as_deref(),
+                    request_observation(
+                        metrics.as_ref(),
+                        route.id.as_str(),
+                        &method,
+                        &path,
+                        &request_id,
+                        request_started_at,
+                    ),
+                    "auth_locked",
+                );
+            }
+
+            let token_label = observability::token_label(credential.token());
+            // 网关凭证不转发给上游
+            query = credential_location.strip(request.headers_mut(), query.as_deref());
+
+            // API Key Manager is required for authentication
+            let Some(api_key_manager) = &runtime.api_key_manager else {
+                return finalize_observed_proxy_response(
+                    json_error(StatusCode::UNAUTHORIZED, "unauthorized"),
+                    cors_config,
+                    request_origin.as_deref(),
+                    request_observation(
+                        metrics.as_ref(),
+                        route.id.as_str(),
+                        &method,
+                        &path,
+                        &request_id,
+                        request_started_at,
+                    ),
+                    "unauthorized",
+                );
+            };
+
+            // Validate the API key (or the identity mapped from a JWT) using ApiKeyManager
+            let validation = match &credential {
+                auth::Credential::ApiKey(token) => api_key_manager.validate_key(token, &route.id).await,
+                auth::Credential::Jwt { identity: Ok(identity), .. } => api_key_manager.validate_jwt(identity, &route.id).await,
+                auth::Credential::Jwt { identity: Err(err), .. } => Err(crate::api_keys::ApiKeyError::InvalidJwt(err.to_string())),
+            };
+            match validation {
+                Ok(validation) => Some((api_key_manager, validation.key_id)),
+                Err(e) => {
+                    // 未知 Key 与无效 JWT 计入鉴权失败；已知 Key 的禁用、过期等状态不属于试探
+                    if matches!(
+                        e,
+                        crate::api_keys::ApiKeyError::KeyNotFound | crate::api_keys::ApiKeyError::InvalidJwt(_)
+                    ) {
+                        state.record_auth_failure(client_ip.as_deref(), AuthScope::Proxy).await;
+                    }
+                    report_rejected_request(
+                        Some(api_key_manager),
+                        client_ip.as_deref(),
+                        StatusCode::UNAUTHORIZED,
+                        request_started_at,
+                    );
+                    let error_code = match e {
+                        crate::api_keys::ApiKeyError::InvalidJwt(_) => "invalid_token",
+                        crate::api_keys::ApiKeyError::KeyDisabled => "api_key_disabled",
+                        crate::api_keys::ApiKeyError::KeyNotYetValid { .. } => "api_key_not_yet_valid",
+                        crate::api_keys::ApiKeyError::KeyExpired { .. } => "api_key_expired",
+                        crate::api_keys::ApiKeyError::RouteNotAllowed => "api_key_route_not_allowed",
+                        _ => "unauthorized",
+                    };
+                    return finalize_observed_proxy_response(
+                        json_error(StatusCode::UNAUTHORIZED, error_code),
+                        cors_config,
+                        request_origin.as_deref(),
+                        request_observation_with_token(
+                            metrics.as_ref(),
+                            route.id.as_str(),
+                            &method,
+                            &path,
+                            Some(token_label.as_str()),
+                            &request_id,
+                            request_started_at,
+                        ),
+                        error_code,
+                    );
+                }
+            }
+        }
+        None => None,
+    };
+    let (api_key_manager, key_id) = match api_key_manager {
+        Some((api_key_manager, key_id)) => (Some(api_key_manager), key_id),
+        None => (None, anonymous_identity(&route.id)),
+    };
+
+    // 认证通过后指标一律以 Key ID（匿名请求为合成身份）作为标签
+    let token_label = key_id.clone();
+
+    // 外部授权：由授权服务决定是否放行，并写入其下发的身份头
+    if let Some(forward_auth) = runtime.forward_auth.get(&route.id) {
+        forward_auth.strip_identity_headers(request.headers_mut());
+        let auth_request = ForwardAuthRequest {
+            method: method.as_str(),
+            path: &path,
+            query: query.as_deref(),
+            route_id: &route.id,
+            key_id: &key_id,
+            headers: forward_auth.selected_headers(request.headers()),
+        };
+        let rejection = match forward_auth.authorize(&auth_request).await {
+            ForwardAuthDecision::Allow(identity_headers) => {
+                request.headers_mut().extend(identity_headers.into_iter().map(|(name, value)| (Some(name), value)));
+                None
+            }
+            ForwardAuthDecision::Deny(status) => Some((json_error(status, "forward_auth_denied"), "forward_auth_denied")),
+            ForwardAuthDecision::Unavailable => Some((
+                json_error(StatusCode::SERVICE_UNAVAILABLE, "forward_auth_unavailable"),
+                "forward_auth_unavailable",
+            )),
+        };
+        if let Some((response, outcome)) = rejection {
+            return finalize_observed_proxy_response(
+                response,
+                cors_config,
+                request_origin.as_deref(),
+                request_observation_with_token(
+                    metrics.as_ref(),
+                    route.id.as_str(),
+                    &method,
+                    &path,
+                    Some(token_label.as_str()),
+                    &request_id,
+                    request_started_at,
+                ),
+                outcome,
+            );
+        }
+    }
+
     // Rate limiting: prefer API Key level, fallback to global level
-    let api_key_info = api_key_manager.get_key_info(&token).await;
-    let api_key_id = api_key_info.as_ref().map(|k| k.id.clone());
-
-    // Token配额检查
-    if let Some(key_id) = &api_key_id {
-        if let Err(e) = api_key_manager.check_token_quota_by_id(key_id) {
-            if let crate::api_keys::ApiKeyError::TokenQuotaExceeded { quota_type, limit: _, used: _ } = e {
-                let outcome = match quota_type.as_str() {
-                    "daily_total" => "token_quota_exceeded_daily_total",
-                    "daily_input" => "token_quota_exceeded_daily_input",
-                    "daily_output" => "token_quota_exceeded_daily_output",
-                    "weekly_total" => "token_quota_exceeded_weekly_total",
-                    _ => "token_quota_exceeded",
-                };
+    let api_key_info = match api_key_manager {
+        Some(api_key_manager) => api_key_manager.get_key_info(&key_id).await,
+        None => None,
+    };
+    // 匿名请求没有 Key 级配置，按合成身份计入用量统计、全局预算与路由级 TPM/TPD
+    let api_key_id = match api_key_manager {
+        Some(_) => api_key_info.as_ref().map(|k| k.id.clone()),
+        None => Some(key_id.clone()),
+    };
+    let max_output_tokens_per_request = api_key_info.as_ref().and_then(|k| k.max_output_tokens_per_request);
+
+    // 预估输入 token：用于配额/TPM 准入、收紧 max_tokens，以及上游未返回 usage 时的兜底用量；
+    // 配置价格表时同时读取请求模型用于费用核算
+    let quota_manager = state.observability.token_quota_manager.as_ref();
+    let mut buffered_body: Option<Bytes> = None;
+    let mut estimated_input_tokens: Option<u64> = None;
+    let mut request_model: Option<String> = None;
+    if let Some(key_id) = &api_key_id
+        && (max_output_tokens_per_request.is_some()
+            || quota_manager.is_some_and(|quota_manager| {
+                runtime.tokenizer.is_some()
+                    || runtime.price_table.is_some()
+                    || quota_manager.has_token_rate_limit(key_id, &route.id)
+                    || quota_manager.get_quota(key_id).is_some()
+            }))
+    {
+        let (parts, body) = request.into_parts();
+        // 超过缓冲上限的请求体不做分词，按字节数保守预估后原样流式转发
+        match buffer_request_body(body, MAX_BUFFERED_REQUEST_BODY_BYTES).await {
+            Ok(BufferedRequestBody::Buffered(body)) => {
+                // 分词与 JSON 解析耗时随请求体增长，放到阻塞线程池执行，避免占住异步工作线程
+                let tokenizer = runtime.tokenizer.clone();
+                let estimate_body = body.clone();
+                let estimate = tokio::task::spawn_blocking(move || {
+                    tokenizer
+                        .as_deref()
+                        .and_then(|tokenizer| tokenizer.estimate_request(&estimate_body))
+                        .map(|estimate| estimate.prompt_tokens)
+                        .unwrap_or_else(|| TokenExtractor::estimate_input_tokens(&estimate_body))
+                })
+                .await
+                .unwrap_or_else(|_| (body.len() as u64).div_ceil(4));
+                estimated_input_tokens = Some(estimate);
+                request_model = TokenExtractor::request_model(&body);
+                request = Request::from_parts(parts, Body::from(body.clone()));
+                buffered_body = Some(body);
+            }
+            Ok(BufferedRequestBody::Oversized { body, read_bytes }) => {
+                let content_length = parts
+                    .headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(0);
+                estimated_input_tokens = Some(content_length.max(read_bytes as u64).div_ceil(4));
+                request = Request::from_parts(parts, body);
+            }
+            Err(err) => {
+                tracing::debug!("Failed to read request body: {}", err);
+                return finalize_observed_proxy_response(
+                    json_error(StatusCode::BAD_REQUEST, "invalid_request_body"),
+                    cors_config,
+                    request_origin.as_deref(),
+                    request_observation_with_token(
+                        metrics.as_ref(),
+                        route.id.as_str(),
+                        &method,
+                        &path,
+                        Some(token_label.as_str()),
+                        &request_id,
+                        request_started_at,
+                    ),
+                    "gateway_error",
+                );
+            }
+        }
+    }
+
+    // Token配额检查（计入本次请求的预估输入）
+    let mut quota_result = None;
+    if let (Some(api_key_manager), Some(key_id)) = (api_key_manager, &api_key_id) {
+        match api_key_manager.check_token_quota_by_id(key_id, estimated_input_tokens.unwrap_or(0)) {
+            Ok(result) => quota_result = Some(result),
+            Err(crate::api_keys::ApiKeyError::TokenQuotaExceeded { quota_type, limit: _, used: _ }) => {
+                let outcome = token_quota_outcome(&quota_type);
                 return finalize_observed_proxy_response(
                     json_error(StatusCode::TOO_MANY_REQUESTS, "token_quota_exceeded"),
                     cors_config,
@@ -659,26 +1178,89 @@ async fn proxy_handler(
                     outcome,
                 );
             }
+            Err(_) => {}
         }
     }
 
-    let api_key_has_rate_limit = api_key_info
-        .as_ref()
-        .and_then(|k| k.rate_limit.clone())
-        .is_some();
+    // 费用预算检查（API Key 级与全局）
+    if let (Some(quota_manager), Some(key_id)) = (quota_manager, &api_key_id)
+        && quota_manager.budget().has_budget(key_id)
+    {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if let Err(exceeded) = quota_manager.budget().check(key_id, now) {
+            let status = StatusCode::from_u16(exceeded.status).unwrap_or(StatusCode::PAYMENT_REQUIRED);
+            return finalize_observed_proxy_response(
+                json_error_with_details(
+                    status,
+                    "budget_exceeded",
+                    &[("scope", exceeded.scope.as_str()), ("window", exceeded.window.as_str())],
+                ),
+                cors_config,
+                request_origin.as_deref(),
+                request_observation_with_token(
+                    metrics.as_ref(),
+                    route.id.as_str(),
+                    &method,
+                    &path,
+                    Some(token_label.as_str()),
+                    &request_id,
+                    request_started_at,
+                ),
+                "budget_exceeded",
+            );
+        }
+    }
 
-    if api_key_has_rate_limit {
-        // Use API Key level rate limiting
-        if let Err(e) = api_key_manager.check_rate_limit(&token, &route.id).await {
-            if let crate::api_keys::ApiKeyError::RateLimitExceeded { retry_after_secs } = e {
-                let mut response = json_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited");
-                set_header(
-                    response.headers_mut(),
-                    "retry-after",
-                    &retry_after_secs.to_string(),
-                );
+    // 限流：API Key 级（或全局）与路由级各维度组合检查，最严格者生效
+    let rate_limit_result = match api_key_manager {
+        Some(api_key_manager) => {
+            api_key_manager
+                .check_rate_limit(
+                    &key_id,
+                    &route.id,
+                    runtime.route_rate_limiters.get(&route.id),
+                    client_ip.as_deref(),
+                    runtime.rate_limiter.as_deref(),
+                )
+                .await
+        }
+        None => check_anonymous_rate_limit(&runtime, &route.id, &key_id, client_ip.as_deref()).await,
+    };
+    if let Err(crate::api_keys::ApiKeyError::RateLimitExceeded {
+        retry_after_secs,
+        dimension,
+    }) = rate_limit_result
+    {
+        return finalize_observed_proxy_response(
+            rate_limited_response(dimension, retry_after_secs),
+            cors_config,
+            request_origin.as_deref(),
+            request_observation_with_token(
+                metrics.as_ref(),
+                route.id.as_str(),
+                &method,
+                &path,
+                Some(token_label.as_str()),
+                &request_id,
+                request_started_at,
+            ),
+            "rate_limited",
+        );
+    }
+
+    // TPM/TPD：按预估输入 token 准入，请求结束后按实际用量校正
+    let mut token_reservation = None;
+    if let (Some(quota_manager), Some(key_id)) = (quota_manager, &api_key_id)
+        && quota_manager.has_token_rate_limit(key_id, &route.id)
+    {
+        let estimated_tokens = estimated_input_tokens.unwrap_or(0);
+        match quota_manager.reserve_tokens(key_id, &route.id, estimated_tokens) {
+            Ok(reservation) => token_reservation = Some(reservation),
+            Err(exceeded) => {
                 return finalize_observed_proxy_response(
-                    response,
+                    token_rate_limited_response(&exceeded),
                     cors_config,
                     request_origin.as_deref(),
                     request_observation_with_token(
@@ -690,23 +1272,66 @@ async fn proxy_handler(
                         &request_id,
                         request_started_at,
                     ),
-                    "rate_limited",
+                    "token_rate_limited",
                 );
             }
         }
-    } else if let Some(rate_limiter) = &runtime.rate_limiter {
-        // 回退到全局限流器
-        match rate_limiter.check(&token, &route.id) {
-            RateLimitDecision::Allowed => {}
-            RateLimitDecision::Rejected { retry_after_secs } => {
-                let mut response = json_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited");
-                set_header(
-                    response.headers_mut(),
-                    "retry-after",
-                    &retry_after_secs.to_string(),
-                );
+    }
+
+    // 按单次输出上限、剩余配额与 TPM/TPD 余量收紧输出上限；流式响应超出时截断
+    let output_limit = [
+        max_output_tokens_per_request.map(|max_tokens| OutputLimit {
+            max_tokens,
+            reason: "max_output_tokens_exceeded",
+        }),
+        quota_result
+            .as_ref()
+            .and_then(|result| result.output_budget())
+            .map(|max_tokens| OutputLimit {
+                max_tokens,
+                reason: "token_quota_exceeded",
+            }),
+        token_reservation
+            .as_ref()
+            .and_then(|reservation| reservation.remaining_tokens())
+            .map(|max_tokens| OutputLimit {
+                max_tokens,
+                reason: "token_rate_limited",
+            }),
+    ]
+    .into_iter()
+    .flatten()
+    .min_by_key(|limit| limit.max_tokens);
+    let output_budget = output_limit.map(|limit| limit.max_tokens);
+    // 请求未声明输出上限时插入该接口的原生字段
+    if let (Some(body), Some(budget)) = (&buffered_body, output_budget)
+        && let Some(clamped) = tokenizer::clamp_max_tokens(body, budget.max(1), &path)
+    {
+        let clamped = Bytes::from(clamped);
+        request
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(clamped.len()));
+        *request.body_mut() = Body::from(clamped.clone());
+        buffered_body = Some(clamped);
+    }
+
+    // 配额预留：预占预估输入与（收紧后的）输出上限，请求结束记录实际用量后释放
+    let mut quota_reservation = None;
+    if let (Some(quota_manager), Some(key_id)) = (quota_manager, &api_key_id)
+        && quota_manager.get_quota(key_id).is_some()
+    {
+        let output_tokens = buffered_body
+            .as_deref()
+            .and_then(tokenizer::max_output_tokens)
+            .map_or(0, |max| output_budget.map_or(max, |budget| max.min(budget.max(1))));
+        match quota_manager.reserve_quota(key_id, estimated_input_tokens.unwrap_or(0), output_tokens) {
+            Ok(reservation) => quota_reservation = Some(reservation),
+            Err(result) => {
+                let outcome = result
+                    .exceeded_quota()
+                    .map_or("token_quota_exceeded", |(quota_type, _, _)| token_quota_outcome(quota_type));
                 return finalize_observed_proxy_response(
-                    response,
+                    json_error(StatusCode::TOO_MANY_REQUESTS, "token_quota_exceeded"),
                     cors_config,
                     request_origin.as_deref(),
                     request_observation_with_token(
@@ -718,7 +1343,7 @@ async fn proxy_handler(
                         &request_id,
                         request_started_at,
                     ),
-                    "rate_limited",
+                    outcome,
                 );
             }
         }
@@ -732,9 +1357,9 @@ async fn proxy_handler(
         .and_then(|c| c.downstream_max_inflight)
         .is_some();
 
-    let downstream_permit = if api_key_has_concurrency {
+    let downstream_permit = if let Some(api_key_manager) = api_key_manager.filter(|_| api_key_has_concurrency) {
         // Use API Key level concurrency control
-        match api_key_manager.acquire_concurrency_permit(&token).await {
+        match api_key_manager.acquire_concurrency_permit(&key_id).await {
             Ok(permit) => permit,
             Err(crate::api_keys::ApiKeyError::ConcurrencyLimitExceeded) => {
                 return finalize_observed_proxy_response(
@@ -816,6 +1441,43 @@ async fn proxy_handler(
         None
     };
 
+    // 集群级并发：多实例部署时通过共享后端占用槽位，后端不可用时仅依赖本地限制
+    let mut shared_slots: Vec<SlotLease> = Vec::new();
+    let downstream_scope = match api_key_info
+        .as_ref()
+        .and_then(|k| Some((k.id.as_str(), k.concurrency.as_ref()?.downstream_max_inflight?)))
+    {
+        Some((key_id, limit)) => Some((format!("key:{key_id}"), limit, "api_key_concurrency_exceeded")),
+        None => runtime
+            .concurrency
+            .as_ref()
+            .and_then(|c| c.downstream_limit())
+            .map(|limit| ("downstream".to_string(), limit, "downstream_concurrency_exceeded")),
+    };
+    if let Some((scope, limit, error)) = downstream_scope {
+        match runtime.shared_state.acquire_slot(&scope, limit).await {
+            SlotAcquisition::Acquired(lease) => shared_slots.push(lease),
+            SlotAcquisition::LimitReached => {
+                return finalize_observed_proxy_response(
+                    json_error(StatusCode::SERVICE_UNAVAILABLE, error),
+                    cors_config,
+                    request_origin.as_deref(),
+                    request_observation_with_token(
+                        metrics.as_ref(),
+                        route.id.as_str(),
+                        &method,
+                        &path,
+                        Some(token_label.as_str()),
+                        &request_id,
+                        request_started_at,
+                    ),
+                    "concurrency",
+                );
+            }
+            SlotAcquisition::Skipped => {}
+        }
+    }
+
     let Some(upstream_url) = proxy::build_upstream_url_for_route(route, &path, query.as_deref())
     else {
         return finalize_observed_proxy_response(
@@ -903,6 +1565,33 @@ async fn proxy_handler(
         None
     };
 
+    if let Some((scope, limit)) = runtime.concurrency.as_ref().and_then(|c| c.upstream_scope(route)) {
+        match runtime.shared_state.acquire_slot(&format!("upstream:{scope}"), limit).await {
+            SlotAcquisition::Acquired(lease) => shared_slots.push(lease),
+            SlotAcquisition::LimitReached => {
+                return finalize_observed_proxy_response(
+                    json_error(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "upstream_concurrency_exceeded",
+                    ),
+                    cors_config,
+                    request_origin.as_deref(),
+                    request_observation_with_token(
+                        metrics.as_ref(),
+                        route.id.as_str(),
+                        &method,
+                        &path,
+                        Some(token_label.as_str()),
+                        &request_id,
+                        request_started_at,
+                    ),
+                    "concurrency",
+                );
+            }
+            SlotAcquisition::Skipped => {}
+        }
+    }
+
     if let Some(metrics) = &metrics {
         metrics.inc_inflight(route.id.as_str());
     }
@@ -939,6 +1628,15 @@ async fn proxy_handler(
             let bytes_sent = Arc::new(AtomicU64::new(0));
             let input_tokens = Arc::new(AtomicU64::new(0));
             let output_tokens = Arc::new(AtomicU64::new(0));
+            let cached_input_tokens = Arc::new(AtomicU64::new(0));
+            let reasoning_tokens = Arc::new(AtomicU64::new(0));
+
+            // 流式响应按增量输出计数，超出上限时截断
+            let output_limiter = output_limit.filter(|_| is_sse).map(|limit| OutputLimiter {
+                limit,
+                counter: SseOutputCounter::new(runtime.tokenizer.clone(), request_model.clone()),
+                pending: Vec::new(),
+            });
 
             let completion_guard = ResponseCompletionGuard {
                 metrics: metrics.clone(),
@@ -953,12 +1651,20 @@ async fn proxy_handler(
                 bytes_sent: bytes_sent.clone(),
                 track_inflight: metrics.is_some(),
                 track_sse: is_sse,
-                api_key_manager: Some(Arc::clone(api_key_manager)),
-                token: Some(token.clone()),
+                api_key_manager: api_key_manager.cloned(),
                 token_stats: state.token_stats(),
                 api_key_id: api_key_id.clone(),
+                client_ip: client_ip.clone(),
                 input_tokens: input_tokens.clone(),
                 output_tokens: output_tokens.clone(),
+                cached_input_tokens: cached_input_tokens.clone(),
+                reasoning_tokens: reasoning_tokens.clone(),
+                token_reservation,
+                quota_reservation,
+                estimated_input_tokens,
+                model: request_model,
+                price_table: runtime.price_table.clone(),
+                _shared_slots: shared_slots,
             };
 
             // 创建响应guard（包含token提取功能）
@@ -977,8 +1683,11 @@ async fn proxy_handler(
                 bytes_sent: Some(bytes_sent),
                 input_tokens: Some(input_tokens),
                 output_tokens: Some(output_tokens),
+                cached_input_tokens: Some(cached_input_tokens),
+                reasoning_tokens: Some(reasoning_tokens),
                 extract_tokens: should_extract_tokens,
                 is_sse,
+                output_limiter,
             };
             let mut response = attach_response_guards(response, response_guards);
             observability::insert_request_id_header(response.headers_mut(), &request_id);
@@ -989,19 +1698,25 @@ async fn proxy_handler(
                 metrics.dec_inflight(route.id.as_str());
             }
 
+            let response = error_response(error);
+
             // 上报请求失败给封禁规则引擎
-            {
+            if let Some(api_key_manager) = api_key_manager {
                 let latency_ms = request_started_at.elapsed().as_millis() as u64;
-                let token_clone = token.clone();
+                let response_status = response.status().as_u16();
+                let client_ip = client_ip.clone();
+                let key_id = key_id.clone();
                 let manager = Arc::clone(api_key_manager);
                 tokio::spawn(async move {
                     manager
                         .report_request_result(
-                            &token_clone,
+                            &key_id,
                             crate::api_keys::RequestResult {
                                 success: false,
                                 latency_ms,
-                                response_status: 0,
+                                response_status,
+                                tokens: 0,
+                                client_ip,
                             },
                         )
                         .await;
@@ -1009,7 +1724,7 @@ async fn proxy_handler(
             }
 
             finalize_observed_proxy_response(
-                error_response(error),
+                response,
                 cors_config,
                 request_origin.as_deref(),
                 request_observation_with_token(
@@ -1077,32 +1792,70 @@ fn request_observation_with_token<'a>(
     }
 }
 
-/// 从请求头或 TCP 连接中提取客户端 IP 地址
-fn extract_client_ip(headers: &HeaderMap, client_addr: SocketAddr) -> Option<String> {
-    // 按优先级检查各种转发头（适用于反向代理场景）
-    let header_names = [
-        "x-forwarded-for",
-        "x-real-ip",
-        "cf-connecting-ip",
-        "true-client-ip",
-    ];
-
-    for header_name in header_names {
-        if let Some(value) = headers.get(header_name) {
-            if let Ok(s) = value.to_str() {
-                // x-forwarded-for 可能包含多个 IP，取第一个
-                if let Some(ip) = s.split(',').next() {
-                    let ip = ip.trim();
-                    if !ip.is_empty() {
-                        return Some(ip.to_string());
-                    }
-                }
-            }
-        }
+/// 匿名请求的合成身份
+fn anonymous_identity(route_id: &str) -> String {
+    format!("anonymous:{route_id}")
+}
+
+/// 匿名请求限流：全局默认限流按客户端 IP 计数，路由级 `per_key_per_minute` 限制该路由的匿名请求合计
+async fn check_anonymous_rate_limit(
+    runtime: &RuntimeState,
+    route_id: &str,
+    identity: &str,
+    client_ip: Option<&str>,
+) -> Result<(), crate::api_keys::ApiKeyError> {
+    let mut checks = Vec::new();
+    if let Some(limiter) = runtime.rate_limiter.as_deref() {
+        checks.push(RateLimitCheck::new(
+            RateLimitDimension::Global,
+            limiter.clone(),
+            format!("{route_id}\n{identity}\n{}", client_ip.unwrap_or_default()),
+        ));
+    }
+    if let Some(route_limiters) = runtime.route_rate_limiters.get(route_id) {
+        checks.extend(route_limiters.checks(route_id, identity, client_ip));
     }
 
-    // 如果没有转发头，使用 TCP 连接的客户端地址
-    Some(client_addr.ip().to_string())
+    let result = match check_combined(&checks) {
+        Ok(()) => runtime
+            .shared_state
+            .check_rate_limits(route_id, &checks)
+            .await
+            .inspect_err(|_| release_combined(&checks)),
+        Err(rejection) => Err(rejection),
+    };
+    result.map_err(|rejection| crate::api_keys::ApiKeyError::RateLimitExceeded {
+        retry_after_secs: rejection.retry_after_secs,
+        dimension: rejection.dimension,
+    })
+}
+
+/// 提取客户端 IP 地址：默认取 TCP 连接的对端地址，对端为可信代理时才读取转发头
+pub(crate) fn extract_client_ip(
+    headers: &HeaderMap,
+    client_addr: SocketAddr,
+    trusted_proxies: &[IpNetwork],
+) -> Option<String> {
+    Some(crate::client_ip::resolve_client_ip(headers, client_addr, trusted_proxies).to_string())
+}
+
+/// 未进入转发即被拒绝的请求（缺少凭证、鉴权失败）计入 IP / 网段封禁规则
+fn report_rejected_request(
+    api_key_manager: Option<&Arc<ApiKeyManager>>,
+    client_ip: Option<&str>,
+    status: StatusCode,
+    request_started_at: tokio::time::Instant,
+) {
+    let Some(api_key_manager) = api_key_manager else {
+        return;
+    };
+    api_key_manager.report_ip_result(&crate::api_keys::RequestResult {
+        success: false,
+        latency_ms: request_started_at.elapsed().as_millis() as u64,
+        response_status: status.as_u16(),
+        tokens: 0,
+        client_ip: client_ip.map(str::to_string),
+    });
 }
 
 fn finalize_observed_proxy_response(
@@ -1278,22 +2031,46 @@ struct ResponseCompletionGuard {
     track_inflight: bool,
     track_sse: bool,
     api_key_manager: Option<Arc<ApiKeyManager>>,
-    token: Option<String>,
     /// Token统计收集器
     token_stats: Option<Arc<TokenStatsCollector>>,
     /// API Key ID（用于token统计）
     api_key_id: Option<String>,
+    /// 客户端 IP（上报封禁规则引擎）
+    client_ip: Option<String>,
     /// 输入token数量（从响应解析获得）
     input_tokens: Arc<AtomicU64>,
     /// 输出token数量（从响应解析获得）
     output_tokens: Arc<AtomicU64>,
+    /// 命中缓存的输入token数量（从响应解析获得）
+    cached_input_tokens: Arc<AtomicU64>,
+    /// 推理token数量（从响应解析获得）
+    reasoning_tokens: Arc<AtomicU64>,
+    /// TPM/TPD 预占额度（记录实际用量后释放）
+    token_reservation: Option<TokenRateReservation>,
+    /// 配额预留（记录实际用量后释放）
+    quota_reservation: Option<QuotaReservation>,
+    /// 预估输入token数量（上游未返回 usage 时兜底）
+    estimated_input_tokens: Option<u64>,
+    /// 请求模型（用于费用核算）
+    model: Option<String>,
+    /// 模型价格表
+    price_table: Option<Arc<PriceTable>>,
+    /// 集群并发槽位（请求结束时释放）
+    _shared_slots: Vec<SlotLease>,
 }
 
 impl Drop for ResponseCompletionGuard {
     fn drop(&mut self) {
         let bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
-        let input_tokens = self.input_tokens.load(Ordering::Relaxed);
+        let mut input_tokens = self.input_tokens.load(Ordering::Relaxed);
         let output_tokens = self.output_tokens.load(Ordering::Relaxed);
+        // 上游未返回输入 usage 时（含流式截断），以预估输入 token 作为用量
+        if input_tokens == 0
+            && self.status.is_success()
+            && let Some(estimate) = self.estimated_input_tokens
+        {
+            input_tokens = estimate;
+        }
 
         tracing::info!(
             "ResponseCompletionGuard dropping: route_id={}, api_key_id={:?}, input_tokens={}, output_tokens={}, bytes_sent={}",
@@ -1325,21 +2102,25 @@ impl Drop for ResponseCompletionGuard {
 
         // 上报请求结果给封禁规则引擎
         if let Some(manager) = &self.api_key_manager {
-            if let Some(token) = &self.token {
+            if let Some(key_id) = &self.api_key_id {
                 let success = self.outcome == "success";
                 let latency_ms = self.request_started_at.elapsed().as_millis() as u64;
                 let status = self.status.as_u16();
-                let token = token.clone();
+                let tokens = input_tokens + output_tokens;
+                let client_ip = self.client_ip.clone();
+                let key_id = key_id.clone();
                 let manager = Arc::clone(manager);
 
                 tokio::spawn(async move {
                     manager
                         .report_request_result(
-                            &token,
+                            &key_id,
                             crate::api_keys::RequestResult {
                                 success,
                                 latency_ms,
                                 response_status: status,
+                                tokens,
+                                client_ip,
                             },
                         )
                         .await;
@@ -1354,6 +2135,26 @@ impl Drop for ResponseCompletionGuard {
             let route_id = self.route_id.clone();
             let request_id = self.request_id.clone();
             let stats = Arc::clone(token_stats);
+            let token_reservation = self.token_reservation.take();
+            let quota_reservation = self.quota_reservation.take();
+            let model = self.model.take();
+            let usage = TokenUsage {
+                input_tokens,
+                output_tokens,
+                total_tokens: input_tokens + output_tokens,
+                cached_input_tokens: self.cached_input_tokens.load(Ordering::Relaxed),
+                reasoning_tokens: self.reasoning_tokens.load(Ordering::Relaxed),
+            };
+            // 按请求模型与当前生效价格核算费用，未配置价格的模型记为 0
+            let cost = match (&self.price_table, &model) {
+                (Some(price_table), Some(model)) => {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs());
+                    price_table.cost(model, &usage, now).unwrap_or(0.0)
+                }
+                _ => 0.0,
+            };
 
             tracing::info!(
                 "Preparing to record token usage: api_key_id={}, route_id={}, input_tokens={}, output_tokens={}",
@@ -1361,13 +2162,16 @@ impl Drop for ResponseCompletionGuard {
             );
 
             tokio::spawn(async move {
-                stats.record_usage(
+                stats.record_usage_with_cost(
                     &api_key_id,
                     &route_id,
-                    input_tokens,
-                    output_tokens,
+                    model.as_deref(),
+                    &usage,
+                    cost,
                     Some(request_id),
                 );
+                drop(token_reservation);
+                drop(quota_reservation);
             });
         } else {
             tracing::debug!(
@@ -1386,8 +2190,11 @@ struct ResponseGuards {
     bytes_sent: Option<Arc<AtomicU64>>,
     input_tokens: Option<Arc<AtomicU64>>,
     output_tokens: Option<Arc<AtomicU64>>,
+    cached_input_tokens: Option<Arc<AtomicU64>>,
+    reasoning_tokens: Option<Arc<AtomicU64>>,
     extract_tokens: bool,
     is_sse: bool,
+    output_limiter: Option<OutputLimiter>,
 }
 
 impl ResponseGuards {
@@ -1396,19 +2203,113 @@ impl ResponseGuards {
             && self.upstream_permit.is_none()
             && self.completion_guard.is_none()
             && !self.extract_tokens
+            && self.output_limiter.is_none()
+    }
+}
+
+/// 单次请求的输出 token 上限及超出时返回的错误码
+#[derive(Debug, Clone, Copy)]
+struct OutputLimit {
+    max_tokens: u64,
+    reason: &'static str,
+}
+
+/// 流式输出截断状态
+struct OutputLimiter {
+    limit: OutputLimit,
+    counter: SseOutputCounter,
+    /// 尚未凑成完整事件的数据
+    pending: Vec<u8>,
+}
+
+impl OutputLimiter {
+    /// 按完整 SSE 事件转发数据；输出超出上限时丢弃当前事件并返回截断标记
+    fn push(&mut self, chunk: &[u8]) -> (Vec<u8>, bool) {
+        self.pending.extend_from_slice(chunk);
+        let mut forwarded = Vec::new();
+        while let Some(end) = sse_event_end(&self.pending) {
+            let event: Vec<u8> = self.pending.drain(..end).collect();
+            self.counter.observe_event(&event);
+            if self.counter.output_tokens() > self.limit.max_tokens {
+                self.pending.clear();
+                return (forwarded, true);
+            }
+            forwarded.extend_from_slice(&event);
+        }
+        (forwarded, false)
+    }
+
+    /// 截断时追加的 SSE 错误事件
+    fn error_event(&self) -> Bytes {
+        Bytes::from(format!(
+            "event: error\ndata: {{\"error\":\"{}\",\"limit\":{},\"output_tokens\":{}}}\n\n",
+            self.limit.reason,
+            self.limit.max_tokens,
+            self.counter.output_tokens()
+        ))
     }
 }
 
+/// 返回首个完整 SSE 事件（以空行结束）的结束位置
+fn sse_event_end(buf: &[u8]) -> Option<usize> {
+    let lf = buf.windows(2).position(|w| w == b"\n\n").map(|i| i + 2);
+    let crlf = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4);
+    lf.into_iter().chain(crlf).min()
+}
+
 /// 用于捕获响应体、解析token使用信息并统计字节数的流
 struct TokenCapturingStreamWithGuards<S> {
-    inner: S,
+    /// 上游响应流，截断后置空以取消上游请求
+    inner: Option<S>,
     buffer: Vec<u8>,
     input_tokens: Arc<AtomicU64>,
     output_tokens: Arc<AtomicU64>,
+    cached_input_tokens: Option<Arc<AtomicU64>>,
+    reasoning_tokens: Option<Arc<AtomicU64>>,
     bytes_sent: Option<Arc<AtomicU64>>,
     // 使用Box确保流是Unpin
-    _completion_guard: Option<Box<ResponseCompletionGuard>>,
+    completion_guard: Option<Box<ResponseCompletionGuard>>,
     is_sse: bool,
+    output_limiter: Option<OutputLimiter>,
+}
+
+impl<S> TokenCapturingStreamWithGuards<S> {
+    fn store_usage(&self, usage: &TokenUsage) {
+        self.input_tokens.store(usage.input_tokens, Ordering::Relaxed);
+        self.output_tokens.store(usage.output_tokens, Ordering::Relaxed);
+        if let Some(cached_input_tokens) = &self.cached_input_tokens {
+            cached_input_tokens.store(usage.cached_input_tokens, Ordering::Relaxed);
+        }
+        if let Some(reasoning_tokens) = &self.reasoning_tokens {
+            reasoning_tokens.store(usage.reasoning_tokens, Ordering::Relaxed);
+        }
+    }
+
+    fn count_bytes(&self, len: usize) {
+        if let Some(bytes_sent) = &self.bytes_sent {
+            bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// 输出超出上限：记录已产生的部分用量，取消上游并标记请求结果
+    fn cut_off(&mut self) {
+        self.inner = None;
+        let output_tokens = self.output_limiter.as_ref().map_or(0, |limiter| limiter.counter.output_tokens());
+        let mut usage = TokenExtractor::extract_from_sse_body(&Bytes::from(std::mem::take(&mut self.buffer)))
+            .unwrap_or_default();
+        usage.output_tokens = usage.output_tokens.max(output_tokens);
+        usage.reasoning_tokens = usage.reasoning_tokens.min(usage.output_tokens);
+        usage.total_tokens = usage.input_tokens + usage.output_tokens;
+        self.store_usage(&usage);
+        if let Some(guard) = self.completion_guard.as_mut() {
+            guard.outcome = "output_limit_exceeded";
+        }
+        tracing::warn!(
+            "Stream cut off: output_tokens={}, reason={}",
+            usage.output_tokens,
+            self.output_limiter.as_ref().map_or("", |limiter| limiter.limit.reason)
+        );
+    }
 }
 
 impl<S, E> Stream for TokenCapturingStreamWithGuards<S>
@@ -1419,42 +2320,81 @@ where
     type Item = Result<Bytes, E>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
-        match Pin::new(&mut self.inner).poll_next(cx) {
-            std::task::Poll::Ready(Some(Ok(chunk))) => {
-                // 累积响应体数据
-                let chunk_len = chunk.len();
-                self.buffer.extend_from_slice(&chunk);
-                // 统计字节数
-                if let Some(bytes_sent) = &self.bytes_sent {
-                    bytes_sent.fetch_add(chunk_len as u64, Ordering::Relaxed);
+        loop {
+            let Some(inner) = self.inner.as_mut() else {
+                return std::task::Poll::Ready(None);
+            };
+            match Pin::new(inner).poll_next(cx) {
+                std::task::Poll::Ready(Some(Ok(chunk))) => {
+                    // 累积响应体数据
+                    self.buffer.extend_from_slice(&chunk);
+                    tracing::debug!("Received chunk: {} bytes, total buffer: {} bytes", chunk.len(), self.buffer.len());
+                    let Some(limiter) = self.output_limiter.as_mut() else {
+                        // 统计字节数
+                        self.count_bytes(chunk.len());
+                        return std::task::Poll::Ready(Some(Ok(chunk)));
+                    };
+
+                    let (forwarded, exceeded) = limiter.push(&chunk);
+                    if exceeded {
+                        let mut forwarded = forwarded;
+                        forwarded.extend_from_slice(&limiter.error_event());
+                        self.cut_off();
+                        self.count_bytes(forwarded.len());
+                        return std::task::Poll::Ready(Some(Ok(Bytes::from(forwarded))));
+                    }
+                    if forwarded.is_empty() {
+                        // 事件尚不完整，继续读取
+                        continue;
+                    }
+                    self.count_bytes(forwarded.len());
+                    return std::task::Poll::Ready(Some(Ok(Bytes::from(forwarded))));
                 }
-                tracing::debug!("Received chunk: {} bytes, total buffer: {} bytes", chunk_len, self.buffer.len());
-                std::task::Poll::Ready(Some(Ok(chunk)))
-            }
-            std::task::Poll::Ready(None) => {
-                // 流结束，解析token usage
-                let usage = if self.is_sse {
-                    // 对于SSE流，从SSE格式中提取最后一条data消息
-                    TokenExtractor::extract_from_sse_body(&Bytes::from(std::mem::take(&mut self.buffer)))
-                } else {
-                    // 对于非SSE流，直接解析JSON
-                    TokenExtractor::extract_from_body(&Bytes::from(std::mem::take(&mut self.buffer)))
-                };
+                std::task::Poll::Ready(None) => {
+                    self.inner = None;
+                    // 流结束，解析token usage
+                    let usage = if self.is_sse {
+                        // 对于SSE流，从SSE格式中提取最后一条data消息
+                        TokenExtractor::extract_from_sse_body(&Bytes::from(std::mem::take(&mut self.buffer)))
+                    } else {
+                        // 对于非SSE流，直接解析JSON
+                        TokenExtractor::extract_from_body(&Bytes::from(std::mem::take(&mut self.buffer)))
+                    };
+                    // 上游未返回 usage 时，以增量计数作为输出用量
+                    let usage = usage.or_else(|| {
+                        let output_tokens = self.output_limiter.as_ref()?.counter.output_tokens();
+                        (output_tokens > 0).then(|| TokenUsage {
+                            output_tokens,
+                            total_tokens: output_tokens,
+                            ..TokenUsage::default()
+                        })
+                    });
+
+                    if let Some(usage) = usage {
+                        self.store_usage(&usage);
+                        tracing::debug!(
+                            "Extracted token usage: input={}, output={}",
+                            usage.input_tokens, usage.output_tokens
+                        );
+                    } else {
+                        tracing::debug!("No token usage found in response body");
+                    }
 
-                if let Some(usage) = usage {
-                    self.input_tokens.store(usage.input_tokens, Ordering::Relaxed);
-                    self.output_tokens.store(usage.output_tokens, Ordering::Relaxed);
-                    tracing::debug!(
-                        "Extracted token usage: input={}, output={}",
-                        usage.input_tokens, usage.output_tokens
-                    );
-                } else {
-                    tracing::debug!("No token usage found in response body");
+                    // 转发末尾未以空行结束的数据
+                    let rest = self
+                        .output_limiter
+                        .as_mut()
+                        .map(|limiter| std::mem::take(&mut limiter.pending))
+                        .unwrap_or_default();
+                    if rest.is_empty() {
+                        return std::task::Poll::Ready(None);
+                    }
+                    self.count_bytes(rest.len());
+                    return std::task::Poll::Ready(Some(Ok(Bytes::from(rest))));
                 }
-                std::task::Poll::Ready(None)
+                std::task::Poll::Ready(Some(Err(e))) => return std::task::Poll::Ready(Some(Err(e))),
+                std::task::Poll::Pending => return std::task::Poll::Pending,
             }
-            std::task::Poll::Ready(Some(Err(e))) => std::task::Poll::Ready(Some(Err(e))),
-            std::task::Poll::Pending => std::task::Poll::Pending,
         }
     }
 }
@@ -1498,21 +2438,27 @@ fn attach_response_guards(response: Response<Body>, guards: ResponseGuards) -> R
         guards.output_tokens.is_some()
     );
 
-    // 如果需要提取token，使用TokenCapturingStream
-    if guards.extract_tokens && guards.input_tokens.is_some() && guards.output_tokens.is_some() {
+    // 如果需要提取token或限制流式输出，使用TokenCapturingStream
+    if (guards.extract_tokens || guards.output_limiter.is_some())
+        && guards.input_tokens.is_some()
+        && guards.output_tokens.is_some()
+    {
         tracing::debug!("Using TokenCapturingStreamWithGuards for token extraction");
         let input_tokens = guards.input_tokens.clone().unwrap();
         let output_tokens = guards.output_tokens.clone().unwrap();
         let bytes_sent = guards.bytes_sent.clone();
 
         let captured_stream = TokenCapturingStreamWithGuards {
-            inner: body.into_data_stream(),
+            inner: Some(body.into_data_stream()),
             buffer: Vec::new(),
             input_tokens,
             output_tokens,
+            cached_input_tokens: guards.cached_input_tokens.clone(),
+            reasoning_tokens: guards.reasoning_tokens.clone(),
             bytes_sent,
-            _completion_guard: guards.completion_guard.map(Box::new), // 保持completion_guard存活
+            completion_guard: guards.completion_guard.map(Box::new), // 保持completion_guard存活
             is_sse: guards.is_sse,
+            output_limiter: guards.output_limiter,
         };
 
         return Response::from_parts(parts, Body::from_stream(captured_stream));
@@ -1542,6 +2488,77 @@ fn json_error(status: StatusCode, code: &'static str) -> Response<Body> {
     response
 }
 
+/// 配额超限时记录的请求结果
+fn token_quota_outcome(quota_type: &str) -> &'static str {
+    match quota_type {
+        "daily_total" => "token_quota_exceeded_daily_total",
+        "daily_input" => "token_quota_exceeded_daily_input",
+        "daily_output" => "token_quota_exceeded_daily_output",
+        "weekly_total" => "token_quota_exceeded_weekly_total",
+        "weekly_input" => "token_quota_exceeded_weekly_input",
+        "weekly_output" => "token_quota_exceeded_weekly_output",
+        "monthly_total" => "token_quota_exceeded_monthly_total",
+        "monthly_input" => "token_quota_exceeded_monthly_input",
+        "monthly_output" => "token_quota_exceeded_monthly_output",
+        "rolling_total" => "token_quota_exceeded_rolling_total",
+        "rolling_input" => "token_quota_exceeded_rolling_input",
+        "rolling_output" => "token_quota_exceeded_rolling_output",
+        _ => "token_quota_exceeded",
+    }
+}
+
+/// 构造带附加字段的错误响应，字段值需为无需转义的标识符
+fn json_error_with_details(
+    status: StatusCode,
+    code: &'static str,
+    details: &[(&str, &str)],
+) -> Response<Body> {
+    let mut body = format!(r#"{{"error":"{code}""#);
+    for (name, value) in details {
+        body.push_str(&format!(r#","{name}":"{value}""#));
+    }
+    body.push('}');
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        http::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+fn rate_limited_response(dimension: RateLimitDimension, retry_after_secs: u64) -> Response<Body> {
+    let mut response = json_error_with_details(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limited",
+        &[("dimension", dimension.as_str())],
+    );
+    set_header(
+        response.headers_mut(),
+        "retry-after",
+        &retry_after_secs.to_string(),
+    );
+    response
+}
+
+fn token_rate_limited_response(exceeded: &TokenRateLimitExceeded) -> Response<Body> {
+    let mut response = json_error_with_details(
+        StatusCode::TOO_MANY_REQUESTS,
+        "token_rate_limited",
+        &[
+            ("dimension", exceeded.scope.as_str()),
+            ("window", exceeded.window.as_str()),
+        ],
+    );
+    set_header(
+        response.headers_mut(),
+        "retry-after",
+        &exceeded.retry_after_secs.to_string(),
+    );
+    response
+}
+
 fn error_response(error: UpstreamError) -> Response<Body> {
     match error {
         UpstreamError::Timeout => json_error(StatusCode::GATEWAY_TIMEOUT, "upstream_timeout"),
@@ -1764,6 +2781,64 @@ fn build_upstream_clients(config: &AppConfig) -> Result<HashMap<String, reqwest:
     Ok(clients)
 }
 
+/// 创建并发控制器，并启动信号量清理任务
+fn build_concurrency_controller(config: &AppConfig) -> Option<Arc<ConcurrencyController>> {
+    let concurrency = ConcurrencyController::new(config).map(Arc::new)?;
+    let ctrl_clone = Arc::clone(&concurrency);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            ctrl_clone.cleanup_unused_semaphores();
+        }
+    });
+    Some(concurrency)
+}
+
+fn build_route_rate_limiters(config: &AppConfig) -> HashMap<String, RouteRateLimiters> {
+    config
+        .routes
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|route| {
+            route
+                .rate_limit
+                .as_ref()
+                .map(|rate_limit| (route.id.clone(), RouteRateLimiters::from_config(rate_limit)))
+        })
+        .collect()
+}
+
+fn build_route_authenticators(config: &AppConfig) -> Result<HashMap<String, Arc<auth::Authenticator>>, String> {
+    config
+        .routes
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|route| {
+            let auth = route.auth.as_ref().filter(|auth| !auth.token_sources.is_empty())?;
+            Some(
+                auth::Authenticator::from_sources(&auth.token_sources)
+                    .map(|authenticator| (route.id.clone(), Arc::new(authenticator))),
+            )
+        })
+        .collect()
+}
+
+fn build_forward_auth(config: &AppConfig) -> Result<HashMap<String, Arc<ForwardAuth>>, String> {
+    config
+        .routes
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|route| {
+            let forward_auth = route.forward_auth.as_ref()?;
+            Some(ForwardAuth::from_config(forward_auth).map(|forward_auth| (route.id.clone(), Arc::new(forward_auth))))
+        })
+        .collect()
+}
+
 fn build_upstream_client(upstream: &UpstreamConfig) -> Result<reqwest::Client, String> {
     let mut builder = reqwest::Client::builder()
         .connect_timeout(Duration::from_millis(upstream.connect_timeout_ms))
@@ -1841,8 +2916,44 @@ fn enforce_response_deadline(
     ))
 }
 
+/// 缓冲后的请求体
+enum BufferedRequestBody {
+    Buffered(Bytes),
+    /// 超过缓冲上限：已读取的部分与剩余部分重新拼接为流式请求体
+    Oversized { body: Body, read_bytes: usize },
+}
+
+/// 读取请求体用于预估 token，超过 `limit` 时停止缓冲并还原为流式请求体
+async fn buffer_request_body(body: Body, limit: usize) -> Result<BufferedRequestBody, axum::Error> {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut len = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        len += chunk.len();
+        chunks.push(chunk);
+        if len > limit {
+            let read = futures_util::stream::iter(chunks.into_iter().map(Ok::<_, axum::Error>));
+            return Ok(BufferedRequestBody::Oversized {
+                body: Body::from_stream(read.chain(stream)),
+                read_bytes: len,
+            });
+        }
+    }
+
+    let mut body = Vec::with_capacity(len);
+    for chunk in chunks {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(BufferedRequestBody::Buffered(Bytes::from(body)))
+}
+
 /// SSE流空闲超时配置
 const SSE_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// 需要预估 token 时允许缓冲的最大请求体，超过时按字节数预估并直接流式转发
+const MAX_BUFFERED_REQUEST_BODY_BYTES: usize = 32 * 1024 * 1024;
+/// API Key 生命周期后台任务间隔
+const API_KEY_LIFECYCLE_INTERVAL: Duration = Duration::from_secs(60);
 
 /// 为SSE流应用空闲超时机制
 /// SSE连接应该只在空闲时断开，而不是整体超时
@@ -1885,7 +2996,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{build_app, build_proxy_url, build_upstream_clients};
+    use super::{BufferedRequestBody, build_app, build_proxy_url, build_upstream_clients, buffer_request_body};
     use crate::config::{
         AppConfig, GatewayAuthConfig, ProxyProtocol, RouteConfig, TokenSourceConfig,
         UpstreamConfig, UpstreamProxyConfig,
@@ -1916,6 +3027,27 @@ mod tests {
         request
     }
 
+    #[tokio::test]
+    async fn oversized_request_body_is_restored_for_streaming() {
+        let body = "x".repeat(100);
+        match buffer_request_body(Body::from(body.clone()), 1_000).await.unwrap() {
+            BufferedRequestBody::Buffered(buffered) => assert_eq!(buffered, body.as_bytes()),
+            BufferedRequestBody::Oversized { .. } => panic!("body within limit should be buffered"),
+        }
+
+        let chunks = futures_util::stream::iter(
+            ["a".repeat(40), "b".repeat(40), "c".repeat(40)].map(Ok::<_, std::io::Error>),
+        );
+        match buffer_request_body(Body::from_stream(chunks), 50).await.unwrap() {
+            BufferedRequestBody::Oversized { body, read_bytes } => {
+                assert_eq!(read_bytes, 80);
+                let restored = to_bytes(body, usize::MAX).await.unwrap();
+                assert_eq!(restored, format!("{}{}{}", "a".repeat(40), "b".repeat(40), "c".repeat(40)));
+            }
+            BufferedRequestBody::Buffered(_) => panic!("body over limit should not be buffered"),
+        }
+    }
+
     #[tokio::test]
     async fn healthz_returns_ok() {
         let app = build_app(Arc::new(test_config()), None).await.expect("app should build");
@@ -2005,6 +3137,10 @@ mod tests {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         });
 
         let clients = build_upstream_clients(&config).expect("clients should build");
@@ -2052,6 +3188,10 @@ mod tests {
                     upstream_key_max_inflight: None,
                     user_agent: None,
                 },
+                rate_limit: None,
+                token_rate_limit: None,
+                forward_auth: None,
+                auth: None,
             }]),
             api_keys: None,
             inbound_tls: None,
@@ -2062,6 +3202,12 @@ mod tests {
             admin: None,
             config_db_path: "./data/config.db".to_string(),
             token_stats: None,
+            tokenizer: None,
+            pricing: None,
+            global_budget: None,
+            state_backend: None,
+            auth_protection: None,
+            trusted_proxies: Vec::new(),
         }
     }
 }
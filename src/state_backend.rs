@@ -0,0 +1,1022 @@
+//! 共享状态后端
+//!
+//! 多实例部署时在实例间共享限流计数、并发槽位、Token 配额用量与封禁状态。
+//! 本地限流器、信号量与配额窗口始终生效；配置共享后端后额外执行集群级检查，
+//! 后端不可用时跳过集群级检查，降级为各实例的本地限制。
+
+use crate::api_keys::{ApiKeyManager, BanStatus};
+use crate::config::{BanSubject, RedisStateBackendConfig, StateBackendConfig};
+use crate::ratelimit::{RateLimitCheck, RateLimitDimension, RateLimitRejection};
+use crate::token_quota::{HourlyTokenStat, TokenQuotaManager, USAGE_RETENTION_HOURS};
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// 连接失败后暂停重连的时长
+const RECONNECT_BACKOFF_MS: u64 = 5_000;
+
+/// 共享状态后端错误
+#[derive(Debug, Error)]
+pub enum StateBackendError {
+    #[error("state backend unavailable: {0}")]
+    Unavailable(String),
+    #[error("state backend operation timed out")]
+    Timeout,
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+}
+
+impl From<redis::RedisError> for StateBackendError {
+    fn from(err: redis::RedisError) -> Self {
+        StateBackendError::Unavailable(err.to_string())
+    }
+}
+
+/// 固定窗口计数检查项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterCheck {
+    /// 计数键（调用方负责带上窗口编号）
+    pub key: String,
+    pub limit: u64,
+}
+
+/// 共享状态后端 trait
+#[async_trait]
+pub trait StateBackend: Send + Sync {
+    /// 后端名称（用于日志）
+    fn name(&self) -> &'static str;
+
+    /// 状态是否在多个网关实例间共享
+    fn is_shared(&self) -> bool;
+
+    /// 全部计数项未达上限时一起加一并返回 `None`；否则不计数，返回首个已满项的下标
+    async fn check_and_incr(
+        &self,
+        checks: &[CounterCheck],
+        ttl_secs: u64,
+    ) -> Result<Option<usize>, StateBackendError>;
+
+    /// 占用并发槽位，`key` 下已有 `limit` 个未过期持有者时返回 `false`
+    async fn acquire_slot(
+        &self,
+        key: &str,
+        holder: &str,
+        limit: u64,
+        lease_secs: u64,
+    ) -> Result<bool, StateBackendError>;
+
+    /// 续期并发槽位
+    async fn renew_slot(&self, key: &str, holder: &str, lease_secs: u64) -> Result<(), StateBackendError>;
+
+    /// 释放并发槽位
+    async fn release_slot(&self, key: &str, holder: &str) -> Result<(), StateBackendError>;
+
+    /// 累加 API Key 的小时级 token 用量
+    async fn add_usage(&self, api_key_id: &str, usage: &[HourlyTokenStat]) -> Result<(), StateBackendError>;
+
+    /// 读取 API Key 的小时级 token 用量（所有实例合计，按时间升序）
+    async fn usage(&self, api_key_id: &str) -> Result<Vec<HourlyTokenStat>, StateBackendError>;
+
+    /// 写入 API Key 的封禁状态
+    async fn put_ban_status(&self, api_key_id: &str, status: &BanStatus) -> Result<(), StateBackendError>;
+
+    /// 读取所有 API Key 的封禁状态（api_key_id -> 状态）
+    async fn ban_statuses(&self) -> Result<HashMap<String, BanStatus>, StateBackendError>;
+
+    /// 写入 IP 或网段的封禁状态
+    async fn put_ip_ban_status(
+        &self,
+        subject: BanSubject,
+        target: &str,
+        status: &BanStatus,
+    ) -> Result<(), StateBackendError>;
+
+    /// 读取所有 IP 与网段的封禁状态（(对象类型, IP 或网段) -> 状态）
+    async fn ip_ban_statuses(&self) -> Result<HashMap<(BanSubject, String), BanStatus>, StateBackendError>;
+}
+
+/// 进程内存后端（默认），状态不在实例间共享
+pub struct MemoryStateBackend {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    /// 计数键 -> (计数, 过期时间)
+    counters: HashMap<String, (u64, u64)>,
+    /// 槽位键 -> 持有者 -> 租约到期时间
+    slots: HashMap<String, HashMap<String, u64>>,
+    /// api_key_id -> 小时 -> (输入, 输出)
+    usage: HashMap<String, BTreeMap<u64, (u64, u64)>>,
+    ban_statuses: HashMap<String, BanStatus>,
+    ip_ban_statuses: HashMap<(BanSubject, String), BanStatus>,
+}
+
+impl MemoryStateBackend {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MemoryState::default()),
+        }
+    }
+
+    /// 锁定状态；持锁时发生 panic 不影响后续请求，沿用已写入的状态
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MemoryStateBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl StateBackend for MemoryStateBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+
+    async fn check_and_incr(
+        &self,
+        checks: &[CounterCheck],
+        ttl_secs: u64,
+    ) -> Result<Option<usize>, StateBackendError> {
+        let now = current_epoch_seconds();
+        let mut state = self.state();
+        state.counters.retain(|_, (_, expires_at)| *expires_at > now);
+
+        for (index, check) in checks.iter().enumerate() {
+            let used = state.counters.get(&check.key).map_or(0, |(count, _)| *count);
+            if used >= check.limit {
+                return Ok(Some(index));
+            }
+        }
+        for check in checks {
+            state
+                .counters
+                .entry(check.key.clone())
+                .or_insert((0, now + ttl_secs))
+                .0 += 1;
+        }
+        Ok(None)
+    }
+
+    async fn acquire_slot(
+        &self,
+        key: &str,
+        holder: &str,
+        limit: u64,
+        lease_secs: u64,
+    ) -> Result<bool, StateBackendError> {
+        let now = current_epoch_seconds();
+        let mut state = self.state();
+        let holders = state.slots.entry(key.to_string()).or_default();
+        holders.retain(|_, expires_at| *expires_at > now);
+        if !holders.contains_key(holder) && holders.len() as u64 >= limit {
+            return Ok(false);
+        }
+        holders.insert(holder.to_string(), now + lease_secs);
+        Ok(true)
+    }
+
+    async fn renew_slot(&self, key: &str, holder: &str, lease_secs: u64) -> Result<(), StateBackendError> {
+        let now = current_epoch_seconds();
+        let mut state = self.state();
+        if let Some(expires_at) = state.slots.get_mut(key).and_then(|holders| holders.get_mut(holder)) {
+            *expires_at = now + lease_secs;
+        }
+        Ok(())
+    }
+
+    async fn release_slot(&self, key: &str, holder: &str) -> Result<(), StateBackendError> {
+        let mut state = self.state();
+        if let Some(holders) = state.slots.get_mut(key) {
+            holders.remove(holder);
+            if holders.is_empty() {
+                state.slots.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn add_usage(&self, api_key_id: &str, usage: &[HourlyTokenStat]) -> Result<(), StateBackendError> {
+        let oldest = oldest_retained_hour();
+        let mut state = self.state();
+        let hours = state.usage.entry(api_key_id.to_string()).or_default();
+        for stat in usage {
+            let entry = hours.entry(stat.hour_epoch).or_default();
+            entry.0 += stat.input_tokens;
+            entry.1 += stat.output_tokens;
+        }
+        hours.retain(|hour, _| *hour >= oldest);
+        Ok(())
+    }
+
+    async fn usage(&self, api_key_id: &str) -> Result<Vec<HourlyTokenStat>, StateBackendError> {
+        let state = self.state();
+        Ok(state
+            .usage
+            .get(api_key_id)
+            .map(|hours| {
+                hours
+                    .iter()
+                    .map(|(hour_epoch, (input_tokens, output_tokens))| HourlyTokenStat {
+                        hour_epoch: *hour_epoch,
+                        input_tokens: *input_tokens,
+                        output_tokens: *output_tokens,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn put_ban_status(&self, api_key_id: &str, status: &BanStatus) -> Result<(), StateBackendError> {
+        let mut state = self.state();
+        state.ban_statuses.insert(api_key_id.to_string(), status.clone());
+        Ok(())
+    }
+
+    async fn ban_statuses(&self) -> Result<HashMap<String, BanStatus>, StateBackendError> {
+        Ok(self.state().ban_statuses.clone())
+    }
+
+    async fn put_ip_ban_status(
+        &self,
+        subject: BanSubject,
+        target: &str,
+        status: &BanStatus,
+    ) -> Result<(), StateBackendError> {
+        let mut state = self.state();
+        state.ip_ban_statuses.insert((subject, target.to_string()), status.clone());
+        Ok(())
+    }
+
+    async fn ip_ban_statuses(&self) -> Result<HashMap<(BanSubject, String), BanStatus>, StateBackendError> {
+        Ok(self.state().ip_ban_statuses.clone())
+    }
+}
+
+/// 全部计数项未满时一起加一，返回首个已满项的序号（从 1 开始，0 表示通过）
+const CHECK_AND_INCR_SCRIPT: &str = r#"
+for i, key in ipairs(KEYS) do
+  local used = tonumber(redis.call('GET', key) or '0')
+  if used >= tonumber(ARGV[i + 1]) then
+    return i
+  end
+end
+for _, key in ipairs(KEYS) do
+  if redis.call('INCR', key) == 1 then
+    redis.call('EXPIRE', key, ARGV[1])
+  end
+end
+return 0
+"#;
+
+/// 并发槽位：有序集合的成员为持有者，分值为租约到期时间（毫秒，取 Redis 服务器时间）
+const ACQUIRE_SLOT_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = tonumber(t[1]) * 1000 + math.floor(tonumber(t[2]) / 1000)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now)
+if not redis.call('ZSCORE', KEYS[1], ARGV[1]) and redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[2]) then
+  return 0
+end
+redis.call('ZADD', KEYS[1], now + tonumber(ARGV[3]), ARGV[1])
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return 1
+"#;
+
+const RENEW_SLOT_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = tonumber(t[1]) * 1000 + math.floor(tonumber(t[2]) / 1000)
+redis.call('ZADD', KEYS[1], 'XX', now + tonumber(ARGV[2]), ARGV[1])
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Redis 协议后端
+pub struct RedisStateBackend {
+    client: redis::Client,
+    connection: tokio::sync::Mutex<Option<ConnectionManager>>,
+    key_prefix: String,
+    timeout: Duration,
+    /// 连接失败后下次允许重连的时间（Unix 毫秒）
+    reconnect_at_ms: AtomicU64,
+    /// 最近一次操作是否成功（用于只在状态切换时输出日志）
+    available: AtomicBool,
+    check_and_incr_script: redis::Script,
+    acquire_slot_script: redis::Script,
+    renew_slot_script: redis::Script,
+}
+
+impl RedisStateBackend {
+    /// 创建后端，首次使用时才建立连接
+    pub fn new(config: &RedisStateBackendConfig) -> Result<Self, String> {
+        let client = redis::Client::open(config.url.as_str())
+            .map_err(|err| format!("invalid `state_backend.url`: {err}"))?;
+        Ok(Self {
+            client,
+            connection: tokio::sync::Mutex::new(None),
+            key_prefix: config.key_prefix.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            reconnect_at_ms: AtomicU64::new(0),
+            available: AtomicBool::new(true),
+            check_and_incr_script: redis::Script::new(CHECK_AND_INCR_SCRIPT),
+            acquire_slot_script: redis::Script::new(ACQUIRE_SLOT_SCRIPT),
+            renew_slot_script: redis::Script::new(RENEW_SLOT_SCRIPT),
+        })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!("{}{suffix}", self.key_prefix)
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, StateBackendError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+        if current_epoch_millis() < self.reconnect_at_ms.load(Ordering::Relaxed) {
+            return Err(StateBackendError::Unavailable("waiting to reconnect".to_string()));
+        }
+
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(self.timeout)
+            .set_response_timeout(self.timeout)
+            .set_number_of_retries(1);
+        let result = match tokio::time::timeout(
+            self.timeout * 2,
+            ConnectionManager::new_with_config(self.client.clone(), config),
+        )
+        .await
+        {
+            Ok(Ok(manager)) => Ok(manager),
+            Ok(Err(err)) => Err(StateBackendError::from(err)),
+            Err(_) => Err(StateBackendError::Timeout),
+        };
+        match result {
+            Ok(manager) => {
+                *connection = Some(manager.clone());
+                Ok(manager)
+            }
+            Err(err) => {
+                self.reconnect_at_ms
+                    .store(current_epoch_millis() + RECONNECT_BACKOFF_MS, Ordering::Relaxed);
+                self.mark(Err(&err));
+                Err(err)
+            }
+        }
+    }
+
+    /// 执行一次操作：统一超时并记录可用性变化
+    async fn run<T, F>(&self, op: F) -> Result<T, StateBackendError>
+    where
+        F: AsyncFnOnce(&mut ConnectionManager) -> redis::RedisResult<T>,
+    {
+        let mut connection = self.connection().await?;
+        let result = match tokio::time::timeout(self.timeout, op(&mut connection)).await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => Err(StateBackendError::from(err)),
+            Err(_) => Err(StateBackendError::Timeout),
+        };
+        self.mark(result.as_ref().map(|_| ()));
+        result
+    }
+
+    fn mark(&self, result: Result<(), &StateBackendError>) {
+        let available = result.is_ok();
+        let was_available = self.available.swap(available, Ordering::Relaxed);
+        match result {
+            Err(err) if was_available => {
+                tracing::warn!("State backend redis unavailable, falling back to local limits: {}", err);
+            }
+            Ok(()) if !was_available => tracing::info!("State backend redis recovered"),
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl StateBackend for RedisStateBackend {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+
+    async fn check_and_incr(
+        &self,
+        checks: &[CounterCheck],
+        ttl_secs: u64,
+    ) -> Result<Option<usize>, StateBackendError> {
+        let mut invocation = self.check_and_incr_script.prepare_invoke();
+        invocation.arg(ttl_secs);
+        for check in checks {
+            invocation.key(self.key(&check.key)).arg(check.limit);
+        }
+        let full: usize = self
+            .run(async |connection| invocation.invoke_async(connection).await)
+            .await?;
+        Ok(full.checked_sub(1))
+    }
+
+    async fn acquire_slot(
+        &self,
+        key: &str,
+        holder: &str,
+        limit: u64,
+        lease_secs: u64,
+    ) -> Result<bool, StateBackendError> {
+        let mut invocation = self.acquire_slot_script.prepare_invoke();
+        invocation
+            .key(self.key(key))
+            .arg(holder)
+            .arg(limit)
+            .arg(lease_secs * 1000);
+        let acquired: u8 = self
+            .run(async |connection| invocation.invoke_async(connection).await)
+            .await?;
+        Ok(acquired == 1)
+    }
+
+    async fn renew_slot(&self, key: &str, holder: &str, lease_secs: u64) -> Result<(), StateBackendError> {
+        let mut invocation = self.renew_slot_script.prepare_invoke();
+        invocation.key(self.key(key)).arg(holder).arg(lease_secs * 1000);
+        self.run(async |connection| invocation.invoke_async::<()>(connection).await)
+            .await
+    }
+
+    async fn release_slot(&self, key: &str, holder: &str) -> Result<(), StateBackendError> {
+        let mut cmd = redis::cmd("ZREM");
+        cmd.arg(self.key(key)).arg(holder);
+        self.run(async |connection| cmd.query_async::<()>(connection).await)
+            .await
+    }
+
+    async fn add_usage(&self, api_key_id: &str, usage: &[HourlyTokenStat]) -> Result<(), StateBackendError> {
+        let key = self.key(&format!("usage:{api_key_id}"));
+        let mut pipe = redis::pipe();
+        for stat in usage {
+            pipe.cmd("HINCRBY")
+                .arg(&key)
+                .arg(format!("{}:in", stat.hour_epoch))
+                .arg(stat.input_tokens)
+                .ignore();
+            pipe.cmd("HINCRBY")
+                .arg(&key)
+                .arg(format!("{}:out", stat.hour_epoch))
+                .arg(stat.output_tokens)
+                .ignore();
+        }
+        pipe.cmd("EXPIRE").arg(&key).arg(USAGE_RETENTION_HOURS * 3600).ignore();
+        self.run(async |connection| pipe.query_async::<()>(connection).await)
+            .await
+    }
+
+    async fn usage(&self, api_key_id: &str) -> Result<Vec<HourlyTokenStat>, StateBackendError> {
+        let key = self.key(&format!("usage:{api_key_id}"));
+        let mut cmd = redis::cmd("HGETALL");
+        cmd.arg(&key);
+        let fields: HashMap<String, u64> = self
+            .run(async |connection| cmd.query_async(connection).await)
+            .await?;
+
+        let oldest = oldest_retained_hour();
+        let mut hours: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+        let mut expired = Vec::new();
+        for (field, value) in fields {
+            let Some((hour, kind)) = field.split_once(':') else {
+                continue;
+            };
+            let Ok(hour) = hour.parse::<u64>() else {
+                continue;
+            };
+            if hour < oldest {
+                expired.push(field);
+                continue;
+            }
+            let entry = hours.entry(hour).or_default();
+            match kind {
+                "in" => entry.0 = value,
+                "out" => entry.1 = value,
+                _ => {}
+            }
+        }
+
+        // 顺带清理超出保留期的小时字段
+        if !expired.is_empty() {
+            let mut cmd = redis::cmd("HDEL");
+            cmd.arg(&key).arg(expired);
+            self.run(async |connection| cmd.query_async::<()>(connection).await)
+                .await?;
+        }
+
+        Ok(hours
+            .into_iter()
+            .map(|(hour_epoch, (input_tokens, output_tokens))| HourlyTokenStat {
+                hour_epoch,
+                input_tokens,
+                output_tokens,
+            })
+            .collect())
+    }
+
+    async fn put_ban_status(&self, api_key_id: &str, status: &BanStatus) -> Result<(), StateBackendError> {
+        let value = serde_json::to_string(status)
+            .map_err(|err| StateBackendError::InvalidData(err.to_string()))?;
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(self.key("bans")).arg(api_key_id).arg(value);
+        self.run(async |connection| cmd.query_async::<()>(connection).await)
+            .await
+    }
+
+    async fn ban_statuses(&self) -> Result<HashMap<String, BanStatus>, StateBackendError> {
+        let mut cmd = redis::cmd("HGETALL");
+        cmd.arg(self.key("bans"));
+        let raw: HashMap<String, String> = self
+            .run(async |connection| cmd.query_async(connection).await)
+            .await?;
+        Ok(raw
+            .into_iter()
+            .filter_map(|(id, value)| match serde_json::from_str(&value) {
+                Ok(status) => Some((id, status)),
+                Err(err) => {
+                    tracing::warn!("Ignoring invalid shared ban status for key {}: {}", id, err);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn put_ip_ban_status(
+        &self,
+        subject: BanSubject,
+        target: &str,
+        status: &BanStatus,
+    ) -> Result<(), StateBackendError> {
+        let value = serde_json::to_string(status)
+            .map_err(|err| StateBackendError::InvalidData(err.to_string()))?;
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(self.key("ip_bans"))
+            .arg(format!("{}:{}", subject.as_str(), target))
+            .arg(value);
+        self.run(async |connection| cmd.query_async::<()>(connection).await)
+            .await
+    }
+
+    async fn ip_ban_statuses(&self) -> Result<HashMap<(BanSubject, String), BanStatus>, StateBackendError> {
+        let mut cmd = redis::cmd("HGETALL");
+        cmd.arg(self.key("ip_bans"));
+        let raw: HashMap<String, String> = self
+            .run(async |connection| cmd.query_async(connection).await)
+            .await?;
+        Ok(raw
+            .into_iter()
+            .filter_map(|(field, value)| {
+                // 字段为 `<ip|subnet>:<IP 或网段>`，IPv6 地址本身含冒号，只按首个冒号拆分
+                let key = match field.split_once(':') {
+                    Some(("ip", target)) => (BanSubject::Ip, target.to_string()),
+                    Some(("subnet", target)) => (BanSubject::Subnet, target.to_string()),
+                    _ => {
+                        tracing::warn!("Ignoring invalid shared ban target {}", field);
+                        return None;
+                    }
+                };
+                match serde_json::from_str(&value) {
+                    Ok(status) => Some((key, status)),
+                    Err(err) => {
+                        tracing::warn!("Ignoring invalid shared ban status for {}: {}", field, err);
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+}
+
+/// 并发槽位的获取结果
+pub enum SlotAcquisition {
+    /// 已占用集群槽位，租约随请求结束释放
+    Acquired(SlotLease),
+    /// 集群内该维度的并发已满
+    LimitReached,
+    /// 未启用共享后端或后端不可用，仅使用本地限制
+    Skipped,
+}
+
+/// 共享并发槽位租约：请求进行中定期续期，释放时归还槽位
+pub struct SlotLease {
+    backend: Arc<dyn StateBackend>,
+    key: String,
+    holder: String,
+    renew_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for SlotLease {
+    fn drop(&mut self) {
+        self.renew_task.abort();
+        let backend = Arc::clone(&self.backend);
+        let key = std::mem::take(&mut self.key);
+        let holder = std::mem::take(&mut self.holder);
+        tokio::spawn(async move {
+            if let Err(err) = backend.release_slot(&key, &holder).await {
+                tracing::debug!("Failed to release shared slot {}: {}", key, err);
+            }
+        });
+    }
+}
+
+/// 共享状态句柄：封装后端、租约与同步参数以及降级策略
+#[derive(Clone)]
+pub struct SharedState {
+    backend: Arc<dyn StateBackend>,
+    config: Option<StateBackendConfig>,
+    slot_lease_secs: u64,
+    sync_interval: Duration,
+}
+
+impl SharedState {
+    pub fn from_config(config: Option<&StateBackendConfig>) -> Result<Self, String> {
+        match config {
+            Some(StateBackendConfig::Redis(redis)) => Ok(Self {
+                backend: Arc::new(RedisStateBackend::new(redis)?),
+                config: config.cloned(),
+                slot_lease_secs: redis.slot_lease_secs,
+                sync_interval: Duration::from_millis(redis.sync_interval_ms),
+            }),
+            Some(StateBackendConfig::Memory) | None => Ok(Self::memory()),
+        }
+    }
+
+    /// 进程内存后端
+    pub fn memory() -> Self {
+        Self {
+            backend: Arc::new(MemoryStateBackend::new()),
+            config: None,
+            slot_lease_secs: 30,
+            sync_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// 配置未变化时复用已建立的连接
+    pub fn reuse_or_build(old: Option<&SharedState>, config: Option<&StateBackendConfig>) -> Result<Self, String> {
+        let config = config.filter(|config| !matches!(config, StateBackendConfig::Memory));
+        match old {
+            Some(old) if old.config.as_ref() == config => Ok(old.clone()),
+            _ => Self::from_config(config),
+        }
+    }
+
+    pub fn backend(&self) -> &Arc<dyn StateBackend> {
+        &self.backend
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.backend.is_shared()
+    }
+
+    pub fn sync_interval(&self) -> Duration {
+        self.sync_interval
+    }
+
+    /// 集群级限流：各维度按分钟固定窗口计数，后端不可用时视为通过
+    pub async fn check_rate_limits(
+        &self,
+        route_id: &str,
+        checks: &[RateLimitCheck],
+    ) -> Result<(), RateLimitRejection> {
+        if !self.is_shared() || checks.is_empty() {
+            return Ok(());
+        }
+
+        let now = current_epoch_seconds();
+        let minute = now / 60;
+        let counters: Vec<CounterCheck> = checks
+            .iter()
+            .map(|check| {
+                // API Key 维度跨路由共享，其余维度的限流器按路由独立
+                let scope = match check.dimension {
+                    RateLimitDimension::ApiKey => check.key().to_string(),
+                    _ => format!("{route_id}\n{}", check.key()),
+                };
+                CounterCheck {
+                    key: format!("rl:{}:{}:{minute}", check.dimension, digest(&scope)),
+                    limit: check.per_minute(),
+                }
+            })
+            .collect();
+
+        match self.backend.check_and_incr(&counters, 120).await {
+            Ok(None) => Ok(()),
+            Ok(Some(index)) => Err(RateLimitRejection {
+                dimension: checks[index].dimension,
+                retry_after_secs: crate::ratelimit::retry_after_seconds(now),
+            }),
+            Err(err) => {
+                tracing::debug!("Skipping shared rate limit check: {}", err);
+                Ok(())
+            }
+        }
+    }
+
+    /// 占用集群级并发槽位
+    pub async fn acquire_slot(&self, scope: &str, limit: usize) -> SlotAcquisition {
+        if !self.is_shared() {
+            return SlotAcquisition::Skipped;
+        }
+
+        let key = format!("slot:{}", digest(scope));
+        let holder = uuid::Uuid::now_v7().to_string();
+        match self
+            .backend
+            .acquire_slot(&key, &holder, limit as u64, self.slot_lease_secs)
+            .await
+        {
+            Ok(true) => {
+                let backend = Arc::clone(&self.backend);
+                let lease_secs = self.slot_lease_secs;
+                let renew_key = key.clone();
+                let renew_holder = holder.clone();
+                let renew_task = tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(Duration::from_secs(lease_secs / 3));
+                    interval.tick().await;
+                    loop {
+                        interval.tick().await;
+                        if let Err(err) = backend.renew_slot(&renew_key, &renew_holder, lease_secs).await {
+                            tracing::debug!("Failed to renew shared slot {}: {}", renew_key, err);
+                        }
+                    }
+                });
+                SlotAcquisition::Acquired(SlotLease {
+                    backend: Arc::clone(&self.backend),
+                    key,
+                    holder,
+                    renew_task,
+                })
+            }
+            Ok(false) => SlotAcquisition::LimitReached,
+            Err(err) => {
+                tracing::debug!("Skipping shared concurrency check: {}", err);
+                SlotAcquisition::Skipped
+            }
+        }
+    }
+
+    /// 同步 Token 配额用量与封禁状态：推送本实例的增量，拉取集群合计
+    pub async fn sync(&self, quota_manager: Option<&TokenQuotaManager>, api_key_manager: Option<&ApiKeyManager>) {
+        if !self.is_shared() {
+            return;
+        }
+        if let Some(quota_manager) = quota_manager {
+            self.sync_usage(quota_manager).await;
+        }
+        if let Some(api_key_manager) = api_key_manager {
+            self.sync_ban_statuses(api_key_manager).await;
+        }
+    }
+
+    async fn sync_usage(&self, quota_manager: &TokenQuotaManager) {
+        let mut unsynced = quota_manager.take_unsynced_usage().into_iter();
+        while let Some((api_key_id, usage)) = unsynced.next() {
+            if let Err(err) = self.backend.add_usage(&api_key_id, &usage).await {
+                tracing::debug!("Failed to push token usage to state backend: {}", err);
+                quota_manager.requeue_unsynced_usage(&api_key_id, &usage);
+                for (api_key_id, usage) in unsynced {
+                    quota_manager.requeue_unsynced_usage(&api_key_id, &usage);
+                }
+                return;
+            }
+        }
+
+        for api_key_id in quota_manager.shared_usage_key_ids() {
+            match self.backend.usage(&api_key_id).await {
+                Ok(usage) => quota_manager.apply_shared_usage(&api_key_id, &usage),
+                Err(err) => {
+                    tracing::debug!("Failed to pull token usage from state backend: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn sync_ban_statuses(&self, api_key_manager: &ApiKeyManager) {
+        let mut dirty = api_key_manager.take_dirty_ban_statuses().await.into_iter();
+        while let Some((api_key_id, status)) = dirty.next() {
+            if let Err(err) = self.backend.put_ban_status(&api_key_id, &status).await {
+                tracing::debug!("Failed to push ban status to state backend: {}", err);
+                api_key_manager.mark_ban_status_dirty(&api_key_id);
+                for (api_key_id, _) in dirty {
+                    api_key_manager.mark_ban_status_dirty(&api_key_id);
+                }
+                return;
+            }
+        }
+
+        match self.backend.ban_statuses().await {
+            Ok(statuses) => api_key_manager.apply_shared_ban_statuses(&statuses).await,
+            Err(err) => {
+                tracing::debug!("Failed to pull ban statuses from state backend: {}", err);
+                return;
+            }
+        }
+
+        let mut dirty = api_key_manager.take_dirty_ip_bans().into_iter();
+        while let Some(((subject, target), status)) = dirty.next() {
+            if let Err(err) = self.backend.put_ip_ban_status(subject, &target, &status).await {
+                tracing::debug!("Failed to push IP ban status to state backend: {}", err);
+                api_key_manager.mark_ip_ban_dirty(subject, &target);
+                for ((subject, target), _) in dirty {
+                    api_key_manager.mark_ip_ban_dirty(subject, &target);
+                }
+                return;
+            }
+        }
+
+        match self.backend.ip_ban_statuses().await {
+            Ok(statuses) => api_key_manager.apply_shared_ip_bans(&statuses),
+            Err(err) => tracing::debug!("Failed to pull IP ban statuses from state backend: {}", err),
+        }
+    }
+}
+
+/// 共享键名中使用摘要，避免原始 API Key、客户端 IP 等写入后端
+fn digest(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())[..32].to_string()
+}
+
+fn oldest_retained_hour() -> u64 {
+    let current_hour = current_epoch_seconds() / 3600 * 3600;
+    current_hour.saturating_sub((USAGE_RETENTION_HOURS - 1) * 3600)
+}
+
+fn current_epoch_seconds() -> u64 {
+    current_epoch_millis() / 1000
+}
+
+fn current_epoch_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_backend_counts_all_or_nothing() {
+        let backend = MemoryStateBackend::new();
+        let checks = vec![
+            CounterCheck { key: "a".to_string(), limit: 2 },
+            CounterCheck { key: "b".to_string(), limit: 1 },
+        ];
+        assert_eq!(backend.check_and_incr(&checks, 60).await.unwrap(), None);
+        // b 已满，a 不再计数
+        assert_eq!(backend.check_and_incr(&checks, 60).await.unwrap(), Some(1));
+        assert_eq!(backend.check_and_incr(&checks[..1], 60).await.unwrap(), None);
+        assert_eq!(backend.check_and_incr(&checks[..1], 60).await.unwrap(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn memory_backend_slots_and_usage() {
+        let backend = MemoryStateBackend::new();
+        assert!(backend.acquire_slot("s", "h1", 1, 30).await.unwrap());
+        assert!(!backend.acquire_slot("s", "h2", 1, 30).await.unwrap());
+        backend.release_slot("s", "h1").await.unwrap();
+        assert!(backend.acquire_slot("s", "h2", 1, 30).await.unwrap());
+
+        let hour = current_epoch_seconds() / 3600 * 3600;
+        let stat = HourlyTokenStat {
+            hour_epoch: hour,
+            input_tokens: 10,
+            output_tokens: 5,
+        };
+        backend.add_usage("k", &[stat]).await.unwrap();
+        backend.add_usage("k", &[stat]).await.unwrap();
+        let usage = backend.usage("k").await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].input_tokens, usage[0].output_tokens), (20, 10));
+    }
+
+    #[tokio::test]
+    async fn memory_backend_recovers_from_poisoned_lock() {
+        let backend = Arc::new(MemoryStateBackend::new());
+        let checks = vec![CounterCheck { key: "a".to_string(), limit: 2 }];
+        assert_eq!(backend.check_and_incr(&checks, 60).await.unwrap(), None);
+
+        let poisoner = Arc::clone(&backend);
+        std::thread::spawn(move || {
+            let _guard = poisoner.state.lock().unwrap();
+            panic!("poison the state lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(backend.state.is_poisoned());
+
+        // 已写入的计数保留，后续检查继续生效
+        assert_eq!(backend.check_and_incr(&checks, 60).await.unwrap(), None);
+        assert_eq!(backend.check_and_incr(&checks, 60).await.unwrap(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn unreachable_redis_degrades_to_local_limits() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let config = StateBackendConfig::Redis(RedisStateBackendConfig {
+            url: format!("redis://{addr}"),
+            key_prefix: "test:".to_string(),
+            timeout_ms: 100,
+            sync_interval_ms: 1_000,
+            slot_lease_secs: 30,
+        });
+        let shared = SharedState::from_config(Some(&config)).unwrap();
+        assert!(shared.is_shared());
+
+        let limiter = crate::ratelimit::RateLimiter::new(1);
+        let checks = vec![RateLimitCheck::new(RateLimitDimension::ApiKey, limiter, "key")];
+        assert!(shared.check_rate_limits("route", &checks).await.is_ok());
+        assert!(matches!(shared.acquire_slot("downstream", 1).await, SlotAcquisition::Skipped));
+    }
+
+    /// 两个实例连接同一 Redis：`REDIS_URL=redis://127.0.0.1:6379/0 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    async fn instances_share_rate_limits_and_bans_through_redis() {
+        use crate::api_keys::ApiKeyManager;
+        use crate::config::ResolvedApiKey;
+        use crate::ratelimit::RateLimiter;
+
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL must point to a Redis server");
+        let config = StateBackendConfig::Redis(RedisStateBackendConfig {
+            url,
+            // 每次运行使用独立前缀，避免与其他数据互相影响
+            key_prefix: format!("ai-gw-test:{}:", current_epoch_millis()),
+            timeout_ms: 1_000,
+            sync_interval_ms: 1_000,
+            slot_lease_secs: 30,
+        });
+        let a = SharedState::from_config(Some(&config)).unwrap();
+        let b = SharedState::from_config(Some(&config)).unwrap();
+
+        // 分钟计数窗口即将切换时等待，避免计数被重置
+        if current_epoch_seconds() % 60 > 55 {
+            tokio::time::sleep(Duration::from_secs(6)).await;
+        }
+        let checks = |limiter: &RateLimiter| {
+            vec![RateLimitCheck::new(RateLimitDimension::ApiKey, limiter.clone(), "shared-key")]
+        };
+        let (limiter_a, limiter_b) = (RateLimiter::new(2), RateLimiter::new(2));
+        assert!(a.check_rate_limits("route", &checks(&limiter_a)).await.is_ok());
+        assert!(b.check_rate_limits("route", &checks(&limiter_b)).await.is_ok());
+        // 每个实例各用一次，集群合计已达上限
+        let rejection = a.check_rate_limits("route", &checks(&limiter_a)).await.unwrap_err();
+        assert_eq!(rejection.dimension, RateLimitDimension::ApiKey);
+        assert!(b.check_rate_limits("route", &checks(&limiter_b)).await.is_err());
+
+        let manager = |shared: &SharedState| {
+            let key = ResolvedApiKey {
+                id: "k1".to_string(),
+                ..ResolvedApiKey::from_key_string("sk-shared-test-key")
+            };
+            ApiKeyManager::new(vec![key], None, Vec::new(), None, shared.clone(), 0)
+        };
+        let (manager_a, manager_b) = (manager(&a), manager(&b));
+
+        // 实例 A 的 Key 封禁与 IP / 网段封禁同步到实例 B
+        manager_a.ban_key("k1", Some(600), "abuse".to_string()).await.unwrap();
+        manager_a.ban_ip(BanSubject::Ip, "10.0.0.1", Some(600), "abuse".to_string());
+        manager_a.ban_ip(BanSubject::Subnet, "10.2.3.0/24", None, "abuse".to_string());
+        a.sync(None, Some(&manager_a)).await;
+        b.sync(None, Some(&manager_b)).await;
+        let status = manager_b.get_key_info("k1").await.unwrap().ban_status.unwrap();
+        assert!(status.is_banned);
+        assert_eq!(status.reason.as_deref(), Some("abuse"));
+        assert_eq!(manager_b.check_ip_ban("10.0.0.1").unwrap().subject, BanSubject::Ip);
+        assert_eq!(manager_b.check_ip_ban("10.2.3.4").unwrap().target, "10.2.3.0/24");
+        assert!(manager_b.check_ip_ban("10.0.0.2").is_none());
+
+        // 实例 B 解封后同步回实例 A
+        manager_b.unban_key("k1").await.unwrap();
+        assert!(manager_b.unban_ip(BanSubject::Ip, "10.0.0.1"));
+        b.sync(None, Some(&manager_b)).await;
+        a.sync(None, Some(&manager_a)).await;
+        assert!(!manager_a.get_key_info("k1").await.unwrap().ban_status.unwrap().is_banned);
+        assert!(manager_a.check_ip_ban("10.0.0.1").is_none());
+        assert!(manager_a.check_ip_ban("10.2.3.4").is_some());
+    }
+}
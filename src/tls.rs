@@ -1,8 +1,21 @@
 use crate::config::InboundTlsConfig;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use http::Request;
 use rcgen::generate_simple_self_signed;
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
 use std::fs;
+use std::future::Future;
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TlsPaths {
@@ -62,6 +75,115 @@ pub fn resolve_tls_paths(
     ))
 }
 
+/// 构建启用客户端证书校验的 TLS 配置
+///
+/// 客户端证书为可选：未出示证书的连接照常建立，出示的证书必须由 `client_ca_path` 中的 CA 签发。
+pub fn build_server_config_with_client_ca(paths: &TlsPaths, client_ca_path: &str) -> Result<ServerConfig, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in read_certs(Path::new(client_ca_path))? {
+        roots
+            .add(cert)
+            .map_err(|err| format!("invalid client ca cert in `{client_ca_path}`: {err}"))?;
+    }
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()
+        .map_err(|err| format!("failed to build client cert verifier: {err}"))?;
+    let key = PrivateKeyDer::from_pem_file(&paths.key_path)
+        .map_err(|err| format!("failed to read key file `{}`: {err}", paths.key_path.display()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(read_certs(&paths.cert_path)?, key)
+        .map_err(|err| format!("invalid inbound tls cert/key: {err}"))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("failed to read cert file `{}`: {err}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in `{}`", path.display()));
+    }
+    Ok(certs)
+}
+
+/// 连接是否出示了经 CA 校验的客户端证书，作为请求扩展注入
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub verified: bool,
+}
+
+/// 在 rustls 握手后记录客户端证书状态的 acceptor
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = <RustlsAcceptor as Accept<I, S>>::Stream;
+    type Service = WithClientCertificate<S>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = inner.accept(stream, service).await?;
+            let verified = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .is_some_and(|certs| !certs.is_empty());
+            Ok((
+                stream,
+                WithClientCertificate {
+                    inner: service,
+                    certificate: ClientCertificate { verified },
+                },
+            ))
+        })
+    }
+}
+
+/// 为连接上的每个请求写入 [`ClientCertificate`] 扩展
+#[derive(Clone)]
+pub struct WithClientCertificate<S> {
+    inner: S,
+    certificate: ClientCertificate,
+}
+
+impl<S, B> tower_service::Service<Request<B>> for WithClientCertificate<S>
+where
+    S: tower_service::Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> S::Future {
+        request.extensions_mut().insert(self.certificate);
+        self.inner.call(request)
+    }
+}
+
 fn generate_self_signed_cert_files(
     cert_path: &Path,
     key_path: &Path,
@@ -119,6 +241,7 @@ mod tests {
             key_path: Some("custom/server.key".to_string()),
             self_signed_cert_path: "certs/self.crt".to_string(),
             self_signed_key_path: "certs/self.key".to_string(),
+            client_ca_path: None,
         };
 
         let (paths, source) = resolve_tls_paths(&tls, test_addr()).expect("paths should resolve");
@@ -137,6 +260,7 @@ mod tests {
             key_path: None,
             self_signed_cert_path: cert_path.to_string_lossy().to_string(),
             self_signed_key_path: key_path.to_string_lossy().to_string(),
+            client_ca_path: None,
         };
 
         let (paths, source) = resolve_tls_paths(&tls, test_addr()).expect("paths should resolve");
@@ -159,6 +283,7 @@ mod tests {
             key_path: None,
             self_signed_cert_path: cert_path.to_string_lossy().to_string(),
             self_signed_key_path: key_path.to_string_lossy().to_string(),
+            client_ca_path: None,
         };
 
         let (paths, source) = resolve_tls_paths(&tls, test_addr()).expect("paths should resolve");
@@ -1,33 +1,18 @@
+use crate::tokenizer::Tokenizer;
 use axum::body::Bytes;
-use serde::Deserialize;
 use std::io::Read;
+use std::sync::Arc;
 
 /// Token使用信息
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct TokenUsage {
     pub input_tokens: u64,
     pub output_tokens: u64,
     pub total_tokens: u64,
-}
-
-/// OpenAI格式的usage字段
-#[derive(Debug, Deserialize)]
-struct OpenAiUsage {
-    #[serde(default)]
-    prompt_tokens: u64,
-    #[serde(default)]
-    completion_tokens: u64,
-    #[serde(default)]
-    total_tokens: u64,
-}
-
-/// Claude格式的usage字段
-#[derive(Debug, Deserialize)]
-struct ClaudeUsage {
-    #[serde(default)]
-    input_tokens: u64,
-    #[serde(default)]
-    output_tokens: u64,
+    /// 命中缓存的输入token（包含在 input_tokens 中）
+    pub cached_input_tokens: u64,
+    /// 推理token（包含在 output_tokens 中）
+    pub reasoning_tokens: u64,
 }
 
 pub struct TokenExtractor;
@@ -92,61 +77,15 @@ impl TokenExtractor {
 
         tracing::debug!("Found usage field: {:?}", usage);
 
-        // 尝试OpenAI格式
-        if let Ok(openai_usage) = serde_json::from_value::<OpenAiUsage>(usage.clone()) {
-            tracing::debug!(
-                "Extracted OpenAI format tokens: prompt={}, completion={}",
-                openai_usage.prompt_tokens,
-                openai_usage.completion_tokens
-            );
-            return Some(TokenUsage {
-                input_tokens: openai_usage.prompt_tokens,
-                output_tokens: openai_usage.completion_tokens,
-                total_tokens: openai_usage.total_tokens,
-            });
-        }
-
-        // 尝试Claude格式
-        if let Ok(claude_usage) = serde_json::from_value::<ClaudeUsage>(usage.clone()) {
-            tracing::debug!(
-                "Extracted Claude format tokens: input={}, output={}",
-                claude_usage.input_tokens,
-                claude_usage.output_tokens
-            );
-            return Some(TokenUsage {
-                input_tokens: claude_usage.input_tokens,
-                output_tokens: claude_usage.output_tokens,
-                total_tokens: claude_usage.input_tokens + claude_usage.output_tokens,
-            });
-        }
-
-        // 尝试通用格式（直接提取字段）
-        let input_tokens = usage
-            .get("prompt_tokens")
-            .or_else(|| usage.get("input_tokens"))
-            .and_then(|v| v.as_u64())
-            .unwrap_or(0);
-
-        let output_tokens = usage
-            .get("completion_tokens")
-            .or_else(|| usage.get("output_tokens"))
-            .and_then(|v| v.as_u64())
-            .unwrap_or(0);
-
-        let total_tokens = usage
-            .get("total_tokens")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(input_tokens + output_tokens);
-
-        if input_tokens > 0 || output_tokens > 0 {
-            Some(TokenUsage {
-                input_tokens,
-                output_tokens,
-                total_tokens,
-            })
-        } else {
-            None
-        }
+        let usage = Self::parse_usage(usage)?;
+        tracing::debug!(
+            "Extracted tokens: input={}, output={}, cached={}, reasoning={}",
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cached_input_tokens,
+            usage.reasoning_tokens
+        );
+        Some(usage)
     }
 
     /// 从累积的SSE chunks中提取token使用信息
@@ -229,50 +168,24 @@ impl TokenExtractor {
 
         if let Some(usage) = json_value.get("usage") {
             tracing::debug!("Found usage field: {:?}", usage);
-            // 尝试OpenAI格式
-            if let Ok(openai_usage) = serde_json::from_value::<OpenAiUsage>(usage.clone()) {
-                if openai_usage.prompt_tokens > 0 || openai_usage.completion_tokens > 0 {
-                    tracing::debug!("Extracted OpenAI format from SSE: prompt={}, completion={}", openai_usage.prompt_tokens, openai_usage.completion_tokens);
-                    return Some(TokenUsage {
-                        input_tokens: openai_usage.prompt_tokens,
-                        output_tokens: openai_usage.completion_tokens,
-                        total_tokens: openai_usage.total_tokens,
-                    });
-                }
-            }
-
-            // 尝试Claude格式
-            if let Ok(claude_usage) = serde_json::from_value::<ClaudeUsage>(usage.clone()) {
-                if claude_usage.input_tokens > 0 || claude_usage.output_tokens > 0 {
-                    tracing::debug!("Extracted Claude format from SSE: input={}, output={}", claude_usage.input_tokens, claude_usage.output_tokens);
-                    return Some(TokenUsage {
-                        input_tokens: claude_usage.input_tokens,
-                        output_tokens: claude_usage.output_tokens,
-                        total_tokens: claude_usage.input_tokens + claude_usage.output_tokens,
-                    });
-                }
+            if let Some(usage) = Self::parse_usage(usage) {
+                tracing::debug!(
+                    "Extracted tokens from SSE: input={}, output={}",
+                    usage.input_tokens,
+                    usage.output_tokens
+                );
+                return Some(usage);
             }
         } else {
             tracing::debug!("No 'usage' field found in SSE JSON");
         }
 
         // 有些SSE流可能将usage放在不同的嵌套位置
-        // 尝试在message或delta中查找
-        if let Some(message) = json_value.get("message") {
-            if let Some(usage) = message.get("usage") {
-                if let Ok(claude_usage) = serde_json::from_value::<ClaudeUsage>(usage.clone()) {
-                    if claude_usage.input_tokens > 0 || claude_usage.output_tokens > 0 {
-                        return Some(TokenUsage {
-                            input_tokens: claude_usage.input_tokens,
-                            output_tokens: claude_usage.output_tokens,
-                            total_tokens: claude_usage.input_tokens + claude_usage.output_tokens,
-                        });
-                    }
-                }
-            }
-        }
-
-        None
+        // 尝试在message中查找
+        json_value
+            .get("message")
+            .and_then(|message| message.get("usage"))
+            .and_then(Self::parse_usage)
     }
 
     /// 从流式SSE chunk中提取token使用信息
@@ -298,33 +211,233 @@ impl TokenExtractor {
                 let json_value: serde_json::Value = serde_json::from_str(json_str).ok()?;
 
                 // 查找usage字段
-                if let Some(usage) = json_value.get("usage") {
-                    // 尝试OpenAI格式
-                    if let Ok(openai_usage) = serde_json::from_value::<OpenAiUsage>(usage.clone()) {
-                        if openai_usage.prompt_tokens > 0 || openai_usage.completion_tokens > 0 {
-                            return Some(TokenUsage {
-                                input_tokens: openai_usage.prompt_tokens,
-                                output_tokens: openai_usage.completion_tokens,
-                                total_tokens: openai_usage.total_tokens,
-                            });
-                        }
-                    }
+                if let Some(usage) = json_value.get("usage").and_then(Self::parse_usage) {
+                    return Some(usage);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// 解析 usage 对象，兼容 OpenAI Chat Completions、OpenAI Responses 与 Claude 格式
+    ///
+    /// Claude 的缓存读写 token 不计入 `input_tokens`，这里统一并入输入并把缓存读取记为命中缓存。
+    pub fn parse_usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        let nested = |object: &str, name: &str| {
+            usage
+                .get(object)
+                .and_then(|details| details.get(name))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0)
+        };
+
+        let (input_tokens, output_tokens, cached_input_tokens, reasoning_tokens) =
+            if usage.get("prompt_tokens").is_some() || usage.get("completion_tokens").is_some() {
+                // OpenAI Chat Completions
+                (
+                    field("prompt_tokens"),
+                    field("completion_tokens"),
+                    nested("prompt_tokens_details", "cached_tokens"),
+                    nested("completion_tokens_details", "reasoning_tokens"),
+                )
+            } else if usage.get("input_tokens_details").is_some() || usage.get("output_tokens_details").is_some() {
+                // OpenAI Responses API
+                (
+                    field("input_tokens"),
+                    field("output_tokens"),
+                    nested("input_tokens_details", "cached_tokens"),
+                    nested("output_tokens_details", "reasoning_tokens"),
+                )
+            } else {
+                // Claude
+                let cache_read = field("cache_read_input_tokens");
+                let cache_creation = field("cache_creation_input_tokens");
+                (
+                    field("input_tokens") + cache_read + cache_creation,
+                    field("output_tokens"),
+                    cache_read,
+                    0,
+                )
+            };
+
+        if input_tokens == 0 && output_tokens == 0 {
+            return None;
+        }
+        let total_tokens = usage
+            .get("total_tokens")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(input_tokens + output_tokens);
+        Some(TokenUsage {
+            input_tokens,
+            output_tokens,
+            total_tokens,
+            cached_input_tokens: cached_input_tokens.min(input_tokens),
+            reasoning_tokens: reasoning_tokens.min(output_tokens),
+        })
+    }
+
+    /// 读取请求体中的 `model` 字段
+    pub fn request_model(body: &[u8]) -> Option<String> {
+        let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+        json.get("model")?.as_str().map(str::to_string)
+    }
+
+    /// 根据请求体粗略预估输入token数量（用于 TPM/TPD 准入）
+    ///
+    /// ASCII 文本按约 4 字符/token 计算，非 ASCII 字符（如中文）按 1 字符/token 计算。
+    pub fn estimate_input_tokens(body: &[u8]) -> u64 {
+        let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) else {
+            return (body.len() as u64).div_ceil(4);
+        };
 
-                    // 尝试Claude格式
-                    if let Ok(claude_usage) = serde_json::from_value::<ClaudeUsage>(usage.clone()) {
-                        if claude_usage.input_tokens > 0 || claude_usage.output_tokens > 0 {
-                            return Some(TokenUsage {
-                                input_tokens: claude_usage.input_tokens,
-                                output_tokens: claude_usage.output_tokens,
-                                total_tokens: claude_usage.input_tokens + claude_usage.output_tokens,
-                            });
-                        }
+        let mut counter = TextCounter::default();
+        for field in ["system", "instructions", "messages", "prompt", "input", "contents"] {
+            if let Some(value) = json.get(field) {
+                counter.add_value(value);
+            }
+        }
+        counter.ascii_chars.div_ceil(4) + counter.other_chars
+    }
+}
+
+/// 统计请求体中的文本字符数
+#[derive(Default)]
+struct TextCounter {
+    ascii_chars: u64,
+    other_chars: u64,
+}
+
+impl TextCounter {
+    fn add_value(&mut self, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => {
+                for ch in text.chars() {
+                    if ch.is_ascii() {
+                        self.ascii_chars += 1;
+                    } else {
+                        self.other_chars += 1;
                     }
                 }
             }
+            serde_json::Value::Array(items) => items.iter().for_each(|item| self.add_value(item)),
+            serde_json::Value::Object(map) => map.values().for_each(|item| self.add_value(item)),
+            _ => {}
         }
+    }
+}
 
-        None
+/// 流式响应的输出 token 计数器
+///
+/// 逐个 SSE 事件累计增量文本（正文、推理、工具调用参数）的 token 数；
+/// 事件中携带 usage 时以二者的较大值为准。
+pub struct SseOutputCounter {
+    tokenizer: Option<Arc<Tokenizer>>,
+    model: Option<String>,
+    counted: u64,
+    reported: u64,
+}
+
+impl SseOutputCounter {
+    pub fn new(tokenizer: Option<Arc<Tokenizer>>, model: Option<String>) -> Self {
+        Self {
+            tokenizer,
+            model,
+            counted: 0,
+            reported: 0,
+        }
+    }
+
+    /// 处理一个完整的 SSE 事件
+    pub fn observe_event(&mut self, event: &[u8]) {
+        let text = String::from_utf8_lossy(event);
+        for line in text.lines() {
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                continue;
+            }
+            let Ok(json) = serde_json::from_str::<serde_json::Value>(data) else {
+                continue;
+            };
+
+            // Responses API 的 usage 位于 response 对象内
+            if let Some(usage) = json
+                .get("usage")
+                .or_else(|| json.get("response").and_then(|response| response.get("usage")))
+                .and_then(TokenExtractor::parse_usage)
+            {
+                self.reported = self.reported.max(usage.output_tokens);
+            }
+
+            let mut delta = String::new();
+            collect_delta_text(&json, &mut delta);
+            if !delta.is_empty() {
+                self.counted += self.count(&delta);
+            }
+        }
+    }
+
+    /// 当前已输出的 token 数
+    pub fn output_tokens(&self) -> u64 {
+        self.counted.max(self.reported)
+    }
+
+    fn count(&self, text: &str) -> u64 {
+        if let Some(tokens) = self
+            .tokenizer
+            .as_deref()
+            .and_then(|tokenizer| tokenizer.count_text(self.model.as_deref(), text))
+        {
+            return tokens;
+        }
+        let mut counter = TextCounter::default();
+        counter.add_value(&serde_json::Value::String(text.to_string()));
+        counter.ascii_chars.div_ceil(4) + counter.other_chars
+    }
+}
+
+/// 提取单个流式事件中的增量文本，兼容 OpenAI Chat Completions、OpenAI Responses 与 Claude 格式
+fn collect_delta_text(json: &serde_json::Value, out: &mut String) {
+    let mut push = |value: Option<&serde_json::Value>| {
+        if let Some(text) = value.and_then(|v| v.as_str()) {
+            out.push_str(text);
+        }
+    };
+
+    // OpenAI Chat Completions / Completions
+    if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
+        for choice in choices {
+            push(choice.get("text"));
+            let Some(delta) = choice.get("delta") else {
+                continue;
+            };
+            for field in ["content", "reasoning_content", "reasoning"] {
+                push(delta.get(field));
+            }
+            if let Some(tool_calls) = delta.get("tool_calls").and_then(|t| t.as_array()) {
+                for call in tool_calls {
+                    push(call.get("function").and_then(|f| f.get("arguments")));
+                }
+            }
+        }
+        return;
+    }
+
+    match json.get("type").and_then(|t| t.as_str()) {
+        // Claude
+        Some("content_block_delta") => {
+            if let Some(delta) = json.get("delta") {
+                for field in ["text", "partial_json", "thinking"] {
+                    push(delta.get(field));
+                }
+            }
+        }
+        // OpenAI Responses API：response.output_text.delta、response.function_call_arguments.delta 等
+        Some(event_type) if event_type.ends_with(".delta") => push(json.get("delta")),
+        _ => {}
     }
 }
 
@@ -396,6 +509,28 @@ data: [DONE]
         assert_eq!(usage.total_tokens, 15);
     }
 
+    #[test]
+    fn test_parse_usage_details() {
+        let openai: serde_json::Value = serde_json::from_str(
+            r#"{"prompt_tokens":100,"completion_tokens":50,"total_tokens":150,
+                "prompt_tokens_details":{"cached_tokens":40},
+                "completion_tokens_details":{"reasoning_tokens":30}}"#,
+        )
+        .unwrap();
+        let usage = TokenExtractor::parse_usage(&openai).unwrap();
+        assert_eq!(usage.cached_input_tokens, 40);
+        assert_eq!(usage.reasoning_tokens, 30);
+
+        let claude: serde_json::Value = serde_json::from_str(
+            r#"{"input_tokens":10,"output_tokens":20,"cache_read_input_tokens":90}"#,
+        )
+        .unwrap();
+        let usage = TokenExtractor::parse_usage(&claude).unwrap();
+        assert_eq!(usage.input_tokens, 100);
+        assert_eq!(usage.cached_input_tokens, 90);
+        assert_eq!(usage.total_tokens, 120);
+    }
+
     #[test]
     fn test_no_usage_field() {
         let json = r#"{"id": "test", "choices": []}"#;
@@ -410,4 +545,36 @@ data: [DONE]
         let usage = TokenExtractor::extract_from_body(&body);
         assert!(usage.is_none());
     }
+
+    #[test]
+    fn test_estimate_input_tokens() {
+        let body = r#"{"model":"gpt-4o","max_tokens":100,"messages":[{"role":"user","content":"abcdefgh"},{"role":"user","content":"你好"}]}"#;
+        // "user" + "abcdefgh" + "user" = 16 个 ASCII 字符 -> 4，"你好" -> 2
+        assert_eq!(TokenExtractor::estimate_input_tokens(body.as_bytes()), 6);
+
+        assert_eq!(TokenExtractor::estimate_input_tokens(b"plain text body"), 4);
+    }
+
+    #[test]
+    fn test_sse_output_counter() {
+        let mut counter = SseOutputCounter::new(None, None);
+        counter.observe_event(br#"data: {"choices":[{"index":0,"delta":{"content":"abcdefgh"}}]}"#);
+        counter.observe_event(
+            br#"data: {"choices":[{"index":0,"delta":{"tool_calls":[{"function":{"arguments":"{}"}}]}}]}"#,
+        );
+        counter.observe_event(b"data: [DONE]");
+        // "abcdefgh" -> 2，"{}" -> 1
+        assert_eq!(counter.output_tokens(), 3);
+
+        let mut counter = SseOutputCounter::new(None, None);
+        counter.observe_event(
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"你好\"}}".as_bytes(),
+        );
+        counter.observe_event(br#"data: {"type":"response.output_text.delta","delta":"abcd"}"#);
+        assert_eq!(counter.output_tokens(), 3);
+
+        // 上游 usage 更大时以 usage 为准
+        counter.observe_event(br#"data: {"type":"message_delta","usage":{"output_tokens":42}}"#);
+        assert_eq!(counter.output_tokens(), 42);
+    }
 }
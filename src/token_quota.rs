@@ -1,7 +1,10 @@
-use crate::config::TokenQuotaConfig;
+use crate::budget::BudgetManager;
+use crate::config::{QuotaWindowMode, ResolvedApiKey, RouteConfig, TokenQuotaConfig, TokenRateLimitConfig};
+use chrono::{DateTime, Datelike};
 use dashmap::DashMap;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Token配额管理器
@@ -10,32 +13,58 @@ pub struct TokenQuotaManager {
     quotas: DashMap<String, TokenQuotaConfig>,
     /// api_key_id -> 实时使用统计
     usage_stats: DashMap<String, TokenUsageWindow>,
+    /// route_id -> 实时使用统计（用于路由 TPM/TPD）
+    route_usage_stats: DashMap<String, TokenUsageWindow>,
+    /// api_key_id -> TPM/TPD 限制
+    key_token_rate_limits: DashMap<String, TokenRateLimitConfig>,
+    /// route_id -> TPM/TPD 限制
+    route_token_rate_limits: DashMap<String, TokenRateLimitConfig>,
+    /// 费用预算
+    budget: BudgetManager,
+    /// 是否与共享状态后端同步 API Key 用量
+    usage_sync: AtomicBool,
 }
 
 /// Token使用时间窗口统计（内存中）
 pub struct TokenUsageWindow {
-    /// 小时级统计（最近24小时）
+    /// 分钟级统计（最近60分钟）
+    minute_stats: VecDeque<MinuteTokenStat>,
+    /// 小时级统计（最近 [`USAGE_RETENTION_HOURS`] 小时，按时间升序）
     hourly_stats: VecDeque<HourlyTokenStat>,
-    /// 天级统计（最近7天）
-    daily_stats: VecDeque<DailyTokenStat>,
+    /// 进行中请求预占的token（按预估输入计算，用于 TPM/TPD）
+    pending_tokens: u64,
+    /// 进行中请求预留的配额（输入 / 输出），请求结束后按实际用量结算
+    reserved_input: u64,
+    reserved_output: u64,
+    /// 持有配额预留的进行中请求数
+    reservations: u64,
+    /// 尚未推送到共享状态后端的本实例用量（小时 -> (输入, 输出)）
+    unsynced: BTreeMap<u64, (u64, u64)>,
+    /// 已推送到共享状态后端的本实例用量
+    synced: BTreeMap<u64, (u64, u64)>,
+    /// 其他实例的用量（共享后端合计减去本实例已推送部分）
+    shared: BTreeMap<u64, (u64, u64)>,
 }
 
-/// 小时级Token统计
+/// 分钟级Token统计
 #[derive(Debug, Clone, Copy)]
-pub struct HourlyTokenStat {
-    pub hour_epoch: u64, // Unix时间戳，小时级（整点）
+pub struct MinuteTokenStat {
+    pub minute_epoch: u64, // Unix时间戳，分钟级（整分）
     pub input_tokens: u64,
     pub output_tokens: u64,
 }
 
-/// 天级Token统计
+/// 小时级Token统计
 #[derive(Debug, Clone, Copy)]
-pub struct DailyTokenStat {
-    pub day_epoch: u64, // Unix时间戳，日期级（00:00:00）
+pub struct HourlyTokenStat {
+    pub hour_epoch: u64, // Unix时间戳，小时级（整点）
     pub input_tokens: u64,
     pub output_tokens: u64,
 }
 
+/// 内存中小时级用量的保留时长，覆盖任意时区下的自然月与滚动窗口
+pub const USAGE_RETENTION_HOURS: u64 = 40 * 24;
+
 /// 配额检查结果
 #[derive(Debug, Clone)]
 pub struct CheckQuotaResult {
@@ -58,14 +87,152 @@ pub struct CheckQuotaResult {
     pub weekly_remaining_input: Option<u64>,
     pub weekly_remaining_output: Option<u64>,
     pub weekly_remaining_total: Option<u64>,
+    pub monthly_used_input: u64,
+    pub monthly_used_output: u64,
+    pub monthly_used_total: u64,
+    pub monthly_limit_input: Option<u64>,
+    pub monthly_limit_output: Option<u64>,
+    pub monthly_limit_total: Option<u64>,
+    pub monthly_remaining_input: Option<u64>,
+    pub monthly_remaining_output: Option<u64>,
+    pub monthly_remaining_total: Option<u64>,
+    /// 滚动 N 小时窗口
+    pub rolling: Vec<RollingQuotaStatus>,
+    /// 进行中请求预留的输入 / 输出 token
+    pub reserved_input_tokens: u64,
+    pub reserved_output_tokens: u64,
+    /// 持有配额预留的进行中请求数
+    pub inflight_reservations: u64,
+    /// 本次请求的预估输入 token（未预估时为 0）
+    pub estimated_input_tokens: u64,
+    /// 本次请求预留的输出 token（仅预留时非 0）
+    pub estimated_output_tokens: u64,
     pub reason: Option<String>,
 }
 
+/// 滚动 N 小时窗口的用量与上限
+#[derive(Debug, Clone)]
+pub struct RollingQuotaStatus {
+    pub hours: u32,
+    pub used_input: u64,
+    pub used_output: u64,
+    pub used_total: u64,
+    pub limit_input: Option<u64>,
+    pub limit_output: Option<u64>,
+    pub limit_total: Option<u64>,
+}
+
+impl CheckQuotaResult {
+    /// 无配额限制时的默认结果
+    pub fn unlimited() -> Self {
+        Self {
+            allowed: true,
+            daily_used_input: 0,
+            daily_used_output: 0,
+            daily_used_total: 0,
+            daily_limit_input: None,
+            daily_limit_output: None,
+            daily_limit_total: None,
+            daily_remaining_input: None,
+            daily_remaining_output: None,
+            daily_remaining_total: None,
+            weekly_used_input: 0,
+            weekly_used_output: 0,
+            weekly_used_total: 0,
+            weekly_limit_input: None,
+            weekly_limit_output: None,
+            weekly_limit_total: None,
+            weekly_remaining_input: None,
+            weekly_remaining_output: None,
+            weekly_remaining_total: None,
+            monthly_used_input: 0,
+            monthly_used_output: 0,
+            monthly_used_total: 0,
+            monthly_limit_input: None,
+            monthly_limit_output: None,
+            monthly_limit_total: None,
+            monthly_remaining_input: None,
+            monthly_remaining_output: None,
+            monthly_remaining_total: None,
+            rolling: Vec::new(),
+            reserved_input_tokens: 0,
+            reserved_output_tokens: 0,
+            inflight_reservations: 0,
+            estimated_input_tokens: 0,
+            estimated_output_tokens: 0,
+            reason: None,
+        }
+    }
+
+    /// 返回首个超限的配额维度：(类型, 上限, 已用)
+    ///
+    /// 已用达到上限，或已用加上进行中请求的预留与本次预估超过上限，均视为超限。
+    pub fn exceeded_quota(&self) -> Option<(&'static str, u64, u64)> {
+        let incoming_input = self.estimated_input_tokens + self.reserved_input_tokens;
+        let incoming_output = self.estimated_output_tokens + self.reserved_output_tokens;
+        let incoming_total = incoming_input + incoming_output;
+        let checks = [
+            ("daily_total", self.daily_limit_total, self.daily_used_total, incoming_total),
+            ("daily_input", self.daily_limit_input, self.daily_used_input, incoming_input),
+            ("daily_output", self.daily_limit_output, self.daily_used_output, incoming_output),
+            ("weekly_total", self.weekly_limit_total, self.weekly_used_total, incoming_total),
+            ("weekly_input", self.weekly_limit_input, self.weekly_used_input, incoming_input),
+            ("weekly_output", self.weekly_limit_output, self.weekly_used_output, incoming_output),
+            ("monthly_total", self.monthly_limit_total, self.monthly_used_total, incoming_total),
+            ("monthly_input", self.monthly_limit_input, self.monthly_used_input, incoming_input),
+            ("monthly_output", self.monthly_limit_output, self.monthly_used_output, incoming_output),
+        ];
+        let rolling_checks = self.rolling.iter().flat_map(|window| {
+            [
+                ("rolling_total", window.limit_total, window.used_total, incoming_total),
+                ("rolling_input", window.limit_input, window.used_input, incoming_input),
+                ("rolling_output", window.limit_output, window.used_output, incoming_output),
+            ]
+        });
+        checks.into_iter().chain(rolling_checks).find_map(|(quota_type, limit, used, incoming)| {
+            let limit = limit?;
+            (used >= limit || used.saturating_add(incoming) > limit).then_some((quota_type, limit, used))
+        })
+    }
+
+    /// 扣除预估输入与进行中预留后，本次请求还能产生的输出 token 上限（无相关限制时为 None）
+    pub fn output_budget(&self) -> Option<u64> {
+        let consumed_total =
+            self.estimated_input_tokens + self.reserved_input_tokens + self.reserved_output_tokens;
+        let consumed_output = self.reserved_output_tokens;
+        let remaining = |limit: Option<u64>, used: u64, consumed: u64| {
+            limit.map(|limit| limit.saturating_sub(used).saturating_sub(consumed))
+        };
+        [
+            remaining(self.daily_limit_total, self.daily_used_total, consumed_total),
+            remaining(self.daily_limit_output, self.daily_used_output, consumed_output),
+            remaining(self.weekly_limit_total, self.weekly_used_total, consumed_total),
+            remaining(self.weekly_limit_output, self.weekly_used_output, consumed_output),
+            remaining(self.monthly_limit_total, self.monthly_used_total, consumed_total),
+            remaining(self.monthly_limit_output, self.monthly_used_output, consumed_output),
+        ]
+        .into_iter()
+        .chain(self.rolling.iter().flat_map(|window| {
+            [
+                remaining(window.limit_total, window.used_total, consumed_total),
+                remaining(window.limit_output, window.used_output, consumed_output),
+            ]
+        }))
+        .flatten()
+        .min()
+    }
+}
+
 impl TokenQuotaManager {
     pub fn new() -> Self {
         Self {
             quotas: DashMap::new(),
             usage_stats: DashMap::new(),
+            route_usage_stats: DashMap::new(),
+            key_token_rate_limits: DashMap::new(),
+            route_token_rate_limits: DashMap::new(),
+            budget: BudgetManager::new(),
+            usage_sync: AtomicBool::new(false),
         }
     }
 
@@ -78,6 +245,18 @@ impl TokenQuotaManager {
         }
     }
 
+    /// 设置或移除单个 API Key 的配额配置（已记录的用量保留）
+    pub fn set_quota(&self, api_key_id: &str, quota: Option<TokenQuotaConfig>) {
+        match quota {
+            Some(quota) => {
+                self.quotas.insert(api_key_id.to_string(), quota);
+            }
+            None => {
+                self.quotas.remove(api_key_id);
+            }
+        }
+    }
+
     /// 获取API Key的配额配置
     pub fn get_quota(&self, api_key_id: &str) -> Option<TokenQuotaConfig> {
         self.quotas.get(api_key_id).map(|q| q.clone())
@@ -85,238 +264,269 @@ impl TokenQuotaManager {
 
     /// 获取或创建使用统计窗口
     fn get_or_create_usage_window(&self, api_key_id: &str) -> dashmap::mapref::one::RefMut<'_, String, TokenUsageWindow> {
-        self.usage_stats.entry(api_key_id.to_string()).or_insert_with(|| TokenUsageWindow {
-            hourly_stats: VecDeque::with_capacity(24),
-            daily_stats: VecDeque::with_capacity(7),
-        })
+        self.usage_stats
+            .entry(api_key_id.to_string())
+            .or_insert_with(TokenUsageWindow::new)
     }
 
     /// 记录token使用（在请求完成后调用）
     pub fn record_usage(&self, api_key_id: &str, input_tokens: u64, output_tokens: u64) {
         let now = current_epoch_seconds();
-        let hour_epoch = truncate_to_hour(now);
-        let day_epoch = truncate_to_day(now);
-
         let mut window = self.get_or_create_usage_window(api_key_id);
+        window.record(now, input_tokens, output_tokens);
+        if self.usage_sync.load(Ordering::Relaxed) {
+            add_hourly(&mut window.unsynced, truncate_to_hour(now), input_tokens, output_tokens);
+        }
+    }
 
-        // 更新小时级统计
-        if let Some(hourly) = window.hourly_stats.back_mut() {
-            if hourly.hour_epoch == hour_epoch {
-                hourly.input_tokens += input_tokens;
-                hourly.output_tokens += output_tokens;
-            } else {
-                // 新的小时，添加新记录
-                window.hourly_stats.push_back(HourlyTokenStat {
+    /// 开启或关闭与共享状态后端的用量同步
+    pub fn set_usage_sync(&self, enabled: bool) {
+        self.usage_sync.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 取出尚未推送的本实例用量，并视为已推送
+    pub fn take_unsynced_usage(&self) -> Vec<(String, Vec<HourlyTokenStat>)> {
+        let mut result = Vec::new();
+        for mut entry in self.usage_stats.iter_mut() {
+            if entry.unsynced.is_empty() {
+                continue;
+            }
+            let window = entry.value_mut();
+            let unsynced = std::mem::take(&mut window.unsynced);
+            let mut usage = Vec::with_capacity(unsynced.len());
+            for (hour_epoch, (input_tokens, output_tokens)) in unsynced {
+                add_hourly(&mut window.synced, hour_epoch, input_tokens, output_tokens);
+                usage.push(HourlyTokenStat {
                     hour_epoch,
                     input_tokens,
                     output_tokens,
                 });
-                // 清理过期数据（保留24小时）
-                while window.hourly_stats.len() > 24 {
-                    window.hourly_stats.pop_front();
-                }
             }
-        } else {
-            window.hourly_stats.push_back(HourlyTokenStat {
-                hour_epoch,
-                input_tokens,
-                output_tokens,
-            });
+            result.push((entry.key().clone(), usage));
         }
+        result
+    }
 
-        // 更新天级统计
-        if let Some(daily) = window.daily_stats.back_mut() {
-            if daily.day_epoch == day_epoch {
-                daily.input_tokens += input_tokens;
-                daily.output_tokens += output_tokens;
-            } else {
-                // 新的一天，添加新记录
-                window.daily_stats.push_back(DailyTokenStat {
-                    day_epoch,
-                    input_tokens,
-                    output_tokens,
-                });
-                // 清理过期数据（保留7天）
-                while window.daily_stats.len() > 7 {
-                    window.daily_stats.pop_front();
-                }
+    /// 推送失败时退回用量，下次同步重试
+    pub fn requeue_unsynced_usage(&self, api_key_id: &str, usage: &[HourlyTokenStat]) {
+        let mut window = self.get_or_create_usage_window(api_key_id);
+        for stat in usage {
+            if let Some((input, output)) = window.synced.get_mut(&stat.hour_epoch) {
+                *input = input.saturating_sub(stat.input_tokens);
+                *output = output.saturating_sub(stat.output_tokens);
             }
-        } else {
-            window.daily_stats.push_back(DailyTokenStat {
-                day_epoch,
-                input_tokens,
-                output_tokens,
-            });
+            add_hourly(&mut window.unsynced, stat.hour_epoch, stat.input_tokens, stat.output_tokens);
         }
     }
 
-    /// 检查配额状态
-    pub fn check_quota(&self, api_key_id: &str) -> CheckQuotaResult {
-        let quota = self.get_quota(api_key_id);
+    /// 需要从共享状态后端拉取用量的 API Key（配置了配额或 TPD）
+    pub fn shared_usage_key_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .quotas
+            .iter()
+            .map(|entry| entry.key().clone())
+            .chain(self.key_token_rate_limits.iter().map(|entry| entry.key().clone()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
 
-        // 计算当前使用量
-        let (daily_input, daily_output, daily_total) = self.calculate_daily_usage(api_key_id);
-        let (weekly_input, weekly_output, weekly_total) = self.calculate_weekly_usage(api_key_id);
-
-        let daily_limit_input = quota.as_ref().and_then(|q| q.daily_input_limit);
-        let daily_limit_output = quota.as_ref().and_then(|q| q.daily_output_limit);
-        let daily_limit_total = quota.as_ref().and_then(|q| q.daily_total_limit);
-        let weekly_limit_input = quota.as_ref().and_then(|q| q.weekly_input_limit);
-        let weekly_limit_output = quota.as_ref().and_then(|q| q.weekly_output_limit);
-        let weekly_limit_total = quota.as_ref().and_then(|q| q.weekly_total_limit);
-
-        // 计算剩余量
-        let daily_remaining_input = daily_limit_input.map(|limit| limit.saturating_sub(daily_input));
-        let daily_remaining_output = daily_limit_output.map(|limit| limit.saturating_sub(daily_output));
-        let daily_remaining_total = daily_limit_total.map(|limit| limit.saturating_sub(daily_total));
-        let weekly_remaining_input = weekly_limit_input.map(|limit| limit.saturating_sub(weekly_input));
-        let weekly_remaining_output = weekly_limit_output.map(|limit| limit.saturating_sub(weekly_output));
-        let weekly_remaining_total = weekly_limit_total.map(|limit| limit.saturating_sub(weekly_total));
-
-        // 检查是否超出限制
-        let mut exceeded = false;
-        let mut reason = None;
-
-        if let Some(limit) = daily_limit_total {
-            if daily_total >= limit {
-                exceeded = true;
-                reason = Some(format!("Daily total token limit exceeded: {}/{} tokens", daily_total, limit));
+    /// 应用共享状态后端中的集群用量，扣除本实例已推送部分后作为其他实例的用量
+    pub fn apply_shared_usage(&self, api_key_id: &str, usage: &[HourlyTokenStat]) {
+        let mut window = self.get_or_create_usage_window(api_key_id);
+        let mut shared = BTreeMap::new();
+        for stat in usage {
+            let (synced_input, synced_output) = window.synced.get(&stat.hour_epoch).copied().unwrap_or_default();
+            let input = stat.input_tokens.saturating_sub(synced_input);
+            let output = stat.output_tokens.saturating_sub(synced_output);
+            if input > 0 || output > 0 {
+                shared.insert(stat.hour_epoch, (input, output));
             }
         }
+        window.shared = shared;
+    }
 
-        if let Some(limit) = daily_limit_input {
-            if daily_input >= limit && !exceeded {
-                exceeded = true;
-                reason = Some(format!("Daily input token limit exceeded: {}/{} tokens", daily_input, limit));
-            }
-        }
+    /// 记录路由级token使用（用于路由 TPM/TPD）
+    pub fn record_route_usage(&self, route_id: &str, input_tokens: u64, output_tokens: u64) {
+        let now = current_epoch_seconds();
+        self.route_usage_stats
+            .entry(route_id.to_string())
+            .or_insert_with(TokenUsageWindow::new)
+            .record(now, input_tokens, output_tokens);
+    }
 
-        if let Some(limit) = daily_limit_output {
-            if daily_output >= limit && !exceeded {
-                exceeded = true;
-                reason = Some(format!("Daily output token limit exceeded: {}/{} tokens", daily_output, limit));
+    /// 按最新配置替换 API Key 与路由的 TPM/TPD 限制
+    pub fn set_token_rate_limits(&self, keys: &[ResolvedApiKey], routes: &[RouteConfig]) {
+        self.key_token_rate_limits.clear();
+        for key in keys {
+            if let Some(limit) = &key.token_rate_limit {
+                self.key_token_rate_limits.insert(key.id.clone(), limit.clone());
             }
         }
-
-        if let Some(limit) = weekly_limit_total {
-            if weekly_total >= limit && !exceeded {
-                exceeded = true;
-                reason = Some(format!("Weekly total token limit exceeded: {}/{} tokens", weekly_total, limit));
+        self.route_token_rate_limits.clear();
+        for route in routes {
+            if let Some(limit) = &route.token_rate_limit {
+                self.route_token_rate_limits.insert(route.id.clone(), limit.clone());
             }
         }
+    }
 
-        if let Some(limit) = weekly_limit_input {
-            if weekly_input >= limit && !exceeded {
-                exceeded = true;
-                reason = Some(format!("Weekly input token limit exceeded: {}/{} tokens", weekly_input, limit));
+    /// 费用预算管理器
+    pub fn budget(&self) -> &BudgetManager {
+        &self.budget
+    }
+
+    /// 该请求是否需要做 TPM/TPD 准入
+    pub fn has_token_rate_limit(&self, api_key_id: &str, route_id: &str) -> bool {
+        self.key_token_rate_limits.contains_key(api_key_id)
+            || self.route_token_rate_limits.contains_key(route_id)
+    }
+
+    /// 按预估输入token做 TPM/TPD 准入，通过后预占额度直到请求结束
+    ///
+    /// Key 与路由窗口各自在同一把锁内完成检查与预占，并发请求不会同时越过上限；
+    /// 路由超限时退回已预占的 Key 额度。预占额度在返回的 [`TokenRateReservation`]
+    /// 释放时归还，实际用量由 `record_usage` 计入。
+    pub fn reserve_tokens(
+        self: &Arc<Self>,
+        api_key_id: &str,
+        route_id: &str,
+        estimated_tokens: u64,
+    ) -> Result<TokenRateReservation, TokenRateLimitExceeded> {
+        let now = current_epoch_seconds();
+        let mut remaining_tokens: Option<u64> = None;
+
+        {
+            let limit = self.key_token_rate_limits.get(api_key_id).map(|limit| limit.clone());
+            let mut window = self.get_or_create_usage_window(api_key_id);
+            if let Some(limit) = &limit {
+                let remaining =
+                    check_token_rate_limit(TokenRateScope::ApiKey, limit, Some(&window), now, estimated_tokens)?;
+                remaining_tokens = min_option(remaining_tokens, remaining);
             }
+            window.pending_tokens += estimated_tokens;
         }
-
-        if let Some(limit) = weekly_limit_output {
-            if weekly_output >= limit && !exceeded {
-                exceeded = true;
-                reason = Some(format!("Weekly output token limit exceeded: {}/{} tokens", weekly_output, limit));
+        {
+            let limit = self.route_token_rate_limits.get(route_id).map(|limit| limit.clone());
+            let mut window = self
+                .route_usage_stats
+                .entry(route_id.to_string())
+                .or_insert_with(TokenUsageWindow::new);
+            if let Some(limit) = &limit {
+                match check_token_rate_limit(TokenRateScope::Route, limit, Some(&window), now, estimated_tokens) {
+                    Ok(remaining) => remaining_tokens = min_option(remaining_tokens, remaining),
+                    Err(exceeded) => {
+                        drop(window);
+                        if let Some(mut window) = self.usage_stats.get_mut(api_key_id) {
+                            window.pending_tokens = window.pending_tokens.saturating_sub(estimated_tokens);
+                        }
+                        return Err(exceeded);
+                    }
+                }
             }
+            window.pending_tokens += estimated_tokens;
         }
 
-        CheckQuotaResult {
-            allowed: !exceeded,
-            daily_used_input: daily_input,
-            daily_used_output: daily_output,
-            daily_used_total: daily_total,
-            daily_limit_input,
-            daily_limit_output,
-            daily_limit_total,
-            daily_remaining_input,
-            daily_remaining_output,
-            daily_remaining_total,
-            weekly_used_input: weekly_input,
-            weekly_used_output: weekly_output,
-            weekly_used_total: weekly_total,
-            weekly_limit_input,
-            weekly_limit_output,
-            weekly_limit_total,
-            weekly_remaining_input,
-            weekly_remaining_output,
-            weekly_remaining_total,
-            reason,
+        Ok(TokenRateReservation {
+            manager: Arc::clone(self),
+            api_key_id: api_key_id.to_string(),
+            route_id: route_id.to_string(),
+            tokens: estimated_tokens,
+            remaining_tokens,
+        })
+    }
+
+    /// 归还预占额度
+    fn release_reserved_tokens(&self, api_key_id: &str, route_id: &str, tokens: u64) {
+        if let Some(mut window) = self.usage_stats.get_mut(api_key_id) {
+            window.pending_tokens = window.pending_tokens.saturating_sub(tokens);
+        }
+        if let Some(mut window) = self.route_usage_stats.get_mut(route_id) {
+            window.pending_tokens = window.pending_tokens.saturating_sub(tokens);
         }
     }
 
-    /// 计算当日使用量（从内存统计）
-    fn calculate_daily_usage(&self, api_key_id: &str) -> (u64, u64, u64) {
-        let now = current_epoch_seconds();
-        let day_start = truncate_to_day(now);
-
-        if let Some(window) = self.usage_stats.get(api_key_id) {
-            let input: u64 = window.hourly_stats
-                .iter()
-                .filter(|h| h.hour_epoch >= day_start)
-                .map(|h| h.input_tokens)
-                .sum();
-            let output: u64 = window.hourly_stats
-                .iter()
-                .filter(|h| h.hour_epoch >= day_start)
-                .map(|h| h.output_tokens)
-                .sum();
-            (input, output, input + output)
-        } else {
-            (0, 0, 0)
+    /// 检查配额状态
+    pub fn check_quota(&self, api_key_id: &str) -> CheckQuotaResult {
+        self.check_quota_with_estimate(api_key_id, 0)
+    }
+
+    /// 检查配额状态，并把本次请求的预估输入 token 计入准入判断
+    pub fn check_quota_with_estimate(&self, api_key_id: &str, estimated_input_tokens: u64) -> CheckQuotaResult {
+        let quota = self.get_quota(api_key_id);
+        let window = self.usage_stats.get(api_key_id);
+        evaluate_quota(
+            quota.as_ref(),
+            window.as_deref(),
+            current_epoch_seconds(),
+            estimated_input_tokens,
+            0,
+        )
+    }
+
+    /// 配额准入并预留本次请求的预估输入与输出 token，直到返回的 [`QuotaReservation`] 释放
+    ///
+    /// 检查与预留在同一把锁内完成，并发请求不会同时越过上限；超限时返回配额状态。
+    pub fn reserve_quota(
+        self: &Arc<Self>,
+        api_key_id: &str,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) -> Result<QuotaReservation, Box<CheckQuotaResult>> {
+        let quota = self.get_quota(api_key_id);
+        let mut window = self.get_or_create_usage_window(api_key_id);
+        let result = evaluate_quota(
+            quota.as_ref(),
+            Some(&window),
+            current_epoch_seconds(),
+            input_tokens,
+            output_tokens,
+        );
+        if !result.allowed {
+            return Err(Box::new(result));
         }
+        window.reserved_input += input_tokens;
+        window.reserved_output += output_tokens;
+        window.reservations += 1;
+
+        Ok(QuotaReservation {
+            manager: Arc::clone(self),
+            api_key_id: api_key_id.to_string(),
+            input_tokens,
+            output_tokens,
+        })
     }
 
-    /// 计算当周使用量（从内存统计）
-    fn calculate_weekly_usage(&self, api_key_id: &str) -> (u64, u64, u64) {
-        let now = current_epoch_seconds();
-        let week_start = truncate_to_day(now - 6 * 86400); // 7天前
-
-        if let Some(window) = self.usage_stats.get(api_key_id) {
-            let input: u64 = window.daily_stats
-                .iter()
-                .filter(|d| d.day_epoch >= week_start)
-                .map(|d| d.input_tokens)
-                .sum();
-            let output: u64 = window.daily_stats
-                .iter()
-                .filter(|d| d.day_epoch >= week_start)
-                .map(|d| d.output_tokens)
-                .sum();
-            (input, output, input + output)
-        } else {
-            (0, 0, 0)
+    /// 释放配额预留
+    fn release_quota(&self, api_key_id: &str, input_tokens: u64, output_tokens: u64) {
+        if let Some(mut window) = self.usage_stats.get_mut(api_key_id) {
+            window.reserved_input = window.reserved_input.saturating_sub(input_tokens);
+            window.reserved_output = window.reserved_output.saturating_sub(output_tokens);
+            window.reservations = window.reservations.saturating_sub(1);
         }
     }
 
+    /// 合并一条从SQLite恢复的小时级用量（启动时调用）
+    pub fn restore_usage(&self, api_key_id: &str, hour_epoch: u64, input_tokens: u64, output_tokens: u64) {
+        self.get_or_create_usage_window(api_key_id)
+            .restore(hour_epoch, input_tokens, output_tokens);
+    }
+
+    /// 合并一条从SQLite恢复的路由小时级用量（启动时调用）
+    pub fn restore_route_usage(&self, route_id: &str, hour_epoch: u64, input_tokens: u64, output_tokens: u64) {
+        self.route_usage_stats
+            .entry(route_id.to_string())
+            .or_insert_with(TokenUsageWindow::new)
+            .restore(hour_epoch, input_tokens, output_tokens);
+    }
+
     /// 获取API Key的统计摘要
     pub fn get_stats_summary(&self, api_key_id: &str) -> Option<TokenStatsSummary> {
         let window = self.usage_stats.get(api_key_id)?;
-        let now = current_epoch_seconds();
-        let day_start = truncate_to_day(now);
-        let week_start = truncate_to_day(now - 6 * 86400);
-
-        // 今日统计
-        let today_input: u64 = window.hourly_stats
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.input_tokens)
-            .sum();
-        let today_output: u64 = window.hourly_stats
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.output_tokens)
-            .sum();
-
-        // 本周统计
-        let week_input: u64 = window.daily_stats
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.input_tokens)
-            .sum();
-        let week_output: u64 = window.daily_stats
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.output_tokens)
-            .sum();
+        let starts = QuotaWindowStarts::new(self.get_quota(api_key_id).as_ref(), current_epoch_seconds());
+        let (today_input, today_output) = window.sum_since(starts.day);
+        let (week_input, week_output) = window.sum_since(starts.week);
 
         Some(TokenStatsSummary {
             today_input,
@@ -327,6 +537,424 @@ impl TokenQuotaManager {
     }
 }
 
+/// 按配额配置与当前用量窗口计算配额状态
+fn evaluate_quota(
+    quota: Option<&TokenQuotaConfig>,
+    window: Option<&TokenUsageWindow>,
+    now: u64,
+    estimated_input_tokens: u64,
+    estimated_output_tokens: u64,
+) -> CheckQuotaResult {
+    let starts = QuotaWindowStarts::new(quota, now);
+    let usage_since = |start: u64| {
+        let (input, output) = window.map_or((0, 0), |w| w.sum_since(start));
+        (input, output, input + output)
+    };
+
+    // 计算当前使用量
+    let (daily_input, daily_output, daily_total) = usage_since(starts.day);
+    let (weekly_input, weekly_output, weekly_total) = usage_since(starts.week);
+    let (monthly_input, monthly_output, monthly_total) = usage_since(starts.month);
+
+    let daily_limit_input = quota.and_then(|q| q.daily_input_limit);
+    let daily_limit_output = quota.and_then(|q| q.daily_output_limit);
+    let daily_limit_total = quota.and_then(|q| q.daily_total_limit);
+    let weekly_limit_input = quota.and_then(|q| q.weekly_input_limit);
+    let weekly_limit_output = quota.and_then(|q| q.weekly_output_limit);
+    let weekly_limit_total = quota.and_then(|q| q.weekly_total_limit);
+    let monthly_limit_input = quota.and_then(|q| q.monthly_input_limit);
+    let monthly_limit_output = quota.and_then(|q| q.monthly_output_limit);
+    let monthly_limit_total = quota.and_then(|q| q.monthly_total_limit);
+
+    // 计算剩余量
+    let daily_remaining_input = daily_limit_input.map(|limit| limit.saturating_sub(daily_input));
+    let daily_remaining_output = daily_limit_output.map(|limit| limit.saturating_sub(daily_output));
+    let daily_remaining_total = daily_limit_total.map(|limit| limit.saturating_sub(daily_total));
+    let weekly_remaining_input = weekly_limit_input.map(|limit| limit.saturating_sub(weekly_input));
+    let weekly_remaining_output = weekly_limit_output.map(|limit| limit.saturating_sub(weekly_output));
+    let weekly_remaining_total = weekly_limit_total.map(|limit| limit.saturating_sub(weekly_total));
+    let monthly_remaining_input = monthly_limit_input.map(|limit| limit.saturating_sub(monthly_input));
+    let monthly_remaining_output = monthly_limit_output.map(|limit| limit.saturating_sub(monthly_output));
+    let monthly_remaining_total = monthly_limit_total.map(|limit| limit.saturating_sub(monthly_total));
+
+    // 滚动 N 小时窗口
+    let rolling = quota
+        .map(|q| q.rolling_windows.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|window| {
+            let start = truncate_to_hour(now).saturating_sub((u64::from(window.hours) - 1) * 3600);
+            let (used_input, used_output, used_total) = usage_since(start);
+            RollingQuotaStatus {
+                hours: window.hours,
+                used_input,
+                used_output,
+                used_total,
+                limit_input: window.input_limit,
+                limit_output: window.output_limit,
+                limit_total: window.total_limit,
+            }
+        })
+        .collect();
+
+    let mut result = CheckQuotaResult {
+        allowed: true,
+        daily_used_input: daily_input,
+        daily_used_output: daily_output,
+        daily_used_total: daily_total,
+        daily_limit_input,
+        daily_limit_output,
+        daily_limit_total,
+        daily_remaining_input,
+        daily_remaining_output,
+        daily_remaining_total,
+        weekly_used_input: weekly_input,
+        weekly_used_output: weekly_output,
+        weekly_used_total: weekly_total,
+        weekly_limit_input,
+        weekly_limit_output,
+        weekly_limit_total,
+        weekly_remaining_input,
+        weekly_remaining_output,
+        weekly_remaining_total,
+        monthly_used_input: monthly_input,
+        monthly_used_output: monthly_output,
+        monthly_used_total: monthly_total,
+        monthly_limit_input,
+        monthly_limit_output,
+        monthly_limit_total,
+        monthly_remaining_input,
+        monthly_remaining_output,
+        monthly_remaining_total,
+        rolling,
+        reserved_input_tokens: window.map_or(0, |w| w.reserved_input),
+        reserved_output_tokens: window.map_or(0, |w| w.reserved_output),
+        inflight_reservations: window.map_or(0, |w| w.reservations),
+        estimated_input_tokens,
+        estimated_output_tokens,
+        reason: None,
+    };
+
+    // 检查是否超出限制
+    if let Some((quota_type, limit, used)) = result.exceeded_quota() {
+        let label = match quota_type {
+            "daily_total" => "Daily total",
+            "daily_input" => "Daily input",
+            "daily_output" => "Daily output",
+            "weekly_total" => "Weekly total",
+            "weekly_input" => "Weekly input",
+            "weekly_output" => "Weekly output",
+            "monthly_total" => "Monthly total",
+            "monthly_input" => "Monthly input",
+            "monthly_output" => "Monthly output",
+            "rolling_total" => "Rolling total",
+            "rolling_input" => "Rolling input",
+            _ => "Rolling output",
+        };
+        result.allowed = false;
+        result.reason = Some(format!("{} token limit exceeded: {}/{} tokens", label, used, limit));
+    }
+    result
+}
+
+/// 配额日/周/月窗口的起点（UTC 时间戳），按配额的重置时区与窗口方式计算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct QuotaWindowStarts {
+    day: u64,
+    week: u64,
+    month: u64,
+}
+
+impl QuotaWindowStarts {
+    fn new(quota: Option<&TokenQuotaConfig>, now: u64) -> Self {
+        let offset = quota.map_or(0, |q| q.reset_offset_secs());
+        let weekly_window = quota.map_or(QuotaWindowMode::Rolling, |q| q.weekly_window);
+        let monthly_window = quota.map_or(QuotaWindowMode::Rolling, |q| q.monthly_window);
+
+        // 在重置时区下截断到天，再换算回 UTC
+        let local_now = now as i64 + offset;
+        let local_day = local_now.div_euclid(86400) * 86400;
+        let day = local_day - offset;
+
+        let week = match weekly_window {
+            QuotaWindowMode::Rolling => day - 6 * 86400,
+            // 1970-01-01 为周四，自然周从周一开始
+            QuotaWindowMode::Calendar => day - (local_day / 86400 + 3).rem_euclid(7) * 86400,
+        };
+        let month = match monthly_window {
+            QuotaWindowMode::Rolling => day - 29 * 86400,
+            QuotaWindowMode::Calendar => {
+                let day_of_month = DateTime::from_timestamp(local_day, 0).map_or(1, |dt| dt.day());
+                day - i64::from(day_of_month - 1) * 86400
+            }
+        };
+
+        Self {
+            day: day.max(0) as u64,
+            week: week.max(0) as u64,
+            month: month.max(0) as u64,
+        }
+    }
+}
+
+impl TokenUsageWindow {
+    fn new() -> Self {
+        Self {
+            minute_stats: VecDeque::with_capacity(60),
+            hourly_stats: VecDeque::with_capacity(24),
+            pending_tokens: 0,
+            reserved_input: 0,
+            reserved_output: 0,
+            reservations: 0,
+            unsynced: BTreeMap::new(),
+            synced: BTreeMap::new(),
+            shared: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, now: u64, input_tokens: u64, output_tokens: u64) {
+        let minute_epoch = truncate_to_minute(now);
+        let hour_epoch = truncate_to_hour(now);
+
+        // 更新分钟级统计
+        match self.minute_stats.back_mut() {
+            Some(minute) if minute.minute_epoch == minute_epoch => {
+                minute.input_tokens += input_tokens;
+                minute.output_tokens += output_tokens;
+            }
+            _ => {
+                self.minute_stats.push_back(MinuteTokenStat {
+                    minute_epoch,
+                    input_tokens,
+                    output_tokens,
+                });
+                // 清理过期数据（保留60分钟）
+                while self.minute_stats.len() > 60 {
+                    self.minute_stats.pop_front();
+                }
+            }
+        }
+
+        // 更新小时级统计
+        match self.hourly_stats.back_mut() {
+            Some(hourly) if hourly.hour_epoch == hour_epoch => {
+                hourly.input_tokens += input_tokens;
+                hourly.output_tokens += output_tokens;
+            }
+            _ => {
+                self.hourly_stats.push_back(HourlyTokenStat {
+                    hour_epoch,
+                    input_tokens,
+                    output_tokens,
+                });
+                self.prune_hourly(hour_epoch);
+            }
+        }
+    }
+
+    /// 合并一条历史小时级用量，保持按时间升序
+    fn restore(&mut self, hour_epoch: u64, input_tokens: u64, output_tokens: u64) {
+        match self.hourly_stats.binary_search_by_key(&hour_epoch, |h| h.hour_epoch) {
+            Ok(index) => {
+                let hourly = &mut self.hourly_stats[index];
+                hourly.input_tokens += input_tokens;
+                hourly.output_tokens += output_tokens;
+            }
+            Err(index) => self.hourly_stats.insert(
+                index,
+                HourlyTokenStat {
+                    hour_epoch,
+                    input_tokens,
+                    output_tokens,
+                },
+            ),
+        }
+        self.prune_hourly(truncate_to_hour(current_epoch_seconds()));
+    }
+
+    /// 清理过期数据（保留 USAGE_RETENTION_HOURS 小时）
+    fn prune_hourly(&mut self, current_hour: u64) {
+        let oldest = current_hour.saturating_sub((USAGE_RETENTION_HOURS - 1) * 3600);
+        while self.hourly_stats.front().is_some_and(|h| h.hour_epoch < oldest) {
+            self.hourly_stats.pop_front();
+        }
+        self.synced = self.synced.split_off(&oldest);
+        self.shared = self.shared.split_off(&oldest);
+    }
+
+    /// 自 `start` 起的用量（含其他实例）：(input, output)
+    ///
+    /// 用量按小时聚合，半点时区的窗口起点落在小时中间时计入整个小时（宁多不少）。
+    fn sum_since(&self, start: u64) -> (u64, u64) {
+        let start = truncate_to_hour(start);
+        let local = self
+            .hourly_stats
+            .iter()
+            .rev()
+            .take_while(|h| h.hour_epoch >= start)
+            .fold((0, 0), |(input, output), h| (input + h.input_tokens, output + h.output_tokens));
+        self.shared
+            .range(start..)
+            .fold(local, |(input, output), (_, (i, o))| (input + i, output + o))
+    }
+
+    /// 当前分钟的总用量
+    fn current_minute_total(&self, now: u64) -> u64 {
+        let minute_epoch = truncate_to_minute(now);
+        self.minute_stats
+            .iter()
+            .filter(|m| m.minute_epoch == minute_epoch)
+            .map(|m| m.input_tokens + m.output_tokens)
+            .sum()
+    }
+
+    /// 当日（UTC）的总用量（含其他实例）
+    fn current_day_total(&self, now: u64) -> u64 {
+        let (input, output) = self.sum_since(truncate_to_day(now));
+        input + output
+    }
+}
+
+fn add_hourly(hours: &mut BTreeMap<u64, (u64, u64)>, hour_epoch: u64, input_tokens: u64, output_tokens: u64) {
+    let entry = hours.entry(hour_epoch).or_default();
+    entry.0 += input_tokens;
+    entry.1 += output_tokens;
+}
+
+/// TPM/TPD 限制的作用维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenRateScope {
+    ApiKey,
+    Route,
+}
+
+impl TokenRateScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenRateScope::ApiKey => "api_key",
+            TokenRateScope::Route => "route",
+        }
+    }
+}
+
+/// TPM/TPD 限制的时间窗口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenRateWindow {
+    Minute,
+    Day,
+}
+
+impl TokenRateWindow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenRateWindow::Minute => "minute",
+            TokenRateWindow::Day => "day",
+        }
+    }
+}
+
+/// TPM/TPD 超限信息
+#[derive(Debug, Clone)]
+pub struct TokenRateLimitExceeded {
+    pub scope: TokenRateScope,
+    pub window: TokenRateWindow,
+    pub limit: u64,
+    /// 已用量 + 预占量
+    pub used: u64,
+    pub retry_after_secs: u64,
+}
+
+/// TPM/TPD 预占额度，释放时归还
+pub struct TokenRateReservation {
+    manager: Arc<TokenQuotaManager>,
+    api_key_id: String,
+    route_id: String,
+    tokens: u64,
+    remaining_tokens: Option<u64>,
+}
+
+impl TokenRateReservation {
+    /// 预占的token数量
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    /// 预占之后窗口内还剩余的token数量（取各维度最小值）
+    pub fn remaining_tokens(&self) -> Option<u64> {
+        self.remaining_tokens
+    }
+}
+
+impl Drop for TokenRateReservation {
+    fn drop(&mut self) {
+        self.manager
+            .release_reserved_tokens(&self.api_key_id, &self.route_id, self.tokens);
+    }
+}
+
+/// 配额预留，请求结束记录实际用量后释放
+pub struct QuotaReservation {
+    manager: Arc<TokenQuotaManager>,
+    api_key_id: String,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        self.manager
+            .release_quota(&self.api_key_id, self.input_tokens, self.output_tokens);
+    }
+}
+
+fn check_token_rate_limit(
+    scope: TokenRateScope,
+    limit: &TokenRateLimitConfig,
+    window: Option<&TokenUsageWindow>,
+    now: u64,
+    estimated_tokens: u64,
+) -> Result<Option<u64>, TokenRateLimitExceeded> {
+    let pending = window.map_or(0, |w| w.pending_tokens);
+    let mut remaining = None;
+
+    if let Some(tokens_per_minute) = limit.tokens_per_minute {
+        let used = window.map_or(0, |w| w.current_minute_total(now)) + pending;
+        if used + estimated_tokens > tokens_per_minute {
+            return Err(TokenRateLimitExceeded {
+                scope,
+                window: TokenRateWindow::Minute,
+                limit: tokens_per_minute,
+                used,
+                retry_after_secs: 60 - now % 60,
+            });
+        }
+        remaining = min_option(remaining, Some(tokens_per_minute - used - estimated_tokens));
+    }
+
+    if let Some(tokens_per_day) = limit.tokens_per_day {
+        let used = window.map_or(0, |w| w.current_day_total(now)) + pending;
+        if used + estimated_tokens > tokens_per_day {
+            return Err(TokenRateLimitExceeded {
+                scope,
+                window: TokenRateWindow::Day,
+                limit: tokens_per_day,
+                used,
+                retry_after_secs: 86400 - now % 86400,
+            });
+        }
+        remaining = min_option(remaining, Some(tokens_per_day - used - estimated_tokens));
+    }
+
+    Ok(remaining)
+}
+
+fn min_option(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 impl Default for TokenQuotaManager {
     fn default() -> Self {
         Self::new()
@@ -357,6 +985,11 @@ impl TokenQuotaChecker {
         self.manager.check_quota(api_key_id)
     }
 
+    /// 检查配额是否已超出（计入预估输入 token）
+    pub fn check_quota_with_estimate(&self, api_key_id: &str, estimated_input_tokens: u64) -> CheckQuotaResult {
+        self.manager.check_quota_with_estimate(api_key_id, estimated_input_tokens)
+    }
+
     /// 记录token使用
     pub fn record_usage(&self, api_key_id: &str, input_tokens: u64, output_tokens: u64) {
         self.manager.record_usage(api_key_id, input_tokens, output_tokens);
@@ -376,6 +1009,11 @@ fn current_epoch_seconds() -> u64 {
         .unwrap_or(0)
 }
 
+/// 截断到分钟（返回整分的Unix时间戳）
+fn truncate_to_minute(timestamp: u64) -> u64 {
+    (timestamp / 60) * 60
+}
+
 /// 截断到小时（返回整点的Unix时间戳）
 fn truncate_to_hour(timestamp: u64) -> u64 {
     (timestamp / 3600) * 3600
@@ -411,6 +1049,7 @@ mod tests {
             weekly_total_limit: None,
             weekly_input_limit: None,
             weekly_output_limit: None,
+            ..Default::default()
         });
 
         // 初始状态应该允许
@@ -434,4 +1073,208 @@ mod tests {
         assert!(!result.allowed);
         assert!(result.reason.is_some());
     }
+
+    #[test]
+    fn test_check_quota_with_estimate() {
+        let manager = TokenQuotaManager::new();
+        let api_key_id = "test_key_estimate";
+        manager.quotas.insert(api_key_id.to_string(), TokenQuotaConfig {
+            daily_total_limit: Some(1000),
+            daily_input_limit: None,
+            daily_output_limit: Some(300),
+            weekly_total_limit: None,
+            weekly_input_limit: None,
+            weekly_output_limit: None,
+            ..Default::default()
+        });
+        manager.record_usage(api_key_id, 500, 100);
+
+        // 已用 600，预估 300 仍在上限内，输出预算取总量余量与输出余量的较小值
+        let result = manager.check_quota_with_estimate(api_key_id, 300);
+        assert!(result.allowed);
+        assert_eq!(result.output_budget(), Some(100));
+
+        // 预估 500 会使总量超过上限
+        let result = manager.check_quota_with_estimate(api_key_id, 500);
+        assert!(!result.allowed);
+        assert_eq!(result.exceeded_quota(), Some(("daily_total", 1000, 600)));
+    }
+
+    #[test]
+    fn test_calendar_windows_follow_reset_timezone() {
+        let quota = TokenQuotaConfig {
+            weekly_window: QuotaWindowMode::Calendar,
+            monthly_window: QuotaWindowMode::Calendar,
+            reset_timezone: Some("+08:00".to_string()),
+            ..Default::default()
+        };
+        // 2024-10-01T20:00:00Z，即 UTC+8 的 2024-10-02（周三）04:00
+        let starts = QuotaWindowStarts::new(Some(&quota), 1_727_812_800);
+        // 2024-10-01T16:00:00Z = UTC+8 的 10-02 00:00
+        assert_eq!(starts.day, 1_727_798_400);
+        // UTC+8 的 09-30（周一）00:00
+        assert_eq!(starts.week, 1_727_798_400 - 2 * 86400);
+        // UTC+8 的 10-01 00:00
+        assert_eq!(starts.month, 1_727_798_400 - 86400);
+
+        // 默认 UTC、滚动窗口
+        let starts = QuotaWindowStarts::new(None, 1_727_812_800);
+        assert_eq!(starts.day, 1_727_740_800);
+        assert_eq!(starts.week, 1_727_740_800 - 6 * 86400);
+        assert_eq!(starts.month, 1_727_740_800 - 29 * 86400);
+
+        // 半点时区：UTC+5:30 的 10-02 00:00 = 2024-10-01T18:30:00Z
+        let quota = TokenQuotaConfig {
+            reset_timezone: Some("+05:30".to_string()),
+            ..Default::default()
+        };
+        let starts = QuotaWindowStarts::new(Some(&quota), 1_727_812_800);
+        assert_eq!(starts.day, 1_727_807_400);
+        assert_eq!(starts.week, 1_727_807_400 - 6 * 86400);
+    }
+
+    #[test]
+    fn test_monthly_and_rolling_quota() {
+        let manager = TokenQuotaManager::new();
+        let api_key_id = "test_key_rolling";
+        manager.quotas.insert(api_key_id.to_string(), TokenQuotaConfig {
+            monthly_total_limit: Some(10_000),
+            rolling_windows: vec![crate::config::RollingQuotaConfig {
+                hours: 5,
+                total_limit: Some(1000),
+                input_limit: None,
+                output_limit: None,
+            }],
+            ..Default::default()
+        });
+
+        // 6 小时前的用量只计入月窗口
+        let current_hour = truncate_to_hour(current_epoch_seconds());
+        manager.restore_usage(api_key_id, current_hour - 6 * 3600, 3000, 0);
+        manager.record_usage(api_key_id, 600, 200);
+
+        let result = manager.check_quota_with_estimate(api_key_id, 100);
+        assert!(result.allowed);
+        assert_eq!(result.rolling[0].used_total, 800);
+        assert_eq!(result.output_budget(), Some(100));
+
+        let result = manager.check_quota_with_estimate(api_key_id, 300);
+        assert_eq!(result.exceeded_quota(), Some(("rolling_total", 1000, 800)));
+    }
+
+    #[test]
+    fn test_quota_reservation_blocks_concurrent_overshoot() {
+        let manager = Arc::new(TokenQuotaManager::new());
+        let api_key_id = "test_key_reserve";
+        manager.quotas.insert(api_key_id.to_string(), TokenQuotaConfig {
+            daily_total_limit: Some(1000),
+            ..Default::default()
+        });
+
+        let first = manager.reserve_quota(api_key_id, 200, 500).expect("first admitted");
+        let status = manager.check_quota(api_key_id);
+        assert_eq!(status.reserved_input_tokens, 200);
+        assert_eq!(status.reserved_output_tokens, 500);
+        assert_eq!(status.inflight_reservations, 1);
+        assert_eq!(status.output_budget(), Some(300));
+
+        // 已预留 700，再预留 400 会超过上限
+        let rejected = manager.reserve_quota(api_key_id, 100, 300).err().expect("overshoot rejected");
+        assert_eq!(rejected.exceeded_quota(), Some(("daily_total", 1000, 0)));
+        assert!(!manager.check_quota_with_estimate(api_key_id, 400).allowed);
+
+        // 结算：记录实际用量后释放预留
+        manager.record_usage(api_key_id, 200, 100);
+        drop(first);
+        let status = manager.check_quota(api_key_id);
+        assert_eq!(status.inflight_reservations, 0);
+        assert_eq!(status.daily_used_total, 300);
+        assert!(manager.reserve_quota(api_key_id, 100, 300).is_ok());
+    }
+
+    fn token_rate_limit(tokens_per_minute: Option<u64>, tokens_per_day: Option<u64>) -> TokenRateLimitConfig {
+        TokenRateLimitConfig {
+            tokens_per_minute,
+            tokens_per_day,
+        }
+    }
+
+    #[test]
+    fn test_reserve_tokens_counts_pending_and_usage() {
+        let manager = Arc::new(TokenQuotaManager::new());
+        manager
+            .key_token_rate_limits
+            .insert("key_a".to_string(), token_rate_limit(Some(1000), None));
+
+        let first = manager.reserve_tokens("key_a", "openai", 600).expect("first admitted");
+        let rejected = manager
+            .reserve_tokens("key_a", "openai", 500)
+            .err()
+            .expect("pending tokens should count against TPM");
+        assert_eq!(rejected.scope, TokenRateScope::ApiKey);
+        assert_eq!(rejected.window, TokenRateWindow::Minute);
+        assert_eq!(rejected.used, 600);
+
+        // 请求完成：记录实际用量后释放预占
+        manager.record_usage("key_a", 300, 100);
+        drop(first);
+        assert!(manager.reserve_tokens("key_a", "openai", 500).is_ok());
+        assert!(manager.reserve_tokens("key_a", "openai", 700).is_err());
+    }
+
+    #[test]
+    fn test_route_token_rate_limit_is_shared_by_keys() {
+        let manager = Arc::new(TokenQuotaManager::new());
+        manager
+            .route_token_rate_limits
+            .insert("openai".to_string(), token_rate_limit(None, Some(100)));
+        assert!(manager.has_token_rate_limit("any_key", "openai"));
+        assert!(!manager.has_token_rate_limit("any_key", "other"));
+
+        manager.record_route_usage("openai", 60, 20);
+        let _held = manager.reserve_tokens("key_a", "openai", 10).expect("admitted");
+        let rejected = manager
+            .reserve_tokens("key_b", "openai", 20)
+            .err()
+            .expect("route TPD exceeded");
+        assert_eq!(rejected.scope, TokenRateScope::Route);
+        assert_eq!(rejected.window, TokenRateWindow::Day);
+        assert_eq!(rejected.limit, 100);
+        // 路由超限时退回 Key 窗口的预占
+        assert_eq!(manager.usage_stats.get("key_b").unwrap().pending_tokens, 0);
+    }
+
+    #[test]
+    fn test_reserve_tokens_is_atomic_under_concurrency() {
+        let manager = Arc::new(TokenQuotaManager::new());
+        manager
+            .key_token_rate_limits
+            .insert("key_a".to_string(), token_rate_limit(Some(500), None));
+        manager
+            .route_token_rate_limits
+            .insert("openai".to_string(), token_rate_limit(Some(1000), None));
+
+        // 16 个并发请求各预占 100：偶数用 key_a（Key 上限 500），奇数各用独立 Key
+        let barrier = Arc::new(std::sync::Barrier::new(16));
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let manager = Arc::clone(&manager);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let key = if i % 2 == 0 { "key_a".to_string() } else { format!("key_{i}") };
+                    manager.reserve_tokens(&key, "openai", 100).ok()
+                })
+            })
+            .collect();
+        let held: Vec<_> = handles.into_iter().filter_map(|h| h.join().unwrap()).collect();
+
+        let route_pending = manager.route_usage_stats.get("openai").unwrap().pending_tokens;
+        let key_pending: u64 = manager.usage_stats.iter().map(|window| window.pending_tokens).sum();
+        assert!(manager.usage_stats.get("key_a").unwrap().pending_tokens <= 500);
+        assert!(route_pending <= 1000);
+        // 被拒绝的请求不残留预占
+        assert_eq!(route_pending, held.len() as u64 * 100);
+        assert_eq!(key_pending, route_pending);
+    }
 }
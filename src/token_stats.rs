@@ -1,4 +1,6 @@
-use crate::token_quota::TokenQuotaManager;
+use crate::budget;
+use crate::token_extractor::TokenUsage;
+use crate::token_quota::{TokenQuotaManager, USAGE_RETENTION_HOURS};
 use crate::token_stats_storage::{TokenStatsRow, TokenStatsStorage, TokenUsageRecord};
 use dashmap::DashMap;
 use std::collections::VecDeque;
@@ -11,6 +13,8 @@ pub struct TokenStatsCollector {
     api_key_stats: DashMap<String, TokenStats>,
     /// Route级别统计（内存缓存）
     route_stats: DashMap<String, TokenStats>,
+    /// 模型级别统计（内存缓存）
+    model_stats: DashMap<String, TokenStats>,
     /// SQLite存储
     storage: Option<Arc<TokenStatsStorage>>,
     /// Token配额管理器（用于实时配额检查）
@@ -18,7 +22,7 @@ pub struct TokenStatsCollector {
 }
 
 /// Token统计（内存表示）
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TokenStats {
     /// 最近24小时（小时粒度）
     hourly_buckets: VecDeque<HourlyTokenCount>,
@@ -33,6 +37,8 @@ pub struct HourlyTokenCount {
     pub input_tokens: u64,
     pub output_tokens: u64,
     pub request_count: u64,
+    /// 费用（按价格表核算）
+    pub cost: f64,
 }
 
 /// 天级Token统计
@@ -42,6 +48,8 @@ pub struct DailyTokenCount {
     pub input_tokens: u64,
     pub output_tokens: u64,
     pub request_count: u64,
+    /// 费用（按价格表核算）
+    pub cost: f64,
 }
 
 /// Token统计摘要
@@ -59,6 +67,9 @@ pub struct TokenStatsSummary {
     pub request_count_today: u64,
     pub request_count_week: u64,
     pub request_count_month: u64,
+    pub today_cost: f64,
+    pub week_cost: f64,
+    pub month_cost: f64,
 }
 
 impl TokenStatsCollector {
@@ -70,6 +81,7 @@ impl TokenStatsCollector {
         Self {
             api_key_stats: DashMap::new(),
             route_stats: DashMap::new(),
+            model_stats: DashMap::new(),
             storage,
             quota_manager,
         }
@@ -83,33 +95,70 @@ impl TokenStatsCollector {
         input_tokens: u64,
         output_tokens: u64,
         request_id: Option<String>,
+    ) {
+        let usage = TokenUsage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            ..TokenUsage::default()
+        };
+        self.record_usage_with_cost(api_key_id, route_id, None, &usage, 0.0, request_id);
+    }
+
+    /// 记录token使用及费用
+    pub fn record_usage_with_cost(
+        &self,
+        api_key_id: &str,
+        route_id: &str,
+        model: Option<&str>,
+        usage: &TokenUsage,
+        cost: f64,
+        request_id: Option<String>,
     ) {
         let now = current_epoch_seconds();
+        let input_tokens = usage.input_tokens;
+        let output_tokens = usage.output_tokens;
 
         tracing::info!(
-            "Recording token usage: api_key_id={}, route_id={}, input={}, output={}",
-            api_key_id, route_id, input_tokens, output_tokens
+            "Recording token usage: api_key_id={}, route_id={}, model={:?}, input={}, output={}, cost={}",
+            api_key_id, route_id, model, input_tokens, output_tokens, cost
         );
 
-        // 1. 更新内存统计（API Key级别）
-        self.update_api_key_stats(api_key_id, input_tokens, output_tokens, now);
-
-        // 2. 更新内存统计（Route级别）
-        self.update_route_stats(route_id, input_tokens, output_tokens, now);
+        // 1. 更新内存统计（API Key / Route / 模型级别）
+        self.api_key_stats
+            .entry(api_key_id.to_string())
+            .or_default()
+            .record(now, input_tokens, output_tokens, cost);
+        self.route_stats
+            .entry(route_id.to_string())
+            .or_default()
+            .record(now, input_tokens, output_tokens, cost);
+        if let Some(model) = model {
+            self.model_stats
+                .entry(model.to_string())
+                .or_default()
+                .record(now, input_tokens, output_tokens, cost);
+        }
 
-        // 3. 更新配额管理器（用于实时配额检查）
+        // 2. 更新配额管理器（用于实时配额检查）
         if let Some(quota_manager) = &self.quota_manager {
             quota_manager.record_usage(api_key_id, input_tokens, output_tokens);
+            quota_manager.record_route_usage(route_id, input_tokens, output_tokens);
+            quota_manager.budget().record_spend(api_key_id, cost, now);
         }
 
-        // 4. 发送到SQLite存储队列
+        // 3. 发送到SQLite存储队列
         if let Some(storage) = &self.storage {
             let record = TokenUsageRecord {
                 timestamp: now as i64,
                 api_key_id: api_key_id.to_string(),
                 route_id: route_id.to_string(),
+                model: model.map(str::to_string),
                 input_tokens,
                 output_tokens,
+                cached_input_tokens: usage.cached_input_tokens,
+                reasoning_tokens: usage.reasoning_tokens,
+                cost,
                 request_id,
             };
             storage.queue_record(record);
@@ -119,318 +168,37 @@ impl TokenStatsCollector {
         }
     }
 
-    /// 更新API Key级别统计
-    fn update_api_key_stats(
-        &self,
-        api_key_id: &str,
-        input_tokens: u64,
-        output_tokens: u64,
-        now: u64,
-    ) {
-        let hour_epoch = truncate_to_hour(now);
-        let day_epoch = truncate_to_day(now);
-
-        let mut stats = self.api_key_stats.entry(api_key_id.to_string()).or_insert_with(|| TokenStats {
-            hourly_buckets: VecDeque::with_capacity(24),
-            daily_buckets: VecDeque::with_capacity(30),
-        });
-
-        // 更新小时级统计
-        if let Some(hourly) = stats.hourly_buckets.back_mut() {
-            if hourly.hour_epoch == hour_epoch {
-                hourly.input_tokens += input_tokens;
-                hourly.output_tokens += output_tokens;
-                hourly.request_count += 1;
-            } else {
-                stats.hourly_buckets.push_back(HourlyTokenCount {
-                    hour_epoch,
-                    input_tokens,
-                    output_tokens,
-                    request_count: 1,
-                });
-                // 清理过期数据
-                while stats.hourly_buckets.len() > 24 {
-                    stats.hourly_buckets.pop_front();
-                }
-            }
-        } else {
-            stats.hourly_buckets.push_back(HourlyTokenCount {
-                hour_epoch,
-                input_tokens,
-                output_tokens,
-                request_count: 1,
-            });
-        }
-
-        // 更新天级统计
-        if let Some(daily) = stats.daily_buckets.back_mut() {
-            if daily.day_epoch == day_epoch {
-                daily.input_tokens += input_tokens;
-                daily.output_tokens += output_tokens;
-                daily.request_count += 1;
-            } else {
-                stats.daily_buckets.push_back(DailyTokenCount {
-                    day_epoch,
-                    input_tokens,
-                    output_tokens,
-                    request_count: 1,
-                });
-                // 清理过期数据
-                while stats.daily_buckets.len() > 30 {
-                    stats.daily_buckets.pop_front();
-                }
-            }
-        } else {
-            stats.daily_buckets.push_back(DailyTokenCount {
-                day_epoch,
-                input_tokens,
-                output_tokens,
-                request_count: 1,
-            });
-        }
-    }
-
-    /// 更新Route级别统计
-    fn update_route_stats(
-        &self,
-        route_id: &str,
-        input_tokens: u64,
-        output_tokens: u64,
-        now: u64,
-    ) {
-        let hour_epoch = truncate_to_hour(now);
-        let day_epoch = truncate_to_day(now);
-
-        let mut stats = self.route_stats.entry(route_id.to_string()).or_insert_with(|| TokenStats {
-            hourly_buckets: VecDeque::with_capacity(24),
-            daily_buckets: VecDeque::with_capacity(30),
-        });
-
-        // 更新小时级统计
-        if let Some(hourly) = stats.hourly_buckets.back_mut() {
-            if hourly.hour_epoch == hour_epoch {
-                hourly.input_tokens += input_tokens;
-                hourly.output_tokens += output_tokens;
-                hourly.request_count += 1;
-            } else {
-                stats.hourly_buckets.push_back(HourlyTokenCount {
-                    hour_epoch,
-                    input_tokens,
-                    output_tokens,
-                    request_count: 1,
-                });
-                while stats.hourly_buckets.len() > 24 {
-                    stats.hourly_buckets.pop_front();
-                }
-            }
-        } else {
-            stats.hourly_buckets.push_back(HourlyTokenCount {
-                hour_epoch,
-                input_tokens,
-                output_tokens,
-                request_count: 1,
-            });
-        }
-
-        // 更新天级统计
-        if let Some(daily) = stats.daily_buckets.back_mut() {
-            if daily.day_epoch == day_epoch {
-                daily.input_tokens += input_tokens;
-                daily.output_tokens += output_tokens;
-                daily.request_count += 1;
-            } else {
-                stats.daily_buckets.push_back(DailyTokenCount {
-                    day_epoch,
-                    input_tokens,
-                    output_tokens,
-                    request_count: 1,
-                });
-                while stats.daily_buckets.len() > 30 {
-                    stats.daily_buckets.pop_front();
-                }
-            }
-        } else {
-            stats.daily_buckets.push_back(DailyTokenCount {
-                day_epoch,
-                input_tokens,
-                output_tokens,
-                request_count: 1,
-            });
-        }
-    }
-
     /// 获取API Key的统计摘要
     pub fn get_api_key_summary(&self, api_key_id: &str) -> Option<TokenStatsSummary> {
         let stats = self.api_key_stats.get(api_key_id)?;
-        let now = current_epoch_seconds();
-        let day_start = truncate_to_day(now);
-        let week_start = truncate_to_day(now - 6 * 86400);
-        let month_start = truncate_to_day(now - 29 * 86400);
-
-        // 今日统计
-        let today_input: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.input_tokens)
-            .sum();
-        let today_output: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.output_tokens)
-            .sum();
-        let request_count_today: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.request_count)
-            .sum();
-
-        // 本周统计
-        let week_input: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.input_tokens)
-            .sum();
-        let week_output: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.output_tokens)
-            .sum();
-        let request_count_week: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.request_count)
-            .sum();
-
-        // 本月统计
-        let month_input: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.input_tokens)
-            .sum();
-        let month_output: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.output_tokens)
-            .sum();
-        let request_count_month: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.request_count)
-            .sum();
-
-        Some(TokenStatsSummary {
-            today_input,
-            today_output,
-            today_total: today_input + today_output,
-            week_input,
-            week_output,
-            week_total: week_input + week_output,
-            month_input,
-            month_output,
-            month_total: month_input + month_output,
-            request_count_today,
-            request_count_week,
-            request_count_month,
-        })
+        Some(stats.summary(current_epoch_seconds()))
     }
 
     /// 获取Route的统计摘要
     pub fn get_route_summary(&self, route_id: &str) -> Option<TokenStatsSummary> {
         let stats = self.route_stats.get(route_id)?;
-        let now = current_epoch_seconds();
-        let day_start = truncate_to_day(now);
-        let week_start = truncate_to_day(now - 6 * 86400);
-        let month_start = truncate_to_day(now - 29 * 86400);
-
-        // 今日统计
-        let today_input: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.input_tokens)
-            .sum();
-        let today_output: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.output_tokens)
-            .sum();
-        let request_count_today: u64 = stats.hourly_buckets
-            .iter()
-            .filter(|h| h.hour_epoch >= day_start)
-            .map(|h| h.request_count)
-            .sum();
-
-        // 本周统计
-        let week_input: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.input_tokens)
-            .sum();
-        let week_output: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.output_tokens)
-            .sum();
-        let request_count_week: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= week_start)
-            .map(|d| d.request_count)
-            .sum();
+        Some(stats.summary(current_epoch_seconds()))
+    }
 
-        // 本月统计
-        let month_input: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.input_tokens)
-            .sum();
-        let month_output: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.output_tokens)
-            .sum();
-        let request_count_month: u64 = stats.daily_buckets
-            .iter()
-            .filter(|d| d.day_epoch >= month_start)
-            .map(|d| d.request_count)
-            .sum();
-
-        Some(TokenStatsSummary {
-            today_input,
-            today_output,
-            today_total: today_input + today_output,
-            week_input,
-            week_output,
-            week_total: week_input + week_output,
-            month_input,
-            month_output,
-            month_total: month_input + month_output,
-            request_count_today,
-            request_count_week,
-            request_count_month,
-        })
+    /// 获取模型的统计摘要
+    pub fn get_model_summary(&self, model: &str) -> Option<TokenStatsSummary> {
+        let stats = self.model_stats.get(model)?;
+        Some(stats.summary(current_epoch_seconds()))
     }
 
     /// 获取所有API Key的统计
     pub fn get_all_api_key_stats(&self) -> Vec<(String, TokenStatsSummary)> {
-        self.api_key_stats
-            .iter()
-            .filter_map(|entry| {
-                let api_key_id = entry.key().clone();
-                self.get_api_key_summary(&api_key_id)
-                    .map(|summary| (api_key_id, summary))
-            })
-            .collect()
+        all_summaries(&self.api_key_stats)
     }
 
     /// 获取所有Route的统计
     pub fn get_all_route_stats(&self) -> Vec<(String, TokenStatsSummary)> {
-        self.route_stats
-            .iter()
-            .filter_map(|entry| {
-                let route_id = entry.key().clone();
-                self.get_route_summary(&route_id)
-                    .map(|summary| (route_id, summary))
-            })
-            .collect()
+        all_summaries(&self.route_stats)
+    }
+
+    /// 获取所有模型的统计
+    pub fn get_all_model_stats(&self) -> Vec<(String, TokenStatsSummary)> {
+        all_summaries(&self.model_stats)
     }
 
     /// 获取存储引用
@@ -464,7 +232,7 @@ impl TokenStatsCollector {
         match storage.query_api_key_stats_for_restore(day_start as i64, week_start as i64).await {
             Ok(records) => {
                 for record in records {
-                    self.restore_api_key_stats(&record);
+                    restore_stats(&self.api_key_stats, record.api_key_id.as_deref(), &record);
                 }
                 tracing::info!("Loaded {} API key stat records from SQLite", self.api_key_stats.len());
             }
@@ -477,7 +245,7 @@ impl TokenStatsCollector {
         match storage.query_route_stats_for_restore(day_start as i64, week_start as i64).await {
             Ok(records) => {
                 for record in records {
-                    self.restore_route_stats(&record);
+                    restore_stats(&self.route_stats, record.route_id.as_deref(), &record);
                 }
                 tracing::info!("Loaded {} route stat records from SQLite", self.route_stats.len());
             }
@@ -486,98 +254,249 @@ impl TokenStatsCollector {
             }
         }
 
+        // 加载模型的今日统计
+        match storage.query_model_stats_for_restore(day_start as i64).await {
+            Ok(records) => {
+                for record in records {
+                    restore_stats(&self.model_stats, record.model.as_deref(), &record);
+                }
+                tracing::info!("Loaded {} model stat records from SQLite", self.model_stats.len());
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load model historical stats: {}", e);
+            }
+        }
+
+        // 加载配额窗口内的小时级用量
+        if let Some(quota_manager) = &self.quota_manager {
+            let start = truncate_to_hour(now).saturating_sub((USAGE_RETENTION_HOURS - 1) * 3600);
+            match storage.query_hourly_usage_for_restore(start as i64).await {
+                Ok(records) => {
+                    for record in &records {
+                        let hour_epoch = record.time_bucket as u64;
+                        if let Some(api_key_id) = &record.api_key_id {
+                            quota_manager.restore_usage(
+                                api_key_id,
+                                hour_epoch,
+                                record.input_tokens,
+                                record.output_tokens,
+                            );
+                        }
+                        if let Some(route_id) = &record.route_id {
+                            quota_manager.restore_route_usage(
+                                route_id,
+                                hour_epoch,
+                                record.input_tokens,
+                                record.output_tokens,
+                            );
+                        }
+                    }
+                    tracing::info!("Loaded {} hourly quota usage records from SQLite", records.len());
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to load quota usage: {}", e);
+                }
+            }
+        }
+
+        // 加载费用预算窗口内的天级花费
+        if let Some(quota_manager) = &self.quota_manager {
+            match storage.query_daily_cost_for_restore(budget::restore_start(now) as i64).await {
+                Ok(records) => {
+                    for record in &records {
+                        if let Some(api_key_id) = &record.api_key_id {
+                            quota_manager.budget().restore_spend(
+                                api_key_id,
+                                record.time_bucket as u64,
+                                record.cost,
+                                now,
+                            );
+                        }
+                    }
+                    tracing::info!("Loaded {} daily spend records from SQLite", records.len());
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to load daily spend: {}", e);
+                }
+            }
+        }
+
         Ok(())
     }
+}
 
-    /// 恢复单个API Key的统计
-    fn restore_api_key_stats(&self, record: &TokenStatsRow) {
-        let hour_epoch = truncate_to_hour(record.time_bucket as u64);
-        let day_epoch = truncate_to_day(record.time_bucket as u64);
+impl TokenStats {
+    /// 记录一次请求的用量
+    fn record(&mut self, now: u64, input_tokens: u64, output_tokens: u64, cost: f64) {
+        let hour_epoch = truncate_to_hour(now);
+        let day_epoch = truncate_to_day(now);
 
-        let api_key_id = record.api_key_id.clone().unwrap_or_default();
-        if api_key_id.is_empty() {
-            return;
+        // 更新小时级统计
+        match self.hourly_buckets.back_mut() {
+            Some(hourly) if hourly.hour_epoch == hour_epoch => {
+                hourly.input_tokens += input_tokens;
+                hourly.output_tokens += output_tokens;
+                hourly.request_count += 1;
+                hourly.cost += cost;
+            }
+            _ => {
+                self.hourly_buckets.push_back(HourlyTokenCount {
+                    hour_epoch,
+                    input_tokens,
+                    output_tokens,
+                    request_count: 1,
+                    cost,
+                });
+                // 清理过期数据
+                while self.hourly_buckets.len() > 24 {
+                    self.hourly_buckets.pop_front();
+                }
+            }
         }
 
-        let mut stats = self.api_key_stats.entry(api_key_id).or_insert_with(|| TokenStats {
-            hourly_buckets: VecDeque::with_capacity(24),
-            daily_buckets: VecDeque::with_capacity(30),
-        });
-
-        // 恢复小时级统计
-        if let Some(hourly) = stats.hourly_buckets.iter_mut().find(|h| h.hour_epoch == hour_epoch) {
-            hourly.input_tokens += record.input_tokens;
-            hourly.output_tokens += record.output_tokens;
-            hourly.request_count += record.request_count;
-        } else {
-            stats.hourly_buckets.push_back(HourlyTokenCount {
-                hour_epoch,
-                input_tokens: record.input_tokens,
-                output_tokens: record.output_tokens,
-                request_count: record.request_count,
-            });
+        // 更新天级统计
+        match self.daily_buckets.back_mut() {
+            Some(daily) if daily.day_epoch == day_epoch => {
+                daily.input_tokens += input_tokens;
+                daily.output_tokens += output_tokens;
+                daily.request_count += 1;
+                daily.cost += cost;
+            }
+            _ => {
+                self.daily_buckets.push_back(DailyTokenCount {
+                    day_epoch,
+                    input_tokens,
+                    output_tokens,
+                    request_count: 1,
+                    cost,
+                });
+                // 清理过期数据
+                while self.daily_buckets.len() > 30 {
+                    self.daily_buckets.pop_front();
+                }
+            }
         }
+    }
 
-        // 恢复天级统计
-        if let Some(daily) = stats.daily_buckets.iter_mut().find(|d| d.day_epoch == day_epoch) {
-            daily.input_tokens += record.input_tokens;
-            daily.output_tokens += record.output_tokens;
-            daily.request_count += record.request_count;
-        } else {
-            stats.daily_buckets.push_back(DailyTokenCount {
-                day_epoch,
-                input_tokens: record.input_tokens,
-                output_tokens: record.output_tokens,
-                request_count: record.request_count,
+    /// 计算今日、近 7 天、近 30 天的统计摘要
+    fn summary(&self, now: u64) -> TokenStatsSummary {
+        let day_start = truncate_to_day(now);
+        let week_start = truncate_to_day(now - 6 * 86400);
+        let month_start = truncate_to_day(now - 29 * 86400);
+
+        // 今日统计
+        let today = self
+            .hourly_buckets
+            .iter()
+            .filter(|h| h.hour_epoch >= day_start)
+            .fold(WindowTotals::default(), |acc, h| {
+                acc.add(h.input_tokens, h.output_tokens, h.request_count, h.cost)
             });
+        // 本周、本月统计
+        let daily_totals = |start: u64| {
+            self.daily_buckets
+                .iter()
+                .filter(|d| d.day_epoch >= start)
+                .fold(WindowTotals::default(), |acc, d| {
+                    acc.add(d.input_tokens, d.output_tokens, d.request_count, d.cost)
+                })
+        };
+        let week = daily_totals(week_start);
+        let month = daily_totals(month_start);
+
+        TokenStatsSummary {
+            today_input: today.input,
+            today_output: today.output,
+            today_total: today.input + today.output,
+            week_input: week.input,
+            week_output: week.output,
+            week_total: week.input + week.output,
+            month_input: month.input,
+            month_output: month.output,
+            month_total: month.input + month.output,
+            request_count_today: today.requests,
+            request_count_week: week.requests,
+            request_count_month: month.requests,
+            today_cost: today.cost,
+            week_cost: week.cost,
+            month_cost: month.cost,
         }
     }
 
-    /// 恢复单个Route的统计
-    fn restore_route_stats(&self, record: &TokenStatsRow) {
+    /// 合并一条从SQLite恢复的统计
+    fn restore(&mut self, record: &TokenStatsRow) {
         let hour_epoch = truncate_to_hour(record.time_bucket as u64);
         let day_epoch = truncate_to_day(record.time_bucket as u64);
 
-        let route_id = record.route_id.clone().unwrap_or_default();
-        if route_id.is_empty() {
-            return;
-        }
-
-        let mut stats = self.route_stats.entry(route_id).or_insert_with(|| TokenStats {
-            hourly_buckets: VecDeque::with_capacity(24),
-            daily_buckets: VecDeque::with_capacity(30),
-        });
-
         // 恢复小时级统计
-        if let Some(hourly) = stats.hourly_buckets.iter_mut().find(|h| h.hour_epoch == hour_epoch) {
+        if let Some(hourly) = self.hourly_buckets.iter_mut().find(|h| h.hour_epoch == hour_epoch) {
             hourly.input_tokens += record.input_tokens;
             hourly.output_tokens += record.output_tokens;
             hourly.request_count += record.request_count;
+            hourly.cost += record.cost;
         } else {
-            stats.hourly_buckets.push_back(HourlyTokenCount {
+            self.hourly_buckets.push_back(HourlyTokenCount {
                 hour_epoch,
                 input_tokens: record.input_tokens,
                 output_tokens: record.output_tokens,
                 request_count: record.request_count,
+                cost: record.cost,
             });
         }
 
         // 恢复天级统计
-        if let Some(daily) = stats.daily_buckets.iter_mut().find(|d| d.day_epoch == day_epoch) {
+        if let Some(daily) = self.daily_buckets.iter_mut().find(|d| d.day_epoch == day_epoch) {
             daily.input_tokens += record.input_tokens;
             daily.output_tokens += record.output_tokens;
             daily.request_count += record.request_count;
+            daily.cost += record.cost;
         } else {
-            stats.daily_buckets.push_back(DailyTokenCount {
+            self.daily_buckets.push_back(DailyTokenCount {
                 day_epoch,
                 input_tokens: record.input_tokens,
                 output_tokens: record.output_tokens,
                 request_count: record.request_count,
+                cost: record.cost,
             });
         }
     }
 }
 
+/// 时间窗口内的累计值
+#[derive(Default)]
+struct WindowTotals {
+    input: u64,
+    output: u64,
+    requests: u64,
+    cost: f64,
+}
+
+impl WindowTotals {
+    fn add(self, input: u64, output: u64, requests: u64, cost: f64) -> Self {
+        Self {
+            input: self.input + input,
+            output: self.output + output,
+            requests: self.requests + requests,
+            cost: self.cost + cost,
+        }
+    }
+}
+
+fn all_summaries(stats: &DashMap<String, TokenStats>) -> Vec<(String, TokenStatsSummary)> {
+    let now = current_epoch_seconds();
+    stats
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().summary(now)))
+        .collect()
+}
+
+fn restore_stats(stats: &DashMap<String, TokenStats>, id: Option<&str>, record: &TokenStatsRow) {
+    let Some(id) = id.filter(|id| !id.is_empty()) else {
+        return;
+    };
+    stats.entry(id.to_string()).or_default().restore(record);
+}
+
 /// 获取当前Unix时间戳（秒）
 fn current_epoch_seconds() -> u64 {
     SystemTime::now()
@@ -623,4 +542,28 @@ mod tests {
         assert_eq!(route_summary.today_output, 150);
         assert_eq!(route_summary.request_count_today, 2);
     }
+
+    #[test]
+    fn test_record_usage_with_cost_by_model() {
+        let collector = TokenStatsCollector::new(None, None);
+        let usage = TokenUsage {
+            input_tokens: 1000,
+            output_tokens: 500,
+            total_tokens: 1500,
+            ..TokenUsage::default()
+        };
+        collector.record_usage_with_cost("key_a", "openai", Some("gpt-4o"), &usage, 0.25, None);
+        collector.record_usage_with_cost("key_a", "openai", Some("gpt-4o-mini"), &usage, 0.5, None);
+        collector.record_usage("key_a", "openai", 10, 10, None);
+
+        let key_summary = collector.get_api_key_summary("key_a").unwrap();
+        assert_eq!(key_summary.request_count_today, 3);
+        assert!((key_summary.today_cost - 0.75).abs() < 1e-9);
+        assert!((key_summary.month_cost - 0.75).abs() < 1e-9);
+
+        let model_summary = collector.get_model_summary("gpt-4o").unwrap();
+        assert_eq!(model_summary.today_total, 1500);
+        assert!((model_summary.today_cost - 0.25).abs() < 1e-9);
+        assert_eq!(collector.get_all_model_stats().len(), 2);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::token_quota::USAGE_RETENTION_HOURS;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Pool, Sqlite, Row};
 use std::path::Path;
@@ -13,8 +14,12 @@ pub struct TokenUsageRecord {
     pub timestamp: i64,
     pub api_key_id: String,
     pub route_id: String,
+    pub model: Option<String>,
     pub input_tokens: u64,
     pub output_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cost: f64,
     pub request_id: Option<String>,
 }
 
@@ -38,6 +43,8 @@ pub struct TokenStatsRow {
     pub input_tokens: u64,
     pub output_tokens: u64,
     pub request_count: u64,
+    pub cost: f64,
+    pub model: Option<String>,
 }
 
 /// Token统计存储
@@ -107,7 +114,11 @@ impl TokenStatsStorage {
                 input_tokens INTEGER NOT NULL DEFAULT 0,
                 output_tokens INTEGER NOT NULL DEFAULT 0,
                 total_tokens INTEGER NOT NULL DEFAULT 0,
-                request_id TEXT
+                request_id TEXT,
+                model TEXT,
+                cached_input_tokens INTEGER NOT NULL DEFAULT 0,
+                reasoning_tokens INTEGER NOT NULL DEFAULT 0,
+                cost REAL NOT NULL DEFAULT 0.0
             )
             "#,
         )
@@ -115,6 +126,16 @@ impl TokenStatsStorage {
         .await
         .map_err(|e| format!("Failed to create token_usage_records table: {}", e))?;
 
+        // 旧版本数据库补齐费用相关字段
+        for (column, definition) in [
+            ("model", "TEXT"),
+            ("cached_input_tokens", "INTEGER NOT NULL DEFAULT 0"),
+            ("reasoning_tokens", "INTEGER NOT NULL DEFAULT 0"),
+            ("cost", "REAL NOT NULL DEFAULT 0.0"),
+        ] {
+            Self::ensure_column(pool, "token_usage_records", column, definition).await?;
+        }
+
         // 创建索引
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_token_usage_time ON token_usage_records(timestamp)"
@@ -137,6 +158,13 @@ impl TokenStatsStorage {
         .await
         .map_err(|e| format!("Failed to create route index: {}", e))?;
 
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_token_usage_model ON token_usage_records(model)"
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to create model index: {}", e))?;
+
         // 小时级聚合表
         sqlx::query(
             r#"
@@ -148,6 +176,7 @@ impl TokenStatsStorage {
                 input_tokens INTEGER NOT NULL DEFAULT 0,
                 output_tokens INTEGER NOT NULL DEFAULT 0,
                 request_count INTEGER NOT NULL DEFAULT 0,
+                cost REAL NOT NULL DEFAULT 0.0,
                 UNIQUE(hour_epoch, api_key_id, route_id)
             )
             "#,
@@ -156,6 +185,8 @@ impl TokenStatsStorage {
         .await
         .map_err(|e| format!("Failed to create hourly stats table: {}", e))?;
 
+        Self::ensure_column(pool, "token_stats_hourly", "cost", "REAL NOT NULL DEFAULT 0.0").await?;
+
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_token_stats_hourly_time ON token_stats_hourly(hour_epoch)"
         )
@@ -188,6 +219,7 @@ impl TokenStatsStorage {
                 input_tokens INTEGER NOT NULL DEFAULT 0,
                 output_tokens INTEGER NOT NULL DEFAULT 0,
                 request_count INTEGER NOT NULL DEFAULT 0,
+                cost REAL NOT NULL DEFAULT 0.0,
                 UNIQUE(day_epoch, api_key_id, route_id)
             )
             "#,
@@ -196,6 +228,8 @@ impl TokenStatsStorage {
         .await
         .map_err(|e| format!("Failed to create daily stats table: {}", e))?;
 
+        Self::ensure_column(pool, "token_stats_daily", "cost", "REAL NOT NULL DEFAULT 0.0").await?;
+
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_token_stats_daily_time ON token_stats_daily(day_epoch)"
         )
@@ -221,6 +255,29 @@ impl TokenStatsStorage {
         Ok(())
     }
 
+    /// 表中缺少字段时追加（兼容旧版本数据库）
+    async fn ensure_column(
+        pool: &Pool<Sqlite>,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), String> {
+        let exists = sqlx::query(&format!("PRAGMA table_info({table})"))
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to inspect table {}: {}", table, e))?
+            .iter()
+            .any(|row| row.try_get::<String, _>("name").is_ok_and(|name| name == column));
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+                .execute(pool)
+                .await
+                .map_err(|e| format!("Failed to add column {}.{}: {}", table, column, e))?;
+            info!("Added column {}.{} to token stats database", table, column);
+        }
+        Ok(())
+    }
+
     /// 后台写入任务
     async fn background_writer(
         pool: Pool<Sqlite>,
@@ -278,8 +335,9 @@ impl TokenStatsStorage {
             if let Err(e) = sqlx::query(
                 r#"
                 INSERT INTO token_usage_records
-                (timestamp, api_key_id, route_id, input_tokens, output_tokens, total_tokens, request_id)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                (timestamp, api_key_id, route_id, input_tokens, output_tokens, total_tokens, request_id,
+                 model, cached_input_tokens, reasoning_tokens, cost)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
                 "#,
             )
             .bind(record.timestamp)
@@ -289,6 +347,10 @@ impl TokenStatsStorage {
             .bind(record.output_tokens as i64)
             .bind(total_tokens as i64)
             .bind(&record.request_id)
+            .bind(&record.model)
+            .bind(record.cached_input_tokens as i64)
+            .bind(record.reasoning_tokens as i64)
+            .bind(record.cost)
             .execute(&mut *tx)
             .await
             {
@@ -300,12 +362,13 @@ impl TokenStatsStorage {
             let hour_epoch = truncate_to_hour(record.timestamp as u64) as i64;
             if let Err(e) = sqlx::query(
                 r#"
-                INSERT INTO token_stats_hourly (hour_epoch, api_key_id, route_id, input_tokens, output_tokens, request_count)
-                VALUES (?1, ?2, ?3, ?4, ?5, 1)
+                INSERT INTO token_stats_hourly (hour_epoch, api_key_id, route_id, input_tokens, output_tokens, request_count, cost)
+                VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
                 ON CONFLICT(hour_epoch, api_key_id, route_id) DO UPDATE SET
                     input_tokens = input_tokens + excluded.input_tokens,
                     output_tokens = output_tokens + excluded.output_tokens,
-                    request_count = request_count + 1
+                    request_count = request_count + 1,
+                    cost = cost + excluded.cost
                 "#,
             )
             .bind(hour_epoch)
@@ -313,6 +376,7 @@ impl TokenStatsStorage {
             .bind(&record.route_id)
             .bind(record.input_tokens as i64)
             .bind(record.output_tokens as i64)
+            .bind(record.cost)
             .execute(&mut *tx)
             .await
             {
@@ -324,12 +388,13 @@ impl TokenStatsStorage {
             let day_epoch = truncate_to_day(record.timestamp as u64) as i64;
             if let Err(e) = sqlx::query(
                 r#"
-                INSERT INTO token_stats_daily (day_epoch, api_key_id, route_id, input_tokens, output_tokens, request_count)
-                VALUES (?1, ?2, ?3, ?4, ?5, 1)
+                INSERT INTO token_stats_daily (day_epoch, api_key_id, route_id, input_tokens, output_tokens, request_count, cost)
+                VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
                 ON CONFLICT(day_epoch, api_key_id, route_id) DO UPDATE SET
                     input_tokens = input_tokens + excluded.input_tokens,
                     output_tokens = output_tokens + excluded.output_tokens,
-                    request_count = request_count + 1
+                    request_count = request_count + 1,
+                    cost = cost + excluded.cost
                 "#,
             )
             .bind(day_epoch)
@@ -337,6 +402,7 @@ impl TokenStatsStorage {
             .bind(&record.route_id)
             .bind(record.input_tokens as i64)
             .bind(record.output_tokens as i64)
+            .bind(record.cost)
             .execute(&mut *tx)
             .await
             {
@@ -364,8 +430,9 @@ impl TokenStatsStorage {
             .execute(pool)
             .await?;
 
-        // 删除小时级聚合过期数据（保留30天）
-        let hour_cutoff = truncate_to_hour(cutoff as u64) as i64;
+        // 删除小时级聚合过期数据（至少保留配额窗口所需的时长）
+        let quota_cutoff = current_epoch_seconds().saturating_sub(USAGE_RETENTION_HOURS * 3600) as i64;
+        let hour_cutoff = truncate_to_hour(cutoff.min(quota_cutoff) as u64) as i64;
         sqlx::query("DELETE FROM token_stats_hourly WHERE hour_epoch < ?1")
             .bind(hour_cutoff)
             .execute(pool)
@@ -392,8 +459,12 @@ impl TokenStatsStorage {
             timestamp: current_epoch_seconds() as i64,
             api_key_id: api_key_id.to_string(),
             route_id: route_id.to_string(),
+            model: None,
             input_tokens,
             output_tokens,
+            cached_input_tokens: 0,
+            reasoning_tokens: 0,
+            cost: 0.0,
             request_id,
         };
         self.queue_record(record);
@@ -415,7 +486,7 @@ impl TokenStatsStorage {
                 sqlx::query(
                     r#"
                     SELECT hour_epoch as time_bucket, api_key_id, route_id,
-                           input_tokens, output_tokens, request_count
+                           input_tokens, output_tokens, request_count, cost
                     FROM token_stats_hourly
                     WHERE api_key_id = ?1 AND hour_epoch >= ?2
                     ORDER BY hour_epoch ASC
@@ -433,6 +504,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -450,7 +523,7 @@ impl TokenStatsStorage {
                 sqlx::query(
                     r#"
                     SELECT day_epoch as time_bucket, api_key_id, route_id,
-                           input_tokens, output_tokens, request_count
+                           input_tokens, output_tokens, request_count, cost
                     FROM token_stats_daily
                     WHERE api_key_id = ?1 AND day_epoch >= ?2
                     ORDER BY day_epoch ASC
@@ -468,6 +541,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -489,7 +564,7 @@ impl TokenStatsStorage {
                 sqlx::query(
                     r#"
                     SELECT hour_epoch as time_bucket, api_key_id, route_id,
-                           input_tokens, output_tokens, request_count
+                           input_tokens, output_tokens, request_count, cost
                     FROM token_stats_hourly
                     WHERE route_id = ?1 AND hour_epoch >= ?2
                     ORDER BY hour_epoch ASC
@@ -507,6 +582,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -523,7 +600,7 @@ impl TokenStatsStorage {
                 sqlx::query(
                     r#"
                     SELECT day_epoch as time_bucket, api_key_id, route_id,
-                           input_tokens, output_tokens, request_count
+                           input_tokens, output_tokens, request_count, cost
                     FROM token_stats_daily
                     WHERE route_id = ?1 AND day_epoch >= ?2
                     ORDER BY day_epoch ASC
@@ -541,6 +618,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -563,7 +642,8 @@ impl TokenStatsStorage {
                     SELECT api_key_id,
                            SUM(input_tokens) as input_tokens,
                            SUM(output_tokens) as output_tokens,
-                           SUM(request_count) as request_count
+                           SUM(request_count) as request_count,
+                           SUM(cost) as cost
                     FROM token_stats_hourly
                     WHERE hour_epoch >= ?1
                     GROUP BY api_key_id
@@ -581,6 +661,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -594,7 +676,8 @@ impl TokenStatsStorage {
                     SELECT api_key_id,
                            SUM(input_tokens) as input_tokens,
                            SUM(output_tokens) as output_tokens,
-                           SUM(request_count) as request_count
+                           SUM(request_count) as request_count,
+                           SUM(cost) as cost
                     FROM token_stats_daily
                     WHERE day_epoch >= ?1
                     GROUP BY api_key_id
@@ -612,6 +695,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -625,7 +710,8 @@ impl TokenStatsStorage {
                     SELECT api_key_id,
                            SUM(input_tokens) as input_tokens,
                            SUM(output_tokens) as output_tokens,
-                           SUM(request_count) as request_count
+                           SUM(request_count) as request_count,
+                           SUM(cost) as cost
                     FROM token_stats_daily
                     WHERE day_epoch >= ?1
                     GROUP BY api_key_id
@@ -643,6 +729,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -659,7 +747,7 @@ impl TokenStatsStorage {
         sqlx::query(
             r#"
             SELECT hour_epoch as time_bucket, api_key_id, route_id,
-                   input_tokens, output_tokens, request_count
+                   input_tokens, output_tokens, request_count, cost
             FROM token_stats_hourly
             WHERE hour_epoch >= ?1 AND api_key_id IS NOT NULL
             ORDER BY hour_epoch ASC
@@ -676,6 +764,8 @@ impl TokenStatsStorage {
             input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
             output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
             request_count: row.try_get::<i64, _>("request_count")? as u64,
+            cost: row.try_get("cost")?,
+            model: None,
         }))
         .collect()
     }
@@ -690,7 +780,7 @@ impl TokenStatsStorage {
         sqlx::query(
             r#"
             SELECT hour_epoch as time_bucket, api_key_id, route_id,
-                   input_tokens, output_tokens, request_count
+                   input_tokens, output_tokens, request_count, cost
             FROM token_stats_hourly
             WHERE hour_epoch >= ?1 AND route_id IS NOT NULL
             ORDER BY hour_epoch ASC
@@ -707,6 +797,109 @@ impl TokenStatsStorage {
             input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
             output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
             request_count: row.try_get::<i64, _>("request_count")? as u64,
+            cost: row.try_get("cost")?,
+            model: None,
+        }))
+        .collect()
+    }
+
+    /// 查询模型的统计数据用于恢复（从明细表按小时聚合）
+    pub async fn query_model_stats_for_restore(
+        &self,
+        day_start: i64,
+    ) -> Result<Vec<TokenStatsRow>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT (timestamp / 3600) * 3600 as time_bucket, model,
+                   SUM(input_tokens) as input_tokens,
+                   SUM(output_tokens) as output_tokens,
+                   COUNT(*) as request_count,
+                   SUM(cost) as cost
+            FROM token_usage_records
+            WHERE timestamp >= ?1 AND model IS NOT NULL
+            GROUP BY time_bucket, model
+            ORDER BY time_bucket ASC
+            "#,
+        )
+        .bind(day_start)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok(TokenStatsRow {
+            time_bucket: row.try_get("time_bucket")?,
+            api_key_id: None,
+            route_id: None,
+            input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
+            output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
+            request_count: row.try_get::<i64, _>("request_count")? as u64,
+            cost: row.try_get("cost")?,
+            model: row.try_get("model")?,
+        }))
+        .collect()
+    }
+
+    /// 查询小时级用量用于恢复配额窗口（从小时级聚合表）
+    pub async fn query_hourly_usage_for_restore(
+        &self,
+        start: i64,
+    ) -> Result<Vec<TokenStatsRow>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT hour_epoch as time_bucket, api_key_id, route_id,
+                   input_tokens, output_tokens, request_count, cost
+            FROM token_stats_hourly
+            WHERE hour_epoch >= ?1
+            ORDER BY hour_epoch ASC
+            "#,
+        )
+        .bind(start)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok(TokenStatsRow {
+            time_bucket: row.try_get("time_bucket")?,
+            api_key_id: row.try_get("api_key_id")?,
+            route_id: row.try_get("route_id")?,
+            input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
+            output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
+            request_count: row.try_get::<i64, _>("request_count")? as u64,
+            cost: row.try_get("cost")?,
+            model: None,
+        }))
+        .collect()
+    }
+
+    /// 查询API Key的天级花费用于恢复费用预算（从日级聚合表）
+    pub async fn query_daily_cost_for_restore(
+        &self,
+        start: i64,
+    ) -> Result<Vec<TokenStatsRow>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT day_epoch as time_bucket, api_key_id,
+                   SUM(input_tokens) as input_tokens,
+                   SUM(output_tokens) as output_tokens,
+                   SUM(request_count) as request_count,
+                   SUM(cost) as cost
+            FROM token_stats_daily
+            WHERE day_epoch >= ?1 AND api_key_id IS NOT NULL
+            GROUP BY day_epoch, api_key_id
+            ORDER BY day_epoch ASC
+            "#,
+        )
+        .bind(start)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok(TokenStatsRow {
+            time_bucket: row.try_get("time_bucket")?,
+            api_key_id: row.try_get("api_key_id")?,
+            route_id: None,
+            input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
+            output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
+            request_count: row.try_get::<i64, _>("request_count")? as u64,
+            cost: row.try_get("cost")?,
+            model: None,
         }))
         .collect()
     }
@@ -728,7 +921,8 @@ impl TokenStatsStorage {
                     SELECT hour_epoch as time_bucket,
                            SUM(input_tokens) as input_tokens,
                            SUM(output_tokens) as output_tokens,
-                           SUM(request_count) as request_count
+                           SUM(request_count) as request_count,
+                           SUM(cost) as cost
                     FROM token_stats_hourly
                     WHERE hour_epoch >= ?1
                     GROUP BY hour_epoch
@@ -746,6 +940,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
@@ -765,7 +961,8 @@ impl TokenStatsStorage {
                     SELECT day_epoch as time_bucket,
                            SUM(input_tokens) as input_tokens,
                            SUM(output_tokens) as output_tokens,
-                           SUM(request_count) as request_count
+                           SUM(request_count) as request_count,
+                           SUM(cost) as cost
                     FROM token_stats_daily
                     WHERE day_epoch >= ?1
                     GROUP BY day_epoch
@@ -783,6 +980,8 @@ impl TokenStatsStorage {
                     input_tokens: row.try_get::<i64, _>("input_tokens")? as u64,
                     output_tokens: row.try_get::<i64, _>("output_tokens")? as u64,
                     request_count: row.try_get::<i64, _>("request_count")? as u64,
+                    cost: row.try_get("cost")?,
+                    model: None,
                 }))
                 .collect()
             }
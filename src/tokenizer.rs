@@ -0,0 +1,395 @@
+//! 内置 BPE 分词器，用于在转发前预估请求的输入 token 数
+//!
+//! 词表使用 tiktoken 格式的本地文件（每行 `base64(token) rank`），
+//! 如 `cl100k_base.tiktoken`、`o200k_base.tiktoken`。
+
+use crate::config::{TokenizerConfig, TokenizerEncoding};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use fancy_regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/// 每条 chat 消息的固定开销（参考 OpenAI 的计算方式）
+const TOKENS_PER_MESSAGE: u64 = 3;
+/// chat 回复的起始开销
+const TOKENS_PER_REPLY: u64 = 3;
+/// 单次 BPE 合并的最大分片长度；合并为平方复杂度，超长分片（如大段空白）按该长度切块分别合并
+const MAX_MERGE_PIECE_BYTES: usize = 512;
+
+/// 单个 BPE 编码
+pub struct BpeEncoding {
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl BpeEncoding {
+    /// 从 tiktoken 格式文本加载
+    pub fn from_tiktoken(contents: &str, pattern: &str) -> Result<Self, String> {
+        let mut ranks = HashMap::new();
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("line {}: expected `<base64> <rank>`", line_no + 1))?;
+            let token = STANDARD
+                .decode(token)
+                .map_err(|e| format!("line {}: invalid base64: {e}", line_no + 1))?;
+            let rank = rank
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("line {}: invalid rank: {e}", line_no + 1))?;
+            ranks.insert(token, rank);
+        }
+        if ranks.is_empty() {
+            return Err("vocabulary is empty".to_string());
+        }
+
+        let pattern = Regex::new(pattern).map_err(|e| format!("invalid split pattern: {e}"))?;
+        Ok(Self { ranks, pattern })
+    }
+
+    /// 统计文本的 token 数
+    pub fn count(&self, text: &str) -> u64 {
+        let mut total = 0u64;
+        for piece in self.pattern.find_iter(text) {
+            let Ok(piece) = piece else {
+                // 回溯超限时按字节数保守估算
+                return total + text.len() as u64;
+            };
+            let bytes = piece.as_str().as_bytes();
+            total += if self.ranks.contains_key(bytes) {
+                1
+            } else {
+                bytes
+                    .chunks(MAX_MERGE_PIECE_BYTES)
+                    .map(|chunk| self.byte_pair_merge(chunk) as u64)
+                    .sum()
+            };
+        }
+        total
+    }
+
+    /// 对单个分片执行 BPE 合并，返回合并后的 token 数
+    fn byte_pair_merge(&self, piece: &[u8]) -> usize {
+        // 每个元素为 (分片起始位置, 与下一个分片合并后的 rank)
+        let rank_of = |parts: &[(usize, u32)], i: usize| -> u32 {
+            if i + 2 < parts.len() {
+                self.ranks
+                    .get(&piece[parts[i].0..parts[i + 2].0])
+                    .copied()
+                    .unwrap_or(u32::MAX)
+            } else {
+                u32::MAX
+            }
+        };
+
+        let mut parts: Vec<(usize, u32)> = (0..=piece.len()).map(|i| (i, u32::MAX)).collect();
+        for i in 0..parts.len().saturating_sub(2) {
+            parts[i].1 = rank_of(&parts, i);
+        }
+
+        while parts.len() > 1 {
+            let Some((min_index, &(_, min_rank))) = parts[..parts.len() - 1]
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, rank))| *rank)
+            else {
+                break;
+            };
+            if min_rank == u32::MAX {
+                break;
+            }
+
+            parts.remove(min_index + 1);
+            parts[min_index].1 = rank_of(&parts, min_index);
+            if min_index > 0 {
+                parts[min_index - 1].1 = rank_of(&parts, min_index - 1);
+            }
+        }
+
+        parts.len() - 1
+    }
+}
+
+/// 请求的输入 token 预估结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptEstimate {
+    pub prompt_tokens: u64,
+}
+
+/// 内置分词器，按模型选择编码
+pub struct Tokenizer {
+    encodings: HashMap<TokenizerEncoding, BpeEncoding>,
+    default_encoding: Option<TokenizerEncoding>,
+}
+
+impl Tokenizer {
+    pub fn from_config(config: &TokenizerConfig) -> Result<Self, String> {
+        let mut encodings = HashMap::new();
+        for (encoding, path) in [
+            (TokenizerEncoding::Cl100kBase, config.cl100k_base_path.as_deref()),
+            (TokenizerEncoding::O200kBase, config.o200k_base_path.as_deref()),
+        ] {
+            let Some(path) = path else {
+                continue;
+            };
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read tokenizer vocabulary '{path}': {e}"))?;
+            let bpe = BpeEncoding::from_tiktoken(&contents, encoding.split_pattern())
+                .map_err(|e| format!("failed to load tokenizer vocabulary '{path}': {e}"))?;
+            encodings.insert(encoding, bpe);
+        }
+
+        Ok(Self {
+            encodings,
+            default_encoding: config.default_encoding,
+        })
+    }
+
+    /// 根据模型名选择编码，未加载的编码回退到任一可用编码
+    fn encoding_for_model(&self, model: Option<&str>) -> Option<&BpeEncoding> {
+        let preferred = model
+            .map(TokenizerEncoding::for_model)
+            .or(self.default_encoding)
+            .unwrap_or(TokenizerEncoding::Cl100kBase);
+        self.encodings
+            .get(&preferred)
+            .or_else(|| self.default_encoding.and_then(|e| self.encodings.get(&e)))
+            .or_else(|| self.encodings.values().next())
+    }
+
+    /// 统计一段文本的 token 数
+    pub fn count_text(&self, model: Option<&str>, text: &str) -> Option<u64> {
+        self.encoding_for_model(model).map(|bpe| bpe.count(text))
+    }
+
+    /// 预估 chat / completions / embeddings 请求体的输入 token 数
+    ///
+    /// 无法解析为 JSON 或没有可用编码时返回 `None`。
+    pub fn estimate_request(&self, body: &[u8]) -> Option<PromptEstimate> {
+        let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+        let model = json.get("model").and_then(|m| m.as_str());
+        let bpe = self.encoding_for_model(model)?;
+
+        let mut prompt_tokens = 0u64;
+        if let Some(messages) = json.get("messages").and_then(|m| m.as_array()) {
+            // chat 格式（OpenAI chat/completions、Claude messages）
+            for message in messages {
+                prompt_tokens += TOKENS_PER_MESSAGE + count_value(bpe, message);
+            }
+            prompt_tokens += TOKENS_PER_REPLY;
+        }
+        for field in ["system", "instructions", "prompt", "input"] {
+            if let Some(value) = json.get(field) {
+                prompt_tokens += count_prompt_value(bpe, value);
+            }
+        }
+        if let Some(tools) = json.get("tools") {
+            prompt_tokens += bpe.count(&tools.to_string());
+        }
+
+        Some(PromptEstimate { prompt_tokens })
+    }
+}
+
+/// 请求体中表示输出 token 上限的字段（OpenAI chat/completions、Claude messages、Responses API）
+const MAX_OUTPUT_FIELDS: [&str; 3] = ["max_tokens", "max_completion_tokens", "max_output_tokens"];
+
+/// 把请求体中超过 `limit` 的输出上限字段收紧到 `limit`
+///
+/// 请求未声明任何输出上限时，按请求路径插入该接口的原生字段（见 [`output_limit_field`]）。
+/// 没有需要改动的字段或请求体不是 JSON 对象时返回 `None`。
+pub fn clamp_max_tokens(body: &[u8], limit: u64, path: &str) -> Option<Vec<u8>> {
+    let mut json: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let object = json.as_object_mut()?;
+    let mut changed = false;
+    if !MAX_OUTPUT_FIELDS.iter().any(|field| object.contains_key(*field)) {
+        let field = output_limit_field(path)?;
+        object.insert(field.to_string(), serde_json::Value::from(limit));
+        changed = true;
+    }
+    for field in MAX_OUTPUT_FIELDS {
+        if let Some(value) = object.get_mut(field)
+            && value.as_u64().is_some_and(|current| current > limit)
+        {
+            *value = serde_json::Value::from(limit);
+            changed = true;
+        }
+    }
+    if !changed {
+        return None;
+    }
+    serde_json::to_vec(&json).ok()
+}
+
+/// 生成类接口的原生输出上限字段：Responses API 为 `max_output_tokens`，
+/// chat/completions、completions 与 Claude messages 为 `max_tokens`；其他接口（如 embeddings）返回 `None`
+fn output_limit_field(path: &str) -> Option<&'static str> {
+    let path = path.trim_end_matches('/');
+    if path.ends_with("/responses") {
+        Some("max_output_tokens")
+    } else if path.ends_with("/completions") || path.ends_with("/messages") {
+        Some("max_tokens")
+    } else {
+        None
+    }
+}
+
+/// 请求体中声明的输出 token 上限（多个字段时取最小值）
+pub fn max_output_tokens(body: &[u8]) -> Option<u64> {
+    let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let object = json.as_object()?;
+    MAX_OUTPUT_FIELDS
+        .iter()
+        .filter_map(|field| object.get(*field).and_then(serde_json::Value::as_u64))
+        .min()
+}
+
+/// 统计消息对象中所有文本字段（role、content、name 等）
+fn count_value(bpe: &BpeEncoding, value: &serde_json::Value) -> u64 {
+    match value {
+        serde_json::Value::String(text) => bpe.count(text),
+        serde_json::Value::Array(items) => items.iter().map(|item| count_value(bpe, item)).sum(),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "type" | "image_url" | "source" | "cache_control"))
+            .map(|(_, item)| count_value(bpe, item))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// 统计 prompt / input 字段，支持字符串、字符串数组与预先分好词的 token 数组
+fn count_prompt_value(bpe: &BpeEncoding, value: &serde_json::Value) -> u64 {
+    match value {
+        serde_json::Value::Array(items) if items.iter().all(|item| item.is_number()) => {
+            items.len() as u64
+        }
+        serde_json::Value::Array(items) => items.iter().map(|item| count_prompt_value(bpe, item)).sum(),
+        other => count_value(bpe, other),
+    }
+}
+
+impl TokenizerEncoding {
+    fn split_pattern(&self) -> &'static str {
+        match self {
+            TokenizerEncoding::Cl100kBase => CL100K_PATTERN,
+            TokenizerEncoding::O200kBase => O200K_PATTERN,
+        }
+    }
+
+    /// 按模型名前缀推断编码
+    pub fn for_model(model: &str) -> Self {
+        const O200K_PREFIXES: [&str; 5] = ["gpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "chatgpt-4o"];
+        let model = model.rsplit('/').next().unwrap_or(model);
+        let is_o_series = model.starts_with('o')
+            && model[1..].chars().next().is_some_and(|c| c.is_ascii_digit());
+        if is_o_series || O200K_PREFIXES.iter().any(|prefix| model.starts_with(prefix)) {
+            TokenizerEncoding::O200kBase
+        } else {
+            TokenizerEncoding::Cl100kBase
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造一个只包含单字节 token 和少量合并规则的小词表
+    fn tiny_encoding() -> BpeEncoding {
+        let mut lines = Vec::new();
+        for byte in 0u8..=255 {
+            lines.push(format!("{} {}", STANDARD.encode([byte]), byte as u32));
+        }
+        for (rank, token) in ["he", "ll", "hell", "hello", " w", "or", " wor"].iter().enumerate() {
+            lines.push(format!("{} {}", STANDARD.encode(token), 256 + rank));
+        }
+        BpeEncoding::from_tiktoken(&lines.join("\n"), CL100K_PATTERN).expect("vocab should load")
+    }
+
+    #[test]
+    fn bpe_merges_known_pairs() {
+        let bpe = tiny_encoding();
+        assert_eq!(bpe.count("hello"), 1);
+        // " world" -> " wor" + "l" + "d"
+        assert_eq!(bpe.count(" world"), 3);
+        assert_eq!(bpe.count("hello world"), 4);
+        assert_eq!(bpe.count(""), 0);
+        // 超长分片按块合并，不退化为平方复杂度
+        assert_eq!(bpe.count(&"ll".repeat(50_000)), 50_000);
+    }
+
+    #[test]
+    fn estimate_chat_request_adds_message_overhead() {
+        let tokenizer = Tokenizer {
+            encodings: HashMap::from([(TokenizerEncoding::Cl100kBase, tiny_encoding())]),
+            default_encoding: None,
+        };
+        let body = br#"{"model":"gpt-4o","messages":[{"role":"user","content":"hello"}]}"#;
+        // role "user" = 4 字节，content "hello" = 1，加上消息与回复开销
+        assert_eq!(
+            tokenizer.estimate_request(body),
+            Some(PromptEstimate {
+                prompt_tokens: TOKENS_PER_MESSAGE + 4 + 1 + TOKENS_PER_REPLY,
+            })
+        );
+
+        let embeddings = br#"{"model":"text-embedding-3-small","input":[[1,2,3],"hello"]}"#;
+        assert_eq!(
+            tokenizer.estimate_request(embeddings),
+            Some(PromptEstimate { prompt_tokens: 4 })
+        );
+        assert_eq!(tokenizer.estimate_request(b"not json"), None);
+    }
+
+    #[test]
+    fn clamp_max_tokens_only_lowers_existing_fields() {
+        let body = br#"{"model":"gpt-4o","max_tokens":4096,"max_completion_tokens":100}"#;
+        let clamped = clamp_max_tokens(body, 500, "/v1/chat/completions").expect("max_tokens should be clamped");
+        let json: serde_json::Value = serde_json::from_slice(&clamped).unwrap();
+        assert_eq!(json["max_tokens"], 500);
+        assert_eq!(json["max_completion_tokens"], 100);
+        assert!(json.get("max_output_tokens").is_none());
+
+        assert_eq!(clamp_max_tokens(br#"{"max_tokens":100}"#, 500, "/v1/chat/completions"), None);
+        assert_eq!(clamp_max_tokens(b"not json", 500, "/v1/chat/completions"), None);
+    }
+
+    #[test]
+    fn clamp_max_tokens_inserts_native_field_when_absent() {
+        let body = br#"{"model":"gpt-4o","messages":[]}"#;
+        let clamped = clamp_max_tokens(body, 500, "/openai/v1/chat/completions").expect("limit should be inserted");
+        let json: serde_json::Value = serde_json::from_slice(&clamped).unwrap();
+        assert_eq!(json["max_tokens"], 500);
+        assert!(json.get("max_completion_tokens").is_none());
+
+        let clamped = clamp_max_tokens(br#"{"input":"hi"}"#, 500, "/v1/responses").expect("limit should be inserted");
+        let json: serde_json::Value = serde_json::from_slice(&clamped).unwrap();
+        assert_eq!(json["max_output_tokens"], 500);
+
+        // 非生成类接口不插入
+        assert_eq!(clamp_max_tokens(br#"{"input":"hi"}"#, 500, "/v1/embeddings"), None);
+    }
+
+    #[test]
+    fn model_encoding_selection() {
+        assert_eq!(TokenizerEncoding::for_model("gpt-4o-mini"), TokenizerEncoding::O200kBase);
+        assert_eq!(TokenizerEncoding::for_model("o3-mini"), TokenizerEncoding::O200kBase);
+        assert_eq!(TokenizerEncoding::for_model("openai/gpt-4.1"), TokenizerEncoding::O200kBase);
+        assert_eq!(TokenizerEncoding::for_model("gpt-3.5-turbo"), TokenizerEncoding::Cl100kBase);
+        assert_eq!(TokenizerEncoding::for_model("claude-3-opus"), TokenizerEncoding::Cl100kBase);
+    }
+}
@@ -1,10 +1,9 @@
 use ai_gw_lite::config::{
-    ApiKeyConfig, ApiKeysGlobalConfig, AppConfig, ConcurrencyConfig, CorsConfig, GatewayAuthConfig,
+    AdminConfig, ApiKeyConfig, ApiKeysGlobalConfig, ApiKeysSqliteConfig, AppConfig, AuthProtectionConfig, BanAction, BanCondition, BanRule, BanRuleMode, BanSubject, ConcurrencyConfig, CorsConfig, ForwardAuthConfig, GatewayAuthConfig,
     HeaderInjection, LogFormat, LoggingConfig, MetricsConfig, ObservabilityConfig, ProxyProtocol,
-    RateLimitConfig, RouteConfig, TokenSourceConfig, TracingConfig, UpstreamConfig,
+    RateLimitConfig, RouteAuthConfig, RouteAuthMode, RouteConfig, RouteRateLimitConfig, TokenRateLimitConfig, TokenSourceConfig, TokenStatsConfig, TracingConfig, UpstreamConfig,
     UpstreamProxyConfig,
 };
-use ai_gw_lite::observability;
 use ai_gw_lite::server::build_app;
 use axum::Router;
 use std::path::PathBuf;
@@ -168,6 +167,86 @@ async fn proxy_passes_sse_response() {
     upstream_handle.abort();
 }
 
+#[tokio::test]
+async fn sse_stream_is_cut_off_when_output_exceeds_key_limit() {
+    let upstream = Router::new().route("/v1/chat/completions", post(upstream_sse_endless_deltas));
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.api_keys.as_mut().expect("api keys").keys[0].max_output_tokens_per_request = Some(3);
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{gateway_addr}/openai/v1/chat/completions"))
+        .header("authorization", "Bearer gw_token")
+        .body(r#"{"model":"gpt-4o","stream":true,"messages":[{"role":"user","content":"hi"}]}"#)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 上游持续输出，网关截断后响应应当结束
+    let bytes = tokio::time::timeout(Duration::from_secs(2), response.bytes())
+        .await
+        .expect("stream should end after cutoff")
+        .expect("body should be readable");
+    let body = String::from_utf8(bytes.to_vec()).expect("body should be utf8");
+    assert_eq!(body.matches(r#""content":"abcd""#).count(), 3);
+    assert!(body.ends_with(
+        "event: error\ndata: {\"error\":\"max_output_tokens_exceeded\",\"limit\":3,\"output_tokens\":4}\n\n"
+    ));
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn oversized_request_body_is_charged_against_key_tpm() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 10_000);
+    config.token_stats = Some(TokenStatsConfig {
+        enabled: true,
+        sqlite: None,
+    });
+    config.api_keys.as_mut().expect("api keys").keys[0].token_rate_limit = Some(TokenRateLimitConfig {
+        tokens_per_minute: Some(1_000_000),
+        tokens_per_day: None,
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{gateway_addr}/openai/v1/chat/completions");
+
+    let small = client
+        .post(&url)
+        .header("authorization", "Bearer gw_token")
+        .body(r#"{"model":"gpt-4o","messages":[{"role":"user","content":"hi"}]}"#)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(small.status(), StatusCode::OK);
+
+    // 超过 32 MiB 缓冲上限的请求体按字节数预估（约 860 万 token），超出 TPM 被拒绝
+    let oversized = client
+        .post(&url)
+        .header("authorization", "Bearer gw_token")
+        .body(vec![b'a'; 33 * 1024 * 1024])
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(oversized.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        oversized.text().await.expect("body should be readable"),
+        r#"{"error":"token_rate_limited","dimension":"api_key","window":"minute"}"#
+    );
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
 #[tokio::test]
 async fn sse_is_not_cut_by_request_timeout() {
     let upstream = Router::new().route("/v1/sse-slow", get(upstream_sse_slow));
@@ -277,7 +356,56 @@ async fn rate_limit_rejects_excess_downstream_requests() {
             .await
             .expect("body should be readable")
             .as_str(),
-        r#"{"error":"rate_limited"}"#
+        r#"{"error":"rate_limited","dimension":"global"}"#
+    );
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn route_rate_limit_reports_triggered_dimension() {
+    let upstream = Router::new()
+        .route("/v1/echo", post(upstream_echo))
+        .with_state(UpstreamCapture::default());
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.rate_limit = Some(RateLimitConfig { per_minute: 10 });
+    config.routes.as_mut().expect("routes")[0].rate_limit = Some(RouteRateLimitConfig {
+        per_minute: Some(10),
+        per_key_per_minute: Some(1),
+        per_ip_per_minute: None,
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let client = reqwest::Client::new();
+    let first = client
+        .post(format!("http://{gateway_addr}/openai/v1/echo"))
+        .header("authorization", "Bearer gw_token")
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(first.status(), StatusCode::OK);
+
+    let second = client
+        .post(format!("http://{gateway_addr}/openai/v1/echo"))
+        .header("authorization", "Bearer gw_token")
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(second.headers().contains_key("retry-after"));
+    assert_eq!(
+        second
+            .text()
+            .await
+            .expect("body should be readable")
+            .as_str(),
+        r#"{"error":"rate_limited","dimension":"api_key_route"}"#
     );
 
     gateway_handle.abort();
@@ -870,8 +998,9 @@ async fn metrics_summary_endpoint_reports_route_and_token_windows() {
     assert!(body.contains("\"route_id\":\"openai\""));
     assert!(body.contains("\"requests_1h\":2"));
     assert!(body.contains("\"requests_24h\":2"));
-    let token_label = observability::token_label("gw_token");
-    assert!(body.contains(format!("\"token\":\"{token_label}\"").as_str()));
+    // 指标以 API Key ID 作为标签，不暴露原始 Key
+    assert!(body.contains("\"token\":\"default\""));
+    assert!(!body.contains("gw_token"));
 
     gateway_handle.abort();
     upstream_handle.abort();
@@ -922,126 +1051,1374 @@ async fn metrics_ui_endpoint_serves_html_dashboard() {
     gateway_handle.abort();
 }
 
-async fn spawn_router(router: Router) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
-    use std::net::SocketAddr;
+#[tokio::test]
+async fn admin_api_key_crud_applies_without_reload() {
+    let capture = UpstreamCapture::default();
+    let upstream = Router::new()
+        .route("/v1/echo", post(upstream_echo))
+        .with_state(capture);
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let key_url = format!("http://{gateway_addr}/admin/api/keys/batch");
+    let echo_url = format!("http://{gateway_addr}/openai/v1/echo");
+
+    // 密钥由服务端生成，仅在创建响应中返回
+    let created = client
+        .post(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "remark": "batch jobs", "route_ids": ["openai"] }).to_string())
+        .send()
         .await
-        .expect("listener should bind");
-    let addr = listener
-        .local_addr()
-        .expect("listener should have local addr");
+        .expect("request should succeed");
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let etag = created
+        .headers()
+        .get("etag")
+        .and_then(|value| value.to_str().ok())
+        .expect("etag should be present")
+        .to_string();
+    let created: serde_json::Value =
+        serde_json::from_str(&created.text().await.expect("body should be readable"))
+            .expect("body should be json");
+    let secret = created["key"].as_str().expect("secret should be returned").to_string();
+    assert!(secret.starts_with("sk-openai-"));
+    assert_eq!(created["api_key"]["key"], serde_json::Value::Null);
+
+    let duplicate = client
+        .post(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(duplicate.status(), StatusCode::CONFLICT);
 
-    let handle = tokio::spawn(async move {
-        axum::serve(
-            listener,
-            router.into_make_service_with_connect_info::<SocketAddr>(),
-        )
+    let proxied = client
+        .post(&echo_url)
+        .header("authorization", format!("Bearer {secret}"))
+        .body("hello")
+        .send()
         .await
-        .expect("server should run");
-    });
+        .expect("request should succeed");
+    assert_eq!(proxied.status(), StatusCode::OK);
+
+    let immutable = client
+        .patch(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header("if-match", &etag)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "key_hash": "sha256$salt$digest" }).to_string())
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(immutable.status(), StatusCode::BAD_REQUEST);
 
-    (addr, handle)
-}
+    let missing_etag = client
+        .patch(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "enabled": false }).to_string())
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(missing_etag.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    let stale_etag = client
+        .patch(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header("if-match", "\"0000000000000000\"")
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "enabled": false }).to_string())
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(stale_etag.status(), StatusCode::PRECONDITION_FAILED);
+
+    let disabled = client
+        .patch(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header("if-match", &etag)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "enabled": false }).to_string())
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(disabled.status(), StatusCode::OK);
+    let etag = disabled
+        .headers()
+        .get("etag")
+        .and_then(|value| value.to_str().ok())
+        .expect("etag should be present")
+        .to_string();
 
-fn unused_local_addr() -> std::net::SocketAddr {
-    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
-    let addr = listener
-        .local_addr()
-        .expect("local addr should be available");
-    drop(listener);
-    addr
-}
+    let rejected = client
+        .post(&echo_url)
+        .header("authorization", format!("Bearer {secret}"))
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_ne!(rejected.status(), StatusCode::OK);
 
-fn gateway_config(upstream_addr: String, request_timeout_ms: u64) -> AppConfig {
-    AppConfig {
-        listen: "127.0.0.1:8080".to_string(),
-        gateway_auth: GatewayAuthConfig {
-            token_sources: vec![TokenSourceConfig::AuthorizationBearer],
-        },
-        data_dir: None,
-        routes: Some(vec![RouteConfig {
-            id: "openai".to_string(),
-            prefix: "/openai".to_string(),
-            upstream: UpstreamConfig {
-                base_url: format!("http://{upstream_addr}"),
-                strip_prefix: true,
-                connect_timeout_ms: 1_000,
-                request_timeout_ms,
-                inject_headers: vec![HeaderInjection {
-                    name: "authorization".to_string(),
-                    value: "Bearer injected-upstream-token".to_string(),
-                }],
-                remove_headers: vec![
-                    "authorization".to_string(),
-                    "x-forwarded-for".to_string(),
-                    "forwarded".to_string(),
-                    "cf-connecting-ip".to_string(),
-                    "true-client-ip".to_string(),
-                ],
-                forward_xff: false,
-                proxy: None,
-                upstream_key_max_inflight: None,
-                user_agent: None,
-            },
-        }]),
-        api_keys: Some(ApiKeysGlobalConfig {
-            keys: vec![ApiKeyConfig {
-                id: "default".to_string(),
-                route_id: None,
-                route_ids: None,
-                key: "gw_token".to_string(),
-                enabled: true,
-                remark: String::new(),
-                rate_limit: None,
-                concurrency: None,
-                ban_rules: Vec::new(),
-                ban_status: None,
-            }],
-            ban_rules: Vec::new(),
-            sqlite: None,
-        }),
-        inbound_tls: None,
-        cors: None,
-        rate_limit: None,
-        concurrency: None,
-        observability: None,
-        admin: None,
-    }
-}
+    // 其他 Key 不受影响
+    let other = client
+        .post(&echo_url)
+        .header("authorization", "Bearer gw_token")
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(other.status(), StatusCode::OK);
 
-async fn upstream_echo(
-    State(capture): State<UpstreamCapture>,
-    headers: HeaderMap,
-    body: Bytes,
-) -> StatusCode {
-    let auth = headers
-        .get("authorization")
-        .and_then(|value| value.to_str().ok())
-        .map(ToString::to_string);
-    let xff = headers
-        .get("x-forwarded-for")
-        .and_then(|value| value.to_str().ok())
-        .map(ToString::to_string);
+    let deleted = client
+        .delete(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .header("if-match", &etag)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(deleted.status(), StatusCode::OK);
 
-    *capture.authorization.lock().expect("lock should succeed") = auth;
-    *capture.x_forwarded_for.lock().expect("lock should succeed") = xff;
+    let missing = client
+        .get(&key_url)
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
 
-    if body == Bytes::from_static(b"hello") {
-        StatusCode::OK
-    } else {
-        StatusCode::BAD_REQUEST
-    }
+    gateway_handle.abort();
+    upstream_handle.abort();
 }
 
-async fn upstream_echo_user_agent(headers: HeaderMap) -> Response<Body> {
-    let user_agent = headers
-        .get("user-agent")
-        .and_then(|value| value.to_str().ok())
-        .unwrap_or_default()
-        .to_string();
+#[tokio::test]
+async fn admin_api_key_rotation_keeps_old_secret_during_grace_period() {
+    let capture = UpstreamCapture::default();
+    let upstream = Router::new()
+        .route("/v1/echo", post(upstream_echo))
+        .with_state(capture);
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
 
-    Response::builder()
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let key_url = format!("http://{gateway_addr}/admin/api/keys/rotating");
+    let echo_url = format!("http://{gateway_addr}/openai/v1/echo");
+
+    let issue_secret = |response: reqwest::Response| async move {
+        assert!(response.status().is_success());
+        let body: serde_json::Value =
+            serde_json::from_str(&response.text().await.expect("body should be readable"))
+                .expect("body should be json");
+        body["key"].as_str().expect("secret should be returned").to_string()
+    };
+    let call = |secret: String| {
+        client
+            .post(&echo_url)
+            .header("authorization", format!("Bearer {secret}"))
+            .body("hello")
+            .send()
+    };
+
+    let first = issue_secret(
+        client
+            .post(&key_url)
+            .header("authorization", "Bearer admin_token")
+            .send()
+            .await
+            .expect("request should succeed"),
+    )
+    .await;
+    let second = issue_secret(
+        client
+            .post(format!("{key_url}/rotate"))
+            .header("authorization", "Bearer admin_token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"grace_period_secs":3600}"#)
+            .send()
+            .await
+            .expect("request should succeed"),
+    )
+    .await;
+    assert_ne!(first, second);
+
+    // 宽限期内新旧密钥都可用
+    let old_secret = call(first.clone()).await.expect("request should succeed");
+    assert_eq!(old_secret.status(), StatusCode::OK);
+    let new_secret = call(second.clone()).await.expect("request should succeed");
+    assert_eq!(new_secret.status(), StatusCode::OK);
+
+    let usage = client
+        .get(format!("{key_url}/previous-keys"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(usage.status(), StatusCode::OK);
+    let usage: serde_json::Value =
+        serde_json::from_str(&usage.text().await.expect("body should be readable"))
+            .expect("body should be json");
+    assert_eq!(usage["previous_keys"][0]["uses"], 1);
+    assert_eq!(usage["previous_keys"][0]["active"], true);
+
+    // 不保留宽限期时被替换的密钥立即失效，更早的旧密钥按自己的宽限期继续有效
+    let third = issue_secret(
+        client
+            .post(format!("{key_url}/rotate"))
+            .header("authorization", "Bearer admin_token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"grace_period_secs":0}"#)
+            .send()
+            .await
+            .expect("request should succeed"),
+    )
+    .await;
+    let revoked = call(second).await.expect("request should succeed");
+    assert_eq!(revoked.status(), StatusCode::UNAUTHORIZED);
+    let still_valid = call(first).await.expect("request should succeed");
+    assert_eq!(still_valid.status(), StatusCode::OK);
+    let current = call(third).await.expect("request should succeed");
+    assert_eq!(current.status(), StatusCode::OK);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn admin_route_crud_supports_dry_run_and_field_errors() {
+    let capture = UpstreamCapture::default();
+    let upstream = Router::new()
+        .route("/v1/echo", post(upstream_echo))
+        .with_state(capture);
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let route_url = format!("http://{gateway_addr}/admin/api/routes/mirror");
+    let mirror_url = format!("http://{gateway_addr}/mirror/v1/echo");
+    let route_body = serde_json::json!({
+        "prefix": "/mirror",
+        "upstream": { "base_url": format!("http://{upstream_addr}") }
+    })
+    .to_string();
+
+    let dry_run = client
+        .post(format!("{route_url}?dry_run=true"))
+        .header("authorization", "Bearer admin_token")
+        .header(CONTENT_TYPE, "application/json")
+        .body(route_body.clone())
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(dry_run.status(), StatusCode::OK);
+    let not_created = client
+        .get(&route_url)
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(not_created.status(), StatusCode::NOT_FOUND);
+
+    let created = client
+        .post(&route_url)
+        .header("authorization", "Bearer admin_token")
+        .header(CONTENT_TYPE, "application/json")
+        .body(route_body)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(created.status(), StatusCode::CREATED);
+
+    let proxied = client
+        .post(&mirror_url)
+        .header("authorization", "Bearer gw_token")
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(proxied.status(), StatusCode::OK);
+
+    let invalid = client
+        .put(&route_url)
+        .header("authorization", "Bearer admin_token")
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({
+                "prefix": "/openai",
+                "upstream": { "base_url": "", "request_timeout_ms": 0 }
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    let invalid: serde_json::Value =
+        serde_json::from_str(&invalid.text().await.expect("body should be readable"))
+            .expect("body should be json");
+    let fields: Vec<_> = invalid["errors"]
+        .as_array()
+        .expect("errors should be listed")
+        .iter()
+        .map(|err| err["field"].as_str().unwrap_or_default().to_string())
+        .collect();
+    assert_eq!(
+        fields,
+        vec!["upstream.base_url", "upstream.request_timeout_ms", "prefix"]
+    );
+
+    let deleted = client
+        .delete(&route_url)
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(deleted.status(), StatusCode::OK);
+
+    let removed = client
+        .post(&mirror_url)
+        .header("authorization", "Bearer gw_token")
+        .body("hello")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(removed.status(), StatusCode::NOT_FOUND);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn jwt_token_source_maps_claims_to_keys_and_dynamic_identities() {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let upstream = Router::new()
+        .route("/v1/echo", post(upstream_echo))
+        .with_state(UpstreamCapture::default());
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    let jwt_source: TokenSourceConfig = serde_yaml::from_str(
+        r#"
+type: jwt
+keys:
+  - kid: "sso-1"
+    alg: HS256
+    secret: "jwt_secret"
+issuer: "https://sso.example.com"
+audiences: ["ai-gw"]
+key_id_claim: "gw_key"
+template_key: "default"
+route_ids_claim: "routes"
+rate_limit_claim: "rpm"
+"#,
+    )
+    .expect("jwt source should parse");
+    config.gateway_auth.token_sources.insert(0, jwt_source);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let sign = |claims: serde_json::Value| {
+        let header = serde_json::json!({"alg": "HS256", "typ": "JWT", "kid": "sso-1"});
+        let input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"jwt_secret");
+        let tag = ring::hmac::sign(&key, input.as_bytes());
+        format!("{input}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    };
+    let claims = |extra: serde_json::Value| {
+        let mut claims = serde_json::json!({
+            "sub": "alice",
+            "iss": "https://sso.example.com",
+            "aud": "ai-gw",
+            "exp": now + 300,
+        });
+        claims.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        claims
+    };
+
+    let client = reqwest::Client::new();
+    let send = |token: String| {
+        let request = client
+            .post(format!("http://{gateway_addr}/openai/v1/echo"))
+            .header("authorization", format!("Bearer {token}"))
+            .body("hello");
+        async move { request.send().await.expect("request should succeed") }
+    };
+
+    // 映射到已有 Key
+    let mapped = send(sign(claims(serde_json::json!({"gw_key": "default"})))).await;
+    assert_eq!(mapped.status(), StatusCode::OK);
+
+    // 不透明 API Key 继续由后续来源处理
+    assert_eq!(send("gw_token".to_string()).await.status(), StatusCode::OK);
+
+    let expired = send(sign(claims(serde_json::json!({"gw_key": "default", "exp": now - 600})))).await;
+    assert_eq!(expired.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(expired.text().await.unwrap(), r#"{"error":"invalid_token"}"#);
+
+    // 动态身份：每分钟请求数取自 claim
+    let dynamic = sign(claims(serde_json::json!({"rpm": 1})));
+    assert_eq!(send(dynamic.clone()).await.status(), StatusCode::OK);
+    assert_eq!(send(dynamic).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // 动态身份只在缓存中，不出现在 Key 列表里
+    let listed = client
+        .get(format!("http://{gateway_addr}/admin/api/keys"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    let listed: serde_json::Value = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+    let ids: Vec<_> = listed["keys"].as_array().unwrap().iter().map(|key| key["id"].clone()).collect();
+    assert_eq!(ids, vec![serde_json::json!("default")]);
+
+    let other_route = send(sign(claims(serde_json::json!({"sub": "bob", "routes": ["anthropic"]})))).await;
+    assert_eq!(other_route.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(other_route.text().await.unwrap(), r#"{"error":"api_key_route_not_allowed"}"#);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn forward_auth_allows_denies_and_caches_decisions() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let auth_calls = Arc::new(AtomicUsize::new(0));
+    let authz = Router::new()
+        .route(
+            "/authz",
+            post(|State(calls): State<Arc<AtomicUsize>>, body: Bytes| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let request: serde_json::Value = serde_json::from_slice(&body).expect("authz body should be JSON");
+                assert_eq!(request["key_id"], "default");
+                assert_eq!(request["path"], "/openai/v1/whoami");
+                if request["headers"]["x-tenant"] == "acme" {
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header("x-user", "alice")
+                        .body(Body::empty())
+                        .expect("response should build")
+                } else {
+                    Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body(Body::empty())
+                        .expect("response should build")
+                }
+            }),
+        )
+        .with_state(auth_calls.clone());
+    let (authz_addr, authz_handle) = spawn_router(authz).await;
+
+    let upstream = Router::new().route(
+        "/v1/whoami",
+        post(|headers: HeaderMap| async move {
+            headers
+                .get("x-user")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        }),
+    );
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.routes.as_mut().unwrap()[0].forward_auth = Some(ForwardAuthConfig {
+        url: format!("http://{authz_addr}/authz"),
+        timeout_ms: 1_000,
+        forward_headers: vec!["x-tenant".to_string()],
+        identity_headers: vec!["x-user".to_string()],
+        cache_ttl_secs: 60,
+        fail_open: false,
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let client = reqwest::Client::new();
+    let send = |tenant: &'static str| {
+        let request = client
+            .post(format!("http://{gateway_addr}/openai/v1/whoami"))
+            .header("authorization", "Bearer gw_token")
+            .header("x-tenant", tenant)
+            .header("x-user", "mallory");
+        async move { request.send().await.expect("request should succeed") }
+    };
+
+    // 放行：身份头取自授权服务，客户端自带的同名头被丢弃
+    let allowed = send("acme").await;
+    assert_eq!(allowed.status(), StatusCode::OK);
+    assert_eq!(allowed.text().await.unwrap(), "alice");
+    let cached = send("acme").await;
+    assert_eq!(cached.text().await.unwrap(), "alice");
+    assert_eq!(auth_calls.load(Ordering::SeqCst), 1);
+
+    let denied = send("other").await;
+    assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+    assert_eq!(denied.text().await.unwrap(), r#"{"error":"forward_auth_denied"}"#);
+
+    // 授权服务不可用时 fail-closed；已缓存的结果仍然有效
+    authz_handle.abort();
+    let _ = authz_handle.await;
+    let unavailable = send("initech").await;
+    assert_eq!(unavailable.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(unavailable.text().await.unwrap(), r#"{"error":"forward_auth_unavailable"}"#);
+    assert_eq!(send("acme").await.status(), StatusCode::OK);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn route_level_token_sources_accept_native_credentials_and_strip_them() {
+    let upstream = Router::new().fallback(|request: Request<Body>| async move {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("-")
+                .to_string()
+        };
+        format!(
+            "query={} x-api-key={} authorization={}",
+            request.uri().query().unwrap_or("-"),
+            header("x-api-key"),
+            header("authorization")
+        )
+    });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    let base_route = config.routes.as_ref().unwrap()[0].clone();
+    let mut gemini = base_route.clone();
+    gemini.id = "gemini".to_string();
+    gemini.prefix = "/gemini".to_string();
+    gemini.upstream.inject_headers = Vec::new();
+    gemini.upstream.remove_headers = Vec::new();
+    gemini.auth = Some(RouteAuthConfig {
+        mode: RouteAuthMode::Required,
+        token_sources: vec![TokenSourceConfig::Query {
+            name: "key".to_string(),
+        }],
+        require_client_cert: false,
+    });
+    let mut anthropic = gemini.clone();
+    anthropic.id = "anthropic".to_string();
+    anthropic.prefix = "/anthropic".to_string();
+    anthropic.upstream.inject_headers = vec![HeaderInjection {
+        name: "x-api-key".to_string(),
+        value: "upstream-secret".to_string(),
+    }];
+    anthropic.auth = Some(RouteAuthConfig {
+        mode: RouteAuthMode::Required,
+        token_sources: vec![TokenSourceConfig::Header {
+            name: "x-api-key".to_string(),
+        }],
+        require_client_cert: false,
+    });
+    config.routes.as_mut().unwrap().extend([gemini, anthropic]);
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let client = reqwest::Client::new();
+    let gemini = client
+        .get(format!("http://{gateway_addr}/gemini/v1/models?alt=sse&key=gw_token"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(gemini.status(), StatusCode::OK);
+    assert_eq!(gemini.text().await.unwrap(), "query=alt=sse x-api-key=- authorization=-");
+
+    let anthropic = client
+        .post(format!("http://{gateway_addr}/anthropic/v1/messages"))
+        .header("x-api-key", "gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(anthropic.status(), StatusCode::OK);
+    assert_eq!(
+        anthropic.text().await.unwrap(),
+        "query=- x-api-key=upstream-secret authorization=-"
+    );
+
+    // 路由级配置替换全局来源
+    let bearer = client
+        .get(format!("http://{gateway_addr}/gemini/v1/models"))
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(bearer.status(), StatusCode::UNAUTHORIZED);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn route_auth_modes_admit_anonymous_traffic_under_ip_rate_limits() {
+    let upstream = Router::new().fallback(|request: Request<Body>| async move {
+        request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("-")
+            .to_string()
+    });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    let base_route = config.routes.as_ref().unwrap()[0].clone();
+    let mut public = base_route.clone();
+    public.id = "public".to_string();
+    public.prefix = "/public".to_string();
+    public.upstream.inject_headers = Vec::new();
+    public.upstream.remove_headers = Vec::new();
+    public.auth = Some(RouteAuthConfig {
+        mode: RouteAuthMode::None,
+        token_sources: Vec::new(),
+        require_client_cert: false,
+    });
+    public.rate_limit = Some(RouteRateLimitConfig {
+        per_minute: None,
+        per_key_per_minute: None,
+        per_ip_per_minute: Some(1),
+    });
+    let mut optional = base_route.clone();
+    optional.id = "optional".to_string();
+    optional.prefix = "/optional".to_string();
+    optional.auth = Some(RouteAuthConfig {
+        mode: RouteAuthMode::Optional,
+        token_sources: Vec::new(),
+        require_client_cert: false,
+    });
+    config.routes.as_mut().unwrap().extend([public, optional]);
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+
+    let client = reqwest::Client::new();
+    // none：不读取也不移除凭证，按客户端 IP 限流
+    let first = client
+        .get(format!("http://{gateway_addr}/public/v1/embeddings"))
+        .header("authorization", "Bearer caller-owned")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.text().await.unwrap(), "Bearer caller-owned");
+
+    let second = client
+        .get(format!("http://{gateway_addr}/public/v1/embeddings"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        second.text().await.unwrap(),
+        r#"{"error":"rate_limited","dimension":"client_ip"}"#
+    );
+
+    // optional：无凭证匿名放行，携带的凭证仍需有效
+    let anonymous = client
+        .get(format!("http://{gateway_addr}/optional/v1/models"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(anonymous.status(), StatusCode::OK);
+    assert_eq!(anonymous.text().await.unwrap(), "Bearer injected-upstream-token");
+
+    let invalid = client
+        .get(format!("http://{gateway_addr}/optional/v1/models"))
+        .header("authorization", "Bearer wrong_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(invalid.status(), StatusCode::UNAUTHORIZED);
+
+    let authenticated = client
+        .get(format!("http://{gateway_addr}/optional/v1/models"))
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(authenticated.status(), StatusCode::OK);
+
+    // 默认 required 路由不受影响
+    let required = client
+        .get(format!("http://{gateway_addr}/openai/v1/models"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(required.status(), StatusCode::UNAUTHORIZED);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn repeated_auth_failures_lock_out_client_ip_for_proxy_and_admin() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    config.trusted_proxies = vec!["127.0.0.1".to_string()];
+    config.auth_protection = Some(AuthProtectionConfig {
+        max_failures: 2,
+        window_secs: 60,
+        lockout_secs: 600,
+        delay_ms: 0,
+        max_delay_ms: 0,
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let proxy_url = format!("http://{gateway_addr}/openai/v1/models");
+    let lockouts_url = format!("http://{gateway_addr}/admin/api/auth-lockouts");
+
+    // 错误的管理 Token 与错误的 API Key 计入同一 IP 的失败次数
+    let wrong_admin = client
+        .get(&lockouts_url)
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("authorization", "Bearer guessed")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(wrong_admin.status(), StatusCode::UNAUTHORIZED);
+    let wrong_key = client
+        .get(&proxy_url)
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("authorization", "Bearer guessed")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(wrong_key.status(), StatusCode::UNAUTHORIZED);
+
+    // 锁定后即使凭证有效也直接拒绝
+    let locked = client
+        .get(&proxy_url)
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(locked.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(locked.headers().contains_key("retry-after"));
+    assert_eq!(locked.text().await.unwrap(), r#"{"error":"auth_locked"}"#);
+    let locked_admin = client
+        .get(&lockouts_url)
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(locked_admin.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // 其他 IP 不受影响，可查看并解除锁定
+    let allowed = client
+        .get(&proxy_url)
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(allowed.status(), StatusCode::OK);
+    let listed = client
+        .get(&lockouts_url)
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(listed.status(), StatusCode::OK);
+    let listed: serde_json::Value = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+    assert_eq!(listed["enabled"], true);
+    assert_eq!(listed["locked"][0]["ip"], "203.0.113.9");
+    assert_eq!(listed["locked"][0]["scope"], "proxy");
+
+    let unlocked = client
+        .delete(format!("{lockouts_url}/203.0.113.9"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(unlocked.status(), StatusCode::OK);
+    let after_unlock = client
+        .get(&proxy_url)
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(after_unlock.status(), StatusCode::OK);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn forwarded_for_is_ignored_unless_peer_is_trusted_proxy() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.auth_protection = Some(AuthProtectionConfig {
+        max_failures: 1,
+        window_secs: 60,
+        lockout_secs: 600,
+        delay_ms: 0,
+        max_delay_ms: 0,
+    });
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let proxy_url = format!("http://{gateway_addr}/openai/v1/models");
+
+    let wrong_key = client
+        .get(&proxy_url)
+        .header("x-forwarded-for", "198.51.100.1")
+        .header("authorization", "Bearer guessed")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(wrong_key.status(), StatusCode::UNAUTHORIZED);
+
+    // 未配置可信代理时伪造的转发头无效，锁定仍作用于连接对端地址
+    let spoofed = client
+        .get(&proxy_url)
+        .header("x-forwarded-for", "198.51.100.2")
+        .header("authorization", "Bearer gw_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(spoofed.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn key_sharing_alert_rule_records_client_ips_without_banning() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    config.trusted_proxies = vec!["127.0.0.1".to_string()];
+    let api_keys = config.api_keys.as_mut().unwrap();
+    api_keys.sqlite = Some(ApiKeysSqliteConfig {
+        path: temp_config_db_path()
+            .with_file_name("ban_logs.db")
+            .to_string_lossy()
+            .into_owned(),
+    });
+    api_keys.ban_rules = vec![BanRule {
+        id: "shared_key".to_string(),
+        name: "Shared key".to_string(),
+        condition: BanCondition::KeySharing {
+            window_secs: 3_600,
+            max_ips: Some(1),
+            max_subnets: None,
+        },
+        ban_duration_secs: 600,
+        enabled: true,
+        trigger_count_threshold: 1,
+        trigger_window_secs: 3_600,
+        action: BanAction::Alert,
+        escalation: None,
+        mode: BanRuleMode::Enforce,
+        subject: BanSubject::Key,
+    }];
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let proxy_url = format!("http://{gateway_addr}/openai/v1/models");
+
+    for ip in ["203.0.113.10", "198.51.100.20", "198.51.100.20"] {
+        let response = client
+            .get(&proxy_url)
+            .header("x-forwarded-for", ip)
+            .header("authorization", "Bearer gw_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        // 仅告警，不封禁
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // 请求结果异步上报，轮询等待告警出现
+    let mut alerts = serde_json::Value::Null;
+    for _ in 0..40 {
+        let listed = client
+            .get(format!("http://{gateway_addr}/admin/api/ban-alerts"))
+            .header("authorization", "Bearer admin_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        assert_eq!(listed.status(), StatusCode::OK);
+        alerts = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+        if alerts["alerts"].as_array().is_some_and(|alerts| !alerts.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    let alerts = alerts["alerts"].as_array().expect("alerts should be listed");
+    // 同一 Key 与规则在触发计数窗口内只告警一次
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0]["api_key_id"], "default");
+    assert_eq!(alerts[0]["rule_id"], "shared_key");
+    assert_eq!(
+        alerts[0]["metrics_snapshot"]["client_ips"],
+        serde_json::json!(["198.51.100.20", "203.0.113.10"])
+    );
+
+    // 告警同时写入封禁日志，重启后可查
+    let mut logs = serde_json::Value::Null;
+    for _ in 0..40 {
+        let listed = client
+            .get(format!("http://{gateway_addr}/admin/api/ban-logs"))
+            .header("authorization", "Bearer admin_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        logs = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+        if logs["logs"].as_array().is_some_and(|logs| !logs.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    assert_eq!(logs["logs"][0]["alert"], true);
+    assert_eq!(logs["logs"][0]["rule_id"], "shared_key");
+    assert_eq!(
+        logs["logs"][0]["metrics_snapshot"]["client_ips"],
+        serde_json::json!(["198.51.100.20", "203.0.113.10"])
+    );
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn shadow_ban_rule_logs_would_be_ban_without_blocking() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    let api_keys = config.api_keys.as_mut().unwrap();
+    api_keys.sqlite = Some(ApiKeysSqliteConfig {
+        path: temp_config_db_path()
+            .with_file_name("ban_logs.db")
+            .to_string_lossy()
+            .into_owned(),
+    });
+    api_keys.ban_rules = vec![BanRule {
+        id: "busy_key".to_string(),
+        name: "Busy key".to_string(),
+        condition: BanCondition::RequestCount {
+            window_secs: 60,
+            max_requests: 2,
+        },
+        ban_duration_secs: 600,
+        enabled: true,
+        trigger_count_threshold: 1,
+        trigger_window_secs: 3_600,
+        action: BanAction::Ban,
+        escalation: None,
+        mode: BanRuleMode::Shadow,
+        subject: BanSubject::Key,
+    }];
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+
+    for _ in 0..4 {
+        let response = client
+            .get(format!("http://{gateway_addr}/openai/v1/models"))
+            .header("authorization", "Bearer gw_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        // 影子模式不拦截请求
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // 请求结果异步上报、封禁日志异步写入，轮询等待统计出现
+    let mut stats = serde_json::Value::Null;
+    for _ in 0..40 {
+        let listed = client
+            .get(format!("http://{gateway_addr}/admin/api/ban-rules/shadow-stats"))
+            .header("authorization", "Bearer admin_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        assert_eq!(listed.status(), StatusCode::OK);
+        stats = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+        if stats["rules"].as_array().is_some_and(|rules| !rules.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    // 模拟封禁期间不重复记录
+    assert_eq!(stats["rules"][0]["rule_id"], "busy_key");
+    assert_eq!(stats["rules"][0]["count"], 1);
+    assert_eq!(stats["rules"][0]["distinct_keys"], 1);
+
+    let logs = client
+        .get(format!("http://{gateway_addr}/admin/api/ban-logs"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    let logs: serde_json::Value = serde_json::from_str(&logs.text().await.unwrap()).unwrap();
+    assert_eq!(logs["logs"][0]["shadow"], true);
+    assert_eq!(logs["logs"][0]["api_key_id"], "default");
+
+    let keys = client
+        .get(format!("http://{gateway_addr}/admin/api/keys"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    let keys: serde_json::Value = serde_json::from_str(&keys.text().await.unwrap()).unwrap();
+    assert_eq!(keys["keys"][0]["is_banned"], false);
+    assert_eq!(keys["keys"][0]["ban_count"], 0);
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+#[tokio::test]
+async fn subnet_ban_rule_blocks_clients_before_authentication() {
+    let upstream = Router::new().fallback(|| async { "ok" });
+    let (upstream_addr, upstream_handle) = spawn_router(upstream).await;
+
+    let mut config = gateway_config(upstream_addr.to_string(), 2_000);
+    config.admin = Some(AdminConfig {
+        enabled: true,
+        token: "admin_token".to_string(),
+        path_prefix: "/admin".to_string(),
+    });
+    config.trusted_proxies = vec!["127.0.0.1".to_string()];
+    let api_keys = config.api_keys.as_mut().unwrap();
+    api_keys.sqlite = Some(ApiKeysSqliteConfig {
+        path: temp_config_db_path()
+            .with_file_name("ban_logs.db")
+            .to_string_lossy()
+            .into_owned(),
+    });
+    api_keys.ban_rules = vec![BanRule {
+        id: "scanner".to_string(),
+        name: "Invalid credentials".to_string(),
+        condition: BanCondition::StatusCode {
+            window_secs: 60,
+            statuses: vec!["401".to_string()],
+            max_count: 3,
+        },
+        ban_duration_secs: 600,
+        enabled: true,
+        trigger_count_threshold: 1,
+        trigger_window_secs: 3_600,
+        action: BanAction::Ban,
+        escalation: None,
+        mode: BanRuleMode::Enforce,
+        subject: BanSubject::Subnet,
+    }];
+    let app = build_test_app(config).await;
+    let (gateway_addr, gateway_handle) = spawn_router(app).await;
+    let client = reqwest::Client::new();
+    let send = |token: &'static str, ip: &'static str| {
+        client
+            .get(format!("http://{gateway_addr}/openai/v1/models"))
+            .header("authorization", format!("Bearer {token}"))
+            .header("x-forwarded-for", ip)
+            .send()
+    };
+
+    // 轮换无效凭证的客户端：第 3 次鉴权失败后整个网段被封禁
+    for _ in 0..3 {
+        let response = send("garbage", "10.1.2.3").await.expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let response = send("gw_token", "10.1.2.99").await.expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.text().await.unwrap(), r#"{"error":"ip_banned"}"#);
+    let response = send("gw_token", "10.1.3.1").await.expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = client
+        .get(format!("http://{gateway_addr}/admin/api/ip-bans"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    let listed: serde_json::Value = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+    assert_eq!(listed["bans"][0]["subject"], "subnet");
+    assert_eq!(listed["bans"][0]["target"], "10.1.2.0/24");
+    assert_eq!(listed["bans"][0]["status"]["triggered_rule_id"], "scanner");
+
+    // 封禁日志异步写入，轮询等待记录出现
+    let mut logs = serde_json::Value::Null;
+    for _ in 0..40 {
+        let listed = client
+            .get(format!("http://{gateway_addr}/admin/api/ban-logs"))
+            .header("authorization", "Bearer admin_token")
+            .send()
+            .await
+            .expect("request should succeed");
+        logs = serde_json::from_str(&listed.text().await.unwrap()).unwrap();
+        if logs["logs"].as_array().is_some_and(|logs| !logs.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    assert_eq!(logs["logs"][0]["subject"], "subnet");
+    assert_eq!(logs["logs"][0]["api_key_id"], "10.1.2.0/24");
+
+    let unbanned = client
+        .delete(format!("http://{gateway_addr}/admin/api/ip-bans/10.1.2.0%2F24"))
+        .header("authorization", "Bearer admin_token")
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(unbanned.status(), StatusCode::OK);
+    let response = send("gw_token", "10.1.2.99").await.expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 手动封禁单个 IP
+    let banned = client
+        .post(format!("http://{gateway_addr}/admin/api/ip-bans"))
+        .header("authorization", "Bearer admin_token")
+        .header("content-type", "application/json")
+        .body(r#"{"target":"10.9.9.9","reason":"abuse","permanent":true}"#)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(banned.status(), StatusCode::OK);
+    let response = send("gw_token", "10.9.9.9").await.expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send("gw_token", "10.9.9.10").await.expect("request should succeed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let invalid = client
+        .post(format!("http://{gateway_addr}/admin/api/ip-bans"))
+        .header("authorization", "Bearer admin_token")
+        .header("content-type", "application/json")
+        .body(r#"{"target":"10.9.0.0/16","reason":"abuse","duration_secs":60}"#)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    // 错误信息列出支持的前缀长度
+    let body = invalid.text().await.unwrap();
+    assert!(body.contains("/24 (IPv4) and /64 (IPv6)"), "{body}");
+
+    gateway_handle.abort();
+    upstream_handle.abort();
+}
+
+async fn spawn_router(router: Router) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    use std::net::SocketAddr;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("listener should bind");
+    let addr = listener
+        .local_addr()
+        .expect("listener should have local addr");
+
+    let handle = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .expect("server should run");
+    });
+
+    (addr, handle)
+}
+
+fn unused_local_addr() -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
+    let addr = listener
+        .local_addr()
+        .expect("local addr should be available");
+    drop(listener);
+    addr
+}
+
+fn gateway_config(upstream_addr: String, request_timeout_ms: u64) -> AppConfig {
+    AppConfig {
+        listen: "127.0.0.1:8080".to_string(),
+        gateway_auth: GatewayAuthConfig {
+            token_sources: vec![TokenSourceConfig::AuthorizationBearer],
+        },
+        data_dir: None,
+        config_db_path: temp_config_db_path().to_string_lossy().to_string(),
+        routes: Some(vec![RouteConfig {
+            id: "openai".to_string(),
+            prefix: "/openai".to_string(),
+            upstream: UpstreamConfig {
+                base_url: format!("http://{upstream_addr}"),
+                strip_prefix: true,
+                connect_timeout_ms: 1_000,
+                request_timeout_ms,
+                inject_headers: vec![HeaderInjection {
+                    name: "authorization".to_string(),
+                    value: "Bearer injected-upstream-token".to_string(),
+                }],
+                remove_headers: vec![
+                    "authorization".to_string(),
+                    "x-forwarded-for".to_string(),
+                    "forwarded".to_string(),
+                    "cf-connecting-ip".to_string(),
+                    "true-client-ip".to_string(),
+                ],
+                forward_xff: false,
+                proxy: None,
+                upstream_key_max_inflight: None,
+                user_agent: None,
+            },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
+        }]),
+        api_keys: Some(ApiKeysGlobalConfig {
+            keys: vec![ApiKeyConfig {
+                id: "default".to_string(),
+                route_id: None,
+                route_ids: None,
+                key: "gw_token".to_string(),
+                key_hash: String::new(),
+                key_prefix: String::new(),
+                previous_keys: Vec::new(),
+                enabled: true,
+                not_before: None,
+                expires_at: None,
+                idle_expiry_days: None,
+                remark: String::new(),
+                rate_limit: None,
+                concurrency: None,
+                token_quota: None,
+                token_rate_limit: None,
+                ban_rules: Vec::new(),
+                ban_status: None,
+                budget: None,
+                max_output_tokens_per_request: None,
+            }],
+            ban_rules: Vec::new(),
+            sqlite: None,
+            expiry_warning_days: 7,
+            rotation_grace_secs: 86_400,
+        }),
+        inbound_tls: None,
+        cors: None,
+        rate_limit: None,
+        concurrency: None,
+        observability: None,
+        admin: None,
+        token_stats: None,
+        tokenizer: None,
+        pricing: None,
+        global_budget: None,
+        state_backend: None,
+        auth_protection: None,
+        trusted_proxies: Vec::new(),
+    }
+}
+
+fn temp_config_db_path() -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("time should move forward")
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!(
+        "ai-gw-lite-gateway-e2e-{}-{nanos}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).expect("temp dir should be created");
+    dir.join("config.db")
+}
+
+async fn upstream_echo(
+    State(capture): State<UpstreamCapture>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let auth = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let xff = headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+
+    *capture.authorization.lock().expect("lock should succeed") = auth;
+    *capture.x_forwarded_for.lock().expect("lock should succeed") = xff;
+
+    if body == Bytes::from_static(b"hello") {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+async fn upstream_echo_user_agent(headers: HeaderMap) -> Response<Body> {
+    let user_agent = headers
+        .get("user-agent")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    Response::builder()
         .status(StatusCode::OK)
         .body(Body::from(user_agent))
         .expect("response should build")
@@ -1061,6 +2438,25 @@ async fn upstream_sse() -> Response<Body> {
     response
 }
 
+async fn upstream_sse_endless_deltas() -> Response<Body> {
+    let events = stream::unfold((), |()| async {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        Some((
+            Ok::<Bytes, std::io::Error>(Bytes::from_static(
+                b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"abcd\"}}]}\n\n",
+            )),
+            (),
+        ))
+    });
+
+    let mut response = Response::new(Body::from_stream(events));
+    *response.status_mut() = StatusCode::OK;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    response
+}
+
 async fn upstream_slow() -> StatusCode {
     tokio::time::sleep(Duration::from_millis(120)).await;
     StatusCode::OK
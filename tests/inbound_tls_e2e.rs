@@ -1,9 +1,12 @@
 use ai_gw_lite::config::{
     ApiKeyConfig, ApiKeysGlobalConfig, AppConfig, GatewayAuthConfig, HeaderInjection,
-    InboundTlsConfig, RouteConfig, TokenSourceConfig, UpstreamConfig,
+    InboundTlsConfig, RouteAuthConfig, RouteAuthMode, RouteConfig, TokenSourceConfig,
+    UpstreamConfig,
 };
 use ai_gw_lite::server::run_server;
+use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
 use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::time::{Duration, sleep};
@@ -15,17 +18,109 @@ async fn https_listener_auto_generates_self_signed_cert() {
     let key_path = temp_dir.join("gateway-selfsigned.key");
     let listen_addr = unused_local_addr();
 
-    let config = AppConfig {
+    let config = tls_gateway_config(listen_addr, &temp_dir, "https://api.openai.com");
+
+    let server_handle = tokio::spawn(async move { run_server(Arc::new(config), None).await });
+
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("https client should build");
+    let healthz_url = format!("https://{listen_addr}/healthz");
+    let response = wait_until_ready(&client, &healthz_url).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(cert_path.exists(), "self-signed cert should be generated");
+    assert!(key_path.exists(), "self-signed key should be generated");
+
+    server_handle.abort();
+    let _ = server_handle.await;
+    let _ = std::fs::remove_dir_all(temp_dir);
+}
+
+#[tokio::test]
+async fn route_requiring_client_cert_rejects_connections_without_one() {
+    let temp_dir = make_temp_dir();
+    let listen_addr = unused_local_addr();
+
+    let ca_key = KeyPair::generate().expect("ca key should generate");
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).expect("ca params should build");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key).expect("ca cert should sign");
+    let client_key = KeyPair::generate().expect("client key should generate");
+    let mut client_params =
+        CertificateParams::new(vec!["client.internal".to_string()]).expect("client params should build");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params
+        .signed_by(&client_key, &ca_cert, &ca_key)
+        .expect("client cert should sign");
+    let ca_path = temp_dir.join("client-ca.crt");
+    std::fs::write(&ca_path, ca_cert.pem()).expect("ca cert should be written");
+
+    let upstream = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("upstream should bind");
+    let upstream_addr = upstream.local_addr().expect("upstream addr should be available");
+    let upstream_handle = tokio::spawn(async move {
+        let app = axum::Router::new().fallback(|| async { "embedded" });
+        axum::serve(upstream, app).await.expect("upstream should run");
+    });
+
+    let mut config = tls_gateway_config(listen_addr, &temp_dir, &format!("http://{upstream_addr}"));
+    config.inbound_tls.as_mut().unwrap().client_ca_path = Some(ca_path.to_string_lossy().to_string());
+    config.routes.as_mut().unwrap()[0].auth = Some(RouteAuthConfig {
+        mode: RouteAuthMode::None,
+        token_sources: Vec::new(),
+        require_client_cert: true,
+    });
+    let server_handle = tokio::spawn(async move { run_server(Arc::new(config), None).await });
+
+    // 客户端证书可选：未出示证书也能完成握手，由路由策略拒绝
+    let anonymous_client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("https client should build");
+    wait_until_ready(&anonymous_client, &format!("https://{listen_addr}/healthz")).await;
+    let url = format!("https://{listen_addr}/openai/v1/embeddings");
+    let rejected = anonymous_client.get(&url).send().await.expect("request should succeed");
+    assert_eq!(rejected.status(), reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(
+        rejected.text().await.unwrap(),
+        r#"{"error":"client_certificate_required"}"#
+    );
+
+    let identity = reqwest::Identity::from_pem(
+        format!("{}{}", client_cert.pem(), client_key.serialize_pem()).as_bytes(),
+    )
+    .expect("client identity should load");
+    let mtls_client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .identity(identity)
+        .build()
+        .expect("mtls client should build");
+    let accepted = mtls_client.get(&url).send().await.expect("request should succeed");
+    assert_eq!(accepted.status(), reqwest::StatusCode::OK);
+    assert_eq!(accepted.text().await.unwrap(), "embedded");
+
+    server_handle.abort();
+    let _ = server_handle.await;
+    upstream_handle.abort();
+    let _ = std::fs::remove_dir_all(temp_dir);
+}
+
+fn tls_gateway_config(listen_addr: SocketAddr, temp_dir: &Path, base_url: &str) -> AppConfig {
+    AppConfig {
         listen: listen_addr.to_string(),
         gateway_auth: GatewayAuthConfig {
             token_sources: vec![TokenSourceConfig::AuthorizationBearer],
         },
         data_dir: None,
+        config_db_path: temp_dir.join("config.db").to_string_lossy().to_string(),
         routes: Some(vec![RouteConfig {
             id: "openai".to_string(),
             prefix: "/openai".to_string(),
             upstream: UpstreamConfig {
-                base_url: "https://api.openai.com".to_string(),
+                base_url: base_url.to_string(),
                 strip_prefix: true,
                 connect_timeout_ms: 1_000,
                 request_timeout_ms: 1_000,
@@ -39,6 +134,10 @@ async fn https_listener_auto_generates_self_signed_cert() {
                 upstream_key_max_inflight: None,
                 user_agent: None,
             },
+            rate_limit: None,
+            token_rate_limit: None,
+            forward_auth: None,
+            auth: None,
         }]),
         api_keys: Some(ApiKeysGlobalConfig {
             keys: vec![ApiKeyConfig {
@@ -46,45 +145,48 @@ async fn https_listener_auto_generates_self_signed_cert() {
                 route_id: None,
                 route_ids: None,
                 key: "gw_token".to_string(),
+                key_hash: String::new(),
+                key_prefix: String::new(),
+                previous_keys: Vec::new(),
                 enabled: true,
+                not_before: None,
+                expires_at: None,
+                idle_expiry_days: None,
                 remark: String::new(),
                 rate_limit: None,
                 concurrency: None,
+                token_quota: None,
+                token_rate_limit: None,
                 ban_rules: Vec::new(),
                 ban_status: None,
+                budget: None,
+                max_output_tokens_per_request: None,
             }],
             ban_rules: Vec::new(),
             sqlite: None,
+            expiry_warning_days: 7,
+            rotation_grace_secs: 86_400,
         }),
         inbound_tls: Some(InboundTlsConfig {
             cert_path: None,
             key_path: None,
-            self_signed_cert_path: cert_path.to_string_lossy().to_string(),
-            self_signed_key_path: key_path.to_string_lossy().to_string(),
+            self_signed_cert_path: temp_dir.join("gateway-selfsigned.crt").to_string_lossy().to_string(),
+            self_signed_key_path: temp_dir.join("gateway-selfsigned.key").to_string_lossy().to_string(),
+            client_ca_path: None,
         }),
         cors: None,
         rate_limit: None,
         concurrency: None,
         observability: None,
         admin: None,
-    };
-
-    let server_handle = tokio::spawn(async move { run_server(Arc::new(config), None).await });
-
-    let client = reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .expect("https client should build");
-    let healthz_url = format!("https://{listen_addr}/healthz");
-    let response = wait_until_ready(&client, &healthz_url).await;
-
-    assert_eq!(response.status(), reqwest::StatusCode::OK);
-    assert!(cert_path.exists(), "self-signed cert should be generated");
-    assert!(key_path.exists(), "self-signed key should be generated");
-
-    server_handle.abort();
-    let _ = server_handle.await;
-    let _ = std::fs::remove_dir_all(temp_dir);
+        token_stats: None,
+        tokenizer: None,
+        pricing: None,
+        global_budget: None,
+        state_backend: None,
+        auth_protection: None,
+        trusted_proxies: Vec::new(),
+    }
 }
 
 async fn wait_until_ready(client: &reqwest::Client, url: &str) -> reqwest::Response {